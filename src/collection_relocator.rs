@@ -0,0 +1,288 @@
+use std::{
+    collections::HashSet,
+    fs::{create_dir_all, remove_dir, remove_file, rename, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use async_std::task::spawn_blocking;
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    file_importer,
+    files::{collect_file_paths, get_file_name},
+    util::{
+        checksum::{compute_file_checksums_with_progress, ChecksumHasher},
+        file_operation::ProgressReporter,
+    },
+};
+
+const STAGING_EXTENSION: &str = "relocating";
+
+/// Copies the whole collection directory tree to a new root and verifies each copied file by
+/// checksum. The collection root in the settings is changed only after every file has been
+/// copied, and the old files are removed after that when requested. Each file is copied to a
+/// staging name and renamed after verification, so an interrupted relocation continues from the
+/// files that haven't been copied yet when it's run again. A relocation interrupted after the
+/// settings were changed only removes the old files when it's run again.
+pub async fn relocate_collection(
+    old_root_dir: String,
+    new_root_dir: String,
+    remove_old_files: bool,
+    reporter: ProgressReporter,
+) -> Result<(), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let mut settings = db.get_settings()?;
+    // a job saved before the old root was stored relocates from the root in the settings
+    let old_root = PathBuf::from(if old_root_dir.is_empty() {
+        &settings.collection_root_dir
+    } else {
+        &old_root_dir
+    });
+    let new_root = PathBuf::from(&new_root_dir);
+
+    if settings.collection_root_dir != new_root_dir {
+        validate_new_root(&old_root, &new_root)?;
+
+        let root = old_root.clone();
+        let relative_paths = spawn_blocking(move || list_relative_paths(&root)).await?;
+        for relative_path in &relative_paths {
+            reporter.check_cancelled()?;
+            let source = old_root.join(relative_path);
+            let destination = new_root.join(relative_path);
+            let reporter = reporter.clone();
+            spawn_blocking(move || copy_and_verify(&source, &destination, &reporter)).await?;
+        }
+
+        settings.collection_root_dir = new_root_dir;
+        db.add_or_update_settings(&settings)?;
+    }
+
+    if remove_old_files && old_root != new_root && old_root.exists() {
+        spawn_blocking(move || {
+            // only the files copied to the new root are removed
+            let relative_paths = list_relative_paths(&old_root)?
+                .into_iter()
+                .filter(|relative_path| new_root.join(relative_path).exists())
+                .collect::<Vec<PathBuf>>();
+            remove_relocated_files(&old_root, &relative_paths);
+            Ok::<(), Error>(())
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Checks that both roots are set and that neither is inside the other, which would make the
+/// copied files part of the tree being copied. The new root is created if it doesn't exist.
+pub fn validate_new_root(old_root: &Path, new_root: &Path) -> Result<(), Error> {
+    if old_root.as_os_str().is_empty() || new_root.as_os_str().is_empty() {
+        return Err(Error::NotFound(
+            "Collection root directory is not set".to_string(),
+        ));
+    }
+    create_dir_all(new_root)
+        .map_err(|e| Error::IoError(format!("Failed creating {:?}: {}", new_root, e)))?;
+    let canonicalize = |path: &Path| {
+        path.canonicalize()
+            .map_err(|e| Error::IoError(format!("Failed resolving {:?}: {}", path, e)))
+    };
+    let old_root = canonicalize(old_root)?;
+    let new_root = canonicalize(new_root)?;
+    if old_root.starts_with(&new_root) || new_root.starts_with(&old_root) {
+        return Err(Error::IoError(format!(
+            "{:?} and {:?} can't be inside each other",
+            old_root, new_root
+        )));
+    }
+    Ok(())
+}
+
+/// Lists the files of the collection relative to the root, leaving out files being imported
+/// or relocated.
+fn list_relative_paths(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut file_paths = Vec::new();
+    collect_file_paths(root, &mut file_paths)?;
+    file_paths.sort();
+    Ok(file_paths
+        .into_iter()
+        .filter(|file_path| {
+            !file_path.extension().is_some_and(|extension| {
+                extension == file_importer::STAGING_EXTENSION || extension == STAGING_EXTENSION
+            })
+        })
+        .filter_map(|file_path| {
+            file_path
+                .strip_prefix(root)
+                .ok()
+                .map(|relative_path| relative_path.to_path_buf())
+        })
+        .collect())
+}
+
+/// Copies the file to a staging name while calculating its checksums, verifies the copy
+/// and renames it into place. An existing destination with the checksums of the source was
+/// copied in an earlier run, other files at the destination are replaced.
+/// This is blocking and should be run with spawn_blocking.
+fn copy_and_verify(
+    source: &Path,
+    destination: &Path,
+    reporter: &ProgressReporter,
+) -> Result<(), Error> {
+    if destination.exists()
+        && compute_file_checksums_with_progress(destination, reporter)?
+            == compute_file_checksums_with_progress(source, reporter)?
+    {
+        return Ok(());
+    }
+    if let Some(parent) = destination.parent() {
+        create_dir_all(parent)
+            .map_err(|e| Error::IoError(format!("Failed creating {:?}: {}", parent, e)))?;
+    }
+    let mut staging_file_name = destination.file_name().unwrap_or_default().to_os_string();
+    staging_file_name.push(".");
+    staging_file_name.push(STAGING_EXTENSION);
+    let staging_path = destination.with_file_name(staging_file_name);
+
+    let result = copy_to_staging(source, &staging_path, reporter)
+        .and_then(|_| rename_into_place(&staging_path, destination));
+    if result.is_err() {
+        let _ = remove_file(&staging_path);
+    }
+    result
+}
+
+fn copy_to_staging(
+    source: &Path,
+    staging_path: &Path,
+    reporter: &ProgressReporter,
+) -> Result<(), Error> {
+    let mut source_file = File::open(source)
+        .map_err(|e| Error::IoError(format!("Failed opening file {:?}: {}", source, e)))?;
+    let total_bytes = source_file.metadata().map(|m| m.len()).unwrap_or_default();
+    let mut staging_file = File::create(staging_path)
+        .map_err(|e| Error::IoError(format!("Failed creating file {:?}: {}", staging_path, e)))?;
+    let mut hasher = ChecksumHasher::new();
+    let description = format!("Copying {}", get_file_name(source)?);
+    reporter.read_with_progress(&mut source_file, &description, total_bytes, |chunk| {
+        hasher.update(chunk);
+        staging_file.write_all(chunk)
+    })?;
+    drop(staging_file);
+
+    if compute_file_checksums_with_progress(staging_path, reporter)? != hasher.finalize() {
+        return Err(Error::IoError(format!(
+            "Checksums of the copy of {:?} don't match the original file",
+            source
+        )));
+    }
+    Ok(())
+}
+
+fn rename_into_place(staging_path: &Path, destination: &Path) -> Result<(), Error> {
+    rename(staging_path, destination).map_err(|e| {
+        Error::IoError(format!(
+            "Failed to rename staged file to {:?}: {}",
+            destination, e
+        ))
+    })
+}
+
+/// Removes the relocated files from the old root and the directories left empty.
+/// Failures are only logged, the collection is already in use in the new root.
+fn remove_relocated_files(old_root: &Path, relative_paths: &[PathBuf]) {
+    let mut directories = HashSet::new();
+    for relative_path in relative_paths {
+        let file_path = old_root.join(relative_path);
+        if let Err(e) = remove_file(&file_path) {
+            println!("Failed to remove relocated file {:?}: {}", file_path, e);
+        }
+        directories.extend(
+            relative_path
+                .ancestors()
+                .skip(1)
+                .filter(|directory| !directory.as_os_str().is_empty())
+                .map(|directory| old_root.join(directory)),
+        );
+    }
+    let mut directories = directories.into_iter().collect::<Vec<PathBuf>>();
+    // sub directories are removed before their parents
+    directories.sort_by_key(|directory| std::cmp::Reverse(directory.components().count()));
+    for directory in directories {
+        let _ = remove_dir(directory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_validate_new_root() {
+        let directory = create_test_directory("test_validate_new_root");
+        let old_root = directory.join("old");
+        std::fs::create_dir_all(&old_root).unwrap();
+
+        assert!(validate_new_root(&old_root, &directory.join("new")).is_ok());
+        assert!(validate_new_root(&old_root, &old_root.join("new")).is_err());
+        assert!(validate_new_root(&old_root, &directory).is_err());
+        assert!(validate_new_root(Path::new(""), &directory.join("new")).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_copy_tree_and_remove_old_files() {
+        let directory = create_test_directory("test_copy_tree_and_remove_old_files");
+        let old_root = directory.join("old");
+        let new_root = directory.join("new");
+        std::fs::create_dir_all(old_root.join("system/disk_images")).unwrap();
+        std::fs::write(old_root.join("system/disk_images/1234.d64"), b"game").unwrap();
+        std::fs::write(old_root.join("system/disk_images/5678.d64.importing"), b"").unwrap();
+
+        let relative_paths = list_relative_paths(&old_root).unwrap();
+        assert_eq!(
+            relative_paths,
+            vec![PathBuf::from("system/disk_images/1234.d64")]
+        );
+        for relative_path in &relative_paths {
+            copy_and_verify(
+                &old_root.join(relative_path),
+                &new_root.join(relative_path),
+                &ProgressReporter::default(),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            std::fs::read(new_root.join("system/disk_images/1234.d64")).unwrap(),
+            b"game"
+        );
+
+        // a partly copied file at the destination is copied again
+        std::fs::write(new_root.join("system/disk_images/1234.d64"), b"ga").unwrap();
+        copy_and_verify(
+            &old_root.join("system/disk_images/1234.d64"),
+            &new_root.join("system/disk_images/1234.d64"),
+            &ProgressReporter::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read(new_root.join("system/disk_images/1234.d64")).unwrap(),
+            b"game"
+        );
+
+        std::fs::remove_file(old_root.join("system/disk_images/5678.d64.importing")).unwrap();
+        remove_relocated_files(&old_root, &relative_paths);
+        assert!(!old_root.join("system").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
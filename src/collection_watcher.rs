@@ -0,0 +1,227 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use async_std::task::{sleep, spawn_blocking};
+use iced::{futures::SinkExt, stream, Subscription};
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::collect_file_paths,
+    integrity_checker::{check_collection_file, get_collection_files_with_paths},
+    job_queue::JobQueue,
+    model::{collection_file::CollectionFile, integrity_check::IntegrityIssue},
+    util::file_path_builder::FilePathBuilder,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const THUMBNAILS_DIRECTORY: &str = "thumbnails";
+
+/// A change made to the collection directory outside the app.
+#[derive(Debug, Clone)]
+pub enum CollectionChange {
+    // a collection file went missing or its content changed
+    FileFlagged(IntegrityIssue),
+    // a file not belonging to the collection appeared in the collection directory
+    FileAdded(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileState {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+type Snapshot = HashMap<PathBuf, FileState>;
+
+/// Polls the collection root dir and the online storage volumes while watching is enabled in
+/// settings. Changes are looked at only while no job is running. The snapshot taken before a
+/// job is compared with the collection after it, so the files a job moved are checked at their
+/// new paths and the paths they left aren't reported.
+pub fn subscription() -> Subscription<CollectionChange> {
+    Subscription::run_with_id(
+        "collection-watcher",
+        stream::channel(100, |mut output| async move {
+            let mut previous_snapshot: Option<Snapshot> = None;
+            loop {
+                sleep(POLL_INTERVAL).await;
+                if JobQueue::get_instance().has_unfinished_job(|_| true) {
+                    continue;
+                }
+                let snapshot = previous_snapshot.take();
+                match spawn_blocking(move || check_for_changes(snapshot)).await {
+                    Ok((snapshot, changes)) => {
+                        previous_snapshot = snapshot;
+                        for change in changes {
+                            let _ = output.send(change).await;
+                        }
+                    }
+                    Err(e) => println!("Failed to check the collection for changes: {}", e),
+                }
+            }
+        }),
+    )
+}
+
+/// Takes a new snapshot of the collection and compares it with the previous one. The first
+/// snapshot is taken without reporting changes and no snapshot is taken while watching is off.
+fn check_for_changes(
+    previous_snapshot: Option<Snapshot>,
+) -> Result<(Option<Snapshot>, Vec<CollectionChange>), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let settings = db.get_settings()?;
+    if !settings.watch_collection || settings.collection_root_dir.is_empty() {
+        return Ok((None, vec![]));
+    }
+    let file_path_builder = FilePathBuilder::from_settings(&settings);
+    let snapshot = take_snapshot(&file_path_builder)?;
+    let Some(previous_snapshot) = previous_snapshot else {
+        return Ok((Some(snapshot), vec![]));
+    };
+    let changed_paths = get_changed_paths(&previous_snapshot, &snapshot);
+    if changed_paths.is_empty() {
+        return Ok((Some(snapshot), vec![]));
+    }
+
+    let mut collection_files: HashMap<PathBuf, Vec<CollectionFile>> = HashMap::new();
+    for (collection_file, file_path) in get_collection_files_with_paths(db)? {
+        collection_files
+            .entry(file_path)
+            .or_default()
+            .push(collection_file);
+    }
+    let known_paths = get_known_paths(db, &file_path_builder, &collection_files)?;
+    let mut integrity_check = db.get_integrity_check()?.unwrap_or_default();
+    let mut changes = Vec::new();
+    for file_path in changed_paths {
+        match collection_files.get(&file_path) {
+            Some(files) => {
+                for collection_file in files {
+                    let collection_file_id = collection_file._id;
+                    // a file put back as it was isn't flagged anymore
+                    integrity_check
+                        .issues
+                        .retain(|issue| Some(issue.collection_file_id) != collection_file_id);
+                    if let Some(issue) = check_collection_file(collection_file, &file_path) {
+                        integrity_check.issues.push(issue.clone());
+                        changes.push(CollectionChange::FileFlagged(issue));
+                    }
+                }
+            }
+            None if snapshot.contains_key(&file_path)
+                && !previous_snapshot.contains_key(&file_path)
+                && !known_paths.contains(&file_path) =>
+            {
+                changes.push(CollectionChange::FileAdded(file_path));
+            }
+            None => {}
+        }
+    }
+    db.save_integrity_check(&integrity_check)?;
+    Ok((Some(snapshot), changes))
+}
+
+/// Returns the paths of all collection files, also the files that aren't in a release, for
+/// example the files of an import that hasn't added its release yet. The path of a file
+/// outside releases is known when it's in the content store or has a relative path.
+fn get_known_paths(
+    db: &DatabaseWithPolo,
+    file_path_builder: &FilePathBuilder,
+    release_files: &HashMap<PathBuf, Vec<CollectionFile>>,
+) -> Result<HashSet<PathBuf>, Error> {
+    let mut known_paths = release_files.keys().cloned().collect::<HashSet<PathBuf>>();
+    for collection_file in db.get_all_collection_files()? {
+        // files on an unknown volume aren't in the snapshot
+        if let Ok(Some(file_path)) =
+            file_path_builder.build_file_path_without_system(&collection_file)
+        {
+            known_paths.insert(file_path);
+        }
+    }
+    Ok(known_paths)
+}
+
+fn take_snapshot(file_path_builder: &FilePathBuilder) -> Result<Snapshot, Error> {
+    let collection_root_dir = PathBuf::from(&file_path_builder.collection_root_dir);
+    let mut roots = vec![collection_root_dir.clone()];
+    roots.extend(
+        file_path_builder
+            .storage_volumes
+            .iter()
+            .filter(|storage_volume| file_path_builder.is_volume_online(Some(&storage_volume.name)))
+            .map(|storage_volume| PathBuf::from(&storage_volume.root_dir)),
+    );
+
+    let mut file_paths = Vec::new();
+    for root in roots.iter().filter(|root| root.is_dir()) {
+        collect_file_paths(root, &mut file_paths)?;
+    }
+    let thumbnails_directory = collection_root_dir.join(THUMBNAILS_DIRECTORY);
+    Ok(file_paths
+        .into_iter()
+        .filter(|file_path| !file_path.starts_with(&thumbnails_directory) && !is_hidden(file_path))
+        .filter_map(|file_path| {
+            let metadata = fs::metadata(&file_path).ok()?;
+            let state = FileState {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            };
+            Some((file_path, state))
+        })
+        .collect())
+}
+
+/// Returns the paths added, removed or modified since the previous snapshot.
+fn get_changed_paths(previous_snapshot: &Snapshot, snapshot: &Snapshot) -> Vec<PathBuf> {
+    let mut changed_paths = previous_snapshot
+        .keys()
+        .chain(snapshot.keys())
+        .filter(|file_path| previous_snapshot.get(*file_path) != snapshot.get(*file_path))
+        .cloned()
+        .collect::<HashSet<PathBuf>>()
+        .into_iter()
+        .collect::<Vec<PathBuf>>();
+    changed_paths.sort();
+    changed_paths
+}
+
+fn is_hidden(file_path: &Path) -> bool {
+    file_path
+        .file_name()
+        .is_some_and(|file_name| file_name.to_string_lossy().starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_changed_paths() {
+        let state = |size| FileState {
+            size,
+            modified: None,
+        };
+        let previous_snapshot = Snapshot::from([
+            (PathBuf::from("kept.d64"), state(1)),
+            (PathBuf::from("modified.d64"), state(1)),
+            (PathBuf::from("removed.d64"), state(1)),
+        ]);
+        let snapshot = Snapshot::from([
+            (PathBuf::from("kept.d64"), state(1)),
+            (PathBuf::from("modified.d64"), state(2)),
+            (PathBuf::from("added.d64"), state(1)),
+        ]);
+        assert_eq!(
+            get_changed_paths(&previous_snapshot, &snapshot),
+            vec![
+                PathBuf::from("added.d64"),
+                PathBuf::from("modified.d64"),
+                PathBuf::from("removed.d64")
+            ]
+        );
+    }
+}
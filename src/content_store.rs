@@ -0,0 +1,228 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{create_dir_all, remove_dir, remove_file, rename},
+    path::Path,
+};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::get_file_extension,
+    model::{collection_file::CollectionFile, model::System},
+    repository::repository::CollectionFilesReadRepository,
+    util::{
+        checksum::compute_file_checksums_with_progress, file_operation::ProgressReporter,
+        file_path_builder::FilePathBuilder,
+    },
+};
+
+const DESCRIPTION: &str = "Converting files to the content store";
+
+/// Returns the key of the file in the content store, made of the SHA256 checksum and the
+/// extension of the file. Files picked before checksums were calculated have no key and
+/// are stored as separate files.
+pub fn get_content_key(collection_file: &CollectionFile) -> Option<String> {
+    if collection_file.checksums.sha256.is_empty() {
+        return None;
+    }
+    let sha256 = collection_file.checksums.sha256.to_lowercase();
+    match get_file_extension(Path::new(&collection_file.original_file_name)) {
+        Ok(extension) if !extension.is_empty() => {
+            Some(format!("{}.{}", sha256, extension.to_lowercase()))
+        }
+        _ => Some(sha256),
+    }
+}
+
+/// Deletes the collection file and its file. A file in the content store is removed only
+/// when the last collection file referencing it is deleted.
+pub async fn remove_collection_file(
+    collection_file: &CollectionFile,
+    system: &System,
+    file_path_builder: &FilePathBuilder,
+) -> Result<(), Error> {
+    // the path is resolved before the collection file is gone
+    let file_path = file_path_builder.build_file_path(system, collection_file)?;
+    let db = DatabaseWithPolo::get_instance();
+    if db.delete_collection_file_reference(collection_file)? {
+        async_std::fs::remove_file(&file_path)
+            .await
+            .map_err(|e| Error::IoError(format!("Failed to delete file {:?}: {}", file_path, e)))?;
+    }
+    Ok(())
+}
+
+/// Moves the files of the collection to the content store, keeping one file for identical
+/// content and removing the other copies. A file is renamed into the store before its
+/// collection file is changed to reference it, so a conversion that is interrupted finds the
+/// renamed files in the store when it's run again. Files without a SHA256 checksum, missing
+/// files and files changed since they were imported are left as they are.
+pub async fn convert_to_content_store(reporter: ProgressReporter) -> Result<(), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let file_path_builder = FilePathBuilder::from_settings(&db.get_settings()?);
+    let systems = db
+        .get_systems()?
+        .into_iter()
+        .filter_map(|system| system._id.map(|id| (id, system)))
+        .collect::<HashMap<ObjectId, System>>();
+
+    // each file is converted once, also when it's in several releases
+    let mut seen_file_ids = HashSet::new();
+    let mut files_to_convert = Vec::new();
+    for release in db.get_releases()? {
+        let Some(system) = release.system_id.and_then(|id| systems.get(&id)) else {
+            continue;
+        };
+        for collection_file in db.get_collection_files(&release.files)? {
+            if collection_file.content_key.is_none()
+                && collection_file
+                    ._id
+                    .is_some_and(|id| seen_file_ids.insert(id))
+            {
+                files_to_convert.push((collection_file, system.clone()));
+            }
+        }
+    }
+
+    let total_bytes = files_to_convert
+        .iter()
+        .map(|(collection_file, _)| collection_file.size)
+        .sum();
+    let mut bytes_done = 0;
+    for (collection_file, system) in files_to_convert {
+        reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+        bytes_done += collection_file.size;
+        // files on an offline volume are converted when the volume is online again
+        if !file_path_builder.is_volume_online(collection_file.volume.as_deref()) {
+            continue;
+        }
+        let file_path_builder = file_path_builder.clone();
+        spawn_blocking(move || convert_file(db, &file_path_builder, &system, &collection_file))
+            .await?;
+    }
+    reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+    Ok(())
+}
+
+fn convert_file(
+    db: &DatabaseWithPolo,
+    file_path_builder: &FilePathBuilder,
+    system: &System,
+    collection_file: &CollectionFile,
+) -> Result<(), Error> {
+    let Some(content_key) = get_content_key(collection_file) else {
+        return Ok(());
+    };
+    // identical content already in the store is kept on the volume where it is
+    let volume = match db.get_content_object(&content_key)? {
+        Some(content_object) => content_object.volume,
+        None => collection_file.volume.clone(),
+    };
+    let file_path = file_path_builder.build_file_path(system, collection_file)?;
+    let content_path = file_path_builder.build_content_path(volume.as_deref(), &content_key)?;
+
+    // a file changed since it was imported doesn't have the content of its key, it's left as
+    // it is for the integrity check to report
+    if file_path.exists() && !has_recorded_sha256(&file_path, collection_file)? {
+        println!(
+            "Not converting {:?}, its content doesn't match the recorded checksum",
+            file_path
+        );
+        return Ok(());
+    }
+
+    if content_path.exists() {
+        // the file is a copy of content already in the store
+        if file_path.exists() {
+            remove_file(&file_path).map_err(|e| {
+                Error::IoError(format!("Failed to delete file {:?}: {}", file_path, e))
+            })?;
+            remove_empty_parents(&file_path);
+        }
+    } else if file_path.exists() {
+        if let Some(parent) = content_path.parent() {
+            create_dir_all(parent)
+                .map_err(|e| Error::IoError(format!("Failed creating {:?}: {}", parent, e)))?;
+        }
+        rename(&file_path, &content_path).map_err(|e| {
+            Error::IoError(format!(
+                "Failed renaming {:?} to {:?}: {}",
+                file_path, content_path, e
+            ))
+        })?;
+        remove_empty_parents(&file_path);
+    } else {
+        return Ok(());
+    }
+
+    let mut collection_file = collection_file.clone();
+    collection_file.volume = volume;
+    db.set_collection_file_content_key(&collection_file, &content_key)
+}
+
+/// Whether the file on disk has the SHA256 checksum recorded for the collection file.
+fn has_recorded_sha256(file_path: &Path, collection_file: &CollectionFile) -> Result<bool, Error> {
+    let (_, checksums) =
+        compute_file_checksums_with_progress(file_path, &ProgressReporter::default())?;
+    Ok(checksums
+        .sha256
+        .eq_ignore_ascii_case(&collection_file.checksums.sha256))
+}
+
+/// Removes the directories left empty by moving a file, up to two levels like the type and
+/// system directories of the id based layout.
+fn remove_empty_parents(file_path: &Path) {
+    for directory in file_path.ancestors().skip(1).take(2) {
+        if remove_dir(directory).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::collection_file::CollectionFileType, util::checksum::compute_checksums};
+
+    #[test]
+    fn test_get_content_key() {
+        let mut collection_file = CollectionFile {
+            _id: None,
+            size: 4,
+            ..CollectionFile::for_test("Game.D64", CollectionFileType::DiskImage)
+        };
+        assert_eq!(get_content_key(&collection_file), None);
+
+        collection_file.checksums.sha256 = "ABCDEF".to_string();
+        assert_eq!(
+            get_content_key(&collection_file),
+            Some("abcdef.d64".to_string())
+        );
+    }
+
+    #[test]
+    fn test_has_recorded_sha256() {
+        let directory = std::env::temp_dir().join("test_has_recorded_sha256");
+        let _ = std::fs::remove_dir_all(&directory);
+        create_dir_all(&directory).unwrap();
+        let file_path = directory.join("1234.d64");
+        std::fs::write(&file_path, b"game").unwrap();
+        let (size, checksums) = compute_checksums(b"game");
+        let mut collection_file = CollectionFile {
+            _id: None,
+            size,
+            checksums,
+            ..CollectionFile::for_test("Game.d64", CollectionFileType::DiskImage)
+        };
+        collection_file.checksums.sha256 = collection_file.checksums.sha256.to_uppercase();
+        assert!(has_recorded_sha256(&file_path, &collection_file).unwrap());
+
+        // the file has been changed since it was imported
+        std::fs::write(&file_path, b"changed").unwrap();
+        assert!(!has_recorded_sha256(&file_path, &collection_file).unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -9,13 +9,20 @@ use polodb_core::{
 use crate::{
     error::Error,
     model::{
-        collection_file::CollectionFile,
-        model::{Emulator, Game, HasOid, Release, ReleasesByGame, Settings, System},
+        collection_file::{CollectionFile, ContentObject},
+        dat::{DatFile, DatGame},
+        integrity_check::{IntegrityCheck, IntegrityIssue},
+        job::{Job, JobStatus},
+        model::{
+            Emulator, EmulatorOverride, FileImportMode, Game, HasOid, Release, ReleasesByGame,
+            Settings, StartupFile, System,
+        },
     },
     repository::repository::{
         CollectionFilesReadRepository, GamesReadRepository, ReleaseReadRepository,
         SystemReadRepository,
     },
+    util::file_path_builder::DEFAULT_FILE_PATH_TEMPLATE,
 };
 
 const COLLECTION_DATABASE_NAME: &str = "iced_game_collection.db";
@@ -27,6 +34,12 @@ const RELEASE_COLLECTION: &str = "release";
 const SETTINGS_ID: &str = "settings";
 const RELEASES_BY_GAMES_COLLECTION: &str = "releases_by_games";
 const COLLECTION_FILE_COLLECTION: &str = "collection_file_collection";
+const DAT_FILE_COLLECTION: &str = "dat_file";
+const DAT_GAME_COLLECTION: &str = "dat_game";
+const INTEGRITY_CHECK_COLLECTION: &str = "integrity_check";
+const INTEGRITY_CHECK_ID: &str = "integrity_check";
+const JOB_COLLECTION: &str = "job";
+const CONTENT_OBJECT_COLLECTION: &str = "content_object";
 
 pub struct DatabaseWithPolo {
     db: Database,
@@ -61,6 +74,213 @@ impl DatabaseWithPolo {
         self.add_item(COLLECTION_FILE_COLLECTION, collection_file)
     }
 
+    /// Adds the collection file and links it to the release, if the release is already saved,
+    /// in one transaction. A collection file in the content store adds a reference to its content.
+    pub fn add_collection_file_to_release(
+        &self,
+        collection_file: &CollectionFile,
+        release_id: Option<&ObjectId>,
+    ) -> Result<ObjectId, Error> {
+        let transaction = self
+            .db
+            .start_transaction()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        let id = match transaction
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .insert_one(collection_file)
+        {
+            Ok(result) => match result.inserted_id.as_object_id() {
+                Some(id) => id,
+                None => {
+                    transaction
+                        .rollback()
+                        .map_err(|e| Error::DbError(e.to_string()))?;
+                    return Err(Error::DbError("Error getting inserted id".to_string()));
+                }
+            },
+            Err(e) => {
+                transaction
+                    .rollback()
+                    .map_err(|e| Error::DbError(e.to_string()))?;
+                return Err(Error::DbError(format!(
+                    "Error adding collection file: {}",
+                    e
+                )));
+            }
+        };
+
+        if let Some(content_key) = &collection_file.content_key {
+            if let Err(error) = self.update_content_references(
+                content_key,
+                collection_file.volume.as_deref(),
+                1,
+                &transaction,
+            ) {
+                transaction
+                    .rollback()
+                    .map_err(|e| Error::DbError(e.to_string()))?;
+                return Err(error);
+            }
+        }
+
+        if let Some(release_id) = release_id {
+            if let Err(e) = transaction
+                .collection::<Release>(RELEASE_COLLECTION)
+                .update_one(doc! {"_id": release_id}, doc! {"$push": {"files": id}})
+            {
+                transaction
+                    .rollback()
+                    .map_err(|e| Error::DbError(e.to_string()))?;
+                return Err(Error::DbError(format!(
+                    "Error adding file to release: {}",
+                    e
+                )));
+            }
+        }
+
+        transaction
+            .commit()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        Ok(id)
+    }
+
+    pub fn delete_collection_file(&self, id: &ObjectId) -> Result<(), Error> {
+        self.delete_item::<CollectionFile>(COLLECTION_FILE_COLLECTION, id)
+    }
+
+    /// Deletes the collection file and removes its reference to its content in the content store
+    /// in one transaction. Returns true when the file of the collection file can be removed,
+    /// meaning that no other collection file references the same content.
+    pub fn delete_collection_file_reference(
+        &self,
+        collection_file: &CollectionFile,
+    ) -> Result<bool, Error> {
+        let transaction = self
+            .db
+            .start_transaction()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        if let Err(e) = transaction
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .delete_one(doc! {"_id": collection_file.id()})
+        {
+            transaction
+                .rollback()
+                .map_err(|e| Error::DbError(e.to_string()))?;
+            return Err(Error::DbError(format!(
+                "Error deleting collection file: {}",
+                e
+            )));
+        }
+
+        let is_unreferenced = match &collection_file.content_key {
+            Some(content_key) => match self.update_content_references(
+                content_key,
+                collection_file.volume.as_deref(),
+                -1,
+                &transaction,
+            ) {
+                Ok(is_unreferenced) => is_unreferenced,
+                Err(error) => {
+                    transaction
+                        .rollback()
+                        .map_err(|e| Error::DbError(e.to_string()))?;
+                    return Err(error);
+                }
+            },
+            None => true,
+        };
+
+        transaction
+            .commit()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+        Ok(is_unreferenced)
+    }
+
+    /// Moves an existing collection file to the content store by setting its content key
+    /// and adding a reference to the content in one transaction.
+    pub fn set_collection_file_content_key(
+        &self,
+        collection_file: &CollectionFile,
+        content_key: &str,
+    ) -> Result<(), Error> {
+        let transaction = self
+            .db
+            .start_transaction()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        if let Err(e) = transaction
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .update_one(
+                doc! {"_id": collection_file.id()},
+                doc! {"$set": {"content_key": content_key, "relative_path": null}},
+            )
+        {
+            transaction
+                .rollback()
+                .map_err(|e| Error::DbError(e.to_string()))?;
+            return Err(Error::DbError(format!(
+                "Error updating collection file: {}",
+                e
+            )));
+        }
+
+        if let Err(error) = self.update_content_references(
+            content_key,
+            collection_file.volume.as_deref(),
+            1,
+            &transaction,
+        ) {
+            transaction
+                .rollback()
+                .map_err(|e| Error::DbError(e.to_string()))?;
+            return Err(error);
+        }
+
+        transaction
+            .commit()
+            .map_err(|e| Error::DbError(e.to_string()))
+    }
+
+    pub fn get_content_object(&self, content_key: &str) -> Result<Option<ContentObject>, Error> {
+        self.get_with_filter(CONTENT_OBJECT_COLLECTION, doc! {"key": content_key})
+    }
+
+    /// Changes the number of collection files referencing the content, adding the content on the
+    /// first reference and deleting it when the last reference is removed.
+    /// Returns true when the content isn't referenced anymore.
+    fn update_content_references(
+        &self,
+        content_key: &str,
+        volume: Option<&str>,
+        change: i64,
+        transaction: &Transaction,
+    ) -> Result<bool, Error> {
+        let content_objects = transaction.collection::<ContentObject>(CONTENT_OBJECT_COLLECTION);
+        let reference_count = content_objects
+            .find_one(doc! {"key": content_key})
+            .map_err(|e| Error::DbError(e.to_string()))?
+            .map_or(0, |content_object| content_object.reference_count as i64)
+            + change;
+        let result = if reference_count > 0 {
+            content_objects
+                .update_one_with_options(
+                    doc! {"key": content_key},
+                    doc! {"$set": {"volume": volume, "reference_count": reference_count}},
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .map(|_| ())
+        } else {
+            content_objects
+                .delete_one(doc! {"key": content_key})
+                .map(|_| ())
+        };
+        result.map_err(|e| Error::DbError(format!("Error updating content references: {}", e)))?;
+        Ok(reference_count <= 0)
+    }
+
     pub fn add_release(&self, release: &Release) -> Result<ObjectId, Error> {
         println!("Adding release: {:?}", release);
         let game_ids = &release.games;
@@ -115,11 +335,110 @@ impl DatabaseWithPolo {
         Ok(release_id)
     }
 
+    /// Adds a DAT file and its games for a system.
+    /// A previously imported DAT with the same name for the same system is replaced in the same
+    /// transaction, so it's kept when adding the new DAT fails.
+    pub fn add_dat_file(
+        &self,
+        dat_file: &DatFile,
+        dat_games: &[DatGame],
+    ) -> Result<ObjectId, Error> {
+        let existing_ids = self
+            .get_items_with_filter::<DatFile>(
+                DAT_FILE_COLLECTION,
+                doc! {"system_id": &dat_file.system_id, "name": &dat_file.name},
+            )?
+            .iter()
+            .map(|existing_dat_file| existing_dat_file.id())
+            .collect::<Vec<ObjectId>>();
+
+        let transaction = self
+            .db
+            .start_transaction()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        if !existing_ids.is_empty() {
+            let result = transaction
+                .collection::<DatGame>(DAT_GAME_COLLECTION)
+                .delete_many(doc! {"dat_file_id": {"$in": &existing_ids}})
+                .and_then(|_| {
+                    transaction
+                        .collection::<DatFile>(DAT_FILE_COLLECTION)
+                        .delete_many(doc! {"_id": {"$in": &existing_ids}})
+                });
+            if let Err(e) = result {
+                transaction
+                    .rollback()
+                    .map_err(|e| Error::DbError(e.to_string()))?;
+                return Err(Error::DbError(format!(
+                    "Error deleting replaced DAT file: {}",
+                    e
+                )));
+            }
+        }
+
+        let dat_file_id = match transaction
+            .collection::<DatFile>(DAT_FILE_COLLECTION)
+            .insert_one(dat_file)
+        {
+            Ok(result) => match result.inserted_id.as_object_id() {
+                Some(id) => id,
+                None => {
+                    transaction
+                        .rollback()
+                        .map_err(|e| Error::DbError(e.to_string()))?;
+                    return Err(Error::DbError("Error getting inserted id".to_string()));
+                }
+            },
+            Err(e) => {
+                transaction
+                    .rollback()
+                    .map_err(|e| Error::DbError(e.to_string()))?;
+                return Err(Error::DbError(format!("Error adding DAT file: {}", e)));
+            }
+        };
+
+        let dat_games = dat_games.iter().map(|dat_game| DatGame {
+            dat_file_id: Some(dat_file_id),
+            system_id: dat_file.system_id,
+            ..dat_game.clone()
+        });
+
+        if let Err(e) = transaction
+            .collection::<DatGame>(DAT_GAME_COLLECTION)
+            .insert_many(dat_games)
+        {
+            transaction
+                .rollback()
+                .map_err(|e| Error::DbError(e.to_string()))?;
+            return Err(Error::DbError(format!("Error adding DAT games: {}", e)));
+        }
+
+        transaction
+            .commit()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        Ok(dat_file_id)
+    }
+
     pub fn add_or_update_settings(&self, settings: &Settings) -> Result<String, Error> {
+        let file_import_mode = bson::to_bson(&settings.file_import_mode)
+            .map_err(|e| Error::DbError(format!("Error serializing file import mode: {}", e)))?;
+        let storage_volumes = bson::to_bson(&settings.storage_volumes)
+            .map_err(|e| Error::DbError(format!("Error serializing storage volumes: {}", e)))?;
+        let placement_rules = bson::to_bson(&settings.placement_rules)
+            .map_err(|e| Error::DbError(format!("Error serializing placement rules: {}", e)))?;
         let filter = doc! {"id": SETTINGS_ID};
         let update_doc = doc! {
             "$set": {
                 "collection_root_dir": &settings.collection_root_dir,
+                "file_import_mode": file_import_mode,
+                "file_path_template": &settings.file_path_template,
+                "storage_volumes": storage_volumes,
+                "placement_rules": placement_rules,
+                "content_addressed_storage": settings.content_addressed_storage,
+                "watch_collection": settings.watch_collection,
+                "retroarch_cores_directory": &settings.retroarch_cores_directory,
             }
         };
         match self
@@ -135,11 +454,150 @@ impl DatabaseWithPolo {
         }
     }
 
+    pub fn save_integrity_check(&self, integrity_check: &IntegrityCheck) -> Result<(), Error> {
+        let issues = bson::to_bson(&integrity_check.issues)
+            .map_err(|e| Error::DbError(format!("Error serializing integrity issues: {}", e)))?;
+        let filter = doc! {"id": INTEGRITY_CHECK_ID};
+        let update_doc = doc! {
+            "$set": {
+                "checked_file_ids": &integrity_check.checked_file_ids,
+                "issues": issues,
+                "total_files": integrity_check.total_files as i64,
+                "completed": integrity_check.completed,
+            }
+        };
+        self.db
+            .collection::<IntegrityCheck>(INTEGRITY_CHECK_COLLECTION)
+            .update_one_with_options(
+                filter,
+                update_doc,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .map_err(|e| Error::DbError(format!("Error saving integrity check: {}", e)))?;
+        Ok(())
+    }
+
+    /// Adds a checked file and its issue to the saved integrity check without rewriting the files
+    /// checked before.
+    pub fn add_integrity_check_result(
+        &self,
+        collection_file_id: &ObjectId,
+        issue: Option<&IntegrityIssue>,
+    ) -> Result<(), Error> {
+        let mut push_doc = doc! {"checked_file_ids": collection_file_id};
+        if let Some(issue) = issue {
+            let issue = bson::to_bson(issue)
+                .map_err(|e| Error::DbError(format!("Error serializing integrity issue: {}", e)))?;
+            push_doc.insert("issues", issue);
+        }
+        self.db
+            .collection::<IntegrityCheck>(INTEGRITY_CHECK_COLLECTION)
+            .update_one(doc! {"id": INTEGRITY_CHECK_ID}, doc! {"$push": push_doc})
+            .map_err(|e| Error::DbError(format!("Error saving integrity check: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn add_job(&self, job: &Job) -> Result<ObjectId, Error> {
+        self.add_item(JOB_COLLECTION, job)
+    }
+
+    pub fn update_job_status(
+        &self,
+        id: &ObjectId,
+        status: &JobStatus,
+        error: Option<&str>,
+    ) -> Result<(), Error> {
+        let status = bson::to_bson(status)
+            .map_err(|e| Error::DbError(format!("Error serializing job status: {}", e)))?;
+        self.db
+            .collection::<Job>(JOB_COLLECTION)
+            .update_one(
+                doc! {"_id": id},
+                doc! {"$set": {"status": status, "error": error}},
+            )
+            .map_err(|e| Error::DbError(format!("Error updating job status: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn update_collection_file_relative_path(
+        &self,
+        id: &ObjectId,
+        relative_path: &str,
+    ) -> Result<(), Error> {
+        self.db
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .update_one(
+                doc! {"_id": id},
+                doc! {"$set": {"relative_path": relative_path}},
+            )
+            .map_err(|e| Error::DbError(format!("Error updating collection file: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn update_collection_file_base_file(
+        &self,
+        id: &ObjectId,
+        base_file_id: &Option<ObjectId>,
+    ) -> Result<(), Error> {
+        self.db
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .update_one(
+                doc! {"_id": id},
+                doc! {"$set": {"base_file_id": base_file_id}},
+            )
+            .map_err(|e| Error::DbError(format!("Error updating collection file: {}", e)))?;
+        Ok(())
+    }
+
+    /// Replaces the stored content of a collection file, its name, checksums and path,
+    /// in a single update.
+    pub fn update_collection_file_content(
+        &self,
+        collection_file: &CollectionFile,
+    ) -> Result<(), Error> {
+        let files = bson::to_bson(&collection_file.files)
+            .map_err(|e| Error::DbError(format!("Error serializing files: {}", e)))?;
+        let checksums = bson::to_bson(&collection_file.checksums)
+            .map_err(|e| Error::DbError(format!("Error serializing checksums: {}", e)))?;
+        self.db
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .update_one(
+                doc! {"_id": collection_file.id()},
+                doc! {"$set": {
+                    "original_file_name": &collection_file.original_file_name,
+                    "is_zip": collection_file.is_zip,
+                    "files": files,
+                    "size": collection_file.size as i64,
+                    "checksums": checksums,
+                    "relative_path": &collection_file.relative_path,
+                }},
+            )
+            .map_err(|e| Error::DbError(format!("Error updating collection file: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn get_jobs(&self) -> Result<Vec<Job>, Error> {
+        self.get_all_items(JOB_COLLECTION)
+    }
+
+    /// Deletes the completed, failed and cancelled jobs.
+    pub fn delete_finished_jobs(&self) -> Result<(), Error> {
+        self.db
+            .collection::<Job>(JOB_COLLECTION)
+            .delete_many(doc! {"status": {"$in": ["Completed", "Failed", "Cancelled"]}})
+            .map_err(|e| Error::DbError(format!("Error deleting finished jobs: {}", e)))?;
+        Ok(())
+    }
+
     pub fn update_system(&self, system: &System) -> Result<ObjectId, Error> {
+        let retroarch_core = bson::to_bson(&system.retroarch_core)
+            .map_err(|e| Error::DbError(format!("Error serializing RetroArch core: {}", e)))?;
         let update_doc = doc! {
             "$set": {
                 "name": &system.name,
                 "notes": &system.notes,
+                "emulator_order": &system.emulator_order,
+                "retroarch_core": retroarch_core,
             }
         };
 
@@ -157,6 +615,12 @@ impl DatabaseWithPolo {
     }
 
     pub fn update_emulator(&self, emulator: &Emulator) -> Result<ObjectId, Error> {
+        let supported_file_types = bson::to_bson(&emulator.supported_file_types)
+            .map_err(|e| Error::DbError(format!("Error serializing file types: {}", e)))?;
+        let kind = bson::to_bson(&emulator.kind)
+            .map_err(|e| Error::DbError(format!("Error serializing emulator kind: {}", e)))?;
+        let save_data_rules = bson::to_bson(&emulator.save_data_rules)
+            .map_err(|e| Error::DbError(format!("Error serializing save data rules: {}", e)))?;
         let update_doc = doc! {
             "$set": {
                 "name": &emulator.name,
@@ -165,7 +629,12 @@ impl DatabaseWithPolo {
                 "system_id": &emulator.system_id,
                 "extract_files": emulator.extract_files,
                 "supported_file_type_extensions": emulator.supported_file_type_extensions.clone(),
-                "notes": &emulator.notes
+                "notes": &emulator.notes,
+                "supported_file_types": supported_file_types,
+                "multiple_media": emulator.multiple_media,
+                "kind": kind,
+                "supports_playlists": emulator.supports_playlists,
+                "save_data_rules": save_data_rules,
             }
         };
 
@@ -272,6 +741,9 @@ impl DatabaseWithPolo {
             .filter(|game_id| !games_in_updated_release.contains(game_id))
             .collect::<Vec<&ObjectId>>();
 
+        let media = bson::to_bson(&release.media)
+            .map_err(|e| Error::DbError(format!("Error serializing media: {}", e)))?;
+
         let transaction = self
             .db
             .start_transaction()
@@ -304,6 +776,7 @@ impl DatabaseWithPolo {
                 "system_id": &release.system_id,
                 "games": &release.games,
                 "files": &release.files,
+                "media": media,
             }
         };
 
@@ -327,6 +800,45 @@ impl DatabaseWithPolo {
         Ok(release.id())
     }
 
+    /// Saves the file and the emulator the release is started with, keeping the other fields.
+    pub fn update_release_startup(
+        &self,
+        release_id: &ObjectId,
+        startup_file: &Option<StartupFile>,
+        emulator_id: &Option<ObjectId>,
+    ) -> Result<(), Error> {
+        let startup_file = bson::to_bson(startup_file)
+            .map_err(|e| Error::DbError(format!("Error serializing startup file: {}", e)))?;
+        self.db
+            .collection::<Release>(RELEASE_COLLECTION)
+            .update_one(
+                doc! {"_id": release_id},
+                doc! {"$set": {
+                    "startup_file": startup_file,
+                    "emulator_id": emulator_id,
+                }},
+            )
+            .map_err(|e| Error::DbError(format!("Error updating release: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn update_release_emulator_override(
+        &self,
+        release_id: &ObjectId,
+        emulator_override: &Option<EmulatorOverride>,
+    ) -> Result<(), Error> {
+        let emulator_override = bson::to_bson(emulator_override)
+            .map_err(|e| Error::DbError(format!("Error serializing emulator override: {}", e)))?;
+        self.db
+            .collection::<Release>(RELEASE_COLLECTION)
+            .update_one(
+                doc! {"_id": release_id},
+                doc! {"$set": {"emulator_override": emulator_override}},
+            )
+            .map_err(|e| Error::DbError(format!("Error updating release: {}", e)))?;
+        Ok(())
+    }
+
     pub fn get_systems(&self) -> Result<Vec<System>, Error> {
         self.get_all_items(SYSTEM_COLLECTION)
     }
@@ -351,6 +863,30 @@ impl DatabaseWithPolo {
         self.get_with_id(SYSTEM_COLLECTION, id)
     }
 
+    pub fn get_dat_files_for_system(&self, system_id: &ObjectId) -> Result<Vec<DatFile>, Error> {
+        self.get_items_with_filter(DAT_FILE_COLLECTION, doc! {"system_id": system_id})
+    }
+
+    pub fn get_dat_games_for_system(&self, system_id: &ObjectId) -> Result<Vec<DatGame>, Error> {
+        self.get_items_with_filter(DAT_GAME_COLLECTION, doc! {"system_id": system_id})
+    }
+
+    pub fn get_releases_with_system(&self, system_id: &ObjectId) -> Result<Vec<Release>, Error> {
+        self.get_items_with_filter(RELEASE_COLLECTION, doc! {"system_id": system_id})
+    }
+
+    pub fn get_releases(&self) -> Result<Vec<Release>, Error> {
+        self.get_all_items(RELEASE_COLLECTION)
+    }
+
+    pub fn get_all_collection_files(&self) -> Result<Vec<CollectionFile>, Error> {
+        self.get_all_items(COLLECTION_FILE_COLLECTION)
+    }
+
+    pub fn get_integrity_check(&self) -> Result<Option<IntegrityCheck>, Error> {
+        self.get_with_filter(INTEGRITY_CHECK_COLLECTION, doc! {"id": INTEGRITY_CHECK_ID})
+    }
+
     pub fn get_settings(&self) -> Result<Settings, Error> {
         let settings = self.get_with_filter(SETTINGS_COLLECTION, doc! {"id": SETTINGS_ID})?;
 
@@ -361,6 +897,14 @@ impl DatabaseWithPolo {
                 let default_settings = Settings {
                     id: SETTINGS_ID.to_string(),
                     collection_root_dir: "".to_string(),
+                    file_import_mode: FileImportMode::default(),
+                    // new collections use the human-readable layout
+                    file_path_template: Some(DEFAULT_FILE_PATH_TEMPLATE.to_string()),
+                    storage_volumes: vec![],
+                    placement_rules: vec![],
+                    content_addressed_storage: false,
+                    watch_collection: false,
+                    retroarch_cores_directory: None,
                 };
                 self.add_or_update_settings(&default_settings)?;
                 Ok(default_settings)
@@ -492,10 +1036,108 @@ impl DatabaseWithPolo {
                 "System cannot be deleted because it is used in a release".to_string(),
             ))
         } else {
+            for dat_file in self.get_dat_files_for_system(id)? {
+                self.delete_dat_file(&dat_file.id())?;
+            }
             self.delete_item::<System>(SYSTEM_COLLECTION, id)
         }
     }
 
+    pub fn delete_dat_file(&self, id: &ObjectId) -> Result<(), Error> {
+        self.db
+            .collection::<DatGame>(DAT_GAME_COLLECTION)
+            .delete_many(doc! {"dat_file_id": id})
+            .map_err(|e| Error::DbError(format!("Error deleting DAT games: {}", e)))?;
+        self.delete_item::<DatFile>(DAT_FILE_COLLECTION, id)
+    }
+
+    /// Replaces the duplicate collection files with the kept file in all releases
+    /// and deletes the duplicate collection files. Returns the ids of the duplicates whose files
+    /// can be removed, which excludes content still referenced in the content store.
+    pub fn merge_duplicate_collection_files(
+        &self,
+        keep_id: &ObjectId,
+        duplicate_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, Error> {
+        let releases = self.get_releases()?;
+        let duplicates = self.get_collection_files(&duplicate_ids.to_vec())?;
+
+        let transaction = self
+            .db
+            .start_transaction()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+
+        for release in releases
+            .iter()
+            .filter(|release| release.files.iter().any(|id| duplicate_ids.contains(id)))
+        {
+            let mut files = Vec::new();
+            for id in &release.files {
+                let id = if duplicate_ids.contains(id) {
+                    *keep_id
+                } else {
+                    *id
+                };
+                if !files.contains(&id) {
+                    files.push(id);
+                }
+            }
+            if let Err(e) = transaction
+                .collection::<Release>(RELEASE_COLLECTION)
+                .update_one(doc! {"_id": release.id()}, doc! {"$set": {"files": &files}})
+            {
+                transaction
+                    .rollback()
+                    .map_err(|e| Error::DbError(e.to_string()))?;
+                return Err(Error::DbError(format!(
+                    "Error updating release files: {}",
+                    e
+                )));
+            }
+        }
+
+        if let Err(e) = transaction
+            .collection::<CollectionFile>(COLLECTION_FILE_COLLECTION)
+            .delete_many(doc! {"_id": {"$in": duplicate_ids}})
+        {
+            transaction
+                .rollback()
+                .map_err(|e| Error::DbError(e.to_string()))?;
+            return Err(Error::DbError(format!(
+                "Error deleting duplicate collection files: {}",
+                e
+            )));
+        }
+
+        let mut removable_ids = Vec::new();
+        for duplicate in &duplicates {
+            let Some(content_key) = &duplicate.content_key else {
+                removable_ids.push(duplicate.id());
+                continue;
+            };
+            match self.update_content_references(
+                content_key,
+                duplicate.volume.as_deref(),
+                -1,
+                &transaction,
+            ) {
+                Ok(true) => removable_ids.push(duplicate.id()),
+                Ok(false) => {}
+                Err(error) => {
+                    transaction
+                        .rollback()
+                        .map_err(|e| Error::DbError(e.to_string()))?;
+                    return Err(error);
+                }
+            }
+        }
+
+        transaction
+            .commit()
+            .map_err(|e| Error::DbError(e.to_string()))?;
+        Ok(removable_ids)
+    }
+
     pub fn delete_release(&self, id: &ObjectId) -> Result<(), Error> {
         let release = self.get_release(id)?.expect("Release not found");
         if release.files.is_empty() {
@@ -643,10 +1285,15 @@ mod tests {
     use crate::{
         database_with_polo::DatabaseWithPolo,
         model::{
-            collection_file::{CollectionFile, CollectionFileType, FileInfo},
-            model::{Game, Release, System},
+            collection_file::{CollectionFile, CollectionFileType},
+            dat::{DatFile, DatGame},
+            integrity_check::{IntegrityCheck, IntegrityIssue, IntegrityIssueKind},
+            job::{Job, JobKind, JobStatus},
+            model::{Game, HasOid, Release, System},
+        },
+        repository::repository::{
+            CollectionFilesReadRepository, GamesReadRepository, ReleaseReadRepository,
         },
-        repository::repository::{GamesReadRepository, ReleaseReadRepository},
     };
 
     fn create_test_system() -> System {
@@ -654,6 +1301,8 @@ mod tests {
             _id: None,
             name: "Test system".to_string(),
             notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
         }
     }
 
@@ -667,13 +1316,8 @@ mod tests {
     fn create_test_collection_file() -> CollectionFile {
         CollectionFile {
             _id: None,
-            original_file_name: "Test file.zip".to_string(),
-            is_zip: true,
-            files: Some(vec![FileInfo {
-                name: "Test file.disk".to_string(),
-                checksum: "checksum".to_string(),
-            }]),
-            collection_file_type: CollectionFileType::DiskImage,
+            ..CollectionFile::for_test("Test file.zip", CollectionFileType::DiskImage)
+                .with_entries(&["Test file.disk"])
         }
     }
 
@@ -688,6 +1332,10 @@ mod tests {
             system_id: Some(system_id),
             games,
             files,
+            startup_file: None,
+            emulator_id: None,
+            emulator_override: None,
+            media: vec![],
         }
     }
 
@@ -836,4 +1484,255 @@ mod tests {
 
         std::fs::remove_dir_all(&test_db_name).unwrap();
     }
+
+    #[test]
+    fn test_add_dat_file_replaces_previous_version() {
+        let test_db_name = "test_add_dat_file.db";
+        let test_db = DatabaseWithPolo::new(test_db_name);
+        let system_id = test_db.add_system(&create_test_system()).unwrap();
+
+        let dat_file = DatFile {
+            _id: None,
+            system_id: Some(system_id),
+            name: "Test DAT".to_string(),
+            description: None,
+            version: Some("1".to_string()),
+        };
+        let dat_game = DatGame {
+            _id: None,
+            dat_file_id: None,
+            system_id: None,
+            name: "Test game".to_string(),
+            description: None,
+            roms: vec![],
+        };
+
+        test_db
+            .add_dat_file(&dat_file, &[dat_game.clone(), dat_game.clone()])
+            .unwrap();
+        let dat_games = test_db.get_dat_games_for_system(&system_id).unwrap();
+        assert_eq!(dat_games.len(), 2);
+
+        let new_version = DatFile {
+            version: Some("2".to_string()),
+            ..dat_file
+        };
+        let dat_file_id = test_db.add_dat_file(&new_version, &[dat_game]).unwrap();
+
+        let dat_files = test_db.get_dat_files_for_system(&system_id).unwrap();
+        assert_eq!(dat_files.len(), 1);
+        assert_eq!(dat_files[0].version, Some("2".to_string()));
+
+        let dat_games = test_db.get_dat_games_for_system(&system_id).unwrap();
+        assert_eq!(dat_games.len(), 1);
+        assert_eq!(dat_games[0].dat_file_id, Some(dat_file_id));
+
+        std::fs::remove_dir_all(test_db_name).unwrap();
+    }
+
+    #[test]
+    fn test_save_integrity_check() {
+        let test_db_name = "test_save_integrity_check.db";
+        let test_db = DatabaseWithPolo::new(test_db_name);
+        assert!(test_db.get_integrity_check().unwrap().is_none());
+
+        let collection_file_id = ObjectId::new();
+        let mut integrity_check = IntegrityCheck {
+            checked_file_ids: vec![collection_file_id],
+            issues: vec![IntegrityIssue {
+                collection_file_id,
+                file_name: "Test file.zip".to_string(),
+                file_path: "collection/Test file.zip".to_string(),
+                kind: IntegrityIssueKind::Missing,
+                details: "File not found".to_string(),
+            }],
+            total_files: 2,
+            completed: false,
+        };
+        test_db.save_integrity_check(&integrity_check).unwrap();
+
+        let checked_file_id = ObjectId::new();
+        test_db
+            .add_integrity_check_result(&checked_file_id, None)
+            .unwrap();
+        let issue = IntegrityIssue {
+            collection_file_id: checked_file_id,
+            kind: IntegrityIssueKind::Changed,
+            ..integrity_check.issues[0].clone()
+        };
+        test_db
+            .add_integrity_check_result(&checked_file_id, Some(&issue))
+            .unwrap();
+        integrity_check.checked_file_ids.push(checked_file_id);
+        integrity_check.checked_file_ids.push(checked_file_id);
+        integrity_check.issues.push(issue);
+        let saved = test_db.get_integrity_check().unwrap().unwrap();
+        assert_eq!(saved.checked_file_ids, integrity_check.checked_file_ids);
+        assert_eq!(saved.issues, integrity_check.issues);
+        assert!(!saved.completed);
+
+        integrity_check.completed = true;
+        test_db.save_integrity_check(&integrity_check).unwrap();
+
+        let saved = test_db.get_integrity_check().unwrap().unwrap();
+        assert_eq!(saved.checked_file_ids, integrity_check.checked_file_ids);
+        assert_eq!(saved.issues, integrity_check.issues);
+        assert_eq!(saved.total_files, 2);
+        assert!(saved.completed);
+
+        std::fs::remove_dir_all(test_db_name).unwrap();
+    }
+
+    #[test]
+    fn test_merge_duplicate_collection_files() {
+        let test_db_name = "test_merge_duplicate_collection_files.db";
+        let test_db = DatabaseWithPolo::new(test_db_name);
+        let system_id = test_db.add_system(&create_test_system()).unwrap();
+        let game_id = test_db.add_game(&create_test_game()).unwrap();
+        let keep_id = test_db
+            .add_collection_file(&create_test_collection_file())
+            .unwrap();
+        let duplicate_id = test_db
+            .add_collection_file(&create_test_collection_file())
+            .unwrap();
+        let release_with_both = test_db
+            .add_release(&create_test_release(
+                system_id,
+                vec![game_id],
+                vec![keep_id, duplicate_id],
+            ))
+            .unwrap();
+        let release_with_duplicate = test_db
+            .add_release(&create_test_release(
+                system_id,
+                vec![game_id],
+                vec![duplicate_id],
+            ))
+            .unwrap();
+
+        let removable_ids = test_db
+            .merge_duplicate_collection_files(&keep_id, &[duplicate_id])
+            .unwrap();
+        assert_eq!(removable_ids, vec![duplicate_id]);
+
+        let release = test_db.get_release(&release_with_both).unwrap().unwrap();
+        assert_eq!(release.files, vec![keep_id]);
+        let release = test_db
+            .get_release(&release_with_duplicate)
+            .unwrap()
+            .unwrap();
+        assert_eq!(release.files, vec![keep_id]);
+        let files = test_db
+            .get_collection_files(&vec![keep_id, duplicate_id])
+            .unwrap();
+        assert_eq!(files.len(), 1);
+
+        std::fs::remove_dir_all(test_db_name).unwrap();
+    }
+
+    #[test]
+    fn test_content_references() {
+        let test_db_name = "test_content_references.db";
+        let test_db = DatabaseWithPolo::new(test_db_name);
+        let system_id = test_db.add_system(&create_test_system()).unwrap();
+        let game_id = test_db.add_game(&create_test_game()).unwrap();
+        let release_id = test_db
+            .add_release(&create_test_release(system_id, vec![game_id], vec![]))
+            .unwrap();
+
+        let mut collection_files = Vec::new();
+        for _ in 0..2 {
+            let mut collection_file = create_test_collection_file();
+            collection_file._id = Some(ObjectId::new());
+            collection_file.content_key = Some("abcd.zip".to_string());
+            test_db
+                .add_collection_file_to_release(&collection_file, Some(&release_id))
+                .unwrap();
+            collection_files.push(collection_file);
+        }
+        let content_object = test_db.get_content_object("abcd.zip").unwrap().unwrap();
+        assert_eq!(content_object.reference_count, 2);
+
+        assert!(!test_db
+            .delete_collection_file_reference(&collection_files[0])
+            .unwrap());
+        assert_eq!(
+            test_db
+                .get_content_object("abcd.zip")
+                .unwrap()
+                .unwrap()
+                .reference_count,
+            1
+        );
+        assert!(test_db
+            .delete_collection_file_reference(&collection_files[1])
+            .unwrap());
+        assert!(test_db.get_content_object("abcd.zip").unwrap().is_none());
+
+        std::fs::remove_dir_all(test_db_name).unwrap();
+    }
+
+    #[test]
+    fn test_add_collection_file_to_release() {
+        let test_db_name = "test_add_collection_file_to_release.db";
+        let test_db = DatabaseWithPolo::new(test_db_name);
+        let system_id = test_db.add_system(&create_test_system()).unwrap();
+        let game_id = test_db.add_game(&create_test_game()).unwrap();
+        let existing_id = test_db
+            .add_collection_file(&create_test_collection_file())
+            .unwrap();
+        let release_id = test_db
+            .add_release(&create_test_release(
+                system_id,
+                vec![game_id],
+                vec![existing_id],
+            ))
+            .unwrap();
+
+        let id = test_db
+            .add_collection_file_to_release(&create_test_collection_file(), Some(&release_id))
+            .unwrap();
+
+        let release = test_db.get_release(&release_id).unwrap().unwrap();
+        assert_eq!(release.files, vec![existing_id, id]);
+
+        test_db.delete_collection_file(&id).unwrap();
+        let files = test_db
+            .get_collection_files(&vec![existing_id, id])
+            .unwrap();
+        assert_eq!(files.len(), 1);
+
+        std::fs::remove_dir_all(test_db_name).unwrap();
+    }
+
+    #[test]
+    fn test_jobs() {
+        let test_db_name = "test_jobs.db";
+        let test_db = DatabaseWithPolo::new(test_db_name);
+        let create_job = || Job {
+            _id: None,
+            kind: JobKind::VerifyCollection,
+            status: JobStatus::Queued,
+            error: None,
+        };
+        let finished_id = test_db.add_job(&create_job()).unwrap();
+        let queued_id = test_db.add_job(&create_job()).unwrap();
+
+        test_db
+            .update_job_status(&finished_id, &JobStatus::Failed, Some("Failed"))
+            .unwrap();
+        let jobs = test_db.get_jobs().unwrap();
+        assert_eq!(jobs.len(), 2);
+        let failed_job = jobs.iter().find(|job| job.id() == finished_id).unwrap();
+        assert_eq!(failed_job.status, JobStatus::Failed);
+        assert_eq!(failed_job.error, Some("Failed".to_string()));
+
+        test_db.delete_finished_jobs().unwrap();
+        let jobs = test_db.get_jobs().unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].id(), queued_id);
+        assert_eq!(jobs[0].status, JobStatus::Queued);
+
+        std::fs::remove_dir_all(test_db_name).unwrap();
+    }
 }
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use async_process::{Command, Stdio};
+use async_std::{future::timeout, task::spawn_blocking};
+use bson::oid::ObjectId;
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    model::{emulator_preset::EmulatorPreset, model::Emulator},
+    util::emulator_presets::load_emulator_presets,
+};
+
+// emulators not exiting after printing the version are stopped after this
+const VERSION_TIMEOUT: Duration = Duration::from_secs(3);
+// directories searched for every preset in addition to PATH
+const COMMON_INSTALL_LOCATIONS: [&str; 5] = [
+    "/usr/bin",
+    "/usr/local/bin",
+    "/usr/games",
+    "/opt/homebrew/bin",
+    "/snap/bin",
+];
+
+/// An emulator of a preset found on the machine.
+#[derive(Debug, Clone)]
+pub struct DetectedEmulator {
+    pub preset: EmulatorPreset,
+    pub executable: PathBuf,
+    pub version: Option<String>,
+    // system matching the system names of the preset
+    pub system_id: Option<ObjectId>,
+    // an emulator with the executable and the arguments of the preset has been added already
+    pub is_added: bool,
+}
+
+impl DetectedEmulator {
+    pub fn to_emulator(&self) -> Emulator {
+        Emulator {
+            executable: self.executable.to_string_lossy().to_string(),
+            ..self.preset.to_emulator(self.system_id)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmulatorDetection {
+    pub detected_emulators: Vec<DetectedEmulator>,
+    // added emulators whose executable isn't found anymore
+    pub missing_executables: Vec<Emulator>,
+}
+
+/// Searches PATH, the common install locations and the install locations of the presets for
+/// the executables of the emulator presets and reads the versions of the found executables.
+/// Also checks that the executables of the added emulators still exist.
+pub async fn detect_emulators() -> Result<EmulatorDetection, Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let emulators = db.get_emulators()?;
+    let systems = db.get_systems()?;
+    let presets = load_emulator_presets();
+
+    let (found_presets, emulator_executables) = spawn_blocking(move || {
+        let search_directories = get_search_directories();
+        let found_presets = presets
+            .into_iter()
+            .filter_map(|preset| {
+                let mut directories = preset
+                    .install_locations
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect::<Vec<PathBuf>>();
+                directories.extend(search_directories.iter().cloned());
+                find_executable(&preset.executable, &directories)
+                    .map(|executable| (preset, executable))
+            })
+            .collect::<Vec<(EmulatorPreset, PathBuf)>>();
+        let emulator_executables = emulators
+            .into_iter()
+            .map(|emulator| {
+                let executable = resolve_executable(&emulator.executable, &search_directories);
+                (emulator, executable)
+            })
+            .collect::<Vec<(Emulator, Option<PathBuf>)>>();
+        (found_presets, emulator_executables)
+    })
+    .await;
+
+    // presets sharing an executable, like the RetroArch cores, read the version once
+    let mut versions: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut detected_emulators = Vec::new();
+    for (preset, executable) in found_presets {
+        let version = match &preset.version_argument {
+            Some(version_argument) => match versions.get(&executable) {
+                Some(version) => version.clone(),
+                None => {
+                    let version = read_version(&executable, version_argument).await;
+                    versions.insert(executable.clone(), version.clone());
+                    version
+                }
+            },
+            None => None,
+        };
+        let system_id = systems
+            .iter()
+            .find(|system| preset.is_for_system(&system.name))
+            .and_then(|system| system._id);
+        let is_added = emulator_executables
+            .iter()
+            .any(|(emulator, emulator_executable)| {
+                emulator.arguments == preset.arguments
+                    && emulator_executable.as_ref() == Some(&executable)
+            });
+        detected_emulators.push(DetectedEmulator {
+            preset,
+            executable,
+            version,
+            system_id,
+            is_added,
+        });
+    }
+
+    let missing_executables = emulator_executables
+        .into_iter()
+        .filter(|(_, executable)| executable.is_none())
+        .map(|(emulator, _)| emulator)
+        .collect();
+    Ok(EmulatorDetection {
+        detected_emulators,
+        missing_executables,
+    })
+}
+
+fn get_search_directories() -> Vec<PathBuf> {
+    let mut directories = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<PathBuf>>())
+        .unwrap_or_default();
+    for location in COMMON_INSTALL_LOCATIONS {
+        let location = PathBuf::from(location);
+        if !directories.contains(&location) {
+            directories.push(location);
+        }
+    }
+    directories
+}
+
+/// Returns the path of the executable with the name in the first of the directories having it.
+fn find_executable(name: &str, directories: &[PathBuf]) -> Option<PathBuf> {
+    let mut file_names = vec![name.to_string()];
+    if cfg!(windows) && Path::new(name).extension().is_none() {
+        file_names.push(format!("{}.exe", name));
+    }
+    directories.iter().find_map(|directory| {
+        file_names
+            .iter()
+            .map(|file_name| directory.join(file_name))
+            .find(|file_path| file_path.is_file())
+    })
+}
+
+/// Returns the path of the executable of an emulator. An executable given by name only is
+/// searched from the directories.
+fn resolve_executable(executable: &str, directories: &[PathBuf]) -> Option<PathBuf> {
+    let path = Path::new(executable);
+    if path.is_absolute() || path.components().count() > 1 {
+        path.is_file().then(|| path.to_path_buf())
+    } else {
+        find_executable(executable, directories)
+    }
+}
+
+/// Returns the first line the executable prints with the version argument, from the standard
+/// error when nothing is printed to the standard output.
+async fn read_version(executable: &Path, version_argument: &str) -> Option<String> {
+    let mut command = Command::new(executable);
+    command
+        .arg(version_argument)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    let output = timeout(VERSION_TIMEOUT, command.output())
+        .await
+        .ok()?
+        .ok()?;
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    String::from_utf8_lossy(&text)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_find_executable() {
+        let directory = env::temp_dir().join("test_find_executable");
+        let other_directory = directory.join("other");
+        fs::create_dir_all(&other_directory).unwrap();
+        let executable = other_directory.join("x64sc");
+        fs::write(&executable, "").unwrap();
+        let directories = vec![directory.clone(), other_directory.clone()];
+
+        assert_eq!(
+            find_executable("x64sc", &directories),
+            Some(executable.clone())
+        );
+        assert_eq!(find_executable("hatari", &directories), None);
+        assert_eq!(
+            resolve_executable(&executable.to_string_lossy(), &[]),
+            Some(executable.clone())
+        );
+        assert_eq!(
+            resolve_executable(&directory.join("x64sc").to_string_lossy(), &directories),
+            None
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
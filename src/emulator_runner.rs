@@ -1,30 +1,139 @@
 use crate::{
+    database_with_polo::DatabaseWithPolo,
     error::Error,
     files::{copy_files, extract_zip_files},
-    model::{collection_file::CollectionFile, model::Emulator},
+    model::{
+        collection_file::{CollectionFile, CollectionFileType},
+        model::{Emulator, EmulatorKind, System},
+    },
+    util::{
+        file_operation::ProgressReporter,
+        file_path_builder::FilePathBuilder,
+        patch::apply_patches,
+        retroarch::get_retroarch_core_path,
+        save_data::{find_changed_save_data, restore_save_data, scan_save_data, SaveData},
+    },
+    view_model::release_view_model::ReleaseViewModel,
 };
 use async_process::Command;
 use async_std::path::Path as AsyncPath;
-use std::path::PathBuf;
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+use std::path::{Path, PathBuf};
+
+// directory under the temporary directory holding a directory for each launch
+const LAUNCH_DIRECTORY: &str = "iced_game_collection";
 
 #[derive(Debug, Clone)]
 pub struct EmulatorRunOptions {
     pub emulator: Emulator,
-    pub files: Vec<CollectionFile>, // all files for release?
+    pub files: Vec<CollectionFile>, // files of the release supported by the emulator
     pub selected_file_name: String, // file name selected for running (either a single file or a file inside a zip archive)
-    pub source_path: PathBuf,       // where to find files
-    pub target_path: PathBuf,       // where to extract / copy files
+    pub system: System,             // system of the release, used for finding the files
+    pub file_path_builder: FilePathBuilder,
+    pub target_path: PathBuf,    // where to extract / copy files
+    pub extra_arguments: String, // arguments added for the release, see EmulatorOverride
+    // other media of the release given after the selected file to emulators supporting
+    // multiple media
+    pub additional_file_names: Vec<String>,
+    // patches applied in order to the selected file after it's copied or extracted
+    pub patches: Vec<CollectionFile>,
+    // media of the release written to an M3U playlist the emulator is started with, for
+    // emulators supporting playlists
+    pub playlist_file_names: Vec<String>,
+    pub release_id: ObjectId,
+    // memory snapshots of the release restored where the save data rules of the emulator
+    // expect them
+    pub snapshots: Vec<CollectionFile>,
+}
+
+/// Creates the options for running the release with the emulator, taking the files of the
+/// release the emulator supports.
+pub fn create_run_options(
+    release: &ReleaseViewModel,
+    emulator: &Emulator,
+    selected_file_name: String,
+    file_path_builder: &FilePathBuilder,
+    target_path: PathBuf,
+) -> EmulatorRunOptions {
+    let files = release
+        .files
+        .iter()
+        .filter(|file| emulator.supports_file(file))
+        .cloned()
+        .collect::<Vec<CollectionFile>>();
+    let media_file_names = release.get_media_file_names(emulator);
+    let (additional_file_names, playlist_file_names) =
+        if emulator.supports_playlists && media_file_names.len() > 1 {
+            (vec![], media_file_names)
+        } else if emulator.multiple_media {
+            let additional_file_names = media_file_names
+                .into_iter()
+                .filter(|file_name| *file_name != selected_file_name)
+                .collect();
+            (additional_file_names, vec![])
+        } else {
+            (vec![], vec![])
+        };
+    EmulatorRunOptions {
+        emulator: emulator.clone(),
+        files,
+        selected_file_name,
+        system: release.system.clone(),
+        file_path_builder: file_path_builder.clone(),
+        target_path,
+        extra_arguments: release.get_extra_arguments(emulator),
+        additional_file_names,
+        patches: vec![],
+        playlist_file_names,
+        release_id: release.id,
+        snapshots: release
+            .files
+            .iter()
+            .filter(|file| file.collection_file_type == CollectionFileType::MemorySnapshot)
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Creates the options for starting the release with the Play button, using the emulator
+/// chosen for the release and the startup file stored with the release. The selected patches
+/// of the startup file are applied. Returns None when either is missing.
+pub fn create_play_options(
+    release: &ReleaseViewModel,
+    emulators: &[Emulator],
+    file_path_builder: &FilePathBuilder,
+    target_path: PathBuf,
+    patch_ids: &[ObjectId],
+) -> Option<EmulatorRunOptions> {
+    let emulator = release.get_play_emulator(emulators)?;
+    let startup_file = release.get_startup_file()?;
+    Some(EmulatorRunOptions {
+        patches: release.get_patches(&startup_file.collection_file_id, patch_ids),
+        ..create_run_options(
+            release,
+            emulator,
+            release.get_startup_file_name(emulator)?,
+            file_path_builder,
+            target_path,
+        )
+    })
 }
 
+/// Runs the emulator and returns the save data the emulator wrote while playing, found with the
+/// save data rules of the emulator.
 pub async fn run_with_emulator_async(
     emulator_run_options: EmulatorRunOptions,
-) -> Result<(), Error> {
+) -> Result<Vec<SaveData>, Error> {
     let EmulatorRunOptions {
         emulator,
         files,
         selected_file_name,
-        source_path,
         target_path,
+        extra_arguments,
+        additional_file_names,
+        system,
+        ..
     } = emulator_run_options;
     if files.is_empty() {
         // TODO use other than IoError
@@ -38,13 +147,34 @@ pub async fn run_with_emulator_async(
     );
 
     let mut command = Command::new(&emulator.executable);
+    // RetroArch is given the core of the system before the content
+    if emulator.kind == EmulatorKind::RetroArch {
+        let settings = DatabaseWithPolo::get_instance().get_settings()?;
+        let core_path =
+            get_retroarch_core_path(&system, settings.retroarch_cores_directory.as_deref())?;
+        command.arg("-L").arg(core_path);
+    }
+    let additional_file_paths = additional_file_names
+        .iter()
+        .map(|file_name| AsyncPath::new(&target_path).join(file_name));
 
-    command.arg(&file_path).current_dir(target_path);
-
-    if emulator.arguments.len() > 0 {
-        // TODO: should use command.args() instead and emulator arguments should be split into separate strings
-        command.arg(&emulator.arguments);
+    match expand_arguments(&emulator.arguments, Path::new(&file_path)) {
+        Some(arguments) => {
+            command.args(arguments).args(additional_file_paths);
+        }
+        None => {
+            command.arg(&file_path).args(additional_file_paths);
+            if emulator.arguments.len() > 0 {
+                // TODO: should use command.args() instead and emulator arguments should be split into separate strings
+                command.arg(&emulator.arguments);
+            }
+        }
     }
+    command.current_dir(&target_path);
+    command.args(extra_arguments.split_whitespace());
+
+    let name = get_save_data_name(&selected_file_name);
+    let scanned_files = scan_save_data(&target_path, &emulator.save_data_rules, &name);
 
     let status = command
         .status()
@@ -56,17 +186,347 @@ pub async fn run_with_emulator_async(
     }
     println!("Finished running with emulator");
 
-    Ok(())
+    Ok(find_changed_save_data(
+        &target_path,
+        &emulator.save_data_rules,
+        &name,
+        &scanned_files,
+    ))
+}
+
+// name of the file run without the extension, the `{name}` placeholder of the save data rules
+fn get_save_data_name(file_name: &str) -> String {
+    Path::new(file_name)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Expands an argument template of an emulator into the arguments. The template is split at
+/// whitespace and the placeholders are replaced in each argument:
+/// `{file}` with the path of the file to run, `{directory}` with the directory of the file and
+/// `{name}` with the file name without the extension. Returns None for arguments without
+/// placeholders, they're given after the file to run.
+pub fn expand_arguments(template: &str, file_path: &Path) -> Option<Vec<String>> {
+    const PLACEHOLDERS: [&str; 3] = ["{file}", "{directory}", "{name}"];
+    if !PLACEHOLDERS
+        .iter()
+        .any(|placeholder| template.contains(placeholder))
+    {
+        return None;
+    }
+    let directory = file_path.parent().unwrap_or(Path::new(""));
+    let name = file_path.file_stem().unwrap_or_default();
+    Some(
+        template
+            .split_whitespace()
+            .map(|argument| {
+                argument
+                    .replace("{file}", &file_path.to_string_lossy())
+                    .replace("{directory}", &directory.to_string_lossy())
+                    .replace("{name}", &name.to_string_lossy())
+            })
+            .collect(),
+    )
+}
+
+/// Returns a new directory for the files of a launch. Each launch has its own directory, so
+/// that launches don't overwrite each other's files and the save data of a launch isn't mixed
+/// with the files left by other launches.
+pub fn get_launch_directory() -> PathBuf {
+    std::env::temp_dir()
+        .join(LAUNCH_DIRECTORY)
+        .join(ObjectId::new().to_hex())
+}
+
+/// Removes the directories left by earlier launches, for example when the app was closed while
+/// an emulator was running.
+pub fn remove_launch_directories() {
+    remove_launch_directory(&std::env::temp_dir().join(LAUNCH_DIRECTORY));
+}
+
+/// Removes the directory of a launch with the files copied or extracted for the emulator.
+pub fn remove_launch_directory(launch_directory: &Path) {
+    if !launch_directory.exists() {
+        return;
+    }
+    if let Err(e) = std::fs::remove_dir_all(launch_directory) {
+        println!("Failed to remove {:?}: {}", launch_directory, e);
+    }
 }
 
-pub fn process_files_for_emulator(options: &EmulatorRunOptions) -> Result<(), Error> {
+/// Extracts or copies the files of the release to the target path in a background thread
+/// and reports the progress. The memory snapshots of the release are restored. When the media
+/// are given in a playlist the playlist is written to the target path and the emulator is
+/// started with it. Returns the options for running the emulator.
+pub async fn process_files_for_emulator(
+    mut options: EmulatorRunOptions,
+    reporter: ProgressReporter,
+) -> Result<EmulatorRunOptions, Error> {
     println!("Processing files for emulator");
-    let source_path = PathBuf::from(&options.source_path);
-    println!("Source path: {:?}", source_path);
-    if options.emulator.extract_files {
-        // TODO: extract all files or only selected_file?
-        extract_zip_files(&options.files, &source_path, &options.target_path)
-    } else {
-        copy_files(&options.files, &source_path, &options.target_path)
+    check_patched_file(&options)?;
+    let files = options
+        .files
+        .iter()
+        .map(|file| {
+            let file_path = options
+                .file_path_builder
+                .build_file_path(&options.system, file)?;
+            Ok((file_path, file.clone()))
+        })
+        .collect::<Result<Vec<(PathBuf, CollectionFile)>, Error>>()?;
+    let patches = options
+        .patches
+        .iter()
+        .map(|patch| {
+            let patch_path = options
+                .file_path_builder
+                .build_file_path(&options.system, patch)?;
+            Ok((patch_path, patch.clone()))
+        })
+        .collect::<Result<Vec<(PathBuf, CollectionFile)>, Error>>()?;
+    let snapshots = options
+        .snapshots
+        .iter()
+        .map(|snapshot| {
+            let snapshot_path = options
+                .file_path_builder
+                .build_file_path(&options.system, snapshot)?;
+            Ok((snapshot_path, snapshot.clone()))
+        })
+        .collect::<Result<Vec<(PathBuf, CollectionFile)>, Error>>()?;
+    let save_data_rules = options.emulator.save_data_rules.clone();
+    // the emulator is run with the playlist named after the first medium when there's one
+    let save_data_name = get_save_data_name(
+        options
+            .playlist_file_names
+            .first()
+            .unwrap_or(&options.selected_file_name),
+    );
+    let extract_files = options.emulator.extract_files;
+    let target_path = options.target_path.clone();
+    let selected_file_path = target_path.join(&options.selected_file_name);
+    let playlist_file_names = options.playlist_file_names.clone();
+    let playlist_file_name = spawn_blocking(move || {
+        std::fs::create_dir_all(&target_path).map_err(|e| {
+            Error::IoError(format!(
+                "Failed creating directory {:?}: {}",
+                target_path, e
+            ))
+        })?;
+        if extract_files {
+            // TODO: extract all files or only selected_file?
+            extract_zip_files(&files, &target_path, &reporter)?;
+        } else {
+            copy_files(&files, &target_path, &reporter)?;
+        }
+        // the patches are applied to the copy, the file in the collection stays unchanged
+        if !patches.is_empty() {
+            apply_patches(&selected_file_path, &patches)?;
+        }
+        // the data saved while playing before replaces the copied files
+        restore_save_data(&target_path, &save_data_rules, &save_data_name, &snapshots)?;
+        if playlist_file_names.is_empty() {
+            Ok::<Option<String>, Error>(None)
+        } else {
+            write_playlist(&target_path, &playlist_file_names).map(Some)
+        }
+    })
+    .await?;
+    if let Some(playlist_file_name) = playlist_file_name {
+        options.selected_file_name = playlist_file_name;
+    }
+    Ok(options)
+}
+
+/// Patches of a zip archive are made for a file inside it, so they can only be applied when the
+/// emulator is given the extracted files.
+fn check_patched_file(options: &EmulatorRunOptions) -> Result<(), Error> {
+    let is_archive_patched = !options.emulator.extract_files
+        && options.patches.iter().any(|patch| {
+            options
+                .files
+                .iter()
+                .any(|file| file.is_zip && file._id.is_some() && file._id == patch.base_file_id)
+        });
+    if is_archive_patched {
+        return Err(Error::IoError(format!(
+            "Patches can't be applied to {}, {} isn't set to extract zip archives",
+            options.selected_file_name, options.emulator.name
+        )));
+    }
+    Ok(())
+}
+
+/// Writes an M3U playlist of the files to the target path, one file name per line. The playlist
+/// is named after the first file. Returns the name of the playlist.
+pub fn write_playlist(target_path: &Path, file_names: &[String]) -> Result<String, Error> {
+    let first_file_name = file_names
+        .first()
+        .ok_or_else(|| Error::IoError("No files for playlist".to_string()))?;
+    let playlist_file_name = format!(
+        "{}.m3u",
+        Path::new(first_file_name)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+    );
+    let contents = file_names
+        .iter()
+        .map(|file_name| format!("{}\n", file_name))
+        .collect::<String>();
+    std::fs::write(target_path.join(&playlist_file_name), contents).map_err(|e| {
+        Error::IoError(format!(
+            "Failed writing playlist {}: {}",
+            playlist_file_name, e
+        ))
+    })?;
+    Ok(playlist_file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::CollectionFileType;
+
+    #[test]
+    fn test_create_run_options() {
+        let release = ReleaseViewModel {
+            files: vec![
+                CollectionFile::for_test("Game.zip", CollectionFileType::DiskImage)
+                    .with_entries(&["Disk 1.d64", "Disk 2.d64", "readme.txt"]),
+                CollectionFile::for_test("Game.t64", CollectionFileType::TapeImage),
+                CollectionFile::for_test("Cover.png", CollectionFileType::CoverScan),
+            ],
+            ..ReleaseViewModel::default()
+        };
+        let mut emulator = Emulator {
+            extract_files: true,
+            supported_file_type_extensions: vec!["d64".to_string()],
+            supported_file_types: vec![CollectionFileType::DiskImage],
+            multiple_media: true,
+            ..Emulator::default()
+        };
+        let file_path_builder = FilePathBuilder::new("collection".to_string());
+
+        let options = create_run_options(
+            &release,
+            &emulator,
+            "Disk 1.d64".to_string(),
+            &file_path_builder,
+            PathBuf::from("target"),
+        );
+        assert_eq!(options.files.len(), 1);
+        assert_eq!(options.additional_file_names, vec!["Disk 2.d64"]);
+
+        // the tape image is supported without the extension filter, the cover scan isn't
+        emulator.supported_file_type_extensions = vec![];
+        emulator.supported_file_types = vec![];
+        emulator.extract_files = false;
+        assert!(emulator.supports_file(&release.files[1]));
+        assert!(!emulator.supports_file(&release.files[2]));
+        let options = create_run_options(
+            &release,
+            &emulator,
+            "Game.zip".to_string(),
+            &file_path_builder,
+            PathBuf::from("target"),
+        );
+        assert_eq!(options.files.len(), 2);
+        assert_eq!(options.additional_file_names, vec!["Game.t64"]);
+        assert!(options.playlist_file_names.is_empty());
+
+        // an emulator supporting playlists is given all the media in a playlist
+        emulator.supports_playlists = true;
+        let options = create_run_options(
+            &release,
+            &emulator,
+            "Game.zip".to_string(),
+            &file_path_builder,
+            PathBuf::from("target"),
+        );
+        assert!(options.additional_file_names.is_empty());
+        assert_eq!(options.playlist_file_names, vec!["Game.zip", "Game.t64"]);
+    }
+
+    #[test]
+    fn test_check_patched_file() {
+        let zip_file = CollectionFile::for_test("Game.zip", CollectionFileType::Rom)
+            .with_entries(&["Game.rom"]);
+        let patch = CollectionFile {
+            base_file_id: zip_file._id,
+            ..CollectionFile::for_test("Translation.ips", CollectionFileType::Patch)
+        };
+        let release = ReleaseViewModel {
+            files: vec![zip_file, patch.clone()],
+            ..ReleaseViewModel::default()
+        };
+        let emulator = Emulator {
+            extract_files: true,
+            supported_file_types: vec![CollectionFileType::Rom],
+            ..Emulator::default()
+        };
+        let mut options = EmulatorRunOptions {
+            patches: vec![patch],
+            ..create_run_options(
+                &release,
+                &emulator,
+                "Game.rom".to_string(),
+                &FilePathBuilder::new("collection".to_string()),
+                PathBuf::from("target"),
+            )
+        };
+        assert!(check_patched_file(&options).is_ok());
+
+        // the patch would be applied to the archive itself
+        options.emulator.extract_files = false;
+        options.selected_file_name = "Game.zip".to_string();
+        assert!(check_patched_file(&options).is_err());
+        options.patches.clear();
+        assert!(check_patched_file(&options).is_ok());
+    }
+
+    #[test]
+    fn test_write_playlist() {
+        let target_path = std::env::temp_dir().join(format!("playlist_{}", ObjectId::new()));
+        std::fs::create_dir_all(&target_path).unwrap();
+        let file_names = vec![
+            "Game (Disk 1).d64".to_string(),
+            "Game (Disk 2).d64".to_string(),
+        ];
+        let playlist_file_name = write_playlist(&target_path, &file_names).unwrap();
+        assert_eq!(playlist_file_name, "Game (Disk 1).m3u");
+        assert_eq!(
+            std::fs::read_to_string(target_path.join(&playlist_file_name)).unwrap(),
+            "Game (Disk 1).d64\nGame (Disk 2).d64\n"
+        );
+        std::fs::remove_dir_all(&target_path).unwrap();
+        assert!(write_playlist(&target_path, &[]).is_err());
+    }
+
+    #[test]
+    fn test_expand_arguments() {
+        let file_path = Path::new("/tmp/games/Pac Man.zip");
+        assert_eq!(expand_arguments("-fullscreen", file_path), None);
+        assert_eq!(
+            expand_arguments("-autostart {file}", file_path),
+            Some(vec![
+                "-autostart".to_string(),
+                "/tmp/games/Pac Man.zip".to_string()
+            ])
+        );
+        assert_eq!(
+            expand_arguments("{name} -rompath {directory}", file_path),
+            Some(vec![
+                "Pac Man".to_string(),
+                "-rompath".to_string(),
+                "/tmp/games".to_string()
+            ])
+        );
+        assert_eq!(
+            expand_arguments("--floppy_drive_0={file}", file_path),
+            Some(vec!["--floppy_drive_0=/tmp/games/Pac Man.zip".to_string()])
+        );
     }
 }
@@ -6,6 +6,8 @@ pub enum Error {
     IoError(String),
     DbError(String),
     NotFound(String),
+    ParseError(String),
+    Cancelled,
 }
 
 impl Display for Error {
@@ -15,6 +17,8 @@ impl Display for Error {
             Error::IoError(message) => write!(f, "IO error: {}", message),
             Error::DbError(message) => write!(f, "Database error: {}", message),
             Error::NotFound(message) => write!(f, "Not found: {}", message),
+            Error::ParseError(message) => write!(f, "Parse error: {}", message),
+            Error::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
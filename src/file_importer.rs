@@ -0,0 +1,365 @@
+use std::path::{Path, PathBuf};
+
+use async_std::fs::{remove_file, rename};
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+
+use crate::{
+    content_store::get_content_key,
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::stage_file,
+    model::{
+        collection_file::{Checksums, CollectionFile},
+        model::{FileImportMode, System},
+    },
+    util::{
+        checksum::compute_file_checksums_with_progress,
+        file_operation::ProgressReporter,
+        file_path_builder::{FilePathBuilder, COLLECTION_ROOT_VOLUME_NAME},
+    },
+};
+
+pub const STAGING_EXTENSION: &str = "importing";
+
+/// Where an imported file is placed and the release it's added to.
+pub struct ImportTarget<'a> {
+    pub file_path_builder: &'a FilePathBuilder,
+    pub system: &'a System,
+    pub release_name: &'a str,
+    pub release_id: Option<ObjectId>,
+}
+
+/// Imports a file to the collection in two phases: the file is first brought to a staging
+/// name next to its final path and verified by checksum, then it's renamed into place and the
+/// collection file is added to the database and linked to the release in one transaction.
+/// On any failure the staged or placed file is removed, or moved back when it was moved,
+/// so that no orphan files or database records are left behind.
+/// With content addressed storage a file whose content is already in the content store
+/// only adds a reference to it.
+/// The collection file is added with its id, or a new id when it doesn't have one.
+/// Returns the collection file as it was added to the database.
+pub async fn import_collection_file(
+    source: PathBuf,
+    mut collection_file: CollectionFile,
+    target: &ImportTarget<'_>,
+    file_import_mode: FileImportMode,
+    reporter: &ProgressReporter,
+) -> Result<CollectionFile, Error> {
+    collection_file._id.get_or_insert_with(ObjectId::new);
+    let db = DatabaseWithPolo::get_instance();
+
+    // a file already in the collection directory or on a storage volume is registered where it is
+    if collection_file.relative_path.is_some() {
+        db.add_collection_file_to_release(&collection_file, target.release_id.as_ref())?;
+        return Ok(collection_file);
+    }
+
+    let file_path_builder = target.file_path_builder;
+    let content_object = match get_content_key(&collection_file)
+        .filter(|_| file_path_builder.content_addressed_storage)
+    {
+        Some(content_key) => {
+            let content_object = db.get_content_object(&content_key)?;
+            collection_file.content_key = Some(content_key);
+            content_object
+        }
+        None => None,
+    };
+    collection_file.volume = match &content_object {
+        // identical content is referenced on the volume where it already is
+        Some(content_object) => content_object.volume.clone(),
+        None => {
+            file_path_builder.select_volume(target.system, &collection_file.collection_file_type)
+        }
+    };
+    // nothing is written to the mount point of an offline volume
+    if !file_path_builder.is_volume_online(collection_file.volume.as_deref()) {
+        return Err(Error::NotFound(format!(
+            "Storage volume {} is offline",
+            collection_file
+                .volume
+                .as_deref()
+                .unwrap_or(COLLECTION_ROOT_VOLUME_NAME)
+        )));
+    }
+    if collection_file.content_key.is_none() {
+        collection_file.relative_path = file_path_builder.build_new_relative_path(
+            target.system,
+            target.release_name,
+            &collection_file,
+        );
+    }
+    let destination = file_path_builder.build_file_path(target.system, &collection_file)?;
+
+    // content already in the content store is only referenced
+    let is_placed = collection_file.content_key.is_none() || !destination.exists();
+    let source_moved = if is_placed {
+        place_file(
+            &source,
+            &destination,
+            collection_file.size,
+            &collection_file.checksums,
+            &file_import_mode,
+            reporter,
+        )
+        .await?
+    } else {
+        false
+    };
+
+    if let Err(err) =
+        db.add_collection_file_to_release(&collection_file, target.release_id.as_ref())
+    {
+        if is_placed {
+            roll_back(&source, &destination, source_moved).await;
+        }
+        return Err(err);
+    }
+
+    // the file was copied across file systems or its content was already in the content store,
+    // the source is removed only after the import is complete
+    if file_import_mode == FileImportMode::Move && !source_moved {
+        if let Err(e) = remove_file(&source).await {
+            println!("Failed to remove moved file {:?}: {}", source, e);
+        }
+    }
+
+    Ok(collection_file)
+}
+
+/// Whether the collection file was imported from a file with the size and checksums of the
+/// picked file, so that a resumed import doesn't add the file again. Files picked without
+/// checksums are matched by their name.
+pub fn is_same_import(imported_file: &CollectionFile, picked_file: &CollectionFile) -> bool {
+    imported_file.collection_file_type == picked_file.collection_file_type
+        && imported_file.size == picked_file.size
+        && if picked_file.checksums.is_empty() {
+            imported_file.original_file_name == picked_file.original_file_name
+        } else {
+            imported_file.checksums == picked_file.checksums
+        }
+}
+
+/// Stages the source file, verifies its size and checksums and renames it to the destination.
+/// Returns true if the source file was moved.
+async fn place_file(
+    source: &Path,
+    destination: &Path,
+    expected_size: u64,
+    expected_checksums: &Checksums,
+    file_import_mode: &FileImportMode,
+    reporter: &ProgressReporter,
+) -> Result<bool, Error> {
+    let staging_path = get_staging_path(destination);
+
+    let source_moved = stage_file(source, &staging_path, file_import_mode, reporter).await?;
+
+    if let Err(err) = verify_file(&staging_path, expected_size, expected_checksums, reporter).await
+    {
+        roll_back(source, &staging_path, source_moved).await;
+        return Err(err);
+    }
+
+    if let Err(e) = rename(&staging_path, destination).await {
+        roll_back(source, &staging_path, source_moved).await;
+        return Err(Error::IoError(format!(
+            "Failed to rename staged file to {:?}: {}",
+            destination, e
+        )));
+    }
+
+    Ok(source_moved)
+}
+
+async fn verify_file(
+    file_path: &Path,
+    expected_size: u64,
+    expected_checksums: &Checksums,
+    reporter: &ProgressReporter,
+) -> Result<(), Error> {
+    let file_path_clone = file_path.to_path_buf();
+    let reporter = reporter.clone();
+    let (size, checksums) =
+        spawn_blocking(move || compute_file_checksums_with_progress(&file_path_clone, &reporter))
+            .await?;
+
+    if size != expected_size {
+        return Err(Error::IoError(format!(
+            "Size of staged file {:?} is {} bytes, expected {} bytes",
+            file_path, size, expected_size
+        )));
+    }
+    // files picked before checksums were calculated can only be verified by size
+    if !expected_checksums.is_empty() && checksums != *expected_checksums {
+        return Err(Error::IoError(format!(
+            "Checksums of staged file {:?} don't match the source file",
+            file_path
+        )));
+    }
+    Ok(())
+}
+
+/// Moves the file back to the source if it was moved, otherwise removes it.
+async fn roll_back(source: &Path, file_path: &Path, source_moved: bool) {
+    let result = if source_moved {
+        rename(file_path, source).await
+    } else {
+        remove_file(file_path).await
+    };
+    if let Err(e) = result {
+        println!("Failed to roll back import of {:?}: {}", file_path, e);
+    }
+}
+
+fn get_staging_path(destination: &Path) -> PathBuf {
+    let mut file_name = destination.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(STAGING_EXTENSION);
+    destination.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::collection_file::CollectionFileType,
+        util::{checksum::compute_checksums, file_operation::Cancellation},
+    };
+
+    fn create_test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_is_same_import() {
+        let (size, checksums) = compute_checksums(b"game");
+        let picked_file = CollectionFile {
+            _id: None,
+            size,
+            checksums,
+            ..CollectionFile::for_test("game.d64", CollectionFileType::DiskImage)
+        };
+        let imported_file = CollectionFile {
+            _id: Some(ObjectId::new()),
+            original_file_name: "renamed.d64".to_string(),
+            relative_path: Some("1234.d64".to_string()),
+            ..picked_file.clone()
+        };
+        assert!(is_same_import(&imported_file, &picked_file));
+        assert!(!is_same_import(
+            &CollectionFile {
+                collection_file_type: CollectionFileType::Rom,
+                ..imported_file.clone()
+            },
+            &picked_file
+        ));
+        let (_, other_checksums) = compute_checksums(b"gamf");
+        assert!(!is_same_import(
+            &CollectionFile {
+                checksums: other_checksums,
+                ..imported_file.clone()
+            },
+            &picked_file
+        ));
+
+        // without checksums the name has to match
+        let picked_file = CollectionFile {
+            checksums: Checksums::default(),
+            ..picked_file
+        };
+        assert!(!is_same_import(&imported_file, &picked_file));
+        assert!(is_same_import(
+            &CollectionFile {
+                original_file_name: "game.d64".to_string(),
+                ..imported_file
+            },
+            &picked_file
+        ));
+    }
+
+    #[test]
+    fn test_place_file() {
+        let directory = create_test_directory("test_place_file");
+        let source = directory.join("game.d64");
+        std::fs::write(&source, b"game").unwrap();
+        let (size, checksums) = compute_checksums(b"game");
+        let destination = directory.join("collection").join("1234.d64");
+
+        let source_moved = async_std::task::block_on(place_file(
+            &source,
+            &destination,
+            size,
+            &checksums,
+            &FileImportMode::Copy,
+            &ProgressReporter::default(),
+        ))
+        .unwrap();
+
+        assert!(!source_moved);
+        assert!(source.exists());
+        assert_eq!(std::fs::read(&destination).unwrap(), b"game");
+        assert!(!get_staging_path(&destination).exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_place_file_with_checksum_mismatch() {
+        let directory = create_test_directory("test_place_file_with_checksum_mismatch");
+        let source = directory.join("game.d64");
+        std::fs::write(&source, b"game").unwrap();
+        let (size, checksums) = compute_checksums(b"gamf");
+        let destination = directory.join("collection").join("1234.d64");
+
+        for file_import_mode in [FileImportMode::Copy, FileImportMode::Move] {
+            let result = async_std::task::block_on(place_file(
+                &source,
+                &destination,
+                size,
+                &checksums,
+                &file_import_mode,
+                &ProgressReporter::default(),
+            ));
+
+            assert!(result.is_err());
+            assert_eq!(std::fs::read(&source).unwrap(), b"game");
+            assert!(!destination.exists());
+            assert!(!get_staging_path(&destination).exists());
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_place_file() {
+        let directory = create_test_directory("test_cancelled_place_file");
+        let source = directory.join("game.d64");
+        std::fs::write(&source, b"game").unwrap();
+        let (size, checksums) = compute_checksums(b"game");
+        let destination = directory.join("collection").join("1234.d64");
+        let cancellation = Cancellation::default();
+        let (sender, _receiver) = iced::futures::channel::mpsc::unbounded();
+        let reporter = ProgressReporter::new(sender, cancellation.clone());
+        cancellation.cancel();
+
+        let result = async_std::task::block_on(place_file(
+            &source,
+            &destination,
+            size,
+            &checksums,
+            &FileImportMode::Copy,
+            &reporter,
+        ));
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(source.exists());
+        assert!(!destination.exists());
+        assert!(!get_staging_path(&destination).exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
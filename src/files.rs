@@ -1,13 +1,15 @@
 use crate::error::Error;
-use crate::model::collection_file::{CollectionFile, FileInfo, GetCollectionFileName};
-use async_std::fs::{copy as async_copy, create_dir_all, remove_file, File as AsyncFile};
+use crate::model::collection_file::{Checksums, CollectionFile, FileInfo};
+use crate::model::model::FileImportMode;
+use crate::util::checksum::{compute_file_checksums_with_progress, ChecksumHasher};
+use crate::util::file_operation::ProgressReporter;
+use async_std::fs::{create_dir_all, hard_link, remove_file, rename, File as AsyncFile};
 use async_std::path::{Path as AsyncPath, PathBuf};
 use async_std::prelude::*;
-use bson::oid::ObjectId;
-use sha1::{Digest, Sha1};
-use std::fs::{copy, File};
+use async_std::task::spawn_blocking;
+use std::fs::{create_dir_all as create_dir_all_sync, File};
+use std::io::Read;
 use std::io::Write;
-use std::io::{Cursor, Read};
 use std::path::{Path as SyncPath, PathBuf as SyncPathBuf};
 use zip::read::ZipArchive;
 
@@ -17,7 +19,8 @@ pub struct PickedFile {
     pub is_zip: bool,
     pub files: Option<Vec<FileInfo>>,
     pub file_path: PathBuf,
-    pub extension: String,
+    pub size: u64,
+    pub checksums: Checksums,
 }
 
 pub async fn pick_folder() -> Result<SyncPathBuf, Error> {
@@ -29,15 +32,41 @@ pub async fn pick_folder() -> Result<SyncPathBuf, Error> {
     Ok(file_handle.path().to_owned())
 }
 
-pub async fn pick_file() -> Result<PickedFile, Error> {
+pub async fn pick_dat_file() -> Result<SyncPathBuf, Error> {
+    let file_handle = rfd::AsyncFileDialog::new()
+        .set_title("Choose a DAT file")
+        .add_filter("DAT file", &["dat", "xml"])
+        .pick_file()
+        .await
+        .ok_or(Error::DialogClosed)?;
+    Ok(file_handle.path().to_owned())
+}
+
+pub async fn pick_csv_file_to_save(file_name: String) -> Result<SyncPathBuf, Error> {
+    let file_handle = rfd::AsyncFileDialog::new()
+        .set_title("Save as CSV")
+        .add_filter("CSV file", &["csv"])
+        .set_file_name(file_name)
+        .save_file()
+        .await
+        .ok_or(Error::DialogClosed)?;
+    Ok(file_handle.path().to_owned())
+}
+
+pub async fn pick_file() -> Result<SyncPathBuf, Error> {
     let picked_file_handle = rfd::AsyncFileDialog::new()
         .set_title("Choose a file")
         .pick_file()
         .await
         .ok_or(Error::DialogClosed)?;
+    Ok(picked_file_handle.path().to_owned())
+}
 
-    let picked_file_path = picked_file_handle.path();
-
+/// Reads the file information and calculates the checksums of a file to be added to the collection.
+pub async fn read_picked_file(
+    picked_file_path: &SyncPath,
+    reporter: &ProgressReporter,
+) -> Result<PickedFile, Error> {
     println!("picked_file_path: {:?}", picked_file_path);
 
     let is_zip = is_zip_file(AsyncPath::new(picked_file_path)).await?;
@@ -45,22 +74,33 @@ pub async fn pick_file() -> Result<PickedFile, Error> {
     println!("is_zip: {:?}", is_zip);
 
     let files_in_zip = if is_zip {
-        Some(read_zip_file(picked_file_path).await?)
+        let zip_file_path = picked_file_path.to_path_buf();
+        let zip_reporter = reporter.clone();
+        Some(spawn_blocking(move || read_zip_file_checksums(&zip_file_path, &zip_reporter)).await?)
     } else {
         None
     };
 
     let picked_file_name = get_file_name(picked_file_path)?;
-    let picked_file_extension = get_file_extension(picked_file_path)?;
+    // files are stored in the collection by id and extension
+    get_file_extension(picked_file_path)?;
 
     println!("picked_file_name: {:?}", picked_file_name);
 
+    let checksum_file_path = picked_file_path.to_path_buf();
+    let checksum_reporter = reporter.clone();
+    let (size, checksums) = spawn_blocking(move || {
+        compute_file_checksums_with_progress(&checksum_file_path, &checksum_reporter)
+    })
+    .await?;
+
     Ok(PickedFile {
         file_name: picked_file_name,
         is_zip,
         files: files_in_zip,
         file_path: PathBuf::from(picked_file_path),
-        extension: picked_file_extension,
+        size,
+        checksums,
     })
 }
 
@@ -92,29 +132,64 @@ pub fn get_file_extension(path: &SyncPath) -> Result<String, Error> {
     Ok(extension)
 }
 
-pub async fn copy_file(
-    destination_directory: SyncPathBuf,
-    file_id: ObjectId,
-    picked_file: PickedFile,
-) -> Result<ObjectId, Error> {
-    let destination_file_path = AsyncPath::new(&destination_directory)
-        .join(&file_id.to_hex())
-        .with_extension(&picked_file.extension);
-
-    println!("destination_file_path: {:?}", destination_file_path);
-
-    if let Some(parent) = destination_file_path.parent() {
+/// Copies, moves or hard links the source file to the staging path.
+/// Returns true if the source file was moved, so that it can be moved back on failure.
+pub async fn stage_file(
+    source: &SyncPath,
+    staging_path: &SyncPath,
+    file_import_mode: &FileImportMode,
+    reporter: &ProgressReporter,
+) -> Result<bool, Error> {
+    if let Some(parent) = staging_path.parent() {
         create_dir_all(parent)
             .await
             .map_err(|e| Error::IoError(format!("Failed to create directory: {}", e)))?;
     }
-    // TODO: add flag to copy or move the file
-    println!("Copying file to destination.");
-    async_copy(picked_file.file_path.clone(), &destination_file_path)
-        .await
-        .map_err(|e| Error::IoError(format!("Failed to copy file: {}", e)))?;
 
-    Ok(file_id)
+    println!(
+        "{} file to staging path {:?}.",
+        file_import_mode, staging_path
+    );
+    match file_import_mode {
+        // rename fails when the destination is on another file system, then the file is copied
+        // and the source is removed after the import is complete
+        FileImportMode::Move if rename(source, staging_path).await.is_ok() => Ok(true),
+        FileImportMode::Copy | FileImportMode::Move => {
+            let source = source.to_path_buf();
+            let staging_path = staging_path.to_path_buf();
+            let reporter = reporter.clone();
+            spawn_blocking(move || copy_file_with_progress(&source, &staging_path, &reporter))
+                .await
+                .map(|_| false)
+        }
+        FileImportMode::HardLink => hard_link(source, staging_path)
+            .await
+            .map(|_| false)
+            .map_err(|e| Error::IoError(format!("Failed to create hard link: {}", e))),
+    }
+}
+
+/// Copies a file in chunks and reports the progress. A partially copied file is removed on failure.
+/// This is blocking and should be run with spawn_blocking.
+pub fn copy_file_with_progress(
+    source: &SyncPath,
+    destination: &SyncPath,
+    reporter: &ProgressReporter,
+) -> Result<u64, Error> {
+    let mut source_file = File::open(source)
+        .map_err(|e| Error::IoError(format!("Failed opening file {:?}: {}", source, e)))?;
+    let total_bytes = source_file.metadata().map(|m| m.len()).unwrap_or_default();
+    let mut destination_file = File::create(destination)
+        .map_err(|e| Error::IoError(format!("Failed creating file {:?}: {}", destination, e)))?;
+
+    let description = format!("Copying {}", get_file_name(source)?);
+    reporter
+        .read_with_progress(&mut source_file, &description, total_bytes, |chunk| {
+            destination_file.write_all(chunk)
+        })
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(destination);
+        })
 }
 
 pub async fn delete_file(path: SyncPathBuf) -> Result<(), Error> {
@@ -134,19 +209,98 @@ pub async fn delete_file(path: SyncPathBuf) -> Result<(), Error> {
     Ok(id)
 }*/
 
-pub async fn read_zip_file(file_path: &SyncPath) -> Result<Vec<FileInfo>, Error> {
-    let file = AsyncFile::open(file_path)
-        .await
-        .map_err(|_| Error::IoError("Failed opening file.".to_string()))?;
-    let mut buffer = Vec::new();
-    file.take(10 * 1024 * 1024) // Read up to 10 MB
-        .read_to_end(&mut buffer)
-        .await
-        .map_err(|_| Error::IoError("Failed reaind contents of file.".to_string()))?;
+#[derive(Debug, Clone)]
+pub struct ScannedFile {
+    pub file_path: SyncPathBuf,
+    // names of the files inside a zip archive
+    pub archived_file_names: Vec<String>,
+}
+
+/// Lists the files in a folder and its sub folders, sorted by path.
+pub async fn scan_folder(folder: SyncPathBuf) -> Result<Vec<ScannedFile>, Error> {
+    spawn_blocking(move || {
+        let mut file_paths = Vec::new();
+        collect_file_paths(&folder, &mut file_paths)?;
+        file_paths.sort();
+        // a file that can't be opened is left out, so that it doesn't stop the scan
+        Ok(file_paths
+            .into_iter()
+            .filter_map(|file_path| {
+                scan_file(file_path)
+                    .map_err(|err| println!("Skipping file: {:?}", err))
+                    .ok()
+            })
+            .collect())
+    })
+    .await
+}
+
+/// Reads the given files like the files of a scanned folder.
+pub async fn scan_files(file_paths: Vec<SyncPathBuf>) -> Result<Vec<ScannedFile>, Error> {
+    spawn_blocking(move || file_paths.into_iter().map(scan_file).collect()).await
+}
+
+// an archive that can't be read is scanned as a plain file, so that it doesn't stop the scan
+fn scan_file(file_path: SyncPathBuf) -> Result<ScannedFile, Error> {
+    let archived_file_names = if is_zip_file_sync(&file_path)? {
+        read_zip_file_names(&file_path).unwrap_or_else(|err| {
+            println!("Failed reading archive {:?}: {:?}", file_path, err);
+            vec![]
+        })
+    } else {
+        vec![]
+    };
+    Ok(ScannedFile {
+        file_path,
+        archived_file_names,
+    })
+}
+
+/// Lists the files in a folder and its sub folders. Symbolic links to folders are not followed,
+/// so a link pointing to a parent folder doesn't make the listing endless, and broken symbolic
+/// links are left out.
+pub fn collect_file_paths(
+    folder: &SyncPath,
+    file_paths: &mut Vec<SyncPathBuf>,
+) -> Result<(), Error> {
+    let entries = std::fs::read_dir(folder)
+        .map_err(|e| Error::IoError(format!("Failed reading folder {:?}: {}", folder, e)))?;
+    for entry in entries {
+        let entry = entry
+            .map_err(|e| Error::IoError(format!("Failed reading folder {:?}: {}", folder, e)))?;
+        let path = entry.path();
+        let is_symlink = entry
+            .file_type()
+            .map_err(|e| Error::IoError(format!("Failed reading {:?}: {}", path, e)))?
+            .is_symlink();
+        if path.is_file() {
+            file_paths.push(path);
+        } else if path.is_dir() && !is_symlink {
+            collect_file_paths(&path, file_paths)?;
+        }
+    }
+    Ok(())
+}
 
-    let reader = Cursor::new(buffer);
-    let mut zip = ZipArchive::new(reader).map_err(|_| {
-        Error::IoError("Failed creating a Zip archive from the buffer.".to_string())
+pub fn read_zip_file_names(file_path: &SyncPath) -> Result<Vec<String>, Error> {
+    let file = File::open(file_path)
+        .map_err(|e| Error::IoError(format!("Failed opening file {:?}: {}", file_path, e)))?;
+    let zip = ZipArchive::new(file).map_err(|e| {
+        Error::IoError(format!("Failed reading Zip archive {:?}: {}", file_path, e))
+    })?;
+    Ok(zip.file_names().map(|name| name.to_string()).collect())
+}
+
+/// Calculates the checksums of the files in a zip archive without loading the whole archive
+/// into memory. This is blocking and should be run with spawn_blocking.
+pub fn read_zip_file_checksums(
+    file_path: &SyncPath,
+    reporter: &ProgressReporter,
+) -> Result<Vec<FileInfo>, Error> {
+    let file = File::open(file_path)
+        .map_err(|e| Error::IoError(format!("Failed opening file {:?}: {}", file_path, e)))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| {
+        Error::IoError(format!("Failed reading Zip archive {:?}: {}", file_path, e))
     })?;
 
     let mut file_infos = Vec::new();
@@ -154,31 +308,23 @@ pub async fn read_zip_file(file_path: &SyncPath) -> Result<Vec<FileInfo>, Error>
         let mut file = zip.by_index(i).map_err(|_| {
             Error::IoError(format!("Failed reading file in index {} in zip file.", i))
         })?;
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents).map_err(|_| {
-            Error::IoError(format!(
-                "Failed reading file {} from Zip archive.",
-                file.name()
-            ))
-        })?;
-
-        let mut hasher = Sha1::new();
-        hasher.update(&contents);
-        let checksum = format!("{:x}", hasher.finalize());
-
-        let zipped_file_path = file.enclosed_name().ok_or(Error::IoError(
-            "Failed to get the name of the file in the Zip archive.".to_string(),
-        ))?;
-
-        let zipped_file_path = zipped_file_path.to_str().ok_or(Error::IoError(
-            "Failed to convert file path to string.".to_string(),
-        ))?;
-
-        println!("zipped_file_path: {:?}", zipped_file_path);
-
+        let name = file.name().to_string();
+        let total_bytes = file.size();
+        let mut hasher = ChecksumHasher::new();
+        reporter.read_with_progress(
+            &mut file,
+            &format!("Calculating checksums of {}", name),
+            total_bytes,
+            |chunk| {
+                hasher.update(chunk);
+                Ok(())
+            },
+        )?;
+        let (size, checksums) = hasher.finalize();
         file_infos.push(FileInfo {
-            name: zipped_file_path.to_string(),
-            checksum,
+            name,
+            size,
+            checksums,
         });
     }
 
@@ -214,88 +360,93 @@ pub fn is_zip_file_sync(file_path: &SyncPath) -> Result<bool, Error> {
 }
 
 /// Extracts the files from the zip files and copies the other files to the destination.
+/// Takes the collection files with their paths in the collection.
+/// This is blocking and should be run with spawn_blocking.
 pub fn extract_zip_files(
-    files: &Vec<CollectionFile>,
-    source: &SyncPathBuf,
+    files: &[(SyncPathBuf, CollectionFile)],
     destination: &SyncPathBuf,
+    reporter: &ProgressReporter,
 ) -> Result<(), Error> {
-    println!(
-        "Extracting zip files from source: {:?}, to destination: {:?}",
-        source, destination
-    );
+    println!("Extracting zip files to destination: {:?}", destination);
     // TODO: no need to extract all files, just the selected one
     // TODO: Or should it be possible for user to select multiple files?
     //       User could add multiple files of the same release and most probably wants to select just one version for running with emulator.
     //       Then again the one version of the same release could consist of multiple files.
     //       But in any case, no need to extract all the files, only the selected ones.
-    for file in files {
-        let file_path = source.join(&file.get_collection_file_name());
+    for (file_path, file) in files {
         println!("file_path: {:?}", file_path);
-        let res = match is_zip_file_sync(file_path.as_path()) {
-            Ok(true) => extract_zip_file(&file_path, &destination),
-            Ok(false) => {
-                let destination_file = destination.join(&file.original_file_name);
-                copy(&file_path, &destination_file)
-                    .map_err(|e| Error::IoError(format!("Failed to copy file: {}", e)))?;
-                Ok(())
-            }
-            Err(e) => Err(e),
-        };
-        if res.is_err() {
-            return res;
+        if is_zip_file_sync(file_path.as_path())? {
+            extract_zip_file(file_path, destination, reporter)?;
+        } else {
+            let destination_file = destination.join(&file.original_file_name);
+            copy_file_with_progress(file_path, &destination_file, reporter)?;
         }
     }
     println!("Finished extracting zip files");
     Ok(())
 }
 
-/// Copies the files to the destination.
+/// Copies the files to the destination. Takes the collection files with their paths in the collection.
+/// This is blocking and should be run with spawn_blocking.
 pub fn copy_files(
-    files: &Vec<CollectionFile>,
-    source: &SyncPathBuf,
+    files: &[(SyncPathBuf, CollectionFile)],
     destination: &SyncPathBuf,
+    reporter: &ProgressReporter,
 ) -> Result<(), Error> {
     // TODO: no need to copy all files, just the selected one
-    for file in files {
-        let file_path = source.join(&file.get_collection_file_name());
+    for (file_path, file) in files {
         println!("file_path: {:?}", file_path);
         let destination_file = destination.join(&file.original_file_name);
-        copy(&file_path, &destination_file)
-            .map_err(|e| Error::IoError(format!("Failed to copy file: {}", e)))?;
+        copy_file_with_progress(file_path, &destination_file, reporter)?;
     }
     Ok(())
 }
 
-/// Extracts the files from the zip file to the destination.
-pub fn extract_zip_file(file_path: &SyncPathBuf, destination: &SyncPathBuf) -> Result<(), Error> {
+/// Extracts the files from the zip file to the destination and reports the progress.
+pub fn extract_zip_file(
+    file_path: &SyncPathBuf,
+    destination: &SyncPathBuf,
+    reporter: &ProgressReporter,
+) -> Result<(), Error> {
     println!(
         "Extracting zip file from path: {:?}, to path {:?}",
         file_path, destination
     );
-    let file = File::open(&file_path)
-        .map_err(|e| Error::IoError(format!("Failed to open file: {}", e)))?;
-    let mut buffer = Vec::new();
-    file.take(10 * 1024 * 1024) // Read up to 10 MB
-        .read_to_end(&mut buffer)
-        .map_err(|e| Error::IoError(format!("Failed to read file: {}", e)))?;
-
-    let reader = Cursor::new(buffer);
-    let mut zip = ZipArchive::new(reader)
+    let file =
+        File::open(file_path).map_err(|e| Error::IoError(format!("Failed to open file: {}", e)))?;
+    let mut zip = ZipArchive::new(file)
         .map_err(|e| Error::IoError(format!("Failed to create Zip archive: {}", e)))?;
 
     for i in 0..zip.len() {
         let mut file = zip
             .by_index(i)
             .map_err(|e| Error::IoError(format!("Failed to read file in Zip archive: {}", e)))?;
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents)
-            .map_err(|e| Error::IoError(format!("Failed to read file in Zip archive: {}", e)))?;
+        let file_name = file.enclosed_name().ok_or(Error::IoError(format!(
+            "Invalid file name {} in Zip archive",
+            file.name()
+        )))?;
+        let file_path = destination.join(file_name);
+        if file.is_dir() {
+            create_dir_all_sync(&file_path)
+                .map_err(|e| Error::IoError(format!("Failed to create directory: {}", e)))?;
+            continue;
+        }
+        if let Some(parent) = file_path.parent() {
+            create_dir_all_sync(parent)
+                .map_err(|e| Error::IoError(format!("Failed to create directory: {}", e)))?;
+        }
 
-        let file_path = destination.join(file.name());
-        let mut file = File::create(&file_path)
+        let mut extracted_file = File::create(&file_path)
             .map_err(|e| Error::IoError(format!("Failed to create file: {}", e)))?;
-        file.write_all(&contents)
-            .map_err(|e| Error::IoError(format!("Failed to write file: {}", e)))?;
+        let total_bytes = file.size();
+        let description = format!("Extracting {}", file.name());
+        reporter
+            .read_with_progress(&mut file, &description, total_bytes, |chunk| {
+                extracted_file.write_all(chunk)
+            })
+            .inspect_err(|_| {
+                let _ = std::fs::remove_file(&file_path);
+            })?;
     }
     Ok(())
 }
@@ -335,3 +486,36 @@ pub fn extract_zip_file(file_path: &SyncPathBuf, destination: &SyncPathBuf) -> R
                     .map_err(|e| Error::IoError(format!("Failed to write file: {}", e)))?;
             }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_folder() {
+        let folder = std::env::temp_dir().join("test_scan_folder");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(folder.join("Disks")).unwrap();
+        std::fs::write(folder.join("Game.d64"), b"game").unwrap();
+        std::fs::write(folder.join("Disks").join("Broken.zip"), b"PK\x03\x04broken").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&folder, folder.join("Disks").join("Parent")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(folder.join("Missing.d64"), folder.join("Link.d64")).unwrap();
+
+        let scanned_files = async_std::task::block_on(scan_folder(folder.clone())).unwrap();
+        assert_eq!(
+            scanned_files
+                .iter()
+                .map(|scanned_file| scanned_file.file_path.clone())
+                .collect::<Vec<SyncPathBuf>>(),
+            vec![
+                folder.join("Disks").join("Broken.zip"),
+                folder.join("Game.d64")
+            ]
+        );
+        assert!(scanned_files[0].archived_file_names.is_empty());
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
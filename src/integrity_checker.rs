@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::read_zip_file_checksums,
+    model::{
+        collection_file::{CollectionFile, FileInfo},
+        integrity_check::{IntegrityCheck, IntegrityIssue, IntegrityIssueKind},
+        model::System,
+    },
+    repository::repository::CollectionFilesReadRepository,
+    util::{
+        checksum::compute_file_checksums, file_operation::ProgressReporter,
+        file_path_builder::FilePathBuilder,
+    },
+};
+
+const DESCRIPTION: &str = "Verifying collection";
+
+/// Re-hashes the files of the collection and reports the progress as the size of the checked
+/// files. The state is persisted after each file, so a cancelled or interrupted check continues
+/// where it was left when it's run again.
+pub async fn run_integrity_check(reporter: ProgressReporter) -> Result<(), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let collection_files = get_collection_files_with_paths(db)?;
+
+    let mut integrity_check = match db.get_integrity_check()? {
+        Some(integrity_check) if !integrity_check.completed => integrity_check,
+        _ => IntegrityCheck::default(),
+    };
+    integrity_check.total_files = collection_files.len() as u64;
+    let checked_file_ids = integrity_check
+        .checked_file_ids
+        .iter()
+        .copied()
+        .collect::<HashSet<ObjectId>>();
+
+    let total_bytes = collection_files
+        .iter()
+        .map(|(collection_file, _)| collection_file.size)
+        .sum();
+    let mut bytes_done = collection_files
+        .iter()
+        .filter(|(collection_file, _)| {
+            collection_file
+                ._id
+                .is_some_and(|id| checked_file_ids.contains(&id))
+        })
+        .map(|(collection_file, _)| collection_file.size)
+        .sum();
+    db.save_integrity_check(&integrity_check)?;
+    reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+
+    for (collection_file, file_path) in collection_files {
+        let collection_file_id = collection_file._id.expect("Collection file id is missing");
+        if checked_file_ids.contains(&collection_file_id) {
+            continue;
+        }
+        let size = collection_file.size;
+        let issue =
+            spawn_blocking(move || check_collection_file(&collection_file, &file_path)).await;
+
+        db.add_integrity_check_result(&collection_file_id, issue.as_ref())?;
+        integrity_check.checked_file_ids.push(collection_file_id);
+        if let Some(issue) = issue {
+            integrity_check.issues.push(issue);
+        }
+
+        bytes_done += size;
+        reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+    }
+
+    integrity_check.completed = true;
+    db.save_integrity_check(&integrity_check)
+}
+
+/// Returns each collection file referenced by a release and stored on an online volume once,
+/// with its path in the collection.
+pub fn get_collection_files_with_paths(
+    db: &DatabaseWithPolo,
+) -> Result<Vec<(CollectionFile, PathBuf)>, Error> {
+    let settings = db.get_settings()?;
+    let file_path_builder = FilePathBuilder::from_settings(&settings);
+    let systems = db
+        .get_systems()?
+        .into_iter()
+        .filter_map(|system| system._id.map(|id| (id, system)))
+        .collect::<HashMap<ObjectId, System>>();
+
+    let mut seen_file_ids = HashSet::new();
+    let mut collection_files = Vec::new();
+    for release in db.get_releases()? {
+        let system = match release.system_id.and_then(|id| systems.get(&id)) {
+            Some(system) => system,
+            None => continue,
+        };
+        for collection_file in db.get_collection_files(&release.files)? {
+            // files on an offline volume are checked when the volume is online again
+            if !file_path_builder.is_volume_online(collection_file.volume.as_deref()) {
+                continue;
+            }
+            if collection_file
+                ._id
+                .is_some_and(|id| seen_file_ids.insert(id))
+            {
+                let file_path = file_path_builder.build_file_path(system, &collection_file)?;
+                collection_files.push((collection_file, file_path));
+            }
+        }
+    }
+    Ok(collection_files)
+}
+
+/// Compares the file on disk and the files inside it, when it's a zip archive,
+/// with the size and checksums stored when the file was imported.
+/// Files imported without checksums can only be checked for being missing or unreadable.
+pub fn check_collection_file(
+    collection_file: &CollectionFile,
+    file_path: &Path,
+) -> Option<IntegrityIssue> {
+    let create_issue = |kind: IntegrityIssueKind, details: String| IntegrityIssue {
+        collection_file_id: collection_file._id.expect("Collection file id is missing"),
+        file_name: collection_file.original_file_name.clone(),
+        file_path: file_path.to_string_lossy().to_string(),
+        kind,
+        details,
+    };
+
+    if !file_path.exists() {
+        return Some(create_issue(
+            IntegrityIssueKind::Missing,
+            "File not found".to_string(),
+        ));
+    }
+
+    let (size, checksums) = match compute_file_checksums(file_path) {
+        Ok(result) => result,
+        Err(e) => return Some(create_issue(IntegrityIssueKind::Unreadable, e.to_string())),
+    };
+    if !collection_file.checksums.is_empty()
+        && (size != collection_file.size || checksums != collection_file.checksums)
+    {
+        return Some(create_issue(
+            IntegrityIssueKind::Changed,
+            "File differs from the imported file".to_string(),
+        ));
+    }
+
+    let stored_entries = match (&collection_file.files, collection_file.is_zip) {
+        (Some(files), true) => files,
+        _ => return None,
+    };
+    let entries = match read_zip_file_checksums(file_path, &ProgressReporter::default()) {
+        Ok(entries) => entries,
+        Err(e) => return Some(create_issue(IntegrityIssueKind::Unreadable, e.to_string())),
+    };
+    stored_entries
+        .iter()
+        .filter(|stored_entry| !stored_entry.checksums.is_empty())
+        .find_map(|stored_entry| {
+            match entries.iter().find(|entry| entry.name == stored_entry.name) {
+                None => Some(create_issue(
+                    IntegrityIssueKind::Changed,
+                    format!("{} is missing from the archive", stored_entry.name),
+                )),
+                Some(entry) if !is_same_entry(entry, stored_entry) => Some(create_issue(
+                    IntegrityIssueKind::Changed,
+                    format!("{} differs from the imported file", stored_entry.name),
+                )),
+                Some(_) => None,
+            }
+        })
+}
+
+// entries stored before all checksums were calculated have only their SHA1 checksum
+fn is_same_entry(entry: &FileInfo, stored_entry: &FileInfo) -> bool {
+    if stored_entry.checksums.crc32.is_empty() {
+        return entry
+            .checksums
+            .sha1
+            .eq_ignore_ascii_case(&stored_entry.checksums.sha1);
+    }
+    entry.size == stored_entry.size && entry.checksums == stored_entry.checksums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::collection_file::{CollectionFileType, FileInfo},
+        util::checksum::compute_checksums,
+    };
+
+    fn create_test_collection_file(data: &[u8]) -> CollectionFile {
+        let (size, checksums) = compute_checksums(data);
+        CollectionFile {
+            size,
+            checksums,
+            ..CollectionFile::for_test("Test file.d64", CollectionFileType::DiskImage)
+        }
+    }
+
+    #[test]
+    fn test_check_collection_file() {
+        let file_path = std::env::temp_dir().join("test_check_collection_file.d64");
+        std::fs::write(&file_path, b"original").unwrap();
+        let collection_file = create_test_collection_file(b"original");
+        assert_eq!(check_collection_file(&collection_file, &file_path), None);
+
+        std::fs::write(&file_path, b"modified").unwrap();
+        let issue = check_collection_file(&collection_file, &file_path).unwrap();
+        assert_eq!(issue.kind, IntegrityIssueKind::Changed);
+
+        std::fs::remove_file(&file_path).unwrap();
+        let issue = check_collection_file(&collection_file, &file_path).unwrap();
+        assert_eq!(issue.kind, IntegrityIssueKind::Missing);
+    }
+
+    #[test]
+    fn test_check_collection_file_zip_entries() {
+        let file_path = std::env::temp_dir().join("test_check_collection_file_zip_entries.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&file_path).unwrap());
+        zip.start_file("disk.d64", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, b"modified").unwrap();
+        zip.finish().unwrap();
+
+        let (size, checksums) = compute_checksums(b"original");
+        let collection_file = CollectionFile {
+            is_zip: true,
+            files: Some(vec![FileInfo {
+                name: "disk.d64".to_string(),
+                size,
+                checksums,
+            }]),
+            checksums: Default::default(),
+            ..create_test_collection_file(b"")
+        };
+        let issue = check_collection_file(&collection_file, &file_path).unwrap();
+        assert_eq!(issue.kind, IntegrityIssueKind::Changed);
+
+        std::fs::remove_file(&file_path).unwrap();
+    }
+}
@@ -0,0 +1,490 @@
+use std::sync::{Mutex, MutexGuard};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+use iced::Subscription;
+use lazy_static::lazy_static;
+
+use crate::{
+    collection_relocator::relocate_collection,
+    content_store::convert_to_content_store,
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    file_importer::{import_collection_file, ImportTarget},
+    integrity_checker::run_integrity_check,
+    layout_migration::migrate_layout,
+    model::{
+        collection_file::{CollectionFile, CollectionFileType},
+        job::{Job, JobKind, JobStatus},
+        model::{HasOid, Settings, System},
+    },
+    repository::repository::{CollectionFilesReadRepository, ReleaseReadRepository},
+    storage_optimizer::optimize_storage,
+    util::{
+        bulk_import::import_proposal,
+        file_operation::{
+            run_file_operation, Cancellation, FileOperationEvent, FileOperationProgress,
+            ProgressReporter,
+        },
+        file_path_builder::FilePathBuilder,
+        image::{create_thumbnail, get_thumbnail_path},
+    },
+};
+
+const MAX_RUNNING_JOBS: usize = 2;
+
+/// A job in the queue with the progress it has reported while running.
+#[derive(Debug, Clone)]
+pub struct QueuedJob {
+    pub job: Job,
+    pub progress: Option<FileOperationProgress>,
+    cancellation: Cancellation,
+}
+
+/// Runs imports, verification and thumbnail creation in the background. Jobs are stored in the
+/// database, so the jobs left unfinished when the app was closed are run again after restart.
+/// At most MAX_RUNNING_JOBS jobs run at the same time, imports run one at a time and relocating
+/// the collection runs alone.
+pub struct JobQueue {
+    jobs: Mutex<Vec<QueuedJob>>,
+}
+
+impl JobQueue {
+    pub fn get_instance() -> &'static Self {
+        lazy_static! {
+            static ref INSTANCE: JobQueue = JobQueue::new();
+        }
+        &INSTANCE
+    }
+
+    fn new() -> Self {
+        let db = DatabaseWithPolo::get_instance();
+        let mut jobs = db.get_jobs().unwrap_or_else(|err| {
+            println!("Failed to get jobs {:?}", err);
+            vec![]
+        });
+        jobs.sort_by_key(|job| job._id);
+        let jobs = jobs
+            .into_iter()
+            .filter(|job| !job.status.is_finished())
+            .map(|mut job| {
+                // jobs that were running when the app was closed are started again
+                job.status = JobStatus::Queued;
+                QueuedJob {
+                    job,
+                    progress: None,
+                    cancellation: Cancellation::default(),
+                }
+            })
+            .collect();
+        let queue = Self {
+            jobs: Mutex::new(jobs),
+        };
+        start_next_jobs(&mut queue.lock());
+        queue
+    }
+
+    fn lock(&self) -> MutexGuard<Vec<QueuedJob>> {
+        self.jobs.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Adds a job to the queue. A job of the same kind that hasn't finished yet is not added again.
+    pub fn submit(&self, kind: JobKind) -> Result<ObjectId, Error> {
+        let mut jobs = self.lock();
+        if let Some(queued_job) = jobs
+            .iter()
+            .find(|queued_job| !queued_job.job.status.is_finished() && queued_job.job.kind == kind)
+        {
+            return Ok(queued_job.job.id());
+        }
+
+        let mut job = Job {
+            _id: None,
+            kind,
+            status: JobStatus::Queued,
+            error: None,
+        };
+        let id = DatabaseWithPolo::get_instance().add_job(&job)?;
+        job._id = Some(id);
+        jobs.push(QueuedJob {
+            job,
+            progress: None,
+            cancellation: Cancellation::default(),
+        });
+        start_next_jobs(&mut jobs);
+        Ok(id)
+    }
+
+    /// Cancels a queued job or asks a running job to stop.
+    pub fn cancel(&self, id: &ObjectId) -> Result<(), Error> {
+        let mut jobs = self.lock();
+        if let Some(queued_job) = jobs
+            .iter_mut()
+            .find(|queued_job| queued_job.job.id() == *id)
+        {
+            match queued_job.job.status {
+                JobStatus::Queued => {
+                    DatabaseWithPolo::get_instance().update_job_status(
+                        id,
+                        &JobStatus::Cancelled,
+                        None,
+                    )?;
+                    queued_job.job.status = JobStatus::Cancelled;
+                }
+                JobStatus::Running => queued_job.cancellation.cancel(),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_jobs(&self) -> Vec<QueuedJob> {
+        self.lock().clone()
+    }
+
+    pub fn has_unfinished_job(&self, predicate: impl Fn(&JobKind) -> bool) -> bool {
+        self.lock().iter().any(|queued_job| {
+            !queued_job.job.status.is_finished() && predicate(&queued_job.job.kind)
+        })
+    }
+
+    /// Removes the completed, failed and cancelled jobs from the queue and the database.
+    pub fn clear_finished(&self) -> Result<(), Error> {
+        let mut jobs = self.lock();
+        DatabaseWithPolo::get_instance().delete_finished_jobs()?;
+        jobs.retain(|queued_job| !queued_job.job.status.is_finished());
+        Ok(())
+    }
+
+    /// Stores the progress of a running job. When the job has finished its status is saved,
+    /// the next queued jobs are started and the finished job is returned.
+    pub fn handle_event(&self, id: &ObjectId, event: FileOperationEvent<()>) -> Option<Job> {
+        let mut jobs = self.lock();
+        let queued_job = jobs
+            .iter_mut()
+            .find(|queued_job| queued_job.job.id() == *id)?;
+        match event {
+            FileOperationEvent::Progress(progress) => {
+                queued_job.progress = Some(progress);
+                None
+            }
+            FileOperationEvent::Finished(result) => {
+                let (status, error) = match result {
+                    Ok(()) => (JobStatus::Completed, None),
+                    Err(Error::Cancelled) => (JobStatus::Cancelled, None),
+                    Err(err) => (JobStatus::Failed, Some(err.to_string())),
+                };
+                if let Err(err) = DatabaseWithPolo::get_instance().update_job_status(
+                    id,
+                    &status,
+                    error.as_deref(),
+                ) {
+                    println!("Failed to save status of job {}: {:?}", id, err);
+                }
+                queued_job.job.status = status;
+                queued_job.job.error = error;
+                queued_job.progress = None;
+                let job = queued_job.job.clone();
+                start_next_jobs(&mut jobs);
+                Some(job)
+            }
+        }
+    }
+
+    /// Submits a thumbnail job for each image file that doesn't have a thumbnail yet.
+    pub fn submit_missing_thumbnails(
+        &self,
+        collection_files: &[CollectionFile],
+        settings: &Settings,
+        system: &System,
+    ) {
+        for collection_file in collection_files
+            .iter()
+            .filter(|collection_file| is_image(collection_file))
+        {
+            let (Some(collection_file_id), Some(system_id)) = (collection_file._id, system._id)
+            else {
+                continue;
+            };
+            let kind = JobKind::CreateThumbnail {
+                collection_file_id,
+                system_id,
+            };
+            // a failed thumbnail job is not retried until the finished jobs have been cleared
+            if get_thumbnail_path(collection_file, settings).exists()
+                || self
+                    .lock()
+                    .iter()
+                    .any(|queued_job| queued_job.job.kind == kind)
+            {
+                continue;
+            }
+            if let Err(err) = self.submit(kind) {
+                println!("Failed to submit thumbnail job {:?}", err);
+            }
+        }
+    }
+
+    /// Each running job runs in its own subscription that reports the progress of the job.
+    pub fn subscription(&self) -> Subscription<(ObjectId, FileOperationEvent<()>)> {
+        Subscription::batch(
+            self.lock()
+                .iter()
+                .filter(|queued_job| queued_job.job.status == JobStatus::Running)
+                .map(|queued_job| {
+                    let id = queued_job.job.id();
+                    let kind = queued_job.job.kind.clone();
+                    Subscription::run_with_id(
+                        id,
+                        run_file_operation(queued_job.cancellation.clone(), move |reporter| {
+                            run_job(kind, reporter)
+                        }),
+                    )
+                    .with(id)
+                }),
+        )
+    }
+}
+
+fn start_next_jobs(jobs: &mut [QueuedJob]) {
+    let job_list = jobs
+        .iter()
+        .map(|queued_job| queued_job.job.clone())
+        .collect::<Vec<Job>>();
+    for index in get_jobs_to_start(&job_list) {
+        let queued_job = &mut jobs[index];
+        if let Err(err) = DatabaseWithPolo::get_instance().update_job_status(
+            &queued_job.job.id(),
+            &JobStatus::Running,
+            None,
+        ) {
+            println!("Failed to save status of job {:?}", err);
+        }
+        queued_job.job.status = JobStatus::Running;
+    }
+}
+
+/// Returns the indices of the queued jobs that can be started, in the order they were submitted.
+fn get_jobs_to_start(jobs: &[Job]) -> Vec<usize> {
+    let running_jobs = jobs
+        .iter()
+        .filter(|job| job.status == JobStatus::Running)
+        .collect::<Vec<&Job>>();
+    let mut running_count = running_jobs.len();
+    let mut import_running = running_jobs.iter().any(|job| job.kind.is_import());
+    let mut jobs_to_start = Vec::new();
+    if running_jobs.iter().any(|job| job.kind.runs_alone()) {
+        return jobs_to_start;
+    }
+    for (index, job) in jobs.iter().enumerate() {
+        if running_count >= MAX_RUNNING_JOBS {
+            break;
+        }
+        if job.status != JobStatus::Queued || (job.kind.is_import() && import_running) {
+            continue;
+        }
+        // jobs submitted after a job running alone wait for it
+        if job.kind.runs_alone() {
+            if running_count == 0 {
+                jobs_to_start.push(index);
+            }
+            break;
+        }
+        import_running |= job.kind.is_import();
+        running_count += 1;
+        jobs_to_start.push(index);
+    }
+    jobs_to_start
+}
+
+fn is_image(collection_file: &CollectionFile) -> bool {
+    matches!(
+        collection_file.collection_file_type,
+        CollectionFileType::CoverScan | CollectionFileType::Screenshot
+    )
+}
+
+fn get_system(db: &DatabaseWithPolo, system_id: &ObjectId) -> Result<System, Error> {
+    db.get_system(system_id)?
+        .ok_or_else(|| Error::NotFound(format!("System with id {} not found", system_id)))
+}
+
+async fn run_job(kind: JobKind, reporter: ProgressReporter) -> Result<(), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    match kind {
+        JobKind::ImportFile {
+            file_path,
+            collection_file,
+            system_id,
+            release_id,
+            file_import_mode,
+        } => {
+            let system = get_system(db, &system_id)?;
+            let settings = db.get_settings()?;
+            let release = db.get_release(&release_id)?.ok_or_else(|| {
+                Error::NotFound(format!("Release with id {} not found", release_id))
+            })?;
+            // the file was imported before the app was closed while the job was running
+            let is_imported = match collection_file._id {
+                Some(collection_file_id) => !db
+                    .get_collection_files(&vec![collection_file_id])?
+                    .is_empty(),
+                None => false,
+            };
+            if is_imported {
+                println!(
+                    "{} is already imported to {}",
+                    collection_file.original_file_name, release.name
+                );
+                return Ok(());
+            }
+            let file_path_builder = FilePathBuilder::from_settings(&settings);
+            let target = ImportTarget {
+                file_path_builder: &file_path_builder,
+                system: &system,
+                release_name: &release.name,
+                release_id: Some(release_id),
+            };
+            let collection_file = import_collection_file(
+                file_path,
+                *collection_file,
+                &target,
+                file_import_mode,
+                &reporter,
+            )
+            .await?;
+            if is_image(&collection_file) {
+                JobQueue::get_instance().submit(JobKind::CreateThumbnail {
+                    collection_file_id: collection_file.id(),
+                    system_id,
+                })?;
+            }
+            Ok(())
+        }
+        JobKind::ImportRelease {
+            proposal,
+            release_id,
+            collection_file_ids,
+            system_id,
+            file_import_mode,
+        } => {
+            let system = get_system(db, &system_id)?;
+            let settings = db.get_settings()?;
+            import_proposal(
+                proposal,
+                release_id,
+                collection_file_ids,
+                system,
+                FilePathBuilder::from_settings(&settings),
+                file_import_mode,
+                reporter,
+            )
+            .await
+            .map(|_| ())
+        }
+        JobKind::VerifyCollection => run_integrity_check(reporter).await,
+        JobKind::RelocateCollection {
+            old_root_dir,
+            new_root_dir,
+            remove_old_files,
+        } => relocate_collection(old_root_dir, new_root_dir, remove_old_files, reporter).await,
+        JobKind::MigrateLayout => migrate_layout(reporter).await,
+        JobKind::ConvertToContentStore => convert_to_content_store(reporter).await,
+        JobKind::OptimizeStorage { optimization } => optimize_storage(optimization, reporter).await,
+        JobKind::CreateThumbnail {
+            collection_file_id,
+            system_id,
+        } => {
+            let system = get_system(db, &system_id)?;
+            let settings = db.get_settings()?;
+            let collection_file = db
+                .get_collection_files(&vec![collection_file_id])?
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    Error::NotFound(format!(
+                        "Collection file with id {} not found",
+                        collection_file_id
+                    ))
+                })?;
+            spawn_blocking(move || create_thumbnail(&collection_file, &settings, &system))
+                .await
+                .map(|_| ())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::bulk_import::ImportProposal;
+
+    fn create_job(kind: JobKind, status: JobStatus) -> Job {
+        Job {
+            _id: Some(ObjectId::new()),
+            kind,
+            status,
+            error: None,
+        }
+    }
+
+    fn create_import_job(status: JobStatus) -> Job {
+        create_job(
+            JobKind::ImportRelease {
+                proposal: ImportProposal {
+                    game_name: "Game".to_string(),
+                    release_name: "Game".to_string(),
+                    files: vec![],
+                    accepted: true,
+                },
+                release_id: ObjectId::new(),
+                collection_file_ids: vec![],
+                system_id: ObjectId::new(),
+                file_import_mode: Default::default(),
+            },
+            status,
+        )
+    }
+
+    #[test]
+    fn test_get_jobs_to_start() {
+        let jobs = vec![
+            create_job(JobKind::VerifyCollection, JobStatus::Completed),
+            create_import_job(JobStatus::Queued),
+            create_import_job(JobStatus::Queued),
+            create_job(JobKind::VerifyCollection, JobStatus::Queued),
+            create_job(JobKind::VerifyCollection, JobStatus::Queued),
+        ];
+        // imports run one at a time
+        assert_eq!(get_jobs_to_start(&jobs), vec![1, 3]);
+
+        let jobs = vec![
+            create_import_job(JobStatus::Running),
+            create_import_job(JobStatus::Queued),
+            create_job(JobKind::VerifyCollection, JobStatus::Running),
+            create_job(JobKind::VerifyCollection, JobStatus::Queued),
+        ];
+        assert_eq!(get_jobs_to_start(&jobs), Vec::<usize>::new());
+
+        let relocate_job = create_job(
+            JobKind::RelocateCollection {
+                old_root_dir: "/old".to_string(),
+                new_root_dir: "/new".to_string(),
+                remove_old_files: false,
+            },
+            JobStatus::Queued,
+        );
+        let jobs = vec![
+            create_job(JobKind::VerifyCollection, JobStatus::Running),
+            relocate_job.clone(),
+            create_job(JobKind::VerifyCollection, JobStatus::Queued),
+        ];
+        // relocating waits for the running jobs and the later jobs wait for relocating
+        assert_eq!(get_jobs_to_start(&jobs), Vec::<usize>::new());
+        let jobs = vec![
+            relocate_job,
+            create_job(JobKind::VerifyCollection, JobStatus::Queued),
+        ];
+        assert_eq!(get_jobs_to_start(&jobs), vec![0]);
+    }
+}
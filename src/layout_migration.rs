@@ -0,0 +1,130 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{create_dir_all, remove_dir, rename},
+};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    model::{
+        collection_file::CollectionFile,
+        model::{HasOid, System},
+    },
+    repository::repository::CollectionFilesReadRepository,
+    util::{file_operation::ProgressReporter, file_path_builder::FilePathBuilder},
+};
+
+const DESCRIPTION: &str = "Migrating files to the new layout";
+
+/// Renames the files stored by id to the layout of the file path template. The new path is saved
+/// to the database before the file is renamed, and a file not found in its new path is still
+/// found in the old one, so an interrupted migration leaves every file reachable and it finishes
+/// the renames when it's run again. Adopted files stay where they are.
+pub async fn migrate_layout(reporter: ProgressReporter) -> Result<(), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let settings = db.get_settings()?;
+    if settings.file_path_template.is_none() {
+        return Err(Error::NotFound("File path template is not set".to_string()));
+    }
+    let file_path_builder = FilePathBuilder::from_settings(&settings);
+    let systems = db
+        .get_systems()?
+        .into_iter()
+        .filter_map(|system| system._id.map(|id| (id, system)))
+        .collect::<HashMap<ObjectId, System>>();
+
+    // each file is migrated once, with the name of the first release it belongs to
+    let mut seen_file_ids = HashSet::new();
+    let mut files_to_migrate = Vec::new();
+    for release in db.get_releases()? {
+        let Some(system) = release.system_id.and_then(|id| systems.get(&id)) else {
+            continue;
+        };
+        for collection_file in db.get_collection_files(&release.files)? {
+            if collection_file
+                ._id
+                .is_some_and(|id| seen_file_ids.insert(id))
+            {
+                files_to_migrate.push((collection_file, system.clone(), release.name.clone()));
+            }
+        }
+    }
+
+    let total_bytes = files_to_migrate
+        .iter()
+        .map(|(collection_file, _, _)| collection_file.size)
+        .sum();
+    let mut bytes_done = 0;
+    for (collection_file, system, release_name) in files_to_migrate {
+        reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+        bytes_done += collection_file.size;
+        let file_path_builder = file_path_builder.clone();
+        spawn_blocking(move || {
+            migrate_file(
+                db,
+                &file_path_builder,
+                &system,
+                &release_name,
+                collection_file,
+            )
+        })
+        .await?;
+    }
+    reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+    Ok(())
+}
+
+fn migrate_file(
+    db: &DatabaseWithPolo,
+    file_path_builder: &FilePathBuilder,
+    system: &System,
+    release_name: &str,
+    mut collection_file: CollectionFile,
+) -> Result<(), Error> {
+    // files in the content store are stored by their content
+    if collection_file.content_key.is_some() {
+        return Ok(());
+    }
+    let id_based_path = file_path_builder.build_id_based_file_path(system, &collection_file)?;
+    // missing files are left to be reported by the integrity check
+    if !id_based_path.exists() {
+        return Ok(());
+    }
+    let relative_path = match &collection_file.relative_path {
+        // the migration was interrupted after the new path was saved
+        Some(relative_path) => relative_path.clone(),
+        None => {
+            let relative_path = file_path_builder
+                .build_new_relative_path(system, release_name, &collection_file)
+                .expect("File path template is set");
+            collection_file.relative_path = Some(relative_path.clone());
+            db.update_collection_file_relative_path(&collection_file.id(), &relative_path)?;
+            relative_path
+        }
+    };
+
+    let new_path = file_path_builder
+        .get_volume_root(collection_file.volume.as_deref())?
+        .join(relative_path);
+    if let Some(parent) = new_path.parent() {
+        create_dir_all(parent)
+            .map_err(|e| Error::IoError(format!("Failed creating {:?}: {}", parent, e)))?;
+    }
+    rename(&id_based_path, &new_path).map_err(|e| {
+        Error::IoError(format!(
+            "Failed renaming {:?} to {:?}: {}",
+            id_based_path, new_path, e
+        ))
+    })?;
+
+    // the type and system directories of the id based layout are removed once empty
+    for directory in id_based_path.ancestors().skip(1).take(2) {
+        if remove_dir(directory).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,20 @@
+mod collection_relocator;
+mod collection_watcher;
+mod content_store;
 mod database_with_polo;
+mod emulator_detection;
 mod emulator_runner;
 mod error;
+mod file_importer;
 mod files;
+mod integrity_checker;
+mod job_queue;
+mod layout_migration;
 mod model;
+mod notifications;
 mod repository;
 mod screen;
+mod storage_optimizer;
 mod tabs;
 mod title_bar;
 mod util;
@@ -13,11 +23,13 @@ mod view_model;
 use std::ops::ControlFlow;
 
 use bson::oid::ObjectId;
-use emulator_runner::{process_files_for_emulator, run_with_emulator_async};
-use error::Error;
+use collection_watcher::CollectionChange;
 use iced::widget::{column, text};
-use iced::{exit, Task};
+use iced::{exit, Subscription, Task};
+use job_queue::JobQueue;
+use notifications::Notifications;
 use screen::add_release_main;
+use screen::bulk_import;
 use screen::error as error_screen;
 use screen::games_main;
 use screen::home;
@@ -26,7 +38,9 @@ use screen::manage_games;
 use screen::manage_systems;
 use screen::settings_main;
 use tabs::tabs_controller::{self, Tab, TabsController};
+use tabs::widgets::emulator_launcher_widget::{self, EmulatorLauncher};
 use title_bar::TitleBar;
+use util::file_operation::FileOperationEvent;
 
 use crate::screen::Screen;
 
@@ -36,6 +50,7 @@ fn main() -> iced::Result {
         IcedGameCollection::update,
         IcedGameCollection::view,
     )
+    .subscription(IcedGameCollection::subscription)
     .run_with(IcedGameCollection::new)
 }
 
@@ -43,6 +58,8 @@ struct IcedGameCollection {
     screen: Screen,
     title_bar: TitleBar,
     tabs_controller: TabsController,
+    emulator_launcher: EmulatorLauncher,
+    notifications: Notifications,
 }
 
 #[derive(Debug, Clone)]
@@ -53,15 +70,20 @@ enum Message {
     ManageEmulators(manage_emulators::Message),
     AddReleaseMain(add_release_main::Message),
     GamesMain(games_main::Message),
-    FinishedRunningWithEmulator(Result<(), Error>),
+    EmulatorLauncher(emulator_launcher_widget::Message),
     Error(error_screen::Message),
     SettingsMain(settings_main::Message),
+    BulkImport(bulk_import::Message),
     TitleBar(title_bar::Message),
     TabsController(tabs::tabs_controller::Message),
+    JobEvent(ObjectId, FileOperationEvent<()>),
+    CollectionChanged(CollectionChange),
+    Notifications(notifications::Message),
 }
 
 impl IcedGameCollection {
     pub fn new() -> (Self, Task<Message>) {
+        emulator_runner::remove_launch_directories();
         let home_screen = match home::Home::new() {
             Ok(screen) => Screen::Home(screen),
             Err(e) => Screen::Error(error_screen::Error::new(e)),
@@ -75,6 +97,8 @@ impl IcedGameCollection {
                     screen: home_screen,
                     title_bar: TitleBar::new(),
                     tabs_controller,
+                    emulator_launcher: EmulatorLauncher::new(),
+                    notifications: Notifications::new(),
                 },
                 Task::none(),
             )
@@ -93,6 +117,7 @@ impl IcedGameCollection {
             Screen::ManageEmulators(add_emulator) => add_emulator.title(),
             Screen::Error(error) => error.title(),
             Screen::SettingsMain(settings_main) => settings_main.title(),
+            Screen::BulkImport(bulk_import) => bulk_import.title(),
         }
     }
 
@@ -104,16 +129,47 @@ impl IcedGameCollection {
             Message::AddReleaseMain(message) => self.update_add_release(message),
             Message::GamesMain(message) => self.update_games_main(message),
             Message::ManageEmulators(message) => self.update_manage_emulators(message),
-            Message::FinishedRunningWithEmulator(result) => {
-                self.update_finished_running_emulator(result)
-            }
+            Message::EmulatorLauncher(message) => self
+                .emulator_launcher
+                .update(message)
+                .map(Message::EmulatorLauncher),
             Message::Error(message) => self.update_error(message),
             Message::SettingsMain(message) => self.update_settings_main(message),
+            Message::BulkImport(message) => self.update_bulk_import(message),
             Message::TitleBar(message) => self.update_title_bar(message),
             Message::TabsController(message) => self.update_tabs_controller(message),
+            Message::JobEvent(id, event) => self.update_job_event(id, event),
+            Message::CollectionChanged(change) => {
+                self.notifications.add(change);
+                self.tabs_controller.collection_changed();
+                Task::none()
+            }
+            Message::Notifications(message) => self.update_notifications(message),
         }
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        let screen_subscription = match &self.screen {
+            Screen::AddReleaseMain(add_release_main) => {
+                add_release_main.subscription().map(Message::AddReleaseMain)
+            }
+            _ => Subscription::none(),
+        };
+        Subscription::batch([
+            screen_subscription,
+            self.emulator_launcher
+                .subscription()
+                .map(Message::EmulatorLauncher),
+            self.tabs_controller
+                .subscription()
+                .map(Message::TabsController),
+            JobQueue::get_instance()
+                .subscription()
+                .map(|(id, event)| Message::JobEvent(id, event)),
+            collection_watcher::subscription().map(Message::CollectionChanged),
+        ])
+    }
+
     fn view(&self) -> iced::Element<Message> {
         let view = match &self.screen {
             Screen::Home(home) => home.view().map(Message::Home),
@@ -128,11 +184,48 @@ impl IcedGameCollection {
             }
             Screen::Error(error) => error.view().map(Message::Error),
             Screen::SettingsMain(settings_main) => settings_main.view().map(Message::SettingsMain),
+            Screen::BulkImport(bulk_import) => bulk_import.view().map(Message::BulkImport),
         };
 
         let tab_view = self.tabs_controller.view().map(Message::TabsController);
 
-        column![self.title_bar.view().map(Message::TitleBar), view, tab_view].into()
+        column![
+            self.title_bar.view().map(Message::TitleBar),
+            self.notifications.view().map(Message::Notifications),
+            view,
+            self.emulator_launcher.view().map(Message::EmulatorLauncher),
+            tab_view
+        ]
+        .into()
+    }
+
+    fn update_bulk_import(&mut self, message: bulk_import::Message) -> Task<Message> {
+        if let Screen::BulkImport(bulk_import) = &mut self.screen {
+            match bulk_import.update(message) {
+                bulk_import::Action::GoHome => self.try_create_home_screen(),
+                bulk_import::Action::None => Task::none(),
+                bulk_import::Action::Run(task) => task.map(Message::BulkImport),
+            }
+        } else {
+            Task::none()
+        }
+    }
+
+    fn update_notifications(&mut self, message: notifications::Message) -> Task<Message> {
+        match self.notifications.update(message) {
+            notifications::Action::None => Task::none(),
+            notifications::Action::AdoptFiles(file_paths) => match screen::BulkImport::new() {
+                Ok(mut bulk_import) => {
+                    let task = bulk_import.adopt_files(file_paths);
+                    self.screen = Screen::BulkImport(bulk_import);
+                    task.map(Message::BulkImport)
+                }
+                Err(e) => {
+                    self.screen = Screen::Error(screen::Error::new(e));
+                    Task::none()
+                }
+            },
+        }
     }
 
     fn update_settings_main(&mut self, message: settings_main::Message) -> Task<Message> {
@@ -153,6 +246,19 @@ impl IcedGameCollection {
             .map(Message::TabsController)
     }
 
+    fn update_job_event(&mut self, id: ObjectId, event: FileOperationEvent<()>) -> Task<Message> {
+        let finished_job = JobQueue::get_instance().handle_event(&id, event);
+        if let (Some(job), Screen::AddReleaseMain(add_release_main)) =
+            (&finished_job, &mut self.screen)
+        {
+            if let Err(e) = add_release_main.job_finished(job) {
+                self.screen = Screen::Error(screen::Error::new(e));
+            }
+        }
+        self.tabs_controller.jobs_updated(finished_job.as_ref());
+        Task::none()
+    }
+
     fn update_title_bar(&mut self, message: title_bar::Message) -> Task<Message> {
         self.title_bar.update(message.clone());
         match message {
@@ -178,6 +284,7 @@ impl IcedGameCollection {
                     self.screen = Screen::Error(screen::Error::new(error));
                     Task::none()
                 }
+                manage_systems::Action::Run(task) => task.map(Message::ManageSystems),
             }
         } else {
             Task::none()
@@ -242,6 +349,17 @@ impl IcedGameCollection {
                     }
                     Task::none()
                 }
+                home::Action::BulkImport => {
+                    match screen::BulkImport::new() {
+                        Ok(screen) => {
+                            self.screen = Screen::BulkImport(screen);
+                        }
+                        Err(e) => {
+                            self.screen = Screen::Error(screen::Error::new(e));
+                        }
+                    }
+                    Task::none()
+                }
                 home::Action::Exit => exit(),
                 home::Action::ManageEmulators => {
                     let screen = screen::ManageEmulators::new(None);
@@ -295,19 +413,10 @@ impl IcedGameCollection {
         if let Screen::GamesMain(games_main) = &mut self.screen {
             match games_main.update(message) {
                 games_main::Action::Back => self.try_create_home_screen(),
-                games_main::Action::RunWithEmulator(options) => {
-                    match process_files_for_emulator(&options) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            self.screen = Screen::Error(screen::Error::new(e));
-                            return Task::none();
-                        }
-                    }
-                    Task::perform(
-                        run_with_emulator_async(options),
-                        Message::FinishedRunningWithEmulator,
-                    )
-                }
+                games_main::Action::RunWithEmulator(options) => self
+                    .emulator_launcher
+                    .update(emulator_launcher_widget::Message::Launch(Box::new(options)))
+                    .map(Message::EmulatorLauncher),
                 games_main::Action::Run(task) => task.map(Message::GamesMain),
                 games_main::Action::None => Task::none(),
                 games_main::Action::Error(error) => {
@@ -354,6 +463,7 @@ impl IcedGameCollection {
                     self.screen = Screen::Error(screen::Error::new(error));
                     Task::none()
                 }
+                manage_emulators::Action::Run(task) => task.map(Message::ManageEmulators),
             }
         } else {
             Task::none()
@@ -381,15 +491,4 @@ impl IcedGameCollection {
             Task::none()
         }
     }
-
-    fn update_finished_running_emulator(&mut self, result: Result<(), Error>) -> Task<Message> {
-        match result {
-            Ok(()) => {
-                println!("Finished running with emulator");
-            }
-            Err(_) => println!("Failed to run with emulator"),
-        }
-        // TODO: clean up temporary files
-        Task::none()
-    }
 }
@@ -18,9 +18,28 @@ pub enum CollectionFileType {
     Manual,
     CoverScan,
     MemorySnapshot,
+    // IPS, BPS or UPS patch applied to its base file when the release is run
+    Patch,
 }
 
 impl CollectionFileType {
+    /// File types an emulator can be started with.
+    pub const RUNNABLE_FILE_TYPES: [CollectionFileType; 4] = [
+        CollectionFileType::Rom,
+        CollectionFileType::DiskImage,
+        CollectionFileType::TapeImage,
+        CollectionFileType::MemorySnapshot,
+    ];
+
+    /// Whether the file is a medium of the game itself rather than a scan, a manual or a
+    /// snapshot.
+    pub fn is_media(&self) -> bool {
+        matches!(
+            self,
+            CollectionFileType::Rom | CollectionFileType::DiskImage | CollectionFileType::TapeImage
+        )
+    }
+
     pub fn directory(&self) -> &str {
         match self {
             CollectionFileType::Rom => "roms",
@@ -30,6 +49,7 @@ impl CollectionFileType {
             CollectionFileType::Manual => "manuals",
             CollectionFileType::CoverScan => "cover_scans",
             CollectionFileType::MemorySnapshot => "memory_snapshots",
+            CollectionFileType::Patch => "patches",
         }
     }
 }
@@ -44,6 +64,7 @@ impl ToString for CollectionFileType {
             CollectionFileType::Manual => "Manual".to_string(),
             CollectionFileType::CoverScan => "Cover Scan".to_string(),
             CollectionFileType::MemorySnapshot => "Memory Snapshot".to_string(),
+            CollectionFileType::Patch => "Patch".to_string(),
         }
     }
 }
@@ -54,10 +75,58 @@ impl HasOid for CollectionFile {
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Checksums {
+    pub crc32: String,
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl Checksums {
+    pub fn is_empty(&self) -> bool {
+        self.crc32.is_empty()
+            && self.md5.is_empty()
+            && self.sha1.is_empty()
+            && self.sha256.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(from = "StoredFileInfo")]
 pub struct FileInfo {
     pub name: String,
-    pub checksum: String,
+    pub size: u64,
+    pub checksums: Checksums,
+}
+
+// FileInfo as stored in the database. Files added before size and checksums were calculated at
+// import have only the SHA1 checksum in the checksum field.
+#[derive(Deserialize)]
+struct StoredFileInfo {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    checksums: Checksums,
+    #[serde(default)]
+    checksum: Option<String>,
+}
+
+impl From<StoredFileInfo> for FileInfo {
+    fn from(stored: StoredFileInfo) -> Self {
+        let mut checksums = stored.checksums;
+        if checksums.sha1.is_empty() {
+            if let Some(checksum) = stored.checksum {
+                checksums.sha1 = checksum;
+            }
+        }
+        FileInfo {
+            name: stored.name,
+            size: stored.size,
+            checksums,
+        }
+    }
 }
 
 pub trait GetFileExtensions {
@@ -81,6 +150,35 @@ pub struct CollectionFile {
     pub is_zip: bool,
     pub files: Option<Vec<FileInfo>>,
     pub collection_file_type: CollectionFileType,
+    #[serde(default)]
+    pub size: u64,
+    // checksums of the collection file itself, for a zip file these are the checksums of the archive
+    #[serde(default)]
+    pub checksums: Checksums,
+    // path relative to the collection root for a file adopted where it already was in the collection,
+    // other files are stored by id in the directory of the system and file type
+    #[serde(default)]
+    pub relative_path: Option<String>,
+    // name of the storage volume holding the file, None for the collection root dir
+    #[serde(default)]
+    pub volume: Option<String>,
+    // key of the shared content in the content store, see ContentObject
+    #[serde(default)]
+    pub content_key: Option<String>,
+    // for a patch, the file the patch is applied to
+    #[serde(default)]
+    pub base_file_id: Option<ObjectId>,
+}
+
+/// Content in the content store, shared by the collection files with identical content.
+/// The content file is removed when the last collection file referencing it is removed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContentObject {
+    pub _id: Option<ObjectId>,
+    // SHA256 checksum and extension of the content, also the name of the file in the store
+    pub key: String,
+    pub volume: Option<String>,
+    pub reference_count: u32,
 }
 
 impl GetFileExtensions for CollectionFile {
@@ -130,6 +228,44 @@ impl Into<Bson> for CollectionFile {
     }
 }
 
+#[cfg(test)]
+impl CollectionFile {
+    /// Creates a collection file with a new id for tests, the other fields are empty.
+    pub fn for_test(original_file_name: &str, collection_file_type: CollectionFileType) -> Self {
+        CollectionFile {
+            _id: Some(ObjectId::new()),
+            original_file_name: original_file_name.to_string(),
+            is_zip: false,
+            files: None,
+            collection_file_type,
+            size: 0,
+            checksums: Checksums::default(),
+            relative_path: None,
+            volume: None,
+            content_key: None,
+            base_file_id: None,
+        }
+    }
+
+    /// Makes the file a zip archive with the named entries.
+    pub fn with_entries(self, entries: &[&str]) -> Self {
+        CollectionFile {
+            is_zip: true,
+            files: Some(
+                entries
+                    .iter()
+                    .map(|entry| FileInfo {
+                        name: entry.to_string(),
+                        size: 0,
+                        checksums: Checksums::default(),
+                    })
+                    .collect(),
+            ),
+            ..self
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -137,16 +273,8 @@ mod tests {
 
     #[test]
     fn test_get_file_extensions() {
-        let collection_file = CollectionFile {
-            original_file_name: "game.zip".to_string(),
-            _id: Some(ObjectId::new()),
-            is_zip: true,
-            files: Some(vec![FileInfo {
-                name: "game.rom".to_string(),
-                checksum: "checksum".to_string(),
-            }]),
-            collection_file_type: CollectionFileType::Rom,
-        };
+        let collection_file = CollectionFile::for_test("game.zip", CollectionFileType::Rom)
+            .with_entries(&["game.rom"]);
 
         let extensions = collection_file.get_file_extensions();
         assert_eq!(extensions, vec!["rom".to_string()]);
@@ -155,11 +283,8 @@ mod tests {
     #[test]
     fn test_get_collection_file_name() {
         let collection_file = CollectionFile {
-            original_file_name: "game.zip".to_string(),
-            _id: Some(ObjectId::new()),
             is_zip: true,
-            files: None,
-            collection_file_type: CollectionFileType::Rom,
+            ..CollectionFile::for_test("game.zip", CollectionFileType::Rom)
         };
 
         let file_name = collection_file.get_collection_file_name();
@@ -168,4 +293,27 @@ mod tests {
             format!("{}.zip", &collection_file.get_id_string())
         );
     }
+
+    #[test]
+    fn test_deserialize_file_info_with_legacy_checksum() {
+        let file_info: FileInfo = bson::from_document(bson::doc! {
+            "name": "game.rom",
+            "checksum": "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3",
+        })
+        .unwrap();
+        assert_eq!(file_info.size, 0);
+        assert_eq!(
+            file_info.checksums.sha1,
+            "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3"
+        );
+        assert!(file_info.checksums.crc32.is_empty());
+
+        let serialized = bson::to_document(&file_info).unwrap();
+        assert!(!serialized.contains_key("checksum"));
+        let file_info: FileInfo = bson::from_document(serialized).unwrap();
+        assert_eq!(
+            file_info.checksums.sha1,
+            "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3"
+        );
+    }
 }
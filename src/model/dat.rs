@@ -0,0 +1,92 @@
+use std::fmt::{self, Display, Formatter};
+
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use super::model::HasOid;
+
+/// Dump status of a rom as given in the status attribute of a Logiqx DAT file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum DumpStatus {
+    Good,
+    Verified,
+    BadDump,
+    NoDump,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DatRom {
+    pub name: String,
+    pub size: Option<u64>,
+    pub crc32: Option<String>,
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub status: DumpStatus,
+}
+
+/// A game (or machine) entry in a DAT file, stored separately from the DAT header.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DatGame {
+    pub _id: Option<ObjectId>,
+    pub dat_file_id: Option<ObjectId>,
+    pub system_id: Option<ObjectId>,
+    pub name: String,
+    pub description: Option<String>,
+    pub roms: Vec<DatRom>,
+}
+
+/// Header of an imported DAT file (No-Intro, TOSEC, Redump etc. in Logiqx format).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DatFile {
+    pub _id: Option<ObjectId>,
+    pub system_id: Option<ObjectId>,
+    pub name: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+}
+
+impl HasOid for DatFile {
+    fn id(&self) -> ObjectId {
+        self._id.expect("Object id not set")
+    }
+}
+
+impl HasOid for DatGame {
+    fn id(&self) -> ObjectId {
+        self._id.expect("Object id not set")
+    }
+}
+
+impl Display for DatFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} ({})", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Result of matching a file against the DAT entries of its system.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationStatus {
+    Verified,
+    BadDump,
+    Unknown,
+}
+
+impl Display for VerificationStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationStatus::Verified => write!(f, "Verified"),
+            VerificationStatus::BadDump => write!(f, "Bad dump"),
+            VerificationStatus::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+/// Have / missing report of the collection against the DAT entries of a system.
+#[derive(Debug, Clone, Default)]
+pub struct DatReport {
+    pub have: Vec<String>,
+    pub missing: Vec<String>,
+}
@@ -0,0 +1,83 @@
+use std::fmt::{self, Display, Formatter};
+
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    collection_file::CollectionFileType,
+    model::{Emulator, EmulatorKind, SaveDataRule},
+};
+
+/// Settings of a common emulator that an emulator can be created from. The arguments are a
+/// template, see `expand_arguments` for the placeholders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmulatorPreset {
+    // identifies the preset, a user preset with the id of a bundled preset replaces it
+    pub id: String,
+    pub name: String,
+    pub executable: String,
+    #[serde(default)]
+    pub arguments: String,
+    // names of the systems the emulator is for, used for choosing the system of the emulator
+    #[serde(default)]
+    pub system_names: Vec<String>,
+    #[serde(default)]
+    pub supported_file_types: Vec<CollectionFileType>,
+    #[serde(default)]
+    pub supported_file_type_extensions: Vec<String>,
+    #[serde(default)]
+    pub extract_files: bool,
+    #[serde(default)]
+    pub multiple_media: bool,
+    #[serde(default)]
+    pub notes: Option<String>,
+    // directories the emulator is usually installed to, searched in addition to PATH
+    #[serde(default)]
+    pub install_locations: Vec<String>,
+    // argument making the emulator print its version and exit, the version isn't read without
+    #[serde(default)]
+    pub version_argument: Option<String>,
+    #[serde(default)]
+    pub kind: EmulatorKind,
+    #[serde(default)]
+    pub supports_playlists: bool,
+    #[serde(default)]
+    pub save_data_rules: Vec<SaveDataRule>,
+}
+
+impl Display for EmulatorPreset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl EmulatorPreset {
+    /// Whether the preset is for the system, the system names are compared ignoring case.
+    pub fn is_for_system(&self, system_name: &str) -> bool {
+        self.system_names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(system_name.trim()))
+    }
+
+    pub fn to_emulator(&self, system_id: Option<ObjectId>) -> Emulator {
+        Emulator {
+            _id: None,
+            name: self.name.clone(),
+            executable: self.executable.clone(),
+            arguments: self.arguments.clone(),
+            system_id,
+            extract_files: self.extract_files,
+            supported_file_type_extensions: self
+                .supported_file_type_extensions
+                .iter()
+                .map(|extension| extension.to_lowercase())
+                .collect(),
+            notes: self.notes.clone(),
+            supported_file_types: self.supported_file_types.clone(),
+            multiple_media: self.multiple_media,
+            kind: self.kind.clone(),
+            supports_playlists: self.supports_playlists,
+            save_data_rules: self.save_data_rules.clone(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt::{self, Display, Formatter};
+
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum IntegrityIssueKind {
+    Missing,
+    Changed,
+    Unreadable,
+}
+
+impl Display for IntegrityIssueKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityIssueKind::Missing => write!(f, "Missing"),
+            IntegrityIssueKind::Changed => write!(f, "Changed"),
+            IntegrityIssueKind::Unreadable => write!(f, "Unreadable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IntegrityIssue {
+    pub collection_file_id: ObjectId,
+    pub file_name: String,
+    pub file_path: String,
+    pub kind: IntegrityIssueKind,
+    pub details: String,
+}
+
+impl Display for IntegrityIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({}) {}",
+            self.kind, self.file_name, self.file_path, self.details
+        )
+    }
+}
+
+/// State of the collection integrity check, persisted after each checked file
+/// so that an interrupted check can be resumed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntegrityCheck {
+    pub checked_file_ids: Vec<ObjectId>,
+    pub issues: Vec<IntegrityIssue>,
+    pub total_files: u64,
+    pub completed: bool,
+}
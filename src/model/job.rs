@@ -0,0 +1,143 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::util::bulk_import::ImportProposal;
+
+use super::{
+    collection_file::CollectionFile,
+    model::{FileImportMode, HasOid, StorageOptimization},
+};
+
+/// Work that is run in the background by the job queue. A job has everything needed to run it
+/// again after restarting the app.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum JobKind {
+    ImportFile {
+        file_path: PathBuf,
+        // the file is added with the id of the collection file, a resumed import whose file
+        // exists is complete
+        collection_file: Box<CollectionFile>,
+        system_id: ObjectId,
+        release_id: ObjectId,
+        file_import_mode: FileImportMode,
+    },
+    ImportRelease {
+        proposal: ImportProposal,
+        // the release is added with this id, a resumed import whose release exists is complete
+        release_id: ObjectId,
+        // the files of the proposal are added with these ids, in the same order, so that a
+        // resumed import finds the files it already imported
+        collection_file_ids: Vec<ObjectId>,
+        system_id: ObjectId,
+        file_import_mode: FileImportMode,
+    },
+    VerifyCollection,
+    CreateThumbnail {
+        collection_file_id: ObjectId,
+        system_id: ObjectId,
+    },
+    RelocateCollection {
+        // the old files are removed from the old root also when the relocation is resumed
+        // after the settings have been changed
+        #[serde(default)]
+        old_root_dir: String,
+        new_root_dir: String,
+        remove_old_files: bool,
+    },
+    MigrateLayout,
+    ConvertToContentStore,
+    OptimizeStorage {
+        optimization: StorageOptimization,
+    },
+}
+
+impl JobKind {
+    /// Imports are run one at a time, so that releases of the same game share the game.
+    pub fn is_import(&self) -> bool {
+        matches!(
+            self,
+            JobKind::ImportFile { .. } | JobKind::ImportRelease { .. }
+        )
+    }
+
+    /// Jobs moving the collection files don't run at the same time with jobs using the files.
+    pub fn runs_alone(&self) -> bool {
+        matches!(
+            self,
+            JobKind::RelocateCollection { .. }
+                | JobKind::MigrateLayout
+                | JobKind::ConvertToContentStore
+                | JobKind::OptimizeStorage { .. }
+        )
+    }
+}
+
+impl Display for JobKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JobKind::ImportFile {
+                collection_file, ..
+            } => write!(f, "Import file {}", collection_file.original_file_name),
+            JobKind::ImportRelease { proposal, .. } => {
+                write!(f, "Import release {}", proposal.release_name)
+            }
+            JobKind::VerifyCollection => write!(f, "Verify collection"),
+            JobKind::CreateThumbnail { .. } => write!(f, "Create thumbnail"),
+            JobKind::RelocateCollection { new_root_dir, .. } => {
+                write!(f, "Move collection to {}", new_root_dir)
+            }
+            JobKind::MigrateLayout => write!(f, "Migrate files to the file path template"),
+            JobKind::ConvertToContentStore => write!(f, "Convert files to the content store"),
+            JobKind::OptimizeStorage { .. } => write!(f, "Optimize storage"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Queued => write!(f, "Queued"),
+            JobStatus::Running => write!(f, "Running"),
+            JobStatus::Completed => write!(f, "Completed"),
+            JobStatus::Failed => write!(f, "Failed"),
+            JobStatus::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Job {
+    pub _id: Option<ObjectId>,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub error: Option<String>,
+}
+
+impl HasOid for Job {
+    fn id(&self) -> ObjectId {
+        self._id.expect("Object id not set")
+    }
+}
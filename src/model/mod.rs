@@ -1,2 +1,6 @@
 pub mod collection_file;
+pub mod dat;
+pub mod emulator_preset;
+pub mod integrity_check;
+pub mod job;
 pub mod model;
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 
 use polodb_core::bson::oid::ObjectId;
 
+use super::collection_file::{CollectionFile, CollectionFileType, GetFileExtensions};
+
 pub trait GetIdString {
     fn get_id_string(&self) -> String;
 }
@@ -13,6 +15,12 @@ pub struct System {
     pub _id: Option<ObjectId>,
     pub name: String,
     pub notes: Option<String>,
+    // emulators of the system in order of preference, the first one is the default emulator
+    #[serde(default)]
+    pub emulator_order: Vec<ObjectId>,
+    // core RetroArch emulators run the releases of the system with
+    #[serde(default)]
+    pub retroarch_core: Option<RetroArchCore>,
 }
 
 impl Display for System {
@@ -21,6 +29,48 @@ impl Display for System {
     }
 }
 
+impl System {
+    /// Returns the emulators of the system in the order of preference. Emulators missing from
+    /// the order come last in their original order.
+    pub fn order_emulators<'a>(&self, emulators: &'a [Emulator]) -> Vec<&'a Emulator> {
+        let mut emulators_for_system = emulators
+            .iter()
+            .filter(|emulator| self._id.is_some() && emulator.system_id == self._id)
+            .collect::<Vec<&Emulator>>();
+        emulators_for_system.sort_by_key(|emulator| {
+            self.emulator_order
+                .iter()
+                .position(|id| Some(*id) == emulator._id)
+                .unwrap_or(usize::MAX)
+        });
+        emulators_for_system
+    }
+
+    /// Returns the emulator at the top of the order, if it's an emulator of the system.
+    pub fn get_default_emulator<'a>(&self, emulators: &'a [Emulator]) -> Option<&'a Emulator> {
+        let default_emulator_id = self.emulator_order.first()?;
+        self.order_emulators(emulators)
+            .into_iter()
+            .find(|emulator| emulator._id == Some(*default_emulator_id))
+    }
+}
+
+/// A libretro core in the RetroArch cores directory with the details read from its info file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetroArchCore {
+    // file name of the core in the cores directory, for example vice_x64sc_libretro.so
+    pub file_name: String,
+    pub display_name: String,
+    // lowercase extensions of the files the core accepts, any extension when empty
+    pub supported_extensions: Vec<String>,
+}
+
+impl Display for RetroArchCore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display_name)
+    }
+}
+
 impl GetIdString for System {
     fn get_id_string(&self) -> String {
         self.id().to_hex()
@@ -35,6 +85,45 @@ pub struct Release {
     pub files: Vec<ObjectId>,
     // Release can be a single game or compilation of games
     pub games: Vec<ObjectId>,
+    // file the release is started from with the Play button
+    #[serde(default)]
+    pub startup_file: Option<StartupFile>,
+    // emulator the Play button runs the release with
+    #[serde(default)]
+    pub emulator_id: Option<ObjectId>,
+    // emulator the release has to be run with, overriding the emulator of the release and
+    // the default emulator of the system
+    #[serde(default)]
+    pub emulator_override: Option<EmulatorOverride>,
+    // media in the order they're inserted, the other media follow ordered by their disk numbers
+    #[serde(default)]
+    pub media: Vec<Medium>,
+}
+
+/// Emulator a release is run with and the arguments added for the release, for example for
+/// choosing the joystick port, PAL or NTSC or true drive emulation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmulatorOverride {
+    pub emulator_id: ObjectId,
+    pub arguments: String,
+}
+
+/// Collection file a release is started from. For an archive the entry inside it is chosen
+/// too, as emulators extracting the files need the name of the file to start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartupFile {
+    pub collection_file_id: ObjectId,
+    pub file_name: Option<String>,
+}
+
+/// A medium of a multi-disk release, for example a disk or a side of a disk. The medium is a
+/// collection file or an entry inside an archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Medium {
+    pub collection_file_id: ObjectId,
+    pub file_name: Option<String>,
+    // for example "Disk 1 Side A", empty when not labeled
+    pub label: String,
 }
 
 impl Display for Release {
@@ -50,9 +139,119 @@ pub struct Emulator {
     pub executable: String,
     pub arguments: String,
     pub system_id: Option<ObjectId>,
+    // archives are extracted and the emulator is started with a file inside the archive
     pub extract_files: bool,
+    // extensions of the files the emulator accepts, any extension when empty
     pub supported_file_type_extensions: Vec<String>,
     pub notes: Option<String>,
+    // types of the files the emulator accepts, the media file types when empty
+    #[serde(default)]
+    pub supported_file_types: Vec<CollectionFileType>,
+    // the emulator is started with all the media of the release, for example all the disks
+    // of a game, not only the startup file
+    #[serde(default)]
+    pub multiple_media: bool,
+    #[serde(default)]
+    pub kind: EmulatorKind,
+    // the emulator is started with an M3U playlist of the media of the release, so the disks can
+    // be swapped in the emulator
+    #[serde(default)]
+    pub supports_playlists: bool,
+    // where the emulator writes the data saved while playing
+    #[serde(default)]
+    pub save_data_rules: Vec<SaveDataRule>,
+}
+
+/// RetroArch emulators aren't bound to a system, they run the releases of every system with a
+/// RetroArch core, using the core of the system.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum EmulatorKind {
+    #[default]
+    Standalone,
+    RetroArch,
+}
+
+/// Where the emulator writes the data saved while playing, relative to the directory the release
+/// is run in. The files are captured as memory snapshots after playing and restored before the
+/// next run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveDataRule {
+    pub kind: SaveDataKind,
+    // file name pattern with `*` and `?` wildcards and the `{name}` placeholder for the name of
+    // the file run without the extension, for example "saves/{name}.srm"
+    pub pattern: String,
+}
+
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SaveDataKind {
+    #[default]
+    SaveState,
+    BatterySave,
+    ModifiedMedia,
+}
+
+impl SaveDataKind {
+    pub const ALL: [SaveDataKind; 3] = [
+        SaveDataKind::SaveState,
+        SaveDataKind::BatterySave,
+        SaveDataKind::ModifiedMedia,
+    ];
+}
+
+impl Display for SaveDataKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveDataKind::SaveState => write!(f, "Save state"),
+            SaveDataKind::BatterySave => write!(f, "Battery save"),
+            SaveDataKind::ModifiedMedia => write!(f, "Modified media"),
+        }
+    }
+}
+
+impl Emulator {
+    pub fn supports_file_type(&self, file_type: &CollectionFileType) -> bool {
+        if self.supported_file_types.is_empty() {
+            file_type.is_media()
+        } else {
+            self.supported_file_types.contains(file_type)
+        }
+    }
+
+    fn supports_extension(&self, file_name: &str) -> bool {
+        self.supported_file_type_extensions.is_empty()
+            || file_name.rsplit_once('.').is_some_and(|(_, extension)| {
+                self.supported_file_type_extensions
+                    .contains(&extension.to_lowercase())
+            })
+    }
+
+    /// Whether the emulator can be started with the file, or with a file inside it when the
+    /// file is an archive.
+    pub fn supports_file(&self, file: &CollectionFile) -> bool {
+        self.supports_file_type(&file.collection_file_type)
+            && (self.supports_extension(&file.original_file_name)
+                || file
+                    .get_file_extensions()
+                    .iter()
+                    .any(|extension| self.supported_file_type_extensions.contains(extension)))
+    }
+
+    /// Returns the names of the files the emulator can be started with from the file: the
+    /// accepted files inside an archive when the archives are extracted, otherwise the file
+    /// itself.
+    pub fn get_startup_file_names(&self, file: &CollectionFile) -> Vec<String> {
+        if !self.supports_file(file) {
+            return vec![];
+        }
+        match (&file.files, self.extract_files) {
+            (Some(files), true) => files
+                .iter()
+                .filter(|file_info| self.supports_extension(&file_info.name))
+                .map(|file_info| file_info.name.clone())
+                .collect(),
+            _ => vec![file.original_file_name.clone()],
+        }
+    }
 }
 
 impl HasOid for Game {
@@ -110,10 +309,115 @@ pub enum FolderType {
     Destination,
 }
 
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FileImportMode {
+    #[default]
+    Copy,
+    Move,
+    HardLink,
+}
+
+impl Display for FileImportMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FileImportMode::Copy => write!(f, "Copy"),
+            FileImportMode::Move => write!(f, "Move"),
+            FileImportMode::HardLink => write!(f, "Hard link"),
+        }
+    }
+}
+
+/// Lossless format the scanned images are converted to by the storage optimizer.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ScanImageFormat {
+    #[default]
+    Png,
+    WebP,
+}
+
+impl ScanImageFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            ScanImageFormat::Png => "png",
+            ScanImageFormat::WebP => "webp",
+        }
+    }
+}
+
+impl Display for ScanImageFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanImageFormat::Png => write!(f, "PNG"),
+            ScanImageFormat::WebP => write!(f, "WebP"),
+        }
+    }
+}
+
+/// What the storage optimizer does to the files of the collection.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StorageOptimization {
+    // roms, disk and tape images that aren't zipped are stored in zip archives
+    pub zip_plain_files: bool,
+    // zip archives are compressed again at the maximum compression level
+    pub recompress_archives: bool,
+    // BMP and TIFF scans are converted to the format
+    pub scan_image_format: Option<ScanImageFormat>,
+}
+
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct Settings {
     pub id: String,
     pub collection_root_dir: String,
+    // default for how files are brought to the collection, can be changed for each import
+    #[serde(default)]
+    pub file_import_mode: FileImportMode,
+    // layout of the files added to the collection, see FilePathBuilder
+    #[serde(default)]
+    pub file_path_template: Option<String>,
+    // drives for files placed outside the collection root dir by the placement rules
+    #[serde(default)]
+    pub storage_volumes: Vec<StorageVolume>,
+    #[serde(default)]
+    pub placement_rules: Vec<PlacementRule>,
+    // identical files are stored once in the content store
+    #[serde(default)]
+    pub content_addressed_storage: bool,
+    // changes made to the collection directory outside the app are noticed
+    #[serde(default)]
+    pub watch_collection: bool,
+    // directory the RetroArch cores are looked up from
+    #[serde(default)]
+    pub retroarch_cores_directory: Option<String>,
+}
+
+/// A named directory holding part of the collection, for example on a NAS or an external disk.
+/// A volume is offline when its root dir can't be found.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StorageVolume {
+    pub name: String,
+    pub root_dir: String,
+}
+
+impl Display for StorageVolume {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Places the new files of a system, of a file type or of both on a storage volume.
+/// The first matching rule is used, files without a matching rule go to the collection root dir.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlacementRule {
+    pub system_id: Option<ObjectId>,
+    pub file_type: Option<CollectionFileType>,
+    pub volume: String,
+}
+
+impl PlacementRule {
+    pub fn matches(&self, system: &System, file_type: &CollectionFileType) -> bool {
+        self.system_id.is_none_or(|id| system._id == Some(id))
+            && self.file_type.as_ref().is_none_or(|t| t == file_type)
+    }
 }
 
 impl Default for Emulator {
@@ -127,6 +431,11 @@ impl Default for Emulator {
             extract_files: false,
             supported_file_type_extensions: vec![],
             notes: None,
+            supported_file_types: vec![],
+            multiple_media: false,
+            kind: EmulatorKind::default(),
+            supports_playlists: false,
+            save_data_rules: vec![],
         }
     }
 }
@@ -137,6 +446,8 @@ impl Default for System {
             _id: None,
             name: "".to_string(),
             notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
         }
     }
 }
@@ -158,6 +469,10 @@ impl Default for Release {
             system_id: None,
             files: vec![],
             games: vec![],
+            startup_file: None,
+            emulator_id: None,
+            emulator_override: None,
+            media: vec![],
         }
     }
 }
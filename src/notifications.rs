@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use iced::{
+    widget::{button, column, row, text, Column},
+    Element,
+};
+
+use crate::collection_watcher::CollectionChange;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Dismiss(usize),
+    DismissAll,
+    Adopt(usize),
+    AdoptAll,
+}
+
+pub enum Action {
+    None,
+    AdoptFiles(Vec<PathBuf>),
+}
+
+/// Shows the changes made to the collection directory outside the app until they're dismissed.
+pub struct Notifications {
+    changes: Vec<CollectionChange>,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self { changes: vec![] }
+    }
+
+    /// A file flagged again replaces the earlier notification of the file.
+    pub fn add(&mut self, change: CollectionChange) {
+        self.changes.retain(|existing| match (existing, &change) {
+            (CollectionChange::FileFlagged(existing), CollectionChange::FileFlagged(issue)) => {
+                existing.collection_file_id != issue.collection_file_id
+            }
+            (CollectionChange::FileAdded(existing), CollectionChange::FileAdded(file_path)) => {
+                existing != file_path
+            }
+            _ => true,
+        });
+        self.changes.push(change);
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::Dismiss(index) => {
+                if index < self.changes.len() {
+                    self.changes.remove(index);
+                }
+                Action::None
+            }
+            Message::DismissAll => {
+                self.changes.clear();
+                Action::None
+            }
+            Message::Adopt(index) => match self.changes.get(index) {
+                Some(CollectionChange::FileAdded(file_path)) => {
+                    let file_path = file_path.clone();
+                    self.changes.remove(index);
+                    Action::AdoptFiles(vec![file_path])
+                }
+                _ => Action::None,
+            },
+            Message::AdoptAll => {
+                let mut file_paths = Vec::new();
+                self.changes.retain(|change| match change {
+                    CollectionChange::FileAdded(file_path) => {
+                        file_paths.push(file_path.clone());
+                        false
+                    }
+                    CollectionChange::FileFlagged(_) => true,
+                });
+                Action::AdoptFiles(file_paths)
+            }
+        }
+    }
+
+    pub fn view(&self) -> Element<Message> {
+        if self.changes.is_empty() {
+            return column![].into();
+        }
+        let notifications = self
+            .changes
+            .iter()
+            .enumerate()
+            .map(|(index, change)| {
+                let dismiss_button = button("Dismiss").on_press(Message::Dismiss(index));
+                match change {
+                    CollectionChange::FileFlagged(issue) => {
+                        row![text(issue.to_string()), dismiss_button]
+                    }
+                    CollectionChange::FileAdded(file_path) => row![
+                        text(format!("New file: {}", file_path.display())),
+                        button("Adopt").on_press(Message::Adopt(index)),
+                        dismiss_button
+                    ],
+                }
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        let has_added_files = self
+            .changes
+            .iter()
+            .any(|change| matches!(change, CollectionChange::FileAdded(_)));
+        let adopt_all_button = button("Adopt all new files")
+            .on_press_maybe(has_added_files.then_some(Message::AdoptAll));
+        column![
+            text("Changes in the collection directory"),
+            Column::with_children(notifications),
+            row![
+                adopt_all_button,
+                button("Dismiss all").on_press(Message::DismissAll)
+            ]
+            .spacing(10)
+        ]
+        .spacing(5)
+        .into()
+    }
+}
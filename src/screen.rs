@@ -1,5 +1,6 @@
 pub mod add_release_main;
 pub mod add_release_screen;
+pub mod bulk_import;
 pub mod error;
 pub mod games_main;
 pub mod games_screen;
@@ -15,6 +16,7 @@ pub mod view_game_screen;
 pub mod view_image;
 pub mod view_release;
 pub use add_release_main::AddReleaseMain;
+pub use bulk_import::BulkImport;
 pub use error::Error;
 pub use games_main::GamesMain;
 pub use home::Home;
@@ -32,4 +34,5 @@ pub enum Screen {
     Error(Error),
     GamesMain(GamesMain),
     SettingsMain(SettingsMain),
+    BulkImport(BulkImport),
 }
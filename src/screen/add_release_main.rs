@@ -1,14 +1,19 @@
 use crate::database_with_polo::DatabaseWithPolo;
 use crate::error::Error;
+use crate::job_queue::JobQueue;
 use crate::manage_games;
 use crate::manage_systems;
+use crate::model::collection_file::CollectionFile;
+use crate::model::job::{Job, JobKind};
+use crate::model::model::FileImportMode;
 use crate::model::model::HasOid;
 use crate::model::model::Release;
 use crate::repository::repository::ReleaseReadRepository;
 use crate::screen::add_release_screen::add_release_main_screen;
 use crate::screen::add_release_screen::AddReleaseScreen;
 use bson::oid::ObjectId;
-use iced::{Element, Task};
+use iced::{Element, Subscription, Task};
+use std::path::PathBuf;
 
 use super::view_image;
 
@@ -19,6 +24,7 @@ pub struct AddReleaseMain {
     // NOTE! Do not move state to sub screen, when moving between screens, changes won't be lost event without saving to db
     // Also, we don't want to save to db after each state, because of cancel functionality.
     // Only changes that are saved to db immediately are adding or deleting files because actual files are copied or deleted.
+    // Files are added by import jobs, so the files of the release are reloaded from db before saving.
     release: Release,
 }
 
@@ -94,10 +100,9 @@ impl AddReleaseMain {
                             self.release.system_id = Some(system.id());
                             self.switch_main_screen()
                         }
-                        add_release_main_screen::Action::AddFile(file) => {
-                            self.release.files.push(file);
-
-                            match self.update_release() {
+                        add_release_main_screen::Action::ImportFile(import) => {
+                            let (file_path, collection_file, file_import_mode) = *import;
+                            match self.submit_import(file_path, collection_file, file_import_mode) {
                                 Ok(_) => self.switch_main_screen(),
                                 Err(e) => Action::Error(e),
                             }
@@ -105,14 +110,18 @@ impl AddReleaseMain {
                         add_release_main_screen::Action::Run(task) => {
                             Action::Run(task.map(Message::AddReleaseMainScreen))
                         }
-                        add_release_main_screen::Action::Submit => match self.update_release() {
-                            Ok(_) => Action::ReleaseSubmitted,
-                            Err(e) => Action::Error(e),
-                        },
-                        add_release_main_screen::Action::Save => match self.update_release() {
-                            Ok(_) => self.switch_main_screen(),
-                            Err(e) => Action::Error(e),
-                        },
+                        add_release_main_screen::Action::Submit => {
+                            match self.reload_files().and_then(|_| self.update_release()) {
+                                Ok(_) => Action::ReleaseSubmitted,
+                                Err(e) => Action::Error(e),
+                            }
+                        }
+                        add_release_main_screen::Action::Save => {
+                            match self.reload_files().and_then(|_| self.update_release()) {
+                                Ok(_) => self.switch_main_screen(),
+                                Err(e) => Action::Error(e),
+                            }
+                        }
                         add_release_main_screen::Action::Clear => {
                             self.release = Release::default();
                             self.switch_main_screen()
@@ -123,7 +132,14 @@ impl AddReleaseMain {
                             Action::None
                         }
                         add_release_main_screen::Action::Error(error) => Action::Error(error),
+                        add_release_main_screen::Action::MediaChanged(media) => {
+                            self.release.media = media;
+                            self.switch_main_screen()
+                        }
                         add_release_main_screen::Action::DeleteFile(file_id) => {
+                            if let Err(e) = self.reload_files() {
+                                return Action::Error(e);
+                            }
                             self.release.files.retain(|f| *f != file_id);
                             match self.update_release() {
                                 Ok(_) => self.switch_main_screen(),
@@ -157,6 +173,9 @@ impl AddReleaseMain {
                         manage_systems::Action::None => Action::None,
                         manage_systems::Action::SystemSubmitted => self.switch_main_screen(),
                         manage_systems::Action::Error(error) => Action::Error(error),
+                        manage_systems::Action::Run(task) => {
+                            Action::Run(task.map(Message::ManageSystemsScreen))
+                        }
                     }
                 } else {
                     Action::None
@@ -185,6 +204,15 @@ impl AddReleaseMain {
         }
     }
 
+    pub fn subscription(&self) -> Subscription<Message> {
+        match &self.screen {
+            AddReleaseScreen::AddReleaseMainScreen(screen) => {
+                screen.subscription().map(Message::AddReleaseMainScreen)
+            }
+            _ => Subscription::none(),
+        }
+    }
+
     pub fn view(&self) -> Element<Message> {
         match &self.screen {
             AddReleaseScreen::AddReleaseMainScreen(screen) => {
@@ -201,6 +229,57 @@ impl AddReleaseMain {
             }
         }
     }
+    /// Shows the files imported to this release by a finished import job.
+    pub fn job_finished(&mut self, job: &Job) -> Result<(), Error> {
+        match &job.kind {
+            JobKind::ImportFile { release_id, .. } if self.release._id == Some(*release_id) => {
+                self.reload_files()?;
+                if let AddReleaseScreen::AddReleaseMainScreen(screen) = &mut self.screen {
+                    screen.set_release_files(self.release.files.clone())?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The file is imported by a job that adds the file to the release in db,
+    /// so the release is saved first to have an id.
+    fn submit_import(
+        &mut self,
+        file_path: PathBuf,
+        collection_file: CollectionFile,
+        file_import_mode: FileImportMode,
+    ) -> Result<(), Error> {
+        let system_id = self
+            .release
+            .system_id
+            .ok_or_else(|| Error::NotFound("System of the release is not selected".to_string()))?;
+        self.reload_files()?;
+        let release_id = self.update_release()?;
+        JobQueue::get_instance().submit(JobKind::ImportFile {
+            file_path,
+            collection_file: Box::new(CollectionFile {
+                _id: Some(ObjectId::new()),
+                ..collection_file
+            }),
+            system_id,
+            release_id,
+            file_import_mode,
+        })?;
+        Ok(())
+    }
+
+    fn reload_files(&mut self) -> Result<(), Error> {
+        if let Some(id) = self.release._id {
+            let db = DatabaseWithPolo::get_instance();
+            if let Some(release) = db.get_release(&id)? {
+                self.release.files = release.files;
+            }
+        }
+        Ok(())
+    }
+
     fn update_release(&mut self) -> Result<ObjectId, Error> {
         let db = DatabaseWithPolo::get_instance();
         match self.release._id.is_some() {
@@ -1,20 +1,27 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{collections::HashMap, vec};
 
+use crate::content_store::remove_collection_file;
 use crate::database_with_polo::DatabaseWithPolo;
 use crate::error::Error;
-use crate::files::{copy_file, delete_file, pick_file, PickedFile};
+use crate::files::{pick_file, read_picked_file, PickedFile};
+use crate::job_queue::JobQueue;
 use crate::model::model::HasOid;
 use crate::model::{
     collection_file::{CollectionFile, CollectionFileType},
-    model::{Game, Release, Settings, System},
+    model::{FileImportMode, Game, Medium, Release, Settings, System},
 };
 use crate::repository::repository::CollectionFilesReadRepository;
+use crate::util::duplicates::{find_files_with_same_content, get_releases_with_file};
+use crate::util::file_operation::{
+    run_file_operation, Cancellation, FileOperationEvent, FileOperationProgress,
+};
 use crate::util::file_path_builder::FilePathBuilder;
 use crate::util::image::get_thumbnail_path;
+use crate::util::media::{get_medium_name, get_ordered_media};
 use bson::oid::ObjectId;
-use iced::widget::{button, column, image, pick_list, row, text, text_input, Column};
-use iced::{Element, Task};
+use iced::widget::{button, column, image, pick_list, progress_bar, row, text, text_input, Column};
+use iced::{Element, Subscription, Task};
 
 #[derive(Debug, Clone)]
 pub struct AddReleaseMainScreen {
@@ -27,6 +34,21 @@ pub struct AddReleaseMainScreen {
     settings: Settings,
     file_path_builder: FilePathBuilder,
     files: Vec<CollectionFile>,
+    file_import_mode: FileImportMode,
+    // picked file waiting for confirmation because the collection already has the same content
+    pending_file: Option<Box<(PickedFile, CollectionFile)>>,
+    duplicates: Vec<String>,
+    // picked file being read in a subscription, the file is imported by a job after reading
+    file_operation: Option<Box<RunningFileOperation>>,
+    // identifies the running file operation subscription
+    file_operation_id: u64,
+}
+
+#[derive(Debug, Clone)]
+struct RunningFileOperation {
+    file_path: PathBuf,
+    cancellation: Cancellation,
+    progress: Option<FileOperationProgress>,
 }
 
 #[derive(Debug, Clone)]
@@ -38,16 +60,23 @@ pub enum Message {
     NameChanged(String),
     SystemSelected(System),
     SelectFile,
-    FilePicked(Result<PickedFile, Error>),
+    FilePicked(Result<PathBuf, Error>),
+    FileRead(FileOperationEvent<Box<PickedFile>>),
+    AddDuplicateFile,
+    CancelDuplicateFile,
     Submit,
     Clear,
     FileSelected(ObjectId, String),
     CollectionFileTypeSelected(CollectionFileType),
+    FileImportModeSelected(FileImportMode),
     ViewImage(PathBuf),
-    FileCopied(Result<ObjectId, Error>),
+    CancelFileOperation,
     DeleteFile(ObjectId),
     FileDeleted(Result<(), Error>, ObjectId),
     Save,
+    MoveMediumUp(usize),
+    MoveMediumDown(usize),
+    MediumLabelChanged(usize, String),
 }
 
 pub enum Action {
@@ -59,13 +88,14 @@ pub enum Action {
     None,
     SystemSelected(System),
     Run(Task<Message>),
-    AddFile(ObjectId),
+    ImportFile(Box<(PathBuf, CollectionFile, FileImportMode)>),
     Submit,
     Clear,
     ViewImage(PathBuf),
     Error(Error),
     DeleteFile(ObjectId),
     Save,
+    MediaChanged(Vec<Medium>),
 }
 
 // TODO: add ViewReleaseScreen just for viewing release and using the view model
@@ -77,9 +107,18 @@ impl AddReleaseMainScreen {
         let games = db.get_all_games()?;
         let systems = db.get_systems()?;
         let settings = db.get_settings()?;
-        let file_path_builder = FilePathBuilder::new(settings.collection_root_dir.clone());
+        let file_path_builder = FilePathBuilder::from_settings(&settings);
         let files = db.get_collection_files(&release.files)?;
 
+        let file_import_mode = settings.file_import_mode.clone();
+
+        if let Some(system) = systems
+            .iter()
+            .find(|system| system._id.is_some() && system._id == release.system_id)
+        {
+            JobQueue::get_instance().submit_missing_thumbnails(&files, &settings, system);
+        }
+
         Ok(Self {
             games,
             selected_game: None,
@@ -90,6 +129,11 @@ impl AddReleaseMainScreen {
             settings,
             file_path_builder,
             files,
+            file_import_mode,
+            pending_file: None,
+            duplicates: vec![],
+            file_operation: None,
+            file_operation_id: 0,
         })
     }
 
@@ -103,49 +147,41 @@ impl AddReleaseMainScreen {
             Message::SystemSelected(system) => Action::SystemSelected(system),
             Message::SelectFile => Action::Run(Task::perform(pick_file(), Message::FilePicked)),
             Message::FilePicked(result) => {
-                let selected_system = self.systems.iter().find(|system| {
-                    self.release
-                        .system_id
-                        .map_or(false, |system_id| system.id() == system_id)
-                });
-
-                if let (Some(system), Some(selected_file_type)) =
-                    (selected_system, self.selected_file_type.clone())
-                {
-                    match result {
-                        Ok(picked_file) => {
-                            let collection_file = CollectionFile {
-                                _id: None,
-                                original_file_name: picked_file.file_name.clone(),
-                                collection_file_type: self.selected_file_type.clone().unwrap(),
-                                files: picked_file.files.clone(),
-                                is_zip: picked_file.is_zip,
-                            };
-                            let db = DatabaseWithPolo::get_instance();
-                            match db.add_collection_file(&collection_file) {
-                                Ok(id) => Action::Run(Task::perform(
-                                    copy_file(
-                                        self.file_path_builder
-                                            .build_target_directory(system, &selected_file_type),
-                                        id,
-                                        picked_file,
-                                    ),
-                                    Message::FileCopied,
-                                )),
-                                Err(err) => Action::Error(err),
-                            }
-                        }
-                        Err(_) => Action::None,
+                if let Ok(file_path) = result {
+                    self.start_file_operation(file_path);
+                }
+                Action::None
+            }
+            Message::FileRead(FileOperationEvent::Progress(progress)) => {
+                if let Some(file_operation) = &mut self.file_operation {
+                    file_operation.progress = Some(progress);
+                }
+                Action::None
+            }
+            Message::CancelFileOperation => {
+                if let Some(file_operation) = &self.file_operation {
+                    file_operation.cancellation.cancel();
+                }
+                Action::None
+            }
+            Message::FileRead(FileOperationEvent::Finished(result)) => {
+                self.finish_file_operation();
+                self.handle_read_file(result)
+            }
+            Message::AddDuplicateFile => {
+                self.duplicates.clear();
+                match self.pending_file.take().map(|pending_file| *pending_file) {
+                    Some((picked_file, collection_file)) => {
+                        self.add_picked_file(picked_file, collection_file)
                     }
-                } else {
-                    Action::None
+                    None => Action::None,
                 }
             }
-            Message::FileCopied(result) => match result {
-                Ok(id) => Action::AddFile(id),
-                // TODO: if copy fails, remove the file from the database
-                Err(err) => Action::Error(err),
-            },
+            Message::CancelDuplicateFile => {
+                self.duplicates.clear();
+                self.pending_file = None;
+                Action::None
+            }
             Message::Submit => Action::Submit,
             Message::Clear => Action::Clear,
             Message::FileSelected(id, file) => {
@@ -156,18 +192,30 @@ impl AddReleaseMainScreen {
                 self.selected_file_type = Some(file_type);
                 Action::None
             }
+            Message::FileImportModeSelected(file_import_mode) => {
+                self.file_import_mode = file_import_mode;
+                Action::None
+            }
             Message::ViewImage(file_path) => Action::ViewImage(file_path),
             Message::DeleteFile(id) => {
+                // a file shared with another release is only removed from this release
+                match self.is_file_in_other_release(&id) {
+                    Ok(true) => return Action::DeleteFile(id),
+                    Ok(false) => {}
+                    Err(err) => return Action::Error(err),
+                }
                 if let Some(system) = self.get_release_system() {
                     if let Some(file) = self.files.iter().find(|f| f.id() == id) {
-                        if let Ok(file_path) = self.file_path_builder.build_file_path(system, file)
-                        {
-                            // TODO: remove also thumbnail if exists
-                            return Action::Run(Task::perform(
-                                delete_file(file_path.clone()),
-                                move |result| Message::FileDeleted(result, id.clone()),
-                            ));
-                        }
+                        let file = file.clone();
+                        let system = system.clone();
+                        let file_path_builder = self.file_path_builder.clone();
+                        // TODO: remove also thumbnail if exists
+                        return Action::Run(Task::perform(
+                            async move {
+                                remove_collection_file(&file, &system, &file_path_builder).await
+                            },
+                            move |result| Message::FileDeleted(result, id),
+                        ));
                     }
                 }
                 Action::None
@@ -184,17 +232,40 @@ impl AddReleaseMainScreen {
                 Err(err) => Action::Error(err),
             },
             Message::Save => Action::Save,
+            Message::MoveMediumUp(index) => {
+                let mut media = get_ordered_media(&self.release.media, &self.files);
+                if index > 0 && index < media.len() {
+                    media.swap(index - 1, index);
+                }
+                Action::MediaChanged(media)
+            }
+            Message::MoveMediumDown(index) => {
+                let mut media = get_ordered_media(&self.release.media, &self.files);
+                if index + 1 < media.len() {
+                    media.swap(index, index + 1);
+                }
+                Action::MediaChanged(media)
+            }
+            Message::MediumLabelChanged(index, label) => {
+                let mut media = get_ordered_media(&self.release.media, &self.files);
+                if let Some(medium) = media.get_mut(index) {
+                    medium.label = label;
+                }
+                Action::MediaChanged(media)
+            }
         }
     }
 
     pub fn view(&self) -> iced::Element<Message> {
-        let back_button = button("Back").on_press(Message::Back);
+        // leaving the screen is not possible while a file is being read
+        let is_idle = self.file_operation.is_none();
+        let back_button = button("Back").on_press_maybe(is_idle.then_some(Message::Back));
         let release_name_input_field =
             text_input("Enter release name", &self.release.name).on_input(Message::NameChanged);
         let selected_games_list = self.create_selected_games_list();
         let manage_games_button: button::Button<'_, Message> = button("Manage Games")
             .width(iced::Length::Fixed(200.0))
-            .on_press(Message::ManageGames);
+            .on_press_maybe(is_idle.then_some(Message::ManageGames));
 
         let selected_system = self.get_release_system();
 
@@ -205,17 +276,18 @@ impl AddReleaseMainScreen {
         );
         let manage_systems_button = button("Manage Systems")
             .width(iced::Length::Fixed(200.0))
-            .on_press(Message::ManageSystems);
+            .on_press_maybe(is_idle.then_some(Message::ManageSystems));
 
         let file_picker_row = self.create_file_picker();
         let emulator_files_list = self.create_emulator_files_list();
+        let media_list = self.create_media_list();
         let scan_files_list = self.create_files_list(CollectionFileType::CoverScan);
         let screenshot_files_list = self.create_files_list(CollectionFileType::Screenshot);
 
         let main_buttons = row![
-            button("Save").on_press(Message::Save),
-            button("Submit").on_press(Message::Submit),
-            button("Clear").on_press(Message::Clear)
+            button("Save").on_press_maybe(is_idle.then_some(Message::Save)),
+            button("Submit").on_press_maybe(is_idle.then_some(Message::Submit)),
+            button("Clear").on_press_maybe(is_idle.then_some(Message::Clear))
         ];
 
         column![
@@ -227,6 +299,7 @@ impl AddReleaseMainScreen {
             manage_systems_button,
             file_picker_row,
             emulator_files_list,
+            media_list,
             scan_files_list,
             screenshot_files_list,
             main_buttons
@@ -234,6 +307,137 @@ impl AddReleaseMainScreen {
         .into()
     }
 
+    fn handle_read_file(&mut self, result: Result<Box<PickedFile>, Error>) -> Action {
+        match (result, self.selected_file_type.clone()) {
+            (Ok(picked_file), Some(selected_file_type)) => {
+                let picked_file = *picked_file;
+                let collection_file = CollectionFile {
+                    _id: None,
+                    original_file_name: picked_file.file_name.clone(),
+                    collection_file_type: selected_file_type,
+                    files: picked_file.files.clone(),
+                    is_zip: picked_file.is_zip,
+                    size: picked_file.size,
+                    checksums: picked_file.checksums.clone(),
+                    relative_path: None,
+                    volume: None,
+                    content_key: None,
+                    base_file_id: None,
+                };
+                match self.get_duplicates(&collection_file) {
+                    Ok(duplicates) if duplicates.is_empty() => {
+                        self.add_picked_file(picked_file, collection_file)
+                    }
+                    Ok(duplicates) => {
+                        self.duplicates = duplicates;
+                        self.pending_file = Some(Box::new((picked_file, collection_file)));
+                        Action::None
+                    }
+                    Err(err) => Action::Error(err),
+                }
+            }
+            (Err(Error::Cancelled), _) => Action::None,
+            (Err(err), _) => Action::Error(err),
+            _ => Action::None,
+        }
+    }
+
+    /// Shows the files of the release after import jobs have added files to it.
+    pub fn set_release_files(&mut self, files: Vec<ObjectId>) -> Result<(), Error> {
+        let db = DatabaseWithPolo::get_instance();
+        self.files = db.get_collection_files(&files)?;
+        self.release.files = files;
+        Ok(())
+    }
+
+    fn start_file_operation(&mut self, file_path: PathBuf) {
+        self.file_operation = Some(Box::new(RunningFileOperation {
+            file_path,
+            cancellation: Cancellation::default(),
+            progress: None,
+        }));
+        self.file_operation_id += 1;
+    }
+
+    fn finish_file_operation(&mut self) {
+        self.file_operation = None;
+    }
+
+    /// Reading the picked file runs in a subscription that reports the progress.
+    pub fn subscription(&self) -> Subscription<Message> {
+        let Some(file_operation) = &self.file_operation else {
+            return Subscription::none();
+        };
+        let file_path = file_operation.file_path.clone();
+        Subscription::run_with_id(
+            ("add_release_file_operation", self.file_operation_id),
+            run_file_operation(
+                file_operation.cancellation.clone(),
+                move |reporter| async move {
+                    read_picked_file(&file_path, &reporter).await.map(Box::new)
+                },
+            ),
+        )
+        .map(Message::FileRead)
+    }
+
+    fn add_picked_file(
+        &mut self,
+        picked_file: PickedFile,
+        mut collection_file: CollectionFile,
+    ) -> Action {
+        if self.get_release_system().is_none() {
+            return Action::None;
+        }
+        // a file already in the collection directory or on a storage volume is registered where it is
+        let (volume, relative_path) = self
+            .file_path_builder
+            .get_relative_path(Path::new(&picked_file.file_path))
+            .unzip();
+        collection_file.relative_path = relative_path;
+        collection_file.volume = volume.flatten();
+        Action::ImportFile(Box::new((
+            picked_file.file_path.into(),
+            collection_file,
+            self.file_import_mode.clone(),
+        )))
+    }
+
+    fn is_file_in_other_release(&self, collection_file_id: &ObjectId) -> Result<bool, Error> {
+        let releases = DatabaseWithPolo::get_instance().get_releases()?;
+        Ok(releases.iter().any(|release| {
+            release._id != self.release._id && release.files.contains(collection_file_id)
+        }))
+    }
+
+    /// Describes the existing files with the same content as the picked file
+    /// and the releases they belong to.
+    fn get_duplicates(&self, collection_file: &CollectionFile) -> Result<Vec<String>, Error> {
+        let db = DatabaseWithPolo::get_instance();
+        let collection_files = db.get_all_collection_files()?;
+        let releases = db.get_releases()?;
+
+        Ok(
+            find_files_with_same_content(collection_file, &collection_files)
+                .into_iter()
+                .map(|duplicate| {
+                    let mut release_names = get_releases_with_file(&duplicate.id(), &releases)
+                        .into_iter()
+                        .filter(|release| release._id != self.release._id)
+                        .map(|release| release.name.clone())
+                        .collect::<Vec<String>>();
+                    if self.release.files.contains(&duplicate.id()) {
+                        release_names.insert(0, "this release".to_string());
+                    }
+                    if release_names.is_empty() {
+                        release_names.push("no release".to_string());
+                    }
+                    format!("{} in {}", duplicate, release_names.join(", "))
+                })
+                .collect(),
+        )
+    }
+
     fn get_release_system(&self) -> Option<&System> {
         self.systems.iter().find(|system| {
             self.release
@@ -289,15 +493,70 @@ impl AddReleaseMainScreen {
                 CollectionFileType::Manual,
                 CollectionFileType::Screenshot,
                 CollectionFileType::TapeImage,
+                CollectionFileType::Patch,
             ],
             self.selected_file_type.clone(),
             Message::CollectionFileTypeSelected,
         );
         let add_file_button = button("Add File").on_press_maybe(
-            (self.release.system_id.is_some() && self.selected_file_type.is_some())
-                .then(|| Message::SelectFile),
+            (self.release.system_id.is_some()
+                && self.selected_file_type.is_some()
+                && self.file_operation.is_none())
+            .then(|| Message::SelectFile),
+        );
+        if let Some((progress, cancellation)) =
+            self.file_operation.as_ref().and_then(|file_operation| {
+                file_operation
+                    .progress
+                    .as_ref()
+                    .map(|progress| (progress, &file_operation.cancellation))
+            })
+        {
+            return row![
+                column![
+                    text(progress.to_string()),
+                    progress_bar(
+                        0.0..=progress.total_bytes.max(1) as f32,
+                        progress.bytes_done as f32
+                    ),
+                ],
+                button("Cancel").on_press_maybe(
+                    (!cancellation.is_cancelled()).then_some(Message::CancelFileOperation)
+                )
+            ]
+            .into();
+        }
+        if self.pending_file.is_some() {
+            let duplicates_list = self
+                .duplicates
+                .iter()
+                .map(|duplicate| text(duplicate).into())
+                .collect::<Vec<Element<Message>>>();
+            return column![
+                text("The collection already contains this file:"),
+                Column::with_children(duplicates_list),
+                row![
+                    button("Add anyway").on_press(Message::AddDuplicateFile),
+                    button("Cancel").on_press(Message::CancelDuplicateFile)
+                ]
+            ]
+            .into();
+        }
+        let file_import_mode_picker = pick_list(
+            vec![
+                FileImportMode::Copy,
+                FileImportMode::Move,
+                FileImportMode::HardLink,
+            ],
+            Some(self.file_import_mode.clone()),
+            Message::FileImportModeSelected,
         );
-        row![collection_file_type_picker, add_file_button].into()
+        row![
+            collection_file_type_picker,
+            file_import_mode_picker,
+            add_file_button
+        ]
+        .into()
     }
 
     fn create_files_list(&self, file_type: CollectionFileType) -> Element<Message> {
@@ -307,16 +566,18 @@ impl AddReleaseMainScreen {
             .filter(|f| f.collection_file_type == file_type)
             .filter_map(|file| {
                 if let Some(system) = self.get_release_system() {
-                    if let Ok(thumb_path) = get_thumbnail_path(file, &self.settings, system) {
-                        if let Ok(file_path) = self.file_path_builder.build_file_path(system, file)
-                        {
-                            let image = image(thumb_path);
-                            let view_image_button =
-                                button(image).on_press(Message::ViewImage(file_path));
-                            let delete_button =
-                                button("Delete").on_press(Message::DeleteFile(file.id()));
-                            return Some(row![view_image_button, delete_button].into());
+                    if let Ok(file_path) = self.file_path_builder.build_file_path(system, file) {
+                        // the file name is shown until the thumbnail job has created the thumbnail
+                        let thumb_path = get_thumbnail_path(file, &self.settings);
+                        let view_image_button = if thumb_path.exists() {
+                            button(image(thumb_path))
+                        } else {
+                            button(text(file.to_string()))
                         }
+                        .on_press(Message::ViewImage(file_path));
+                        let delete_button =
+                            button("Delete").on_press(Message::DeleteFile(file.id()));
+                        return Some(row![view_image_button, delete_button].into());
                     }
                 }
 
@@ -326,14 +587,43 @@ impl AddReleaseMainScreen {
         Column::with_children(files_list).into()
     }
 
+    /// Lists the media of a multi-disk release in the order they're given to the emulator with
+    /// a label for each medium, like "Disk 1 Side A".
+    fn create_media_list(&self) -> Element<Message> {
+        let media = get_ordered_media(&self.release.media, &self.files);
+        if media.len() < 2 {
+            return Column::new().into();
+        }
+        let media_count = media.len();
+        let media_list = media
+            .iter()
+            .enumerate()
+            .map(|(index, medium)| {
+                row![
+                    text(get_medium_name(medium, &self.files)).width(iced::Length::Fixed(300.0)),
+                    text_input("Enter label", &medium.label)
+                        .on_input(move |label| Message::MediumLabelChanged(index, label))
+                        .width(iced::Length::Fixed(200.0)),
+                    button("Up")
+                        .on_press_maybe((index > 0).then_some(Message::MoveMediumUp(index))),
+                    button("Down").on_press_maybe(
+                        (index + 1 < media_count).then_some(Message::MoveMediumDown(index))
+                    ),
+                ]
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        column![text("Media order:"), Column::with_children(media_list)].into()
+    }
+
     fn create_emulator_files_list(&self) -> Element<Message> {
         let files_list = self
             .files
             .iter()
+            // patches are listed with the media they're applied to
             .filter(|f| {
-                f.collection_file_type == CollectionFileType::Rom
-                    || f.collection_file_type == CollectionFileType::DiskImage
-                    || f.collection_file_type == CollectionFileType::TapeImage
+                f.collection_file_type.is_media()
+                    || f.collection_file_type == CollectionFileType::Patch
             })
             .map(|file| {
                 let container_filename = text(file.to_string());
@@ -0,0 +1,322 @@
+use std::path::PathBuf;
+
+use bson::oid::ObjectId;
+use iced::{
+    widget::{button, checkbox, column, pick_list, row, scrollable, text, text_input, Column},
+    Element, Task,
+};
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::{pick_folder, scan_files, scan_folder, ScannedFile},
+    job_queue::JobQueue,
+    model::{
+        collection_file::CollectionFileType,
+        job::JobKind,
+        model::{FileImportMode, System},
+    },
+    util::bulk_import::{create_proposals, ImportProposal},
+};
+
+pub struct BulkImport {
+    systems: Vec<System>,
+    selected_system: Option<System>,
+    file_import_mode: FileImportMode,
+    folder: Option<PathBuf>,
+    proposals: Vec<ImportProposal>,
+    // releases submitted to the job queue for importing
+    queued_releases: Vec<String>,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    GoHome,
+    SelectFolder,
+    FolderPicked(Result<PathBuf, Error>),
+    FolderScanned(Result<Vec<ScannedFile>, Error>),
+    SystemSelected(System),
+    FileImportModeSelected(FileImportMode),
+    GameNameChanged(usize, String),
+    ReleaseNameChanged(usize, String),
+    FileTypeSelected(usize, usize, CollectionFileType),
+    AcceptedToggled(usize, bool),
+    AcceptAll,
+    SkipAll,
+    Import,
+}
+
+pub enum Action {
+    GoHome,
+    None,
+    Run(Task<Message>),
+}
+
+impl BulkImport {
+    pub fn new() -> Result<Self, Error> {
+        let db = DatabaseWithPolo::get_instance();
+        let systems = db.get_systems()?;
+        let settings = db.get_settings()?;
+        Ok(Self {
+            systems,
+            selected_system: None,
+            file_import_mode: settings.file_import_mode,
+            folder: None,
+            proposals: vec![],
+            queued_releases: vec![],
+            errors: vec![],
+        })
+    }
+
+    pub fn title(&self) -> String {
+        "Bulk import".to_string()
+    }
+
+    /// Proposes releases for files that appeared in the collection directory, so that they
+    /// are adopted where they are.
+    pub fn adopt_files(&mut self, file_paths: Vec<PathBuf>) -> Task<Message> {
+        self.folder = None;
+        self.proposals.clear();
+        Task::perform(scan_files(file_paths), Message::FolderScanned)
+    }
+
+    pub fn update(&mut self, message: Message) -> Action {
+        match message {
+            Message::GoHome => Action::GoHome,
+            Message::SelectFolder => {
+                Action::Run(Task::perform(pick_folder(), Message::FolderPicked))
+            }
+            Message::FolderPicked(result) => match result {
+                Ok(folder) => {
+                    self.folder = Some(folder.clone());
+                    self.proposals.clear();
+                    Action::Run(Task::perform(scan_folder(folder), Message::FolderScanned))
+                }
+                Err(_) => Action::None,
+            },
+            Message::FolderScanned(result) => {
+                match result {
+                    Ok(scanned_files) => self.proposals = create_proposals(&scanned_files),
+                    Err(e) => self.errors.push(e.to_string()),
+                }
+                Action::None
+            }
+            Message::SystemSelected(system) => {
+                self.selected_system = Some(system);
+                Action::None
+            }
+            Message::FileImportModeSelected(file_import_mode) => {
+                self.file_import_mode = file_import_mode;
+                Action::None
+            }
+            Message::GameNameChanged(index, name) => {
+                if let Some(proposal) = self.proposals.get_mut(index) {
+                    proposal.game_name = name;
+                }
+                Action::None
+            }
+            Message::ReleaseNameChanged(index, name) => {
+                if let Some(proposal) = self.proposals.get_mut(index) {
+                    proposal.release_name = name;
+                }
+                Action::None
+            }
+            Message::FileTypeSelected(index, file_index, file_type) => {
+                if let Some(file) = self
+                    .proposals
+                    .get_mut(index)
+                    .and_then(|proposal| proposal.files.get_mut(file_index))
+                {
+                    file.file_type = file_type;
+                }
+                Action::None
+            }
+            Message::AcceptedToggled(index, accepted) => {
+                if let Some(proposal) = self.proposals.get_mut(index) {
+                    proposal.accepted = accepted;
+                }
+                Action::None
+            }
+            Message::AcceptAll => {
+                self.set_all_accepted(true);
+                Action::None
+            }
+            Message::SkipAll => {
+                self.set_all_accepted(false);
+                Action::None
+            }
+            Message::Import => {
+                self.queued_releases.clear();
+                self.errors.clear();
+                self.submit_accepted_proposals();
+                Action::None
+            }
+        }
+    }
+
+    fn set_all_accepted(&mut self, accepted: bool) {
+        self.proposals
+            .iter_mut()
+            .for_each(|proposal| proposal.accepted = accepted);
+    }
+
+    /// Each accepted proposal is imported by its own job. The job queue runs imports one at a time
+    /// so that releases of the same game share the game.
+    fn submit_accepted_proposals(&mut self) {
+        let Some(system_id) = self.selected_system.as_ref().and_then(|system| system._id) else {
+            return;
+        };
+        let (accepted, skipped) = std::mem::take(&mut self.proposals)
+            .into_iter()
+            .partition::<Vec<ImportProposal>, _>(|proposal| proposal.accepted);
+        self.proposals = skipped;
+        for proposal in accepted {
+            let release_name = proposal.release_name.clone();
+            let collection_file_ids = proposal.files.iter().map(|_| ObjectId::new()).collect();
+            match JobQueue::get_instance().submit(JobKind::ImportRelease {
+                proposal,
+                release_id: ObjectId::new(),
+                collection_file_ids,
+                system_id,
+                file_import_mode: self.file_import_mode.clone(),
+            }) {
+                Ok(_) => self.queued_releases.push(release_name),
+                Err(e) => self.errors.push(format!(
+                    "Failed queueing {} for import: {}",
+                    release_name, e
+                )),
+            }
+        }
+    }
+
+    pub fn view(&self) -> Element<Message> {
+        let back_button = button("Back").on_press(Message::GoHome);
+        let system_select = pick_list(
+            self.systems.as_slice(),
+            self.selected_system.as_ref(),
+            Message::SystemSelected,
+        );
+        let file_import_mode_picker = pick_list(
+            vec![
+                FileImportMode::Copy,
+                FileImportMode::Move,
+                FileImportMode::HardLink,
+            ],
+            Some(self.file_import_mode.clone()),
+            Message::FileImportModeSelected,
+        );
+        let folder_button = button("Select folder").on_press(Message::SelectFolder);
+        let folder = text(
+            self.folder
+                .as_ref()
+                .map(|folder| folder.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+
+        let accepted_count = self
+            .proposals
+            .iter()
+            .filter(|proposal| proposal.accepted)
+            .count();
+        let import_button = button("Import accepted").on_press_maybe(
+            (self.selected_system.is_some() && accepted_count > 0).then_some(Message::Import),
+        );
+        let buttons = row![
+            button("Accept all").on_press(Message::AcceptAll),
+            button("Skip all").on_press(Message::SkipAll),
+            import_button,
+            text(format!(
+                "{} of {} proposals accepted",
+                accepted_count,
+                self.proposals.len()
+            ))
+        ]
+        .spacing(10);
+
+        let status = self
+            .queued_releases
+            .iter()
+            .map(|release_name| {
+                text(format!(
+                    "Queued {} for import, the progress is shown in Jobs",
+                    release_name
+                ))
+                .into()
+            })
+            .chain(self.errors.iter().map(|error| text(error).into()))
+            .collect::<Vec<Element<Message>>>();
+
+        let proposals = self
+            .proposals
+            .iter()
+            .enumerate()
+            .map(|(index, proposal)| self.create_proposal_view(index, proposal))
+            .collect::<Vec<Element<Message>>>();
+
+        column![
+            back_button,
+            row![text("System"), system_select].spacing(10),
+            row![folder_button, folder].spacing(10),
+            row![text("Import mode"), file_import_mode_picker].spacing(10),
+            buttons,
+            Column::with_children(status),
+            scrollable(Column::with_children(proposals).spacing(10))
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn create_proposal_view<'a>(
+        &'a self,
+        index: usize,
+        proposal: &'a ImportProposal,
+    ) -> Element<'a, Message> {
+        let accepted_checkbox = checkbox("Accept", proposal.accepted)
+            .on_toggle(move |accepted| Message::AcceptedToggled(index, accepted));
+        let game_name_input = text_input("Game name", &proposal.game_name)
+            .on_input(move |name| Message::GameNameChanged(index, name));
+        let release_name_input = text_input("Release name", &proposal.release_name)
+            .on_input(move |name| Message::ReleaseNameChanged(index, name));
+
+        let files = proposal
+            .files
+            .iter()
+            .enumerate()
+            .map(|(file_index, file)| {
+                let file_type_picker = pick_list(
+                    vec![
+                        CollectionFileType::Rom,
+                        CollectionFileType::DiskImage,
+                        CollectionFileType::TapeImage,
+                        CollectionFileType::CoverScan,
+                        CollectionFileType::Manual,
+                        CollectionFileType::Screenshot,
+                        CollectionFileType::Patch,
+                    ],
+                    Some(file.file_type.clone()),
+                    move |file_type| Message::FileTypeSelected(index, file_index, file_type),
+                );
+                row![text(&file.file_name), file_type_picker]
+                    .spacing(10)
+                    .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        column![
+            row![
+                accepted_checkbox,
+                text(if proposal.is_multi_disk() {
+                    "Multi-disk set"
+                } else {
+                    ""
+                })
+            ]
+            .spacing(10),
+            row![text("Game"), game_name_input].spacing(10),
+            row![text("Release"), release_name_input].spacing(10),
+            Column::with_children(files)
+        ]
+        .into()
+    }
+}
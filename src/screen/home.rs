@@ -10,6 +10,7 @@ pub struct Home {
 pub enum Message {
     ViewGames,
     AddRelease,
+    BulkImport,
     ManageSystems,
     ManageGames,
     ManageEmulators,
@@ -20,6 +21,7 @@ pub enum Message {
 pub enum Action {
     ViewGames,
     AddRelease,
+    BulkImport,
     ManageSystems,
     ManageGames,
     ManageEmulators,
@@ -44,6 +46,7 @@ impl Home {
             Message::ManageSystems => Action::ManageSystems,
             Message::ManageGames => Action::ManageGames,
             Message::AddRelease => Action::AddRelease,
+            Message::BulkImport => Action::BulkImport,
             Message::ManageEmulators => Action::ManageEmulators,
             Message::ManageSettings => Action::ManageSettings,
             Message::Exit => Action::Exit,
@@ -73,6 +76,9 @@ impl Home {
         let add_release_button = button("Add release")
             .width(iced::Length::Fixed(200.0))
             .on_press(Message::AddRelease);
+        let bulk_import_button = button("Bulk import")
+            .width(iced::Length::Fixed(200.0))
+            .on_press(Message::BulkImport);
         let manage_systems_button = button("Manage systems")
             .width(iced::Length::Fixed(200.0))
             .on_press(Message::ManageSystems);
@@ -85,6 +91,7 @@ impl Home {
         column![
             view_games_button,
             add_release_button,
+            bulk_import_button,
             manage_systems_button,
             manage_games_button,
             manage_emulators_button,
@@ -1,8 +1,12 @@
 use crate::database_with_polo::DatabaseWithPolo;
+use crate::emulator_detection::{detect_emulators, EmulatorDetection};
 use crate::error::Error;
-use crate::model::model::{Emulator, HasOid, System};
+use crate::model::collection_file::CollectionFileType;
+use crate::model::emulator_preset::EmulatorPreset;
+use crate::model::model::{Emulator, EmulatorKind, HasOid, SaveDataKind, SaveDataRule, System};
+use crate::util::emulator_presets::load_emulator_presets;
 use iced::widget::{button, checkbox, column, pick_list, row, text, text_input, Column};
-use iced::Element;
+use iced::{Element, Task};
 use polodb_core::bson::oid::ObjectId;
 
 pub struct ManageEmulators {
@@ -10,6 +14,10 @@ pub struct ManageEmulators {
     pub emulators: Vec<Emulator>,
     pub systems: Vec<System>,
     pub is_edit: bool,
+    presets: Vec<EmulatorPreset>,
+    selected_preset: Option<EmulatorPreset>,
+    detection: Option<EmulatorDetection>,
+    is_detecting: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -26,6 +34,18 @@ pub enum Message {
     Clear,
     ExtractFilesChanged(bool),
     SupportedFileTypeExtensionsChanged(String),
+    SupportedFileTypeToggled(CollectionFileType, bool),
+    MultipleMediaChanged(bool),
+    SupportsPlaylistsChanged(bool),
+    AddSaveDataRule,
+    RemoveSaveDataRule(usize),
+    SaveDataKindSelected(usize, SaveDataKind),
+    SaveDataPatternChanged(usize, String),
+    RetroArchChanged(bool),
+    PresetSelected(EmulatorPreset),
+    DetectEmulators,
+    EmulatorsDetected(Result<EmulatorDetection, Error>),
+    AddDetectedEmulator(usize),
 }
 
 pub enum Action {
@@ -35,6 +55,7 @@ pub enum Action {
     EmulatorSubmitted,
     EmulatorDeleted,
     Error(Error),
+    Run(Task<Message>),
 }
 
 impl ManageEmulators {
@@ -57,6 +78,10 @@ impl ManageEmulators {
             emulators,
             systems,
             is_edit,
+            presets: load_emulator_presets(),
+            selected_preset: None,
+            detection: None,
+            is_detecting: false,
         })
     }
 
@@ -114,6 +139,7 @@ impl ManageEmulators {
             }
             Message::Clear => {
                 self.emulator = Emulator::default();
+                self.selected_preset = None;
                 Action::None
             }
             Message::ExtractFilesChanged(is_checked) => {
@@ -127,10 +153,128 @@ impl ManageEmulators {
                     .collect();
                 Action::None
             }
+            Message::SupportedFileTypeToggled(file_type, is_checked) => {
+                // an emulator without file types supports the media file types
+                if self.emulator.supported_file_types.is_empty() {
+                    self.emulator.supported_file_types = CollectionFileType::RUNNABLE_FILE_TYPES
+                        .into_iter()
+                        .filter(|file_type| file_type.is_media())
+                        .collect();
+                }
+                self.emulator
+                    .supported_file_types
+                    .retain(|supported_file_type| *supported_file_type != file_type);
+                if is_checked {
+                    self.emulator.supported_file_types.push(file_type);
+                }
+                Action::None
+            }
+            Message::MultipleMediaChanged(is_checked) => {
+                self.emulator.multiple_media = is_checked;
+                Action::None
+            }
+            Message::SupportsPlaylistsChanged(is_checked) => {
+                self.emulator.supports_playlists = is_checked;
+                Action::None
+            }
+            Message::AddSaveDataRule => {
+                self.emulator.save_data_rules.push(SaveDataRule {
+                    kind: SaveDataKind::default(),
+                    pattern: String::new(),
+                });
+                Action::None
+            }
+            Message::RemoveSaveDataRule(index) => {
+                if index < self.emulator.save_data_rules.len() {
+                    self.emulator.save_data_rules.remove(index);
+                }
+                Action::None
+            }
+            Message::SaveDataKindSelected(index, kind) => {
+                if let Some(rule) = self.emulator.save_data_rules.get_mut(index) {
+                    rule.kind = kind;
+                }
+                Action::None
+            }
+            Message::SaveDataPatternChanged(index, pattern) => {
+                if let Some(rule) = self.emulator.save_data_rules.get_mut(index) {
+                    rule.pattern = pattern;
+                }
+                Action::None
+            }
+            Message::RetroArchChanged(is_checked) => {
+                // a RetroArch emulator is used for every system with a RetroArch core
+                if is_checked {
+                    self.emulator.kind = EmulatorKind::RetroArch;
+                    self.emulator.system_id = None;
+                } else {
+                    self.emulator.kind = EmulatorKind::Standalone;
+                }
+                Action::None
+            }
+            Message::PresetSelected(preset) => {
+                // the system of the emulator is kept, otherwise a system matching the preset
+                // is chosen
+                let system_id = self.emulator.system_id.or_else(|| {
+                    self.systems
+                        .iter()
+                        .find(|system| preset.is_for_system(&system.name))
+                        .and_then(|system| system._id)
+                });
+                self.emulator = Emulator {
+                    _id: self.emulator._id,
+                    ..preset.to_emulator(system_id)
+                };
+                self.selected_preset = Some(preset);
+                Action::None
+            }
+            Message::DetectEmulators => {
+                self.is_detecting = true;
+                Action::Run(Task::perform(
+                    detect_emulators(),
+                    Message::EmulatorsDetected,
+                ))
+            }
+            Message::EmulatorsDetected(result) => {
+                self.is_detecting = false;
+                match result {
+                    Ok(detection) => {
+                        self.detection = Some(detection);
+                        Action::None
+                    }
+                    Err(e) => Action::Error(e),
+                }
+            }
+            Message::AddDetectedEmulator(index) => {
+                let Some(detected_emulator) = self
+                    .detection
+                    .as_mut()
+                    .and_then(|detection| detection.detected_emulators.get_mut(index))
+                else {
+                    return Action::None;
+                };
+                let mut emulator = detected_emulator.to_emulator();
+                let db = DatabaseWithPolo::get_instance();
+                match db.add_emulator(&emulator) {
+                    Ok(id) => {
+                        emulator._id = Some(id);
+                        detected_emulator.is_added = true;
+                        self.emulators.push(emulator);
+                        Action::None
+                    }
+                    Err(e) => Action::Error(e),
+                }
+            }
         }
     }
 
     pub fn view(&self) -> Element<Message> {
+        let preset_select = pick_list(
+            self.presets.as_slice(),
+            self.selected_preset.clone(),
+            Message::PresetSelected,
+        )
+        .placeholder("Fill in from a preset");
         let name_input_field =
             text_input("Enter name", &self.emulator.name).on_input(Message::NameChanged);
         let current_notes = self.emulator.notes.clone().unwrap_or_default();
@@ -138,17 +282,51 @@ impl ManageEmulators {
             text_input("Enter notes", &current_notes).on_input(Message::NotesChanged);
         let executable_input_field = text_input("Enter executable", &self.emulator.executable)
             .on_input(Message::ExecutableChanged);
-        let systems_select = pick_list(
-            self.systems.as_slice(),
-            self.systems
-                .iter()
-                .find(|s| self.emulator.system_id.map_or(false, |id| s.id() == id)),
-            Message::SystemSelected,
-        );
+        let is_retroarch = self.emulator.kind == EmulatorKind::RetroArch;
+        let retroarch_checkbox = checkbox(
+            "RetroArch, started with the core chosen for the system",
+            is_retroarch,
+        )
+        .on_toggle(Message::RetroArchChanged);
+        let systems_select: Element<Message> = if is_retroarch {
+            text("Used for the systems with a RetroArch core").into()
+        } else {
+            pick_list(
+                self.systems.as_slice(),
+                self.systems
+                    .iter()
+                    .find(|s| self.emulator.system_id.map_or(false, |id| s.id() == id)),
+                Message::SystemSelected,
+            )
+            .into()
+        };
         let arguments_input_field = text_input("Enter arguments", &self.emulator.arguments)
             .on_input(Message::ArgumentsChanged);
         let extract_files_checkbox = checkbox("Extract files", self.emulator.extract_files)
             .on_toggle(Message::ExtractFilesChanged);
+        let multiple_media_checkbox = checkbox(
+            "Start with all media of the release",
+            self.emulator.multiple_media,
+        )
+        .on_toggle(Message::MultipleMediaChanged);
+        let supports_playlists_checkbox = checkbox(
+            "Start with an M3U playlist of the media of the release",
+            self.emulator.supports_playlists,
+        )
+        .on_toggle(Message::SupportsPlaylistsChanged);
+        let supported_file_types = CollectionFileType::RUNNABLE_FILE_TYPES
+            .into_iter()
+            .map(|file_type| {
+                checkbox(
+                    file_type.to_string(),
+                    self.emulator.supports_file_type(&file_type),
+                )
+                .on_toggle(move |is_checked| {
+                    Message::SupportedFileTypeToggled(file_type.clone(), is_checked)
+                })
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
         let main_buttons = row![
             button("Submit").on_press(Message::Submit),
             button("Clear").on_press(Message::Clear)
@@ -173,17 +351,125 @@ impl ManageEmulators {
             })
             .collect::<Vec<Element<Message>>>();
         let back_button = button("Back").on_press(Message::GoHome);
+        let detect_button = button(if self.is_detecting {
+            "Detecting emulators..."
+        } else {
+            "Detect installed emulators"
+        })
+        .on_press_maybe((!self.is_detecting).then_some(Message::DetectEmulators));
         column![
             back_button,
+            preset_select,
             name_input_field,
             executable_input_field,
             arguments_input_field,
             supported_file_type_extensions,
+            row(supported_file_types).spacing(10),
+            retroarch_checkbox,
             systems_select,
             extract_files_checkbox,
+            multiple_media_checkbox,
+            supports_playlists_checkbox,
+            self.view_save_data_rules(),
             notes_input_field,
             main_buttons,
-            Column::with_children(emulators_list)
+            Column::with_children(emulators_list),
+            detect_button,
+            self.view_detection()
+        ]
+        .into()
+    }
+
+    /// Rules for where the emulator writes save states, battery saves and modified media.
+    fn view_save_data_rules(&self) -> Element<Message> {
+        let rules_list = self
+            .emulator
+            .save_data_rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                row![
+                    pick_list(SaveDataKind::ALL, Some(rule.kind.clone()), move |kind| {
+                        Message::SaveDataKindSelected(index, kind)
+                    }),
+                    text_input("Enter file pattern, for example {name}.srm", &rule.pattern)
+                        .on_input(move |pattern| Message::SaveDataPatternChanged(index, pattern)),
+                    button("Remove").on_press(Message::RemoveSaveDataRule(index)),
+                ]
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        column![
+            text("Save data:"),
+            Column::with_children(rules_list),
+            button("Add save data rule").on_press(Message::AddSaveDataRule),
+        ]
+        .into()
+    }
+
+    fn view_detection(&self) -> Element<Message> {
+        let Some(detection) = &self.detection else {
+            return Column::new().into();
+        };
+        let detected_emulators = detection
+            .detected_emulators
+            .iter()
+            .enumerate()
+            .map(|(index, detected_emulator)| {
+                let system_name = self
+                    .systems
+                    .iter()
+                    .find(|system| {
+                        system._id.is_some() && system._id == detected_emulator.system_id
+                    })
+                    .map_or("No matching system".to_string(), |system| {
+                        system.name.clone()
+                    });
+                row![
+                    text(detected_emulator.preset.name.clone()).width(iced::Length::Fixed(300.0)),
+                    text(detected_emulator.executable.to_string_lossy().to_string())
+                        .width(iced::Length::Fixed(300.0)),
+                    text(detected_emulator.version.clone().unwrap_or_default())
+                        .width(iced::Length::Fixed(200.0)),
+                    text(system_name).width(iced::Length::Fixed(200.0)),
+                    button(if detected_emulator.is_added {
+                        "Added"
+                    } else {
+                        "Add"
+                    })
+                    .on_press_maybe(
+                        (!detected_emulator.is_added)
+                            .then_some(Message::AddDetectedEmulator(index))
+                    ),
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        let missing_executables = detection
+            .missing_executables
+            .iter()
+            .map(|emulator| {
+                row![
+                    text(format!(
+                        "Executable of {} not found: {}",
+                        emulator.name, emulator.executable
+                    )),
+                    button("Edit").on_press(Message::EditEmulator(emulator.id())),
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        let detected_title = if detected_emulators.is_empty() {
+            "No installed emulators found"
+        } else {
+            "Installed emulators"
+        };
+        column![
+            text(detected_title),
+            Column::with_children(detected_emulators),
+            Column::with_children(missing_executables)
         ]
         .into()
     }
@@ -1,15 +1,29 @@
+use std::collections::HashMap;
+use std::path::Path;
+
 use crate::error::Error;
-use crate::model::model::System;
+use crate::files::pick_dat_file;
+use crate::model::dat::{DatFile, DatReport};
+use crate::model::model::{Emulator, RetroArchCore, System};
+use crate::repository::repository::CollectionFilesReadRepository;
+use crate::util::dat_parser::{load_dat_file, ParsedDat};
+use crate::util::dat_verifier::create_dat_report;
+use crate::util::retroarch::{expand_retroarch_emulators, find_retroarch_cores};
 use crate::view_model::list_models::{get_systems_in_list_model, SystemListModel};
 use crate::{database_with_polo::DatabaseWithPolo, model::model::HasOid};
 use bson::oid::ObjectId;
-use iced::widget::{button, column, row, text, text_input, Column};
+use iced::widget::{button, column, pick_list, row, scrollable, text, text_input, Column};
+use iced::Task;
 
 #[derive(Debug, Clone)]
 pub struct ManageSystems {
     pub system: System,
     pub systems: Vec<SystemListModel>,
     pub isEditing: bool,
+    dat_files: HashMap<ObjectId, Vec<DatFile>>,
+    dat_report: Option<(String, DatReport)>,
+    emulators: Vec<Emulator>,
+    retroarch_cores: Vec<RetroArchCore>,
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +35,15 @@ pub enum Message {
     DeleteSystem(ObjectId),
     Clear,
     NotesChanged(String),
+    ImportDat(ObjectId),
+    DatLoaded(ObjectId, Result<ParsedDat, Error>),
+    ShowDatReport(ObjectId),
+    CloseDatReport,
+    MoveEmulatorUp(ObjectId),
+    MoveEmulatorDown(ObjectId),
+    SetDefaultEmulator(ObjectId),
+    RetroArchCoreSelected(RetroArchCore),
+    ClearRetroArchCore,
 }
 
 pub enum Action {
@@ -29,6 +52,7 @@ pub enum Action {
     EditSystem(ObjectId),
     SystemSubmitted,
     Error(Error),
+    Run(Task<Message>),
 }
 
 impl ManageSystems {
@@ -39,6 +63,16 @@ impl ManageSystems {
             Some(id) => db.get_system(&id)?,
             None => None,
         };
+        let dat_files = get_dat_files_by_system(&systems)?;
+        let emulators = db.get_emulators()?;
+        let retroarch_cores = match db.get_settings()?.retroarch_cores_directory {
+            Some(cores_directory) => find_retroarch_cores(Path::new(&cores_directory))
+                .unwrap_or_else(|err| {
+                    println!("Failed to find RetroArch cores {:?}", err);
+                    vec![]
+                }),
+            None => vec![],
+        };
 
         Ok(Self {
             isEditing: edit_system.is_some(),
@@ -47,6 +81,10 @@ impl ManageSystems {
                 None => System::default(),
             },
             systems,
+            dat_files,
+            dat_report: None,
+            emulators,
+            retroarch_cores,
         })
     }
 
@@ -98,9 +136,104 @@ impl ManageSystems {
                 self.system = System::default();
                 Action::None
             }
+            Message::ImportDat(system_id) => Action::Run(Task::perform(
+                async {
+                    let dat_file_path = pick_dat_file().await?;
+                    load_dat_file(dat_file_path).await
+                },
+                move |result| Message::DatLoaded(system_id, result),
+            )),
+            Message::DatLoaded(system_id, result) => match result {
+                Ok(parsed_dat) => {
+                    let db = DatabaseWithPolo::get_instance();
+                    let dat_file = DatFile {
+                        system_id: Some(system_id),
+                        ..parsed_dat.dat_file
+                    };
+                    let result = db
+                        .add_dat_file(&dat_file, &parsed_dat.games)
+                        .and_then(|_| db.get_dat_files_for_system(&system_id));
+                    match result {
+                        Ok(dat_files) => {
+                            self.dat_files.insert(system_id, dat_files);
+                            Action::None
+                        }
+                        Err(e) => Action::Error(e),
+                    }
+                }
+                Err(Error::DialogClosed) => Action::None,
+                Err(e) => Action::Error(e),
+            },
+            Message::ShowDatReport(system_id) => match create_system_dat_report(&system_id) {
+                Ok(report) => {
+                    let system_name = self
+                        .systems
+                        .iter()
+                        .find(|system| system.id == system_id)
+                        .map(|system| system.name.clone())
+                        .unwrap_or_default();
+                    self.dat_report = Some((system_name, report));
+                    Action::None
+                }
+                Err(e) => Action::Error(e),
+            },
+            Message::CloseDatReport => {
+                self.dat_report = None;
+                Action::None
+            }
+            Message::MoveEmulatorUp(emulator_id) => {
+                let mut emulator_order = self.get_emulator_order();
+                if let Some(index) = emulator_order.iter().position(|id| *id == emulator_id) {
+                    if index > 0 {
+                        emulator_order.swap(index, index - 1);
+                    }
+                }
+                self.system.emulator_order = emulator_order;
+                Action::None
+            }
+            Message::MoveEmulatorDown(emulator_id) => {
+                let mut emulator_order = self.get_emulator_order();
+                if let Some(index) = emulator_order.iter().position(|id| *id == emulator_id) {
+                    if index + 1 < emulator_order.len() {
+                        emulator_order.swap(index, index + 1);
+                    }
+                }
+                self.system.emulator_order = emulator_order;
+                Action::None
+            }
+            Message::SetDefaultEmulator(emulator_id) => {
+                let mut emulator_order = self.get_emulator_order();
+                emulator_order.retain(|id| *id != emulator_id);
+                emulator_order.insert(0, emulator_id);
+                self.system.emulator_order = emulator_order;
+                Action::None
+            }
+            Message::RetroArchCoreSelected(core) => {
+                self.system.retroarch_core = Some(core);
+                Action::None
+            }
+            Message::ClearRetroArchCore => {
+                self.system.retroarch_core = None;
+                Action::None
+            }
         }
     }
 
+    /// Returns the emulators with the RetroArch emulators using the core chosen for the system.
+    fn get_emulators(&self) -> Vec<Emulator> {
+        expand_retroarch_emulators(self.emulators.clone(), std::slice::from_ref(&self.system))
+    }
+
+    /// Returns the ids of all the emulators of the system in the current order, so that a
+    /// moved emulator keeps its place relative to the emulators not ordered before.
+    fn get_emulator_order(&self) -> Vec<ObjectId> {
+        self.system
+            .order_emulators(&self.get_emulators())
+            .into_iter()
+            .map(|emulator| emulator.id())
+            .collect()
+    }
+
     pub fn view(&self) -> iced::Element<Message> {
         let name_input_field =
             text_input("Enter name", &self.system.name).on_input(Message::NameChanged);
@@ -114,6 +247,16 @@ impl ManageSystems {
             .systems
             .iter()
             .map(|system| {
+                let dat_files = self
+                    .dat_files
+                    .get(&system.id())
+                    .cloned()
+                    .unwrap_or_default();
+                let dat_file_names = dat_files
+                    .iter()
+                    .map(|dat_file| dat_file.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
                 row![
                     text(system.to_string()).width(iced::Length::Fixed(300.0)),
                     button("Edit").on_press(Message::EditSystem(system.id())),
@@ -122,6 +265,11 @@ impl ManageSystems {
                             .can_delete
                             .then(|| Message::DeleteSystem(system.id()))
                     ),
+                    button("Import DAT").on_press(Message::ImportDat(system.id())),
+                    button("DAT report").on_press_maybe(
+                        (!dat_files.is_empty()).then(|| Message::ShowDatReport(system.id()))
+                    ),
+                    text(dat_file_names),
                 ]
                 .into()
             })
@@ -132,9 +280,135 @@ impl ManageSystems {
             back_button,
             name_input_field,
             notes_field,
+            self.create_retroarch_core_view(),
             main_buttons,
-            Column::with_children(systems_list)
+            self.create_emulator_order_view(),
+            Column::with_children(systems_list),
+            self.create_dat_report_view()
         ]
         .into()
     }
+
+    fn create_retroarch_core_view(&self) -> iced::Element<Message> {
+        if self.retroarch_cores.is_empty() && self.system.retroarch_core.is_none() {
+            return Column::new().into();
+        }
+        row![
+            text("RetroArch core"),
+            pick_list(
+                self.retroarch_cores.as_slice(),
+                self.system.retroarch_core.clone(),
+                Message::RetroArchCoreSelected,
+            )
+            .placeholder("Choose a core"),
+            button("Clear").on_press_maybe(
+                self.system
+                    .retroarch_core
+                    .is_some()
+                    .then_some(Message::ClearRetroArchCore)
+            ),
+        ]
+        .into()
+    }
+
+    fn create_emulator_order_view(&self) -> iced::Element<Message> {
+        if !self.isEditing {
+            return Column::new().into();
+        }
+        let emulators = self.get_emulators();
+        let default_emulator_id = self
+            .system
+            .get_default_emulator(&emulators)
+            .and_then(|emulator| emulator._id);
+        let emulators_list = self
+            .system
+            .order_emulators(&emulators)
+            .into_iter()
+            .map(|emulator| {
+                let is_default = emulator._id == default_emulator_id;
+                row![
+                    text(if is_default {
+                        format!("{} (default)", emulator.name)
+                    } else {
+                        emulator.name.clone()
+                    })
+                    .width(iced::Length::Fixed(300.0)),
+                    button("Up").on_press(Message::MoveEmulatorUp(emulator.id())),
+                    button("Down").on_press(Message::MoveEmulatorDown(emulator.id())),
+                    button("Set as default").on_press_maybe(
+                        (!is_default).then(|| Message::SetDefaultEmulator(emulator.id()))
+                    ),
+                ]
+                .into()
+            })
+            .collect::<Vec<iced::Element<Message>>>();
+        column![
+            text("Emulators in order of preference:"),
+            Column::with_children(emulators_list)
+        ]
+        .into()
+    }
+
+    fn create_dat_report_view(&self) -> iced::Element<Message> {
+        if let Some((system_name, report)) = &self.dat_report {
+            let total = report.have.len() + report.missing.len();
+            let have_list = report
+                .have
+                .iter()
+                .map(|name| text(name).into())
+                .collect::<Vec<iced::Element<Message>>>();
+            let missing_list = report
+                .missing
+                .iter()
+                .map(|name| text(name).into())
+                .collect::<Vec<iced::Element<Message>>>();
+            column![
+                row![
+                    text!(
+                        "{}: have {} of {} games, missing {}",
+                        system_name,
+                        report.have.len(),
+                        total,
+                        report.missing.len()
+                    ),
+                    button("Close").on_press(Message::CloseDatReport),
+                ],
+                row![
+                    column![text("Have:"), scrollable(Column::with_children(have_list))]
+                        .width(iced::Length::FillPortion(1)),
+                    column![
+                        text("Missing:"),
+                        scrollable(Column::with_children(missing_list))
+                    ]
+                    .width(iced::Length::FillPortion(1)),
+                ]
+            ]
+            .into()
+        } else {
+            Column::new().into()
+        }
+    }
+}
+
+fn get_dat_files_by_system(
+    systems: &[SystemListModel],
+) -> Result<HashMap<ObjectId, Vec<DatFile>>, Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let mut dat_files = HashMap::new();
+    for system in systems {
+        dat_files.insert(system.id, db.get_dat_files_for_system(&system.id)?);
+    }
+    Ok(dat_files)
+}
+
+fn create_system_dat_report(system_id: &ObjectId) -> Result<DatReport, Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let dat_games = db.get_dat_games_for_system(system_id)?;
+    let file_ids = db
+        .get_releases_with_system(system_id)?
+        .into_iter()
+        .flat_map(|release| release.files)
+        .collect::<Vec<ObjectId>>();
+    let collection_files = db.get_collection_files(&file_ids)?;
+    Ok(create_dat_report(&dat_games, &collection_files))
 }
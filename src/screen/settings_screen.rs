@@ -1,7 +1,9 @@
 use settings_main_screen::SettingsMainScreen;
 
+pub mod relocate_collection_widget;
 pub mod settings_main_screen;
 pub mod settings_widget;
+pub mod storage_volumes_widget;
 
 #[derive(Debug, Clone)]
 pub enum SettingsScreen {
@@ -0,0 +1,99 @@
+use std::path::{Path, PathBuf};
+
+use iced::{
+    widget::{button, checkbox, column, row, text},
+    Task,
+};
+
+use crate::{
+    collection_relocator::validate_new_root, error::Error, files::pick_folder, job_queue::JobQueue,
+    model::job::JobKind,
+};
+
+/// Moves the collection to a new root directory with a relocation job.
+#[derive(Debug, Clone)]
+pub struct RelocateCollectionWidget {
+    collection_root_dir: String,
+    new_root_dir: Option<String>,
+    remove_old_files: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    SelectFolder,
+    FolderPicked(Result<PathBuf, Error>),
+    RemoveOldFilesToggled(bool),
+    Start,
+}
+
+impl RelocateCollectionWidget {
+    pub fn new(collection_root_dir: String) -> Self {
+        Self {
+            collection_root_dir,
+            new_root_dir: None,
+            remove_old_files: false,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::SelectFolder => return Task::perform(pick_folder(), Message::FolderPicked),
+            Message::FolderPicked(Ok(path)) => {
+                self.new_root_dir = Some(path.to_string_lossy().to_string());
+                self.error = None;
+            }
+            Message::FolderPicked(Err(_)) => {}
+            Message::RemoveOldFilesToggled(remove_old_files) => {
+                self.remove_old_files = remove_old_files;
+            }
+            Message::Start => {
+                if let Some(new_root_dir) = self.new_root_dir.take() {
+                    self.error = self.submit(new_root_dir).err().map(|e| e.to_string());
+                }
+            }
+        }
+        Task::none()
+    }
+
+    fn submit(&self, new_root_dir: String) -> Result<(), Error> {
+        validate_new_root(
+            Path::new(&self.collection_root_dir),
+            Path::new(&new_root_dir),
+        )?;
+        JobQueue::get_instance().submit(JobKind::RelocateCollection {
+            old_root_dir: self.collection_root_dir.clone(),
+            new_root_dir,
+            remove_old_files: self.remove_old_files,
+        })?;
+        Ok(())
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let is_relocating = JobQueue::get_instance()
+            .has_unfinished_job(|kind| matches!(kind, JobKind::RelocateCollection { .. }));
+        if is_relocating {
+            return text("The collection is being moved, the progress is shown in Jobs").into();
+        }
+
+        let folder_button = button("Move collection to").on_press_maybe(
+            (!self.collection_root_dir.is_empty()).then_some(Message::SelectFolder),
+        );
+        let new_root_dir = text(self.new_root_dir.clone().unwrap_or_default());
+        let remove_old_files_checkbox =
+            checkbox("Remove files from the old location", self.remove_old_files)
+                .on_toggle(Message::RemoveOldFilesToggled);
+        let start_button = button("Start moving")
+            .on_press_maybe(self.new_root_dir.is_some().then_some(Message::Start));
+        let error = text(self.error.clone().unwrap_or_default());
+
+        column![
+            row![folder_button, new_root_dir].spacing(10),
+            row![remove_old_files_checkbox, start_button].spacing(10),
+            error
+        ]
+        .spacing(10)
+        .into()
+    }
+}
@@ -7,10 +7,13 @@ use std::path::PathBuf;
 
 use crate::files::pick_folder;
 
+use super::relocate_collection_widget::{self, RelocateCollectionWidget};
+
 #[derive(Debug, Clone)]
 pub struct SettingsMainScreen {
     collection_root_dir: String,
     is_locked: bool,
+    relocate_collection: RelocateCollectionWidget,
 }
 
 #[derive(Debug, Clone)]
@@ -19,6 +22,7 @@ pub enum Message {
     Back,
     SelectFolder,
     FolderAdded(Result<PathBuf, Error>),
+    RelocateCollection(relocate_collection_widget::Message),
 }
 
 pub enum Action {
@@ -32,6 +36,7 @@ impl SettingsMainScreen {
     pub fn new(collection_root_dir: String) -> Self {
         Self {
             is_locked: !collection_root_dir.clone().is_empty(),
+            relocate_collection: RelocateCollectionWidget::new(collection_root_dir.clone()),
             collection_root_dir,
         }
     }
@@ -54,6 +59,11 @@ impl SettingsMainScreen {
                 print!("Error adding folder: {:?}", err);
                 Action::None
             }
+            Message::RelocateCollection(message) => Action::Run(
+                self.relocate_collection
+                    .update(message)
+                    .map(Message::RelocateCollection),
+            ),
         }
     }
 
@@ -72,6 +82,15 @@ impl SettingsMainScreen {
 
         let back_button = button("Back").on_press(Message::Back);
 
-        column![back_button, root_dir_row].into()
+        let mut settings_column = column![back_button, root_dir_row];
+        // an existing collection is moved instead of changing the root directory
+        if self.is_locked {
+            settings_column = settings_column.push(
+                self.relocate_collection
+                    .view()
+                    .map(Message::RelocateCollection),
+            );
+        }
+        settings_column.into()
     }
 }
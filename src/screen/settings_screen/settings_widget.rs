@@ -1,18 +1,35 @@
 use std::path::PathBuf;
 
 use iced::{
-    widget::{button, row, text},
+    widget::{button, checkbox, column, pick_list, row, text, text_input},
     Task,
 };
 
 use crate::{
-    database_with_polo::DatabaseWithPolo, error::Error, files::pick_folder, model::model::Settings,
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::pick_folder,
+    job_queue::JobQueue,
+    model::{
+        job::JobKind,
+        model::{FileImportMode, Settings},
+    },
+    util::file_path_builder::{validate_file_path_template, FilePathBuilder},
+};
+
+use super::{
+    relocate_collection_widget::{self, RelocateCollectionWidget},
+    storage_volumes_widget::{self, StorageVolumesWidget},
 };
 
 #[derive(Debug, Clone)]
 pub struct SettingsWidget {
     settings: Settings,
     is_locked: bool,
+    file_path_template: String,
+    error: Option<String>,
+    relocate_collection: RelocateCollectionWidget,
+    storage_volumes: StorageVolumesWidget,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +37,17 @@ pub enum Message {
     Submit,
     SelectFolder,
     FolderAdded(Result<PathBuf, Error>),
+    FileImportModeSelected(FileImportMode),
+    FilePathTemplateChanged(String),
+    MigrateLayout,
+    ContentAddressedStorageToggled(bool),
+    ConvertToContentStore,
+    WatchCollectionToggled(bool),
+    SelectRetroArchCoresFolder,
+    RetroArchCoresFolderSelected(Result<PathBuf, Error>),
+    ClearRetroArchCoresFolder,
+    RelocateCollection(relocate_collection_widget::Message),
+    StorageVolumes(storage_volumes_widget::Message),
 }
 
 impl SettingsWidget {
@@ -28,6 +56,12 @@ impl SettingsWidget {
 
         Ok(Self {
             is_locked: !settings.collection_root_dir.is_empty(),
+            file_path_template: settings.file_path_template.clone().unwrap_or_default(),
+            error: None,
+            relocate_collection: RelocateCollectionWidget::new(
+                settings.collection_root_dir.clone(),
+            ),
+            storage_volumes: StorageVolumesWidget::new(FilePathBuilder::from_settings(&settings))?,
             settings,
         })
     }
@@ -35,6 +69,19 @@ impl SettingsWidget {
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Submit => {
+                // an empty template keeps storing the new files by id
+                let file_path_template = self.file_path_template.trim();
+                if !file_path_template.is_empty() {
+                    if let Err(err) = validate_file_path_template(file_path_template) {
+                        self.error = Some(err.to_string());
+                        return Task::none();
+                    }
+                }
+                self.settings.file_path_template =
+                    (!file_path_template.is_empty()).then(|| file_path_template.to_string());
+                self.settings.storage_volumes = self.storage_volumes.storage_volumes();
+                self.settings.placement_rules = self.storage_volumes.placement_rules();
+                self.error = None;
                 let db = DatabaseWithPolo::get_instance();
                 match db.add_or_update_settings(&self.settings) {
                     Ok(_) => {
@@ -52,10 +99,61 @@ impl SettingsWidget {
                 self.settings.collection_root_dir = path.to_string_lossy().to_string();
                 Task::none()
             }
+            Message::FileImportModeSelected(file_import_mode) => {
+                self.settings.file_import_mode = file_import_mode;
+                Task::none()
+            }
+            Message::FilePathTemplateChanged(file_path_template) => {
+                self.file_path_template = file_path_template;
+                Task::none()
+            }
+            Message::MigrateLayout => {
+                if let Err(err) = JobQueue::get_instance().submit(JobKind::MigrateLayout) {
+                    self.error = Some(err.to_string());
+                }
+                Task::none()
+            }
+            Message::ContentAddressedStorageToggled(content_addressed_storage) => {
+                self.settings.content_addressed_storage = content_addressed_storage;
+                Task::none()
+            }
+            Message::ConvertToContentStore => {
+                if let Err(err) = JobQueue::get_instance().submit(JobKind::ConvertToContentStore) {
+                    self.error = Some(err.to_string());
+                }
+                Task::none()
+            }
+            Message::WatchCollectionToggled(watch_collection) => {
+                self.settings.watch_collection = watch_collection;
+                Task::none()
+            }
+            Message::SelectRetroArchCoresFolder => {
+                Task::perform(pick_folder(), Message::RetroArchCoresFolderSelected)
+            }
+            Message::RetroArchCoresFolderSelected(Ok(path)) => {
+                self.settings.retroarch_cores_directory = Some(path.to_string_lossy().to_string());
+                Task::none()
+            }
+            Message::RetroArchCoresFolderSelected(Err(err)) => {
+                print!("Error selecting RetroArch cores folder: {:?}", err);
+                Task::none()
+            }
+            Message::ClearRetroArchCoresFolder => {
+                self.settings.retroarch_cores_directory = None;
+                Task::none()
+            }
             Message::FolderAdded(Err(err)) => {
                 print!("Error adding folder: {:?}", err);
                 Task::none()
             }
+            Message::RelocateCollection(message) => self
+                .relocate_collection
+                .update(message)
+                .map(Message::RelocateCollection),
+            Message::StorageVolumes(message) => self
+                .storage_volumes
+                .update(message)
+                .map(Message::StorageVolumes),
         }
     }
 
@@ -64,12 +162,84 @@ impl SettingsWidget {
 
         let collection_root_dir_button = button("Collection root dir")
             .on_press_maybe((!self.is_locked).then(|| Message::SelectFolder));
+        let file_import_mode_picker = pick_list(
+            vec![
+                FileImportMode::Copy,
+                FileImportMode::Move,
+                FileImportMode::HardLink,
+            ],
+            Some(self.settings.file_import_mode.clone()),
+            Message::FileImportModeSelected,
+        );
+        let file_path_template_input =
+            text_input("Leave empty to store files by id", &self.file_path_template)
+                .on_input(Message::FilePathTemplateChanged);
+        let is_migrating =
+            JobQueue::get_instance().has_unfinished_job(|kind| *kind == JobKind::MigrateLayout);
+        // files are migrated to the saved template
+        let migrate_button = button("Migrate existing files").on_press_maybe(
+            (self.is_locked && self.settings.file_path_template.is_some() && !is_migrating)
+                .then_some(Message::MigrateLayout),
+        );
+        let content_addressed_storage_checkbox = checkbox(
+            "Store identical files once",
+            self.settings.content_addressed_storage,
+        )
+        .on_toggle(Message::ContentAddressedStorageToggled);
+        let is_converting = JobQueue::get_instance()
+            .has_unfinished_job(|kind| *kind == JobKind::ConvertToContentStore);
+        let convert_button = button("Convert existing files").on_press_maybe(
+            (self.is_locked && self.settings.content_addressed_storage && !is_converting)
+                .then_some(Message::ConvertToContentStore),
+        );
+        let watch_collection_checkbox = checkbox(
+            "Watch the collection directory for changes made outside the app",
+            self.settings.watch_collection,
+        )
+        .on_toggle(Message::WatchCollectionToggled);
+        let retroarch_cores_directory_button =
+            button("RetroArch cores dir").on_press(Message::SelectRetroArchCoresFolder);
+        let retroarch_cores_directory_text = text(
+            self.settings
+                .retroarch_cores_directory
+                .clone()
+                .unwrap_or_default(),
+        );
+        let clear_retroarch_cores_directory_button = button("Clear").on_press_maybe(
+            self.settings
+                .retroarch_cores_directory
+                .is_some()
+                .then_some(Message::ClearRetroArchCoresFolder),
+        );
         let save_button = button("Submit").on_press(Message::Submit);
-        row![
-            collection_root_dir_button,
-            collection_root_dir_input,
-            save_button
-        ]
-        .into()
+        let error = text(self.error.clone().unwrap_or_default());
+        let mut settings_column = column![
+            row![collection_root_dir_button, collection_root_dir_input],
+            row![text("Default import mode"), file_import_mode_picker],
+            row![
+                text("File path template"),
+                file_path_template_input,
+                migrate_button
+            ],
+            row![content_addressed_storage_checkbox, convert_button],
+            watch_collection_checkbox,
+            row![
+                retroarch_cores_directory_button,
+                retroarch_cores_directory_text,
+                clear_retroarch_cores_directory_button
+            ],
+            self.storage_volumes.view().map(Message::StorageVolumes),
+            save_button,
+            error
+        ];
+        // an existing collection is moved instead of changing the root directory
+        if self.is_locked {
+            settings_column = settings_column.push(
+                self.relocate_collection
+                    .view()
+                    .map(Message::RelocateCollection),
+            );
+        }
+        settings_column.into()
     }
 }
@@ -0,0 +1,305 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+use iced::{
+    widget::{button, column, pick_list, row, text, text_input, Column},
+    Task,
+};
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::pick_folder,
+    model::{
+        collection_file::CollectionFileType,
+        model::{PlacementRule, StorageVolume, System},
+    },
+    util::file_path_builder::{FilePathBuilder, COLLECTION_ROOT_VOLUME_NAME},
+};
+
+/// Edits the storage volumes and the placement rules, which are saved with the other settings.
+#[derive(Debug, Clone)]
+pub struct StorageVolumesWidget {
+    storage_volumes: Vec<StorageVolume>,
+    placement_rules: Vec<PlacementRule>,
+    systems: Vec<System>,
+    file_path_builder: FilePathBuilder,
+    new_volume_name: String,
+    new_volume_root_dir: Option<String>,
+    rule_system: SystemChoice,
+    rule_file_type: FileTypeChoice,
+    rule_volume: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SystemChoice {
+    Any,
+    System(System),
+}
+
+impl Display for SystemChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SystemChoice::Any => write!(f, "Any system"),
+            SystemChoice::System(system) => write!(f, "{}", system.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileTypeChoice {
+    Any,
+    FileType(CollectionFileType),
+}
+
+impl Display for FileTypeChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FileTypeChoice::Any => write!(f, "Any file type"),
+            FileTypeChoice::FileType(file_type) => write!(f, "{}", file_type.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    VolumeNameChanged(String),
+    SelectFolder,
+    FolderPicked(Result<PathBuf, Error>),
+    AddVolume,
+    RemoveVolume(String),
+    RuleSystemSelected(SystemChoice),
+    RuleFileTypeSelected(FileTypeChoice),
+    RuleVolumeSelected(String),
+    AddRule,
+    RemoveRule(usize),
+}
+
+impl StorageVolumesWidget {
+    pub fn new(file_path_builder: FilePathBuilder) -> Result<Self, Error> {
+        let systems = DatabaseWithPolo::get_instance().get_systems()?;
+        Ok(Self {
+            storage_volumes: file_path_builder.storage_volumes.clone(),
+            placement_rules: file_path_builder.placement_rules.clone(),
+            systems,
+            file_path_builder,
+            new_volume_name: String::new(),
+            new_volume_root_dir: None,
+            rule_system: SystemChoice::Any,
+            rule_file_type: FileTypeChoice::Any,
+            rule_volume: None,
+            error: None,
+        })
+    }
+
+    pub fn storage_volumes(&self) -> Vec<StorageVolume> {
+        self.storage_volumes.clone()
+    }
+
+    pub fn placement_rules(&self) -> Vec<PlacementRule> {
+        self.placement_rules.clone()
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        self.error = None;
+        match message {
+            Message::VolumeNameChanged(name) => self.new_volume_name = name,
+            Message::SelectFolder => return Task::perform(pick_folder(), Message::FolderPicked),
+            Message::FolderPicked(Ok(path)) => {
+                self.new_volume_root_dir = Some(path.to_string_lossy().to_string());
+            }
+            Message::FolderPicked(Err(_)) => {}
+            Message::AddVolume => {
+                if let Err(e) = self.add_volume() {
+                    self.error = Some(e.to_string());
+                }
+            }
+            Message::RemoveVolume(name) => {
+                if let Err(e) = self.remove_volume(&name) {
+                    self.error = Some(e.to_string());
+                }
+            }
+            Message::RuleSystemSelected(system) => self.rule_system = system,
+            Message::RuleFileTypeSelected(file_type) => self.rule_file_type = file_type,
+            Message::RuleVolumeSelected(volume) => self.rule_volume = Some(volume),
+            Message::AddRule => {
+                if let Some(volume) = self.rule_volume.take() {
+                    self.placement_rules.push(PlacementRule {
+                        system_id: match &self.rule_system {
+                            SystemChoice::Any => None,
+                            SystemChoice::System(system) => system._id,
+                        },
+                        file_type: match &self.rule_file_type {
+                            FileTypeChoice::Any => None,
+                            FileTypeChoice::FileType(file_type) => Some(file_type.clone()),
+                        },
+                        volume,
+                    });
+                }
+            }
+            Message::RemoveRule(index) => {
+                if index < self.placement_rules.len() {
+                    self.placement_rules.remove(index);
+                }
+            }
+        }
+        self.file_path_builder.storage_volumes = self.storage_volumes.clone();
+        self.file_path_builder.placement_rules = self.placement_rules.clone();
+        Task::none()
+    }
+
+    fn add_volume(&mut self) -> Result<(), Error> {
+        let name = self.new_volume_name.trim().to_string();
+        if name.is_empty() || name == COLLECTION_ROOT_VOLUME_NAME {
+            return Err(Error::ParseError("Give the volume a name".to_string()));
+        }
+        if self
+            .storage_volumes
+            .iter()
+            .any(|storage_volume| storage_volume.name == name)
+        {
+            return Err(Error::ParseError(format!("Volume {} already exists", name)));
+        }
+        let root_dir = self
+            .new_volume_root_dir
+            .take()
+            .ok_or_else(|| Error::NotFound("Volume root dir is not selected".to_string()))?;
+        self.storage_volumes.push(StorageVolume { name, root_dir });
+        self.new_volume_name.clear();
+        Ok(())
+    }
+
+    /// A volume holding files can't be removed, the rules placing files on it are removed with it.
+    fn remove_volume(&mut self, name: &str) -> Result<(), Error> {
+        let collection_files = DatabaseWithPolo::get_instance().get_all_collection_files()?;
+        if collection_files
+            .iter()
+            .any(|collection_file| collection_file.volume.as_deref() == Some(name))
+        {
+            return Err(Error::IoError(format!(
+                "Volume {} holds files of the collection",
+                name
+            )));
+        }
+        self.storage_volumes
+            .retain(|storage_volume| storage_volume.name != name);
+        self.placement_rules.retain(|rule| rule.volume != name);
+        Ok(())
+    }
+
+    fn describe_rule(&self, rule: &PlacementRule) -> String {
+        let system_name = rule
+            .system_id
+            .and_then(|id| self.systems.iter().find(|system| system._id == Some(id)))
+            .map_or("Any system".to_string(), |system| system.name.clone());
+        let file_type = rule
+            .file_type
+            .as_ref()
+            .map_or("any file type".to_string(), |file_type| {
+                file_type.to_string()
+            });
+        format!("{}, {} -> {}", system_name, file_type, rule.volume)
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let volumes_list = self
+            .storage_volumes
+            .iter()
+            .map(|storage_volume| {
+                let status = if self
+                    .file_path_builder
+                    .is_volume_online(Some(&storage_volume.name))
+                {
+                    "online"
+                } else {
+                    "offline"
+                };
+                row![
+                    text!(
+                        "{}: {} ({})",
+                        storage_volume.name,
+                        storage_volume.root_dir,
+                        status
+                    ),
+                    button("Remove").on_press(Message::RemoveVolume(storage_volume.name.clone()))
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<iced::Element<Message>>>();
+        let volume_name_input =
+            text_input("Volume name", &self.new_volume_name).on_input(Message::VolumeNameChanged);
+        let folder_button = button("Volume root dir").on_press(Message::SelectFolder);
+        let add_volume_button = button("Add volume").on_press_maybe(
+            (!self.new_volume_name.trim().is_empty() && self.new_volume_root_dir.is_some())
+                .then_some(Message::AddVolume),
+        );
+
+        let rules_list = self
+            .placement_rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                row![
+                    text(self.describe_rule(rule)),
+                    button("Remove").on_press(Message::RemoveRule(index))
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<iced::Element<Message>>>();
+        let system_picker = pick_list(
+            std::iter::once(SystemChoice::Any)
+                .chain(self.systems.iter().cloned().map(SystemChoice::System))
+                .collect::<Vec<SystemChoice>>(),
+            Some(self.rule_system.clone()),
+            Message::RuleSystemSelected,
+        );
+        let file_type_picker = pick_list(
+            vec![
+                FileTypeChoice::Any,
+                FileTypeChoice::FileType(CollectionFileType::Rom),
+                FileTypeChoice::FileType(CollectionFileType::DiskImage),
+                FileTypeChoice::FileType(CollectionFileType::TapeImage),
+                FileTypeChoice::FileType(CollectionFileType::CoverScan),
+                FileTypeChoice::FileType(CollectionFileType::Manual),
+                FileTypeChoice::FileType(CollectionFileType::Screenshot),
+                FileTypeChoice::FileType(CollectionFileType::MemorySnapshot),
+                FileTypeChoice::FileType(CollectionFileType::Patch),
+            ],
+            Some(self.rule_file_type.clone()),
+            Message::RuleFileTypeSelected,
+        );
+        let volume_picker = pick_list(
+            self.storage_volumes
+                .iter()
+                .map(|storage_volume| storage_volume.name.clone())
+                .collect::<Vec<String>>(),
+            self.rule_volume.clone(),
+            Message::RuleVolumeSelected,
+        );
+        let add_rule_button = button("Add rule")
+            .on_press_maybe(self.rule_volume.is_some().then_some(Message::AddRule));
+
+        column![
+            text("Storage volumes"),
+            Column::with_children(volumes_list),
+            row![
+                volume_name_input,
+                folder_button,
+                text(self.new_volume_root_dir.clone().unwrap_or_default()),
+                add_volume_button
+            ]
+            .spacing(10),
+            text("Placement rules, files without a matching rule are placed in the collection root dir"),
+            Column::with_children(rules_list),
+            row![system_picker, file_type_picker, volume_picker, add_rule_button].spacing(10),
+            text(self.error.clone().unwrap_or_default())
+        ]
+        .spacing(10)
+        .into()
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
     error::Error,
     model::model::Game,
+    util::file_path_builder::FilePathBuilder,
     view_model::list_models::{get_releases_in_list_model, ReleaseListModel},
 };
 use bson::oid::ObjectId;
@@ -40,7 +41,8 @@ pub enum Action {
 impl ViewGame {
     pub fn new(game_id: ObjectId) -> Result<Self, Error> {
         let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
-        let releases = get_releases_in_list_model(db, &game_id)?;
+        let file_path_builder = FilePathBuilder::from_settings(&db.get_settings()?);
+        let releases = get_releases_in_list_model(db, &game_id, &file_path_builder)?;
 
         let game = db.get_game(&game_id)?;
         match game {
@@ -101,6 +103,7 @@ impl ViewGame {
                     view_release_button,
                     edit_release_button,
                     delete_button,
+                    text(release.availability()),
                 ];
 
                 release_row.into()
@@ -1,35 +1,48 @@
 use std::path::PathBuf;
-use std::{collections::HashMap, env, vec};
+use std::vec;
 
-use crate::emulator_runner::EmulatorRunOptions;
+use crate::emulator_runner::{
+    create_play_options, create_run_options, get_launch_directory, EmulatorRunOptions,
+};
 use crate::error::Error;
+use crate::job_queue::JobQueue;
 use crate::model::model::HasOid;
 use crate::model::{
-    collection_file::{CollectionFileType, GetFileExtensions},
-    model::{Emulator, Settings, System},
+    collection_file::{CollectionFile, CollectionFileType},
+    model::{Emulator, Settings, StartupFile},
 };
 use crate::util::file_path_builder::FilePathBuilder;
 use crate::util::image::get_thumbnail_path;
+use crate::util::patch::validate_patch_file;
+use crate::util::retroarch::expand_retroarch_emulators;
 use crate::view_model::release_view_model::{get_release_view_model, ReleaseViewModel};
 use bson::oid::ObjectId;
-use iced::widget::{button, column, image, pick_list, row, text, Column};
+use iced::widget::{button, checkbox, column, image, pick_list, row, text, Column};
 use iced::{Element, Task};
 
 #[derive(Debug, Clone)]
 pub struct ViewRelease {
     release: ReleaseViewModel,
-    selected_file: HashMap<ObjectId, String>,
     emulators: Vec<Emulator>,
     settings: Settings,
     file_path_builder: FilePathBuilder,
+    // files on an offline storage volume can't be run or viewed
+    offline_volumes: Vec<String>,
+    // patches applied when the release is run, chosen for each launch
+    selected_patches: Vec<ObjectId>,
+    patch_error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     Back,
-    RunWithEmulator(Emulator, String, CollectionFileType),
+    RunWithEmulator(Box<Emulator>, ObjectId, String),
     ViewImage(PathBuf),
     FileSelected(ObjectId, String),
+    Play,
+    PatchToggled(ObjectId, bool),
+    PatchBaseSelected(ObjectId, ObjectId),
+    PatchValidated(ObjectId, ObjectId, Result<(), Error>),
 }
 
 pub enum Action {
@@ -48,19 +61,32 @@ impl ViewRelease {
         // TODO: get emulators for the system of the release
         let emulators = db.get_emulators()?;
         let settings = db.get_settings()?;
-        let file_path_builder = FilePathBuilder::new(settings.collection_root_dir.clone());
+        let file_path_builder = FilePathBuilder::from_settings(&settings);
 
         match release {
             None => Err(Error::NotFound(format!(
                 "Release with id {} not found",
                 release_id
             ))),
-            Some(release) => Ok(Self {
-                release,
-                selected_file: HashMap::new(), emulators,
-                settings,
-                file_path_builder,
-            }),
+            Some(release) => {
+                JobQueue::get_instance().submit_missing_thumbnails(
+                    &release.files,
+                    &settings,
+                    &release.system,
+                );
+                let offline_volumes = file_path_builder.get_offline_volumes(&release.files);
+                let emulators =
+                    expand_retroarch_emulators(emulators, std::slice::from_ref(&release.system));
+                Ok(Self {
+                    release,
+                    emulators,
+                    settings,
+                    file_path_builder,
+                    offline_volumes,
+                    selected_patches: vec![],
+                    patch_error: None,
+                })
+            }
         }
     }
 
@@ -71,39 +97,147 @@ impl ViewRelease {
     pub fn update(&mut self, message: Message) -> Action {
         match message {
             Message::Back => Action::Back,
-            Message::RunWithEmulator(emulator, selected_file_name, collection_file_type) => {
-                let system = &self.release.system;
+            Message::RunWithEmulator(emulator, file_id, selected_file_name) => {
+                // the file and the emulator used last are started with the Play button
+                let startup_file = StartupFile {
+                    collection_file_id: file_id,
+                    file_name: self.release.get_startup_entry(&file_id),
+                };
+                if let Err(err) = self.save_startup(Some(startup_file), emulator._id) {
+                    return Action::Error(err);
+                }
                 let options = EmulatorRunOptions {
-                    emulator,
-                    files: self.release.files.clone(),
-                    selected_file_name: selected_file_name,
-                    source_path: self
-                        .file_path_builder
-                        .build_target_directory(system, &collection_file_type),
-                    target_path: env::temp_dir(),
+                    patches: self.release.get_patches(&file_id, &self.selected_patches),
+                    ..create_run_options(
+                        &self.release,
+                        &emulator,
+                        selected_file_name,
+                        &self.file_path_builder,
+                        get_launch_directory(),
+                    )
                 };
                 Action::RunWithEmulator(options)
             }
             Message::ViewImage(file_path) => Action::ViewImage(file_path),
             Message::FileSelected(id, file) => {
-                self.selected_file.insert(id, file);
+                let startup_file = StartupFile {
+                    collection_file_id: id,
+                    file_name: Some(file),
+                };
+                match self.save_startup(Some(startup_file), self.release.emulator_id) {
+                    Ok(()) => Action::None,
+                    Err(err) => Action::Error(err),
+                }
+            }
+            Message::Play => match self.get_play_options() {
+                Some(options) => Action::RunWithEmulator(options),
+                None => Action::None,
+            },
+            Message::PatchToggled(patch_id, is_checked) => {
+                self.selected_patches.retain(|id| *id != patch_id);
+                if is_checked {
+                    self.selected_patches.push(patch_id);
+                }
                 Action::None
             }
+            Message::PatchBaseSelected(patch_id, base_file_id) => {
+                let patch_file = self.release.files.iter().find(|f| f._id == Some(patch_id));
+                let base_file = self
+                    .release
+                    .files
+                    .iter()
+                    .find(|f| f._id == Some(base_file_id));
+                let (Some(patch_file), Some(base_file)) = (patch_file, base_file) else {
+                    return Action::None;
+                };
+                match self
+                    .file_path_builder
+                    .build_file_path(&self.release.system, patch_file)
+                {
+                    Ok(patch_path) => Action::Run(Task::perform(
+                        validate_patch_file(patch_path, patch_file.clone(), base_file.clone()),
+                        move |result| Message::PatchValidated(patch_id, base_file_id, result),
+                    )),
+                    Err(err) => Action::Error(err),
+                }
+            }
+            Message::PatchValidated(patch_id, base_file_id, result) => {
+                // a patch not made for the file is reported next to the patches
+                if let Err(err) = result {
+                    self.patch_error = Some(err.to_string());
+                    return Action::None;
+                }
+                self.patch_error = None;
+                match self.save_patch_base(patch_id, base_file_id) {
+                    Ok(()) => Action::None,
+                    Err(err) => Action::Error(err),
+                }
+            }
+        }
+    }
+
+    fn save_patch_base(&mut self, patch_id: ObjectId, base_file_id: ObjectId) -> Result<(), Error> {
+        let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
+        db.update_collection_file_base_file(&patch_id, &Some(base_file_id))?;
+        if let Some(patch_file) = self
+            .release
+            .files
+            .iter_mut()
+            .find(|f| f._id == Some(patch_id))
+        {
+            patch_file.base_file_id = Some(base_file_id);
         }
+        Ok(())
+    }
+
+    fn get_play_options(&self) -> Option<EmulatorRunOptions> {
+        if !self.offline_volumes.is_empty() {
+            return None;
+        }
+        create_play_options(
+            &self.release,
+            &self.emulators,
+            &self.file_path_builder,
+            get_launch_directory(),
+            &self.selected_patches,
+        )
+    }
+
+    fn save_startup(
+        &mut self,
+        startup_file: Option<StartupFile>,
+        emulator_id: Option<ObjectId>,
+    ) -> Result<(), Error> {
+        let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
+        db.update_release_startup(&self.release.id, &startup_file, &emulator_id)?;
+        self.release.startup_file = startup_file;
+        self.release.emulator_id = emulator_id;
+        Ok(())
     }
 
     pub fn view(&self) -> iced::Element<Message> {
         let back_button = button("Back").on_press(Message::Back);
         let selected_games_list = self.create_selected_games_list();
 
-        let emulator_files_list = self.create_emulator_files_list(&self.release.system);
+        let emulator_files_list = self.create_emulator_files_list();
         let scan_files_list = self.create_files_list(&CollectionFileType::CoverScan);
         let screenshot_files_list = self.create_files_list(&CollectionFileType::Screenshot);
 
+        let availability = text(if self.offline_volumes.is_empty() {
+            String::new()
+        } else {
+            format!("Unavailable, {} offline", self.offline_volumes.join(", "))
+        });
+
+        let play_button =
+            button("Play").on_press_maybe(self.get_play_options().map(|_| Message::Play));
+
         column![
-            back_button,
+            row![back_button, play_button],
+            availability,
             selected_games_list,
             emulator_files_list,
+            self.create_patches_list(),
             scan_files_list,
             screenshot_files_list
         ]
@@ -127,6 +261,60 @@ impl ViewRelease {
         .into()
     }
 
+    fn create_patches_list(&self) -> Element<Message> {
+        let base_files = self
+            .release
+            .files
+            .iter()
+            .filter(|file| {
+                CollectionFileType::RUNNABLE_FILE_TYPES.contains(&file.collection_file_type)
+            })
+            .cloned()
+            .collect::<Vec<CollectionFile>>();
+        let patches_list = self
+            .release
+            .files
+            .iter()
+            .filter(|file| file.collection_file_type == CollectionFileType::Patch)
+            .map(|patch_file| {
+                let patch_id = patch_file.id();
+                let base_file = base_files
+                    .iter()
+                    .find(|file| file._id.is_some() && file._id == patch_file.base_file_id)
+                    .cloned();
+                let base_file_picker = pick_list(
+                    base_files.clone(),
+                    base_file.clone(),
+                    move |base_file: CollectionFile| {
+                        Message::PatchBaseSelected(patch_id, base_file.id())
+                    },
+                )
+                .placeholder("Choose the file to patch");
+                let apply_checkbox =
+                    checkbox("Apply when run", self.selected_patches.contains(&patch_id))
+                        .on_toggle_maybe(base_file.map(|_| {
+                            move |is_checked| Message::PatchToggled(patch_id, is_checked)
+                        }));
+                row![
+                    text(patch_file.to_string()),
+                    base_file_picker,
+                    apply_checkbox
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        if patches_list.is_empty() {
+            return Column::new().into();
+        }
+        column![
+            text("Patches:"),
+            Column::with_children(patches_list),
+            text(self.patch_error.clone().unwrap_or_default())
+        ]
+        .into()
+    }
+
     fn create_files_list(&self, file_type: &CollectionFileType) -> Element<Message> {
         let scan_files_list = self
             .release
@@ -134,48 +322,48 @@ impl ViewRelease {
             .iter()
             .filter(|f| f.collection_file_type == *file_type)
             .filter_map(|file| {
-                if let Ok(thumb_path) =
-                    get_thumbnail_path(file, &self.settings, &self.release.system)
-                {
-                    if let Ok(file_path) = self
-                        .file_path_builder
-                        .build_file_path(&self.release.system, file)
-                    {
-                        let image = image(thumb_path);
-                        let view_image_button =
-                            button(image).on_press(Message::ViewImage(file_path));
-                        return Some(row![view_image_button].into());
-                    }
+                let file_path = self
+                    .file_path_builder
+                    .build_file_path(&self.release.system, file)
+                    .ok()?;
+                let thumb_path = get_thumbnail_path(file, &self.settings);
+                let view_image_button = if thumb_path.exists() {
+                    button(image(thumb_path))
+                } else {
+                    button(text(file.to_string()))
                 }
-
-                None
+                .on_press_maybe(
+                    self.file_path_builder
+                        .is_volume_online(file.volume.as_deref())
+                        .then_some(Message::ViewImage(file_path)),
+                );
+                Some(row![view_image_button].into())
             })
             .collect::<Vec<iced::Element<Message>>>();
         Column::with_children(scan_files_list).into()
     }
 
-    fn create_emulator_files_list(&self, selected_system: &System) -> Element<Message> {
+    fn create_emulator_files_list(&self) -> Element<Message> {
         let emulators_for_system = self
-            .emulators
-            .iter()
-            .filter(|emulator| {
-                emulator
-                    .system_id
-                    .map_or(false, |system_id| system_id == selected_system.id())
-            })
+            .release
+            .get_emulators(&self.emulators)
+            .into_iter()
+            // the release can't be run while some of its files are offline
+            .filter(|_| self.offline_volumes.is_empty())
             .collect::<Vec<&Emulator>>();
 
-        // TODO: get file types supported by emulators for the selected system
-
         let files_list = self
             .release
             .files
             .iter()
+            // media files are listed even when no emulator has been added for them
             .filter(|f| {
-                // TODO: emulator should know it's supported file types and we would filter by the file types supported by emulator
-                f.collection_file_type == CollectionFileType::Rom
-                    || f.collection_file_type == CollectionFileType::DiskImage
-                    || f.collection_file_type == CollectionFileType::TapeImage
+                f.collection_file_type.is_media()
+                    || self
+                        .release
+                        .get_emulators(&self.emulators)
+                        .iter()
+                        .any(|emulator| emulator.supports_file(f))
             })
             .map(|file| {
                 let container_filename = text(file.to_string());
@@ -186,49 +374,25 @@ impl ViewRelease {
                 };
                 let file_picker = pick_list(
                     content_files,
-                    if self.selected_file.contains_key(&file.id()) {
-                        Some(self.selected_file.get(&file.id()).unwrap())
-                    } else {
-                        None
-                    },
+                    self.release.get_startup_entry(&file.id()),
                     move |selected_file_name| {
                         Message::FileSelected(file.id().clone(), selected_file_name)
                     },
                 );
                 let emulator_buttons = emulators_for_system
                     .iter()
-                    .filter(|e| {
-                        e.supported_file_type_extensions.is_empty()
-                            || e.supported_file_type_extensions.contains(
-                                &file
-                                    .original_file_name
-                                    .split('.')
-                                    .last()
-                                    .unwrap()
-                                    .to_string(),
-                            )
-                            || file.get_file_extensions().into_iter().any(|extension| {
-                                e.supported_file_type_extensions.contains(&extension)
-                            })
-                    })
+                    .filter(|emulator| emulator.supports_file(file))
                     .map(|emulator| {
                         button(emulator.name.as_str())
-                            .on_press_maybe({
-                                let selected_file = self.selected_file.get(&file.id());
-                                match (selected_file, emulator.extract_files) {
-                                    (Some(file_name), true) => Some(Message::RunWithEmulator(
-                                        (*emulator).clone(),
-                                        file_name.clone(),
-                                        file.collection_file_type.clone(),
-                                    )),
-                                    (_, false) => Some(Message::RunWithEmulator(
-                                        (*emulator).clone(),
-                                        file.clone().original_file_name,
-                                        file.collection_file_type.clone(),
-                                    )),
-                                    (_, _) => None,
-                                }
-                            })
+                            .on_press_maybe(self.release.get_file_name_to_run(emulator, file).map(
+                                |file_name| {
+                                    Message::RunWithEmulator(
+                                        Box::new((*emulator).clone()),
+                                        file.id(),
+                                        file_name,
+                                    )
+                                },
+                            ))
                             .into()
                     })
                     .collect::<Vec<iced::Element<Message>>>();
@@ -0,0 +1,647 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    fs::{self, remove_file, rename, File},
+    io::{BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+use image::{DynamicImage, ImageFormat};
+use zip::{read::ZipArchive, write::SimpleFileOptions, CompressionMethod, DateTime, ZipWriter};
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::get_file_extension,
+    model::{
+        collection_file::{CollectionFile, CollectionFileType, FileInfo},
+        model::{GetIdString, ScanImageFormat, StorageOptimization, System},
+    },
+    repository::repository::CollectionFilesReadRepository,
+    util::{
+        checksum::{compute_file_checksums, ChecksumHasher},
+        file_operation::ProgressReporter,
+        file_path_builder::FilePathBuilder,
+    },
+};
+
+const DESCRIPTION: &str = "Optimizing storage";
+const MAXIMUM_COMPRESSION_LEVEL: i64 = 9;
+const CONVERTIBLE_IMAGE_EXTENSIONS: [&str; 3] = ["bmp", "tif", "tiff"];
+
+/// Expected savings of the storage optimization for the files of a system.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemSavings {
+    pub system_name: String,
+    pub file_count: usize,
+    pub current_size: u64,
+    pub optimized_size: u64,
+}
+
+impl SystemSavings {
+    pub fn saved_bytes(&self) -> u64 {
+        self.current_size.saturating_sub(self.optimized_size)
+    }
+}
+
+/// The collection file describing the optimized content of a file.
+struct OptimizedFile {
+    collection_file: CollectionFile,
+    original_size: u64,
+}
+
+/// Optimizes the files to a temporary file without changing the collection and returns the
+/// expected savings for each system, sorted by the system name.
+pub async fn estimate_savings(
+    optimization: StorageOptimization,
+) -> Result<Vec<SystemSavings>, Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let file_path_builder = FilePathBuilder::from_settings(&db.get_settings()?);
+    let mut savings = BTreeMap::new();
+    for (collection_file, system) in get_files_to_optimize(db)? {
+        if !file_path_builder.is_volume_online(collection_file.volume.as_deref()) {
+            continue;
+        }
+        let file_path = file_path_builder.build_file_path(&system, &collection_file)?;
+        let optimization = optimization.clone();
+        let sizes =
+            spawn_blocking(move || {
+                if !file_path.exists() {
+                    return Ok(None);
+                }
+                let output_path =
+                    std::env::temp_dir().join(format!("storage_estimate_{}", ObjectId::new()));
+                let optimized =
+                    optimize_file(&file_path, &collection_file, &optimization, &output_path)?;
+                let _ = remove_file(&output_path);
+                Ok(optimized
+                    .map(|optimized| (optimized.original_size, optimized.collection_file.size)))
+            })
+            .await?;
+        if let Some((current_size, optimized_size)) = sizes {
+            let system_savings =
+                savings
+                    .entry(system.name.clone())
+                    .or_insert_with(|| SystemSavings {
+                        system_name: system.name.clone(),
+                        file_count: 0,
+                        current_size: 0,
+                        optimized_size: 0,
+                    });
+            system_savings.file_count += 1;
+            system_savings.current_size += current_size;
+            system_savings.optimized_size += optimized_size;
+        }
+    }
+    Ok(savings.into_values().collect())
+}
+
+/// Replaces the files of the collection with their optimized versions. The old file is kept
+/// under a backup name until the collection file has been updated with the new name, size and
+/// checksums, and it's put back if the update fails. When the app was closed before the update,
+/// an optimized file matching the optimized backup is taken as the result, and when it was
+/// closed after the update, the backup is removed. Files in the content store and files on
+/// offline volumes are left as they are.
+pub async fn optimize_storage(
+    optimization: StorageOptimization,
+    reporter: ProgressReporter,
+) -> Result<(), Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let file_path_builder = FilePathBuilder::from_settings(&db.get_settings()?);
+    let files_to_optimize = get_files_to_optimize(db)?;
+
+    let total_bytes = files_to_optimize
+        .iter()
+        .map(|(collection_file, _)| collection_file.size)
+        .sum();
+    let mut bytes_done = 0;
+    for (collection_file, system) in files_to_optimize {
+        reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+        bytes_done += collection_file.size;
+        if !file_path_builder.is_volume_online(collection_file.volume.as_deref()) {
+            continue;
+        }
+        let file_path_builder = file_path_builder.clone();
+        let optimization = optimization.clone();
+        spawn_blocking(move || {
+            replace_file(
+                db,
+                &file_path_builder,
+                &system,
+                collection_file,
+                &optimization,
+            )
+        })
+        .await?;
+    }
+    reporter.report(DESCRIPTION, bytes_done, total_bytes)?;
+    Ok(())
+}
+
+/// Returns the files of the releases with their systems, each file once. Files in the content
+/// store are shared by their content and aren't optimized.
+fn get_files_to_optimize(db: &DatabaseWithPolo) -> Result<Vec<(CollectionFile, System)>, Error> {
+    let systems = db
+        .get_systems()?
+        .into_iter()
+        .filter_map(|system| system._id.map(|id| (id, system)))
+        .collect::<HashMap<ObjectId, System>>();
+
+    let mut seen_file_ids = HashSet::new();
+    let mut files = Vec::new();
+    for release in db.get_releases()? {
+        let Some(system) = release.system_id.and_then(|id| systems.get(&id)) else {
+            continue;
+        };
+        for collection_file in db.get_collection_files(&release.files)? {
+            if collection_file.content_key.is_none()
+                && collection_file
+                    ._id
+                    .is_some_and(|id| seen_file_ids.insert(id))
+            {
+                files.push((collection_file, system.clone()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn replace_file(
+    db: &DatabaseWithPolo,
+    file_path_builder: &FilePathBuilder,
+    system: &System,
+    collection_file: CollectionFile,
+    optimization: &StorageOptimization,
+) -> Result<(), Error> {
+    let file_path = file_path_builder.build_file_path(system, &collection_file)?;
+    let backup_path = get_backup_path(&file_path, &collection_file);
+    // the previous optimization was interrupted after the collection file was updated, only the
+    // backup of the original file is left
+    if backup_path.exists() && has_content(&file_path, &collection_file)? {
+        return remove_file(&backup_path).map_err(|e| {
+            Error::IoError(format!("Failed to delete file {:?}: {}", backup_path, e))
+        });
+    }
+    // the previous optimization was interrupted before the collection file was updated, the
+    // original file is in the backup
+    let is_interrupted = backup_path.exists();
+    let source_path = if is_interrupted {
+        &backup_path
+    } else {
+        &file_path
+    };
+    // missing files are left to be reported by the integrity check
+    if !source_path.exists() {
+        return Ok(());
+    }
+    let temporary_path = with_suffix(&file_path, ".optimized");
+    let optimized = optimize_file(source_path, &collection_file, optimization, &temporary_path)?;
+
+    if is_interrupted {
+        if let Some(optimized) = &optimized {
+            let new_path = file_path_builder.build_file_path(system, &optimized.collection_file)?;
+            // the optimized file was renamed into place before the app was closed
+            if has_content(&new_path, &optimized.collection_file)? {
+                let _ = remove_file(&temporary_path);
+                db.update_collection_file_content(&optimized.collection_file)?;
+                return remove_file(&backup_path).map_err(|e| {
+                    Error::IoError(format!("Failed to delete file {:?}: {}", backup_path, e))
+                });
+            }
+        }
+        rename(&backup_path, &file_path).map_err(|e| rename_error(&backup_path, &file_path, e))?;
+    }
+    let Some(optimized) = optimized else {
+        return Ok(());
+    };
+
+    let new_path = file_path_builder.build_file_path(system, &optimized.collection_file)?;
+    if new_path != file_path && new_path.exists() {
+        println!(
+            "Skipping optimization of {:?}, {:?} already exists",
+            file_path, new_path
+        );
+        let _ = remove_file(&temporary_path);
+        return Ok(());
+    }
+    if let Err(e) = rename(&file_path, &backup_path) {
+        let _ = remove_file(&temporary_path);
+        return Err(rename_error(&file_path, &backup_path, e));
+    }
+    if let Err(e) = rename(&temporary_path, &new_path) {
+        let _ = remove_file(&temporary_path);
+        rename(&backup_path, &file_path).map_err(|e| rename_error(&backup_path, &file_path, e))?;
+        return Err(rename_error(&temporary_path, &new_path, e));
+    }
+
+    if let Err(error) = db.update_collection_file_content(&optimized.collection_file) {
+        let _ = remove_file(&new_path);
+        rename(&backup_path, &file_path).map_err(|e| rename_error(&backup_path, &file_path, e))?;
+        return Err(error);
+    }
+    remove_file(&backup_path)
+        .map_err(|e| Error::IoError(format!("Failed to delete file {:?}: {}", backup_path, e)))
+}
+
+// whether the file exists with the size and checksums of the collection file
+fn has_content(file_path: &Path, collection_file: &CollectionFile) -> Result<bool, Error> {
+    if !file_path.exists() {
+        return Ok(false);
+    }
+    let (size, checksums) = compute_file_checksums(file_path)?;
+    Ok(size == collection_file.size && checksums == collection_file.checksums)
+}
+
+/// Writes the optimized version of the file to the output path and returns it when it's
+/// smaller than the file, otherwise the output is removed.
+/// This is blocking and should be run with spawn_blocking.
+fn optimize_file(
+    file_path: &Path,
+    collection_file: &CollectionFile,
+    optimization: &StorageOptimization,
+    output_path: &Path,
+) -> Result<Option<OptimizedFile>, Error> {
+    let original_size = fs::metadata(file_path)
+        .map_err(|e| Error::IoError(format!("Failed reading file {:?}: {}", file_path, e)))?
+        .len();
+    let extension = get_file_extension(Path::new(&collection_file.original_file_name))
+        .unwrap_or_default()
+        .to_lowercase();
+    let optimized = if collection_file.is_zip {
+        if optimization.recompress_archives {
+            Some(recompress_zip_file(file_path, collection_file, output_path))
+        } else {
+            None
+        }
+    } else if is_scan(&collection_file.collection_file_type)
+        && CONVERTIBLE_IMAGE_EXTENSIONS.contains(&extension.as_str())
+    {
+        optimization
+            .scan_image_format
+            .map(|format| convert_image_file(file_path, collection_file, format, output_path))
+    } else if optimization.zip_plain_files
+        && is_emulator_file(&collection_file.collection_file_type)
+    {
+        Some(zip_file(file_path, collection_file, output_path))
+    } else {
+        None
+    };
+
+    match optimized.transpose() {
+        Ok(Some(collection_file)) if collection_file.size < original_size => {
+            Ok(Some(OptimizedFile {
+                collection_file,
+                original_size,
+            }))
+        }
+        result => {
+            if output_path.exists() {
+                let _ = remove_file(output_path);
+            }
+            result.map(|_| None)
+        }
+    }
+}
+
+fn zip_file(
+    file_path: &Path,
+    collection_file: &CollectionFile,
+    output_path: &Path,
+) -> Result<CollectionFile, Error> {
+    let mut file = File::open(file_path)
+        .map_err(|e| Error::IoError(format!("Failed opening file {:?}: {}", file_path, e)))?;
+    let mut zip = ZipWriter::new(create_output_file(output_path)?);
+    // a fixed modification time makes the zipped file the same each time, so that an interrupted
+    // replacement is recognized by the checksums
+    let options = maximum_compression().last_modified_time(DateTime::default());
+    let file_info = write_zip_entry(
+        &mut zip,
+        &collection_file.original_file_name,
+        options,
+        &mut file,
+    )?;
+    finish_zip(zip)?;
+
+    let mut zipped_file = collection_file.clone();
+    zipped_file.original_file_name = replace_extension(&collection_file.original_file_name, "zip");
+    zipped_file.is_zip = true;
+    zipped_file.files = Some(vec![file_info]);
+    set_content(&mut zipped_file, output_path)?;
+    Ok(zipped_file)
+}
+
+fn recompress_zip_file(
+    file_path: &Path,
+    collection_file: &CollectionFile,
+    output_path: &Path,
+) -> Result<CollectionFile, Error> {
+    let file = File::open(file_path)
+        .map_err(|e| Error::IoError(format!("Failed opening file {:?}: {}", file_path, e)))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| {
+        Error::IoError(format!("Failed reading Zip archive {:?}: {}", file_path, e))
+    })?;
+    let mut zip = ZipWriter::new(create_output_file(output_path)?);
+    let mut file_infos = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| Error::IoError(format!("Failed to read file in Zip archive: {}", e)))?;
+        let name = entry.name().to_string();
+        let options =
+            maximum_compression().last_modified_time(entry.last_modified().unwrap_or_default());
+        if entry.is_dir() {
+            zip.add_directory(name, options)
+                .map_err(|e| Error::IoError(format!("Failed writing Zip archive: {}", e)))?;
+            continue;
+        }
+        file_infos.push(write_zip_entry(&mut zip, &name, options, &mut entry)?);
+    }
+    finish_zip(zip)?;
+
+    let mut zipped_file = collection_file.clone();
+    zipped_file.files = Some(file_infos);
+    set_content(&mut zipped_file, output_path)?;
+    Ok(zipped_file)
+}
+
+fn convert_image_file(
+    file_path: &Path,
+    collection_file: &CollectionFile,
+    format: ScanImageFormat,
+    output_path: &Path,
+) -> Result<CollectionFile, Error> {
+    let image = image::open(file_path)
+        .map_err(|e| Error::IoError(format!("Failed opening image {:?}: {}", file_path, e)))?;
+    let (image, image_format) = match format {
+        ScanImageFormat::Png => (image, ImageFormat::Png),
+        // the WebP encoder takes only 8 bit colors
+        ScanImageFormat::WebP if image.color().has_alpha() => (
+            DynamicImage::ImageRgba8(image.to_rgba8()),
+            ImageFormat::WebP,
+        ),
+        ScanImageFormat::WebP => (DynamicImage::ImageRgb8(image.to_rgb8()), ImageFormat::WebP),
+    };
+    let mut output = create_output_file(output_path)?;
+    image
+        .write_to(&mut output, image_format)
+        .map_err(|e| Error::IoError(format!("Failed converting image {:?}: {}", file_path, e)))?;
+    output
+        .flush()
+        .map_err(|e| Error::IoError(format!("Failed writing file {:?}: {}", output_path, e)))?;
+
+    let mut converted_file = collection_file.clone();
+    converted_file.original_file_name =
+        replace_extension(&collection_file.original_file_name, format.extension());
+    set_content(&mut converted_file, output_path)?;
+    Ok(converted_file)
+}
+
+fn create_output_file(output_path: &Path) -> Result<BufWriter<File>, Error> {
+    File::create(output_path)
+        .map(BufWriter::new)
+        .map_err(|e| Error::IoError(format!("Failed creating file {:?}: {}", output_path, e)))
+}
+
+fn maximum_compression() -> SimpleFileOptions {
+    SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .compression_level(Some(MAXIMUM_COMPRESSION_LEVEL))
+}
+
+/// Compresses the data read from the reader to the archive, calculating the checksums of the
+/// data on the way.
+fn write_zip_entry<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    options: SimpleFileOptions,
+    reader: &mut impl Read,
+) -> Result<FileInfo, Error> {
+    zip.start_file(name, options)
+        .map_err(|e| Error::IoError(format!("Failed writing {} to Zip archive: {}", name, e)))?;
+    let mut hasher = ChecksumHasher::new();
+    ProgressReporter::default().read_with_progress(
+        reader,
+        &format!("Compressing {}", name),
+        0,
+        |chunk| {
+            hasher.update(chunk);
+            zip.write_all(chunk)
+        },
+    )?;
+    let (size, checksums) = hasher.finalize();
+    Ok(FileInfo {
+        name: name.to_string(),
+        size,
+        checksums,
+    })
+}
+
+fn finish_zip(zip: ZipWriter<BufWriter<File>>) -> Result<(), Error> {
+    zip.finish()
+        .map_err(|e| Error::IoError(format!("Failed writing Zip archive: {}", e)))?
+        .flush()
+        .map_err(|e| Error::IoError(format!("Failed writing Zip archive: {}", e)))
+}
+
+/// Sets the size and checksums of the collection file to match the new content and changes the
+/// extension of an adopted or template based path to match the new name.
+fn set_content(collection_file: &mut CollectionFile, file_path: &Path) -> Result<(), Error> {
+    let (size, checksums) = compute_file_checksums(file_path)?;
+    collection_file.size = size;
+    collection_file.checksums = checksums;
+    if let Ok(extension) = get_file_extension(Path::new(&collection_file.original_file_name)) {
+        collection_file.relative_path = collection_file
+            .relative_path
+            .as_ref()
+            .map(|relative_path| replace_extension(relative_path, &extension));
+    }
+    Ok(())
+}
+
+fn replace_extension(file_name: &str, extension: &str) -> String {
+    Path::new(file_name)
+        .with_extension(extension)
+        .to_string_lossy()
+        .to_string()
+}
+
+// the backup is named by the id of the collection file, so that it's found by the same name
+// after the optimization has changed the name of the file
+fn get_backup_path(file_path: &Path, collection_file: &CollectionFile) -> PathBuf {
+    file_path.with_file_name(format!("{}.backup", collection_file.get_id_string()))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn rename_error(from: &Path, to: &Path, e: std::io::Error) -> Error {
+    Error::IoError(format!("Failed renaming {:?} to {:?}: {}", from, to, e))
+}
+
+fn is_scan(file_type: &CollectionFileType) -> bool {
+    matches!(
+        file_type,
+        CollectionFileType::CoverScan | CollectionFileType::Manual | CollectionFileType::Screenshot
+    )
+}
+
+fn is_emulator_file(file_type: &CollectionFileType) -> bool {
+    matches!(
+        file_type,
+        CollectionFileType::Rom | CollectionFileType::DiskImage | CollectionFileType::TapeImage
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::checksum::compute_checksums;
+
+    fn create_test_collection_file(
+        original_file_name: &str,
+        collection_file_type: CollectionFileType,
+    ) -> CollectionFile {
+        CollectionFile {
+            relative_path: Some(format!("C64/Game/{}", original_file_name)),
+            ..CollectionFile::for_test(original_file_name, collection_file_type)
+        }
+    }
+
+    #[test]
+    fn test_optimize_file() {
+        let optimization = StorageOptimization {
+            zip_plain_files: true,
+            recompress_archives: true,
+            scan_image_format: Some(ScanImageFormat::Png),
+        };
+        let disk_data = vec![0u8; 174848];
+        let disk_path = std::env::temp_dir().join("test_optimize_file.d64");
+        fs::write(&disk_path, &disk_data).unwrap();
+        let disk_file = create_test_collection_file("Game.d64", CollectionFileType::DiskImage);
+        let output_path = std::env::temp_dir().join("test_optimize_file.optimized");
+
+        let zipped = optimize_file(&disk_path, &disk_file, &optimization, &output_path)
+            .unwrap()
+            .unwrap();
+        let zipped_data = fs::read(&output_path).unwrap();
+        assert_eq!(zipped.original_size, disk_data.len() as u64);
+        assert!(zipped_data.len() < disk_data.len());
+        assert_eq!(zipped.collection_file.original_file_name, "Game.zip");
+        assert_eq!(
+            zipped.collection_file.relative_path,
+            Some("C64/Game/Game.zip".to_string())
+        );
+        assert!(zipped.collection_file.is_zip);
+        let file_infos = zipped.collection_file.files.clone().unwrap();
+        assert_eq!(file_infos[0].name, "Game.d64");
+        assert_eq!(file_infos[0].checksums, compute_checksums(&disk_data).1);
+        assert_eq!(
+            zipped.collection_file.checksums,
+            compute_checksums(&zipped_data).1
+        );
+
+        // zipping again makes the same file, an interrupted replacement is recognized by it
+        let zip_path = std::env::temp_dir().join("test_optimize_file.zip");
+        fs::rename(&output_path, &zip_path).unwrap();
+        let zipped_again = optimize_file(&disk_path, &disk_file, &optimization, &output_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(zipped_again.collection_file, zipped.collection_file);
+        assert!(has_content(&zip_path, &zipped.collection_file).unwrap());
+        assert!(!has_content(&disk_path, &zipped.collection_file).unwrap());
+
+        // a zip archive compressed at the maximum level isn't made smaller
+        assert!(optimize_file(
+            &zip_path,
+            &zipped.collection_file,
+            &optimization,
+            &output_path
+        )
+        .unwrap()
+        .is_none());
+        assert!(!output_path.exists());
+
+        let scan_path = std::env::temp_dir().join("test_optimize_file.bmp");
+        image::RgbImage::new(64, 64).save(&scan_path).unwrap();
+        let scan_file = create_test_collection_file("Cover.bmp", CollectionFileType::CoverScan);
+        let converted = optimize_file(&scan_path, &scan_file, &optimization, &output_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(converted.collection_file.original_file_name, "Cover.png");
+        assert!(!converted.collection_file.is_zip);
+        assert!(has_content(&output_path, &converted.collection_file).unwrap());
+
+        // scans aren't zipped
+        let optimization = StorageOptimization {
+            zip_plain_files: true,
+            ..Default::default()
+        };
+        assert!(
+            optimize_file(&scan_path, &scan_file, &optimization, &output_path)
+                .unwrap()
+                .is_none()
+        );
+
+        fs::remove_file(&disk_path).unwrap();
+        fs::remove_file(&zip_path).unwrap();
+        fs::remove_file(&scan_path).unwrap();
+    }
+
+    #[test]
+    fn test_replace_file_interrupted_after_update() {
+        let directory = std::env::temp_dir().join("test_replace_file_interrupted_after_update");
+        let _ = fs::remove_dir_all(&directory);
+        let game_directory = directory.join("C64").join("Game");
+        fs::create_dir_all(&game_directory).unwrap();
+        let test_db_name = "test_replace_file_interrupted_after_update.db";
+        let db = DatabaseWithPolo::new(test_db_name);
+        let file_path_builder = FilePathBuilder::new(directory.to_string_lossy().to_string());
+        let system = System {
+            _id: Some(ObjectId::new()),
+            name: "C64".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        };
+        let optimization = StorageOptimization {
+            zip_plain_files: true,
+            recompress_archives: false,
+            scan_image_format: None,
+        };
+
+        // the collection file was updated to the zipped file, but the backup of the original
+        // file wasn't removed yet
+        let disk_file = create_test_collection_file("Game.d64", CollectionFileType::DiskImage);
+        let disk_path = game_directory.join("Game.d64");
+        fs::write(&disk_path, vec![0u8; 174848]).unwrap();
+        let zip_path = game_directory.join("Game.zip");
+        let zipped = optimize_file(&disk_path, &disk_file, &optimization, &zip_path)
+            .unwrap()
+            .unwrap();
+        let backup_path = get_backup_path(&disk_path, &disk_file);
+        assert_eq!(
+            get_backup_path(&zip_path, &zipped.collection_file),
+            backup_path
+        );
+        fs::rename(&disk_path, &backup_path).unwrap();
+
+        replace_file(
+            &db,
+            &file_path_builder,
+            &system,
+            zipped.collection_file,
+            &optimization,
+        )
+        .unwrap();
+        assert!(!backup_path.exists());
+        assert!(zip_path.exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+        let _ = fs::remove_dir_all(test_db_name);
+    }
+}
@@ -1,6 +1,6 @@
 use iced::{
     widget::{row, text},
-    Task,
+    Subscription, Task,
 };
 
 use super::widgets::{
@@ -46,6 +46,17 @@ impl GamesTab {
                             .update(releases_list_widget::Message::GameSelected(game_id));
                         Task::none()
                     }
+                    games_list_widget::Message::PlayRelease(release_id) => {
+                        match self
+                            .release_details
+                            .update(release_details_widget::Message::PlayRelease(release_id))
+                        {
+                            release_details_widget::Action::Run(task) => {
+                                task.map(Message::ShowReleaseDetails)
+                            }
+                            _ => Task::none(),
+                        }
+                    }
                 }
             } // Handle other messages here
             Message::ReleaseSelected(message) => {
@@ -84,6 +95,12 @@ impl GamesTab {
         }
     }
 
+    pub fn subscription(&self) -> Subscription<Message> {
+        self.release_details
+            .subscription()
+            .map(Message::ShowReleaseDetails)
+    }
+
     pub fn view(&self) -> iced::Element<Message> {
         row![
             self.games_list.view().map(Message::GameSelected),
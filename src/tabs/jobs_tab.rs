@@ -0,0 +1,91 @@
+use bson::oid::ObjectId;
+use iced::{
+    widget::{button, column, progress_bar, row, scrollable, text, Column},
+    Task,
+};
+
+use crate::{
+    job_queue::{JobQueue, QueuedJob},
+    model::{job::JobStatus, model::HasOid},
+};
+
+pub struct JobsTab {
+    jobs: Vec<QueuedJob>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Cancel(ObjectId),
+    ClearFinished,
+}
+
+impl JobsTab {
+    pub fn new() -> Self {
+        Self {
+            jobs: JobQueue::get_instance().get_jobs(),
+            error: None,
+        }
+    }
+
+    /// Takes the current state of the jobs from the job queue.
+    pub fn refresh(&mut self) {
+        self.jobs = JobQueue::get_instance().get_jobs();
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        let queue = JobQueue::get_instance();
+        let result = match message {
+            Message::Cancel(id) => queue.cancel(&id),
+            Message::ClearFinished => queue.clear_finished(),
+        };
+        self.error = result.err().map(|e| e.to_string());
+        self.refresh();
+        Task::none()
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let title = text("Jobs").size(20);
+        let clear_button = button("Clear finished").on_press_maybe(
+            self.jobs
+                .iter()
+                .any(|queued_job| queued_job.job.status.is_finished())
+                .then_some(Message::ClearFinished),
+        );
+        let error = text(self.error.clone().unwrap_or_default());
+
+        let jobs_list = self
+            .jobs
+            .iter()
+            .map(|queued_job| {
+                let job = &queued_job.job;
+                let mut job_column = column![text(format!("{} - {}", job.kind, job.status))];
+                if let Some(progress) = &queued_job.progress {
+                    job_column = job_column
+                        .push(text(progress.to_string()))
+                        .push(progress_bar(
+                            0.0..=progress.total_bytes.max(1) as f32,
+                            progress.bytes_done as f32,
+                        ));
+                }
+                if let Some(error) = &job.error {
+                    job_column = job_column.push(text(error));
+                }
+                let cancel_button = button("Cancel").on_press_maybe(
+                    matches!(job.status, JobStatus::Queued | JobStatus::Running)
+                        .then_some(Message::Cancel(job.id())),
+                );
+                row![job_column, cancel_button].spacing(10).into()
+            })
+            .collect::<Vec<iced::Element<Message>>>();
+
+        column![
+            title,
+            clear_button,
+            error,
+            scrollable(Column::with_children(jobs_list).spacing(10))
+        ]
+        .spacing(10)
+        .into()
+    }
+}
@@ -0,0 +1,78 @@
+use iced::{widget::column, Task};
+
+use crate::{
+    error::Error,
+    model::job::{Job, JobKind},
+};
+
+use super::widgets::{
+    duplicates_widget::{self, DuplicatesWidget},
+    integrity_check_widget::{self, IntegrityCheckWidget},
+    storage_optimizer_widget::{self, StorageOptimizerWidget},
+};
+
+pub struct MaintenanceTab {
+    integrity_check: IntegrityCheckWidget,
+    duplicates: DuplicatesWidget,
+    storage_optimizer: StorageOptimizerWidget,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    IntegrityCheck(integrity_check_widget::Message),
+    Duplicates(duplicates_widget::Message),
+    StorageOptimizer(storage_optimizer_widget::Message),
+}
+
+impl MaintenanceTab {
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self {
+            integrity_check: IntegrityCheckWidget::new()?,
+            duplicates: DuplicatesWidget::new(),
+            storage_optimizer: StorageOptimizerWidget::new(),
+        })
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::IntegrityCheck(message) => self
+                .integrity_check
+                .update(message)
+                .map(Message::IntegrityCheck),
+            Message::Duplicates(message) => {
+                self.duplicates.update(message).map(Message::Duplicates)
+            }
+            Message::StorageOptimizer(message) => self
+                .storage_optimizer
+                .update(message)
+                .map(Message::StorageOptimizer),
+        }
+    }
+
+    pub fn job_finished(&mut self, job: &Job) {
+        if job.kind == JobKind::VerifyCollection {
+            if let Err(e) = self.integrity_check.refresh() {
+                println!("Failed to refresh integrity check {:?}", e);
+            }
+        }
+        if matches!(job.kind, JobKind::OptimizeStorage { .. }) {
+            self.storage_optimizer.clear_estimate();
+        }
+    }
+
+    pub fn collection_changed(&mut self) {
+        if let Err(e) = self.integrity_check.refresh() {
+            println!("Failed to refresh integrity check {:?}", e);
+        }
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        column![
+            self.integrity_check.view().map(Message::IntegrityCheck),
+            self.duplicates.view().map(Message::Duplicates),
+            self.storage_optimizer.view().map(Message::StorageOptimizer)
+        ]
+        .spacing(20)
+        .into()
+    }
+}
@@ -1,5 +1,8 @@
 pub mod games_tab;
 pub mod home_tab;
+pub mod jobs_tab;
+pub mod maintenance_tab;
 pub mod settings_tab;
+pub mod storage_tab;
 pub mod tabs_controller;
 pub mod widgets;
@@ -1,6 +1,10 @@
 use iced::Task;
 
-use crate::{error::Error, screen::settings_screen::settings_widget};
+use crate::{
+    error::Error,
+    model::job::{Job, JobKind},
+    screen::settings_screen::settings_widget,
+};
 
 pub struct SettingsTab {
     settings_widget: settings_widget::SettingsWidget,
@@ -28,6 +32,16 @@ impl SettingsTab {
         }
     }
 
+    /// Shows the new collection root after the collection has been moved.
+    pub fn job_finished(&mut self, job: &Job) {
+        if let JobKind::RelocateCollection { .. } = job.kind {
+            match settings_widget::SettingsWidget::new() {
+                Ok(settings_widget) => self.settings_widget = settings_widget,
+                Err(e) => println!("Failed to refresh settings {:?}", e),
+            }
+        }
+    }
+
     pub fn view(&self) -> iced::Element<Message> {
         self.settings_widget.view().map(Message::SettingsWidget)
     }
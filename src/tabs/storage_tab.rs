@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use iced::{
+    widget::{button, column, row, scrollable, text, Column},
+    Task,
+};
+
+use crate::{
+    error::Error,
+    files::pick_csv_file_to_save,
+    util::storage_report::{create_storage_report, storage_report_to_csv, StorageReport, UsageRow},
+};
+
+pub struct StorageTab {
+    report: Option<StorageReport>,
+    is_loading: bool,
+    status: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Refresh,
+    ReportCreated(Result<StorageReport, Error>),
+    ExportCsv,
+    CsvExported(Result<PathBuf, Error>),
+}
+
+impl StorageTab {
+    pub fn new() -> Self {
+        Self {
+            report: None,
+            is_loading: false,
+            status: None,
+        }
+    }
+
+    /// The report reads the size of each file from the collection tree, so it's created in the
+    /// background each time the tab is opened.
+    pub fn refresh(&mut self) -> Task<Message> {
+        self.is_loading = true;
+        Task::perform(create_storage_report(), Message::ReportCreated)
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Refresh => return self.refresh(),
+            Message::ReportCreated(result) => {
+                self.is_loading = false;
+                match result {
+                    Ok(report) => {
+                        self.report = Some(report);
+                        self.status = None;
+                    }
+                    Err(e) => self.status = Some(e.to_string()),
+                }
+            }
+            Message::ExportCsv => {
+                if let Some(report) = &self.report {
+                    return Task::perform(
+                        save_csv(storage_report_to_csv(report)),
+                        Message::CsvExported,
+                    );
+                }
+            }
+            Message::CsvExported(Ok(file_path)) => {
+                self.status = Some(format!("Exported to {}", file_path.display()));
+            }
+            Message::CsvExported(Err(Error::DialogClosed)) => {}
+            Message::CsvExported(Err(e)) => self.status = Some(e.to_string()),
+        }
+        Task::none()
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let title = text("Storage usage").size(20);
+        let refresh_button =
+            button("Refresh").on_press_maybe((!self.is_loading).then_some(Message::Refresh));
+        let export_button =
+            button("Export CSV").on_press_maybe(self.report.as_ref().map(|_| Message::ExportCsv));
+        let status = text(self.status.clone().unwrap_or_default());
+
+        let report: iced::Element<Message> = match &self.report {
+            Some(report) => {
+                let growth = report
+                    .growth
+                    .iter()
+                    .map(|row| {
+                        text(format!(
+                            "{}: {} files, {} added, {} in total",
+                            row.month,
+                            row.added_file_count,
+                            format_size(row.added_size),
+                            format_size(row.total_size)
+                        ))
+                        .into()
+                    })
+                    .collect::<Vec<iced::Element<Message>>>();
+                scrollable(
+                    column![
+                        text(format!(
+                            "{} files, {}",
+                            report.file_count,
+                            format_size(report.total_size)
+                        )),
+                        usage_section("By system", &report.by_system),
+                        usage_section("By file type", &report.by_file_type),
+                        usage_section("By release", &report.by_release),
+                        usage_section("Largest files", &report.largest_files),
+                        column![text("Growth").size(16), Column::with_children(growth)]
+                    ]
+                    .spacing(10),
+                )
+                .into()
+            }
+            None if self.is_loading => text("Reading the collection...").into(),
+            None => text("").into(),
+        };
+
+        column![
+            title,
+            row![refresh_button, export_button].spacing(10),
+            status,
+            report
+        ]
+        .spacing(10)
+        .into()
+    }
+}
+
+fn usage_section<'a>(title: &'a str, rows: &[UsageRow]) -> iced::Element<'a, Message> {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            text(format!(
+                "{}: {} files, {}",
+                row.name,
+                row.file_count,
+                format_size(row.size)
+            ))
+            .into()
+        })
+        .collect::<Vec<iced::Element<Message>>>();
+    column![text(title).size(16), Column::with_children(rows)].into()
+}
+
+fn format_size(size: u64) -> String {
+    format!("{:.1} MB", size as f64 / (1024.0 * 1024.0))
+}
+
+async fn save_csv(csv: String) -> Result<PathBuf, Error> {
+    let file_path = pick_csv_file_to_save("storage_usage.csv".to_string()).await?;
+    async_std::fs::write(&file_path, csv)
+        .await
+        .map_err(|e| Error::IoError(format!("Failed writing file {:?}: {}", file_path, e)))?;
+    Ok(file_path)
+}
@@ -1,14 +1,17 @@
-use iced::Task;
+use iced::{Subscription, Task};
 
-use crate::error::Error;
+use crate::{error::Error, model::job::Job};
 
-use super::{games_tab, home_tab, settings_tab};
+use super::{games_tab, home_tab, jobs_tab, maintenance_tab, settings_tab, storage_tab};
 
 #[derive(Debug, Clone)]
 pub enum Tab {
     Home,
     Settings,
     Games,
+    Maintenance,
+    Storage,
+    Jobs,
 }
 
 #[derive(Debug, Clone)]
@@ -16,6 +19,9 @@ pub enum Message {
     Home(home_tab::Message),
     Settings(settings_tab::Message),
     Games(games_tab::Message),
+    Maintenance(maintenance_tab::Message),
+    Storage(storage_tab::Message),
+    Jobs(jobs_tab::Message),
 }
 
 pub struct TabsController {
@@ -23,16 +29,23 @@ pub struct TabsController {
     home_tab: home_tab::HomeTab,
     settings_tab: settings_tab::SettingsTab,
     games_tab: games_tab::GamesTab,
+    maintenance_tab: maintenance_tab::MaintenanceTab,
+    storage_tab: storage_tab::StorageTab,
+    jobs_tab: jobs_tab::JobsTab,
 }
 
 impl TabsController {
     pub fn new(selected_tab: Option<Tab>) -> Result<Self, Error> {
         let settings_tab = settings_tab::SettingsTab::new()?;
+        let maintenance_tab = maintenance_tab::MaintenanceTab::new()?;
         Ok(Self {
             current_tab: selected_tab.unwrap_or(Tab::Home),
             home_tab: home_tab::HomeTab::new(),
             settings_tab,
             games_tab: games_tab::GamesTab::new(),
+            maintenance_tab,
+            storage_tab: storage_tab::StorageTab::new(),
+            jobs_tab: jobs_tab::JobsTab::new(),
         })
     }
 
@@ -41,6 +54,12 @@ impl TabsController {
             Message::Home(message) => self.home_tab.update(message).map(Message::Home),
             Message::Settings(message) => self.settings_tab.update(message).map(Message::Settings),
             Message::Games(message) => self.games_tab.update(message).map(Message::Games),
+            Message::Maintenance(message) => self
+                .maintenance_tab
+                .update(message)
+                .map(Message::Maintenance),
+            Message::Storage(message) => self.storage_tab.update(message).map(Message::Storage),
+            Message::Jobs(message) => self.jobs_tab.update(message).map(Message::Jobs),
         }
     }
 
@@ -49,11 +68,41 @@ impl TabsController {
             Tab::Home => self.home_tab.view().map(Message::Home),
             Tab::Settings => self.settings_tab.view().map(Message::Settings),
             Tab::Games => self.games_tab.view().map(Message::Games),
+            Tab::Maintenance => self.maintenance_tab.view().map(Message::Maintenance),
+            Tab::Storage => self.storage_tab.view().map(Message::Storage),
+            Tab::Jobs => self.jobs_tab.view().map(Message::Jobs),
         }
     }
 
+    pub fn subscription(&self) -> Subscription<Message> {
+        self.games_tab.subscription().map(Message::Games)
+    }
+
     pub fn switch_to_tab(&mut self, tab: Tab) -> Task<Message> {
+        let task = match tab {
+            Tab::Jobs => {
+                self.jobs_tab.refresh();
+                Task::none()
+            }
+            Tab::Storage => self.storage_tab.refresh().map(Message::Storage),
+            _ => Task::none(),
+        };
         self.current_tab = tab;
-        Task::none()
+        task
+    }
+
+    /// Updates the tabs showing the files flagged by the collection watcher.
+    pub fn collection_changed(&mut self) {
+        self.maintenance_tab.collection_changed();
+    }
+
+    /// Updates the tabs showing the state of background jobs after a job has reported progress
+    /// or finished.
+    pub fn jobs_updated(&mut self, finished_job: Option<&Job>) {
+        self.jobs_tab.refresh();
+        if let Some(job) = finished_job {
+            self.maintenance_tab.job_finished(job);
+            self.settings_tab.job_finished(job);
+        }
     }
 }
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use bson::oid::ObjectId;
+use iced::{
+    widget::{button, column, row, scrollable, text, Column},
+    Task,
+};
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    files::delete_file,
+    model::{
+        collection_file::CollectionFile,
+        model::{HasOid, Release, System},
+    },
+    util::{
+        duplicates::{find_duplicate_groups, get_releases_with_file, DuplicateGroup},
+        file_path_builder::FilePathBuilder,
+    },
+};
+
+pub struct DuplicatesWidget {
+    groups: Vec<DuplicateGroup>,
+    collection_files: HashMap<ObjectId, CollectionFile>,
+    releases: Vec<Release>,
+    systems: Vec<System>,
+    is_searched: bool,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    FindDuplicates,
+    KeepFile(ObjectId),
+    FileDeleted(Result<(), Error>),
+}
+
+impl DuplicatesWidget {
+    pub fn new() -> Self {
+        Self {
+            groups: vec![],
+            collection_files: HashMap::new(),
+            releases: vec![],
+            systems: vec![],
+            is_searched: false,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::FindDuplicates => {
+                self.error = None;
+                if let Err(e) = self.find_duplicates() {
+                    self.error = Some(e.to_string());
+                }
+                Task::none()
+            }
+            Message::KeepFile(keep_id) => match self.remove_duplicates_of(&keep_id) {
+                Ok(task) => task,
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    Task::none()
+                }
+            },
+            Message::FileDeleted(result) => {
+                if let Err(e) = result {
+                    self.error = Some(e.to_string());
+                }
+                Task::none()
+            }
+        }
+    }
+
+    fn find_duplicates(&mut self) -> Result<(), Error> {
+        let db = DatabaseWithPolo::get_instance();
+        let collection_files = db.get_all_collection_files()?;
+        self.releases = db.get_releases()?;
+        self.groups = find_duplicate_groups(&collection_files, &self.releases);
+        self.collection_files = collection_files
+            .into_iter()
+            .map(|collection_file| (collection_file.id(), collection_file))
+            .collect();
+        self.systems = db.get_systems()?;
+        self.is_searched = true;
+        Ok(())
+    }
+
+    /// Points the releases using the other copies to the kept file
+    /// and deletes the other copies from the collection.
+    fn remove_duplicates_of(&mut self, keep_id: &ObjectId) -> Result<Task<Message>, Error> {
+        let Some(group) = self
+            .groups
+            .iter()
+            .find(|group| group.collection_file_ids.contains(keep_id))
+        else {
+            return Ok(Task::none());
+        };
+        let duplicate_ids = group
+            .collection_file_ids
+            .iter()
+            .filter(|id| *id != keep_id)
+            .copied()
+            .collect::<Vec<ObjectId>>();
+        // the copies of a group are all in the releases of the same system
+        let system = group
+            .system_id
+            .and_then(|system_id| self.systems.iter().find(|system| system.id() == system_id));
+
+        // paths have to be resolved before the releases are pointed to the kept file
+        let db = DatabaseWithPolo::get_instance();
+        let file_path_builder = FilePathBuilder::from_settings(&db.get_settings()?);
+        let mut file_paths = HashMap::new();
+        for id in &duplicate_ids {
+            let Some(collection_file) = self.collection_files.get(id) else {
+                continue;
+            };
+            // files in no release are found without a system unless they're in the id based layout
+            let file_path = match system {
+                Some(system) => Some(file_path_builder.build_file_path(system, collection_file)?),
+                None => file_path_builder.build_file_path_without_system(collection_file)?,
+            };
+            match file_path {
+                Some(file_path) => {
+                    file_paths.insert(*id, file_path);
+                }
+                None => {
+                    return Err(Error::NotFound(format!(
+                        "The location of {} isn't known, it's in no release",
+                        collection_file
+                    )))
+                }
+            }
+        }
+
+        // content still referenced in the content store is kept
+        let removable_ids = db.merge_duplicate_collection_files(keep_id, &duplicate_ids)?;
+        file_paths.retain(|id, _| removable_ids.contains(id));
+        self.find_duplicates()?;
+
+        Ok(Task::batch(file_paths.into_values().map(|file_path| {
+            Task::perform(delete_file(file_path), Message::FileDeleted)
+        })))
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let title = text("Duplicate files").size(20);
+        let find_button = button("Find duplicates").on_press(Message::FindDuplicates);
+
+        let summary = if self.is_searched {
+            text(format!("{} files with duplicates", self.groups.len()))
+        } else {
+            text("")
+        };
+        let error = text(self.error.clone().unwrap_or_default());
+
+        let groups = self
+            .groups
+            .iter()
+            .map(|group| {
+                let files = group
+                    .collection_file_ids
+                    .iter()
+                    .filter_map(|id| self.collection_files.get(id))
+                    .map(|collection_file| {
+                        let release_names =
+                            get_releases_with_file(&collection_file.id(), &self.releases)
+                                .iter()
+                                .map(|release| release.name.clone())
+                                .collect::<Vec<String>>();
+                        row![
+                            text(format!(
+                                "{} in {}",
+                                collection_file,
+                                if release_names.is_empty() {
+                                    "no release".to_string()
+                                } else {
+                                    release_names.join(", ")
+                                }
+                            )),
+                            button("Keep this").on_press(Message::KeepFile(collection_file.id()))
+                        ]
+                        .spacing(10)
+                        .into()
+                    })
+                    .collect::<Vec<iced::Element<Message>>>();
+                column![
+                    text(format!("SHA1 {} ({} bytes)", group.sha1, group.size)),
+                    Column::with_children(files)
+                ]
+                .into()
+            })
+            .collect::<Vec<iced::Element<Message>>>();
+
+        column![
+            title,
+            find_button,
+            summary,
+            error,
+            scrollable(Column::with_children(groups).spacing(10))
+        ]
+        .spacing(10)
+        .into()
+    }
+}
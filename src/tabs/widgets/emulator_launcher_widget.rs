@@ -0,0 +1,231 @@
+use std::path::PathBuf;
+
+use bson::oid::ObjectId;
+use iced::{
+    widget::{button, column, progress_bar, row, text, Column},
+    Element, Subscription, Task,
+};
+
+use crate::{
+    emulator_runner::{
+        process_files_for_emulator, remove_launch_directory, run_with_emulator_async,
+        EmulatorRunOptions,
+    },
+    error::Error,
+    util::{
+        file_operation::{
+            run_file_operation, Cancellation, FileOperationEvent, FileOperationProgress,
+        },
+        save_data::{import_save_data, SaveData},
+    },
+};
+
+/// Extracts or copies the files for the emulator in a subscription showing the progress,
+/// then runs the emulator. The save data written by the emulator is offered for import to the
+/// release as memory snapshots. The directory of the launch is removed once its save data has
+/// been imported or discarded.
+pub struct EmulatorLauncher {
+    // options of the launch whose files are being processed
+    options: Option<Box<EmulatorRunOptions>>,
+    // identifies the running subscription, a new one is started for each launch
+    launch_id: u64,
+    cancellation: Cancellation,
+    progress: Option<FileOperationProgress>,
+    error: Option<String>,
+    // release and system of the last run, the save data is imported to the release
+    release_id: Option<ObjectId>,
+    system_id: Option<ObjectId>,
+    save_data: Vec<SaveData>,
+    // directory of the last finished run, kept while its save data is waiting to be imported or
+    // discarded, and the save data imports being read from it
+    launch_directory: Option<PathBuf>,
+    pending_imports: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Launch(Box<EmulatorRunOptions>),
+    Cancel,
+    ProcessEvent(FileOperationEvent<Box<EmulatorRunOptions>>),
+    FinishedRunningWithEmulator(PathBuf, Result<Vec<SaveData>, Error>),
+    ImportSaveData(usize),
+    SaveDataImported(Result<(), Error>),
+    DiscardSaveData,
+}
+
+impl EmulatorLauncher {
+    pub fn new() -> Self {
+        Self {
+            options: None,
+            launch_id: 0,
+            cancellation: Cancellation::default(),
+            progress: None,
+            error: None,
+            release_id: None,
+            system_id: None,
+            save_data: vec![],
+            launch_directory: None,
+            pending_imports: 0,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Launch(options) => {
+                if self.options.is_none() {
+                    self.save_data.clear();
+                    self.remove_launch_directory();
+                    self.options = Some(options);
+                    self.launch_id += 1;
+                    self.cancellation = Cancellation::default();
+                    self.error = None;
+                }
+            }
+            Message::Cancel => self.cancellation.cancel(),
+            Message::ProcessEvent(FileOperationEvent::Progress(progress)) => {
+                self.progress = Some(progress);
+            }
+            Message::ProcessEvent(FileOperationEvent::Finished(result)) => {
+                let launch_options = self.options.take();
+                self.progress = None;
+                match result {
+                    Ok(options) => {
+                        self.release_id = Some(options.release_id);
+                        self.system_id = options.system._id;
+                        let target_path = options.target_path.clone();
+                        return Task::perform(run_with_emulator_async(*options), move |result| {
+                            Message::FinishedRunningWithEmulator(target_path.clone(), result)
+                        });
+                    }
+                    Err(Error::Cancelled) => {}
+                    Err(e) => {
+                        println!("Failed to process files for emulator {:?}", e);
+                        self.error = Some(e.to_string());
+                    }
+                }
+                if let Some(launch_options) = launch_options {
+                    remove_launch_directory(&launch_options.target_path);
+                }
+            }
+            Message::FinishedRunningWithEmulator(target_path, result) => {
+                // the save data of an earlier launch not imported yet is replaced
+                self.save_data.clear();
+                self.remove_launch_directory();
+                self.launch_directory = Some(target_path);
+                match result {
+                    Ok(save_data) => {
+                        println!("Finished running with emulator");
+                        self.save_data = save_data;
+                    }
+                    Err(e) => {
+                        println!("Failed to run with emulator {:?}", e);
+                        self.error = Some(e.to_string());
+                    }
+                }
+                self.remove_launch_directory();
+            }
+            Message::ImportSaveData(index) => {
+                if let (Some(release_id), Some(system_id)) = (self.release_id, self.system_id) {
+                    if index < self.save_data.len() {
+                        let save_data = self.save_data.remove(index);
+                        self.pending_imports += 1;
+                        return Task::perform(
+                            import_save_data(save_data, release_id, system_id),
+                            Message::SaveDataImported,
+                        );
+                    }
+                }
+            }
+            Message::SaveDataImported(result) => {
+                if let Err(e) = result {
+                    self.error = Some(e.to_string());
+                }
+                self.pending_imports = self.pending_imports.saturating_sub(1);
+                self.remove_launch_directory();
+            }
+            Message::DiscardSaveData => {
+                self.save_data.clear();
+                self.remove_launch_directory();
+            }
+        }
+        Task::none()
+    }
+
+    // the directory is kept while save data in it is waiting to be imported or discarded
+    fn remove_launch_directory(&mut self) {
+        if !self.save_data.is_empty() || self.pending_imports > 0 {
+            return;
+        }
+        if let Some(launch_directory) = self.launch_directory.take() {
+            remove_launch_directory(&launch_directory);
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        match &self.options {
+            Some(options) => {
+                let options = options.clone();
+                Subscription::run_with_id(
+                    ("emulator_launch", self.launch_id),
+                    run_file_operation(self.cancellation.clone(), move |reporter| async move {
+                        process_files_for_emulator(*options, reporter)
+                            .await
+                            .map(Box::new)
+                    }),
+                )
+                .map(Message::ProcessEvent)
+            }
+            None => Subscription::none(),
+        }
+    }
+
+    pub fn view(&self) -> Element<Message> {
+        match (&self.progress, &self.error) {
+            (Some(progress), _) => row![
+                column![
+                    text(progress.to_string()),
+                    progress_bar(
+                        0.0..=progress.total_bytes.max(1) as f32,
+                        progress.bytes_done as f32
+                    ),
+                ],
+                button("Cancel")
+                    .on_press_maybe((!self.cancellation.is_cancelled()).then_some(Message::Cancel))
+            ]
+            .spacing(10)
+            .into(),
+            (None, Some(error)) => column![text(error), self.view_save_data()].into(),
+            (None, None) => self.view_save_data(),
+        }
+    }
+
+    fn view_save_data(&self) -> Element<Message> {
+        if self.save_data.is_empty() {
+            return Column::new().into();
+        }
+        let save_data_list = self
+            .save_data
+            .iter()
+            .enumerate()
+            .map(|(index, save_data)| {
+                let file_name = save_data
+                    .file_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                row![
+                    text(format!("{}: {}", save_data.kind, file_name)),
+                    button("Import").on_press(Message::ImportSaveData(index)),
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        column![
+            text("Save data written while playing:"),
+            Column::with_children(save_data_list),
+            button("Discard").on_press(Message::DiscardSaveData),
+        ]
+        .into()
+    }
+}
@@ -17,10 +17,12 @@ pub struct GamesList {
 #[derive(Debug, Clone)]
 pub enum Message {
     ViewGame(ObjectId),
+    PlayRelease(ObjectId),
 }
 
 pub enum Action {
     ViewGame(ObjectId),
+    PlayRelease(ObjectId),
 }
 
 impl GamesList {
@@ -44,6 +46,7 @@ impl GamesList {
                 println!("ViewGame message received with id: {:?}", id);
                 Action::ViewGame(id)
             }
+            Message::PlayRelease(release_id) => Action::PlayRelease(release_id),
         }
     }
 
@@ -52,6 +55,7 @@ impl GamesList {
             row![
                 text(game.name.clone()).width(iced::Length::Fixed(300.0)),
                 button("View").on_press(Message::ViewGame(game.id)),
+                button("Play").on_press_maybe(game.play_release_id.map(Message::PlayRelease)),
             ]
             .into()
         });
@@ -0,0 +1,151 @@
+use iced::{
+    widget::{button, column, progress_bar, row, scrollable, text, Column},
+    Task,
+};
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    job_queue::JobQueue,
+    model::{
+        integrity_check::{IntegrityCheck, IntegrityIssue},
+        job::JobKind,
+        model::HasOid,
+    },
+};
+
+pub struct IntegrityCheckWidget {
+    is_running: bool,
+    is_completed: bool,
+    total_files: u64,
+    checked_files: u64,
+    issues: Vec<IntegrityIssue>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Start,
+    Resume,
+    Pause,
+}
+
+impl IntegrityCheckWidget {
+    pub fn new() -> Result<Self, Error> {
+        let mut widget = Self {
+            is_running: false,
+            is_completed: false,
+            total_files: 0,
+            checked_files: 0,
+            issues: vec![],
+            error: None,
+        };
+        widget.refresh()?;
+        Ok(widget)
+    }
+
+    /// Reloads the state of the check, which is run as a job and persisted after each file.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let db = DatabaseWithPolo::get_instance();
+        let integrity_check = db.get_integrity_check()?.unwrap_or_default();
+        self.is_running =
+            JobQueue::get_instance().has_unfinished_job(|kind| *kind == JobKind::VerifyCollection);
+        self.is_completed = integrity_check.completed;
+        self.total_files = integrity_check.total_files;
+        self.checked_files = integrity_check.checked_file_ids.len() as u64;
+        self.issues = integrity_check.issues;
+        Ok(())
+    }
+
+    fn submit_check(&mut self) {
+        match JobQueue::get_instance().submit(JobKind::VerifyCollection) {
+            Ok(_) => {
+                self.is_running = true;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::Start => {
+                let db = DatabaseWithPolo::get_instance();
+                match db.save_integrity_check(&IntegrityCheck::default()) {
+                    Ok(()) => {
+                        self.is_completed = false;
+                        self.total_files = 0;
+                        self.checked_files = 0;
+                        self.issues.clear();
+                        self.submit_check();
+                    }
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+            Message::Resume => self.submit_check(),
+            // cancelling the job stops the check after the file being checked
+            Message::Pause => {
+                let queue = JobQueue::get_instance();
+                for queued_job in queue.get_jobs() {
+                    if queued_job.job.kind == JobKind::VerifyCollection
+                        && !queued_job.job.status.is_finished()
+                    {
+                        if let Err(e) = queue.cancel(&queued_job.job.id()) {
+                            self.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        Task::none()
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let title = text("Collection integrity").size(20);
+        let start_button = button("Verify collection")
+            .on_press_maybe((!self.is_running).then_some(Message::Start));
+        let resume_button = button("Resume").on_press_maybe(
+            (!self.is_running && !self.is_completed && self.checked_files > 0)
+                .then_some(Message::Resume),
+        );
+        let pause_button =
+            button("Pause").on_press_maybe(self.is_running.then_some(Message::Pause));
+        let buttons = row![start_button, resume_button, pause_button].spacing(10);
+
+        let progress = column![
+            text(format!(
+                "Checked {} / {} files, {} issues{}",
+                self.checked_files,
+                self.total_files,
+                self.issues.len(),
+                if self.is_completed {
+                    " - completed"
+                } else {
+                    ""
+                }
+            )),
+            progress_bar(
+                0.0..=self.total_files.max(1) as f32,
+                self.checked_files as f32
+            ),
+        ];
+
+        let issues = self
+            .issues
+            .iter()
+            .map(|issue| text(issue.to_string()).into())
+            .collect::<Vec<iced::Element<Message>>>();
+
+        let error = text(self.error.clone().unwrap_or_default());
+
+        column![
+            title,
+            buttons,
+            progress,
+            error,
+            scrollable(Column::with_children(issues))
+        ]
+        .spacing(10)
+        .into()
+    }
+}
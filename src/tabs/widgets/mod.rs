@@ -1,4 +1,8 @@
+pub mod duplicates_widget;
+pub mod emulator_launcher_widget;
 pub mod games_list_widget;
 pub mod image_viewer_widget;
+pub mod integrity_check_widget;
 pub mod release_details_widget;
 pub mod releases_list_widget;
+pub mod storage_optimizer_widget;
@@ -1,43 +1,72 @@
 use crate::emulator_runner::{
-    process_files_for_emulator, run_with_emulator_async, EmulatorRunOptions,
+    create_play_options, create_run_options, get_launch_directory, EmulatorRunOptions,
 };
 use crate::error::Error;
+use crate::job_queue::JobQueue;
+use crate::model::dat::VerificationStatus;
 use crate::model::model::HasOid;
+use crate::tabs::widgets::emulator_launcher_widget::{self, EmulatorLauncher};
+use crate::util::dat_verifier::verify_collection_files;
 use crate::util::file_path_builder::FilePathBuilder;
 use crate::util::image::get_thumbnail_path;
+use crate::util::patch::validate_patch_file;
+use crate::util::retroarch::expand_retroarch_emulators;
+use crate::view_model::list_models::EmulatorListModel;
 use crate::view_model::release_view_model::ReleaseViewModel;
 use crate::{
     model::{
-        collection_file::{CollectionFileType, GetFileExtensions},
-        model::{Emulator, Settings},
+        collection_file::{Checksums, CollectionFile, CollectionFileType},
+        model::{Emulator, EmulatorOverride, Settings, StartupFile},
     },
     view_model::release_view_model::get_release_view_model,
 };
 use bson::oid::ObjectId;
-use iced::widget::{button, image, pick_list, Column};
-use iced::Element;
+use iced::widget::{button, checkbox, image, pick_list, text_input, Column};
 use iced::{
     widget::{column, row, text},
     Task,
 };
+use iced::{Element, Subscription};
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::{collections::HashMap, env, vec};
+use std::vec;
 
 pub struct ReleaseDetails {
     release: Option<ReleaseViewModel>,
-    selected_file: HashMap<ObjectId, String>,
     emulators: Vec<Emulator>,
     settings: Settings,
     file_path_builder: FilePathBuilder,
+    // files on an offline storage volume can't be run or viewed
+    offline_volumes: Vec<String>,
+    // DAT verification status of the files of the release by file id and name, computed when the
+    // release is selected
+    verification_statuses: HashMap<(ObjectId, String), VerificationStatus>,
+    emulator_launcher: EmulatorLauncher,
+    // emulator override being edited, saved with the release when submitted
+    override_emulator: Option<EmulatorListModel>,
+    override_arguments: String,
+    // patches applied when the release is run, chosen for each launch
+    selected_patches: Vec<ObjectId>,
+    patch_error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ReleaseSelected(ObjectId),
     ViewImage(PathBuf),
-    RunWithEmulator(Emulator, String, CollectionFileType),
+    RunWithEmulator(Box<Emulator>, ObjectId, String),
     FileSelected(ObjectId, String),
-    FinishedRunningWithEmulator(Result<(), Error>),
+    Play,
+    PlayRelease(ObjectId),
+    EmulatorLauncher(emulator_launcher_widget::Message),
+    CopyToClipboard(String),
+    OverrideEmulatorSelected(EmulatorListModel),
+    OverrideArgumentsChanged(String),
+    SaveEmulatorOverride,
+    ClearEmulatorOverride,
+    PatchToggled(ObjectId, bool),
+    PatchBaseSelected(ObjectId, ObjectId),
+    PatchValidated(ObjectId, ObjectId, Result<(), Error>),
 }
 
 pub enum Action {
@@ -49,22 +78,31 @@ pub enum Action {
 impl ReleaseDetails {
     pub fn new() -> Self {
         let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
-        let emulators = db.get_emulators().unwrap_or_else(|err| {
-            println!("Failed to get emulators {:?}", err);
-            vec![]
-        });
+        let emulators = db
+            .get_emulators()
+            .and_then(|emulators| Ok(expand_retroarch_emulators(emulators, &db.get_systems()?)))
+            .unwrap_or_else(|err| {
+                println!("Failed to get emulators {:?}", err);
+                vec![]
+            });
         let settings = db.get_settings().unwrap_or_else(|err| {
             println!("Failed to get settings {:?}", err);
             Settings::default()
         });
-        let file_path_builder = FilePathBuilder::new(settings.collection_root_dir.clone());
+        let file_path_builder = FilePathBuilder::from_settings(&settings);
 
         Self {
             release: None,
-            selected_file: HashMap::new(),
             emulators,
             settings,
             file_path_builder,
+            offline_volumes: vec![],
+            verification_statuses: HashMap::new(),
+            emulator_launcher: EmulatorLauncher::new(),
+            override_emulator: None,
+            override_arguments: String::new(),
+            selected_patches: vec![],
+            patch_error: None,
         }
     }
 
@@ -76,62 +114,352 @@ impl ReleaseDetails {
                     println!("Failed to get release {:?}", err);
                     None
                 });
+                self.verification_statuses = match &release {
+                    Some(release) => {
+                        let dat_games = db
+                            .get_dat_games_for_system(&release.system.id())
+                            .unwrap_or_else(|err| {
+                                println!("Failed to get DAT games {:?}", err);
+                                vec![]
+                            });
+                        verify_collection_files(&release.files, &dat_games)
+                    }
+                    None => HashMap::new(),
+                };
+                self.offline_volumes = match &release {
+                    Some(release) => self.file_path_builder.get_offline_volumes(&release.files),
+                    None => vec![],
+                };
+                if let Some(release) = &release {
+                    JobQueue::get_instance().submit_missing_thumbnails(
+                        &release.files,
+                        &self.settings,
+                        &release.system,
+                    );
+                }
+                let emulator_override = release
+                    .as_ref()
+                    .and_then(|release| release.emulator_override.as_ref());
+                self.override_emulator = emulator_override.and_then(|emulator_override| {
+                    self.emulators
+                        .iter()
+                        .find(|emulator| emulator._id == Some(emulator_override.emulator_id))
+                        .map(EmulatorListModel::from)
+                });
+                self.override_arguments = emulator_override
+                    .map(|emulator_override| emulator_override.arguments.clone())
+                    .unwrap_or_default();
+                self.selected_patches.clear();
+                self.patch_error = None;
                 self.release = release;
             }
             Message::ViewImage(path) => return Action::ImageSelected(path),
             Message::FileSelected(id, file) => {
-                self.selected_file.insert(id, file);
+                if let Some(release) = &self.release {
+                    let emulator_id = release.emulator_id;
+                    self.save_startup(
+                        Some(StartupFile {
+                            collection_file_id: id,
+                            file_name: Some(file),
+                        }),
+                        emulator_id,
+                    );
+                }
             }
-            Message::RunWithEmulator(emulator, selected_file_name, selcted_file_type) => {
+            Message::RunWithEmulator(emulator, file_id, selected_file_name) => {
                 if let Some(release) = &self.release {
-                    let system = &release.system;
                     let options = EmulatorRunOptions {
-                        emulator,
-                        files: release.files.clone(),
-                        selected_file_name: selected_file_name,
-                        source_path: self
-                            .file_path_builder
-                            .build_target_directory(system, &selcted_file_type),
-                        target_path: env::temp_dir(),
+                        patches: release.get_patches(&file_id, &self.selected_patches),
+                        ..create_run_options(
+                            release,
+                            &emulator,
+                            selected_file_name,
+                            &self.file_path_builder,
+                            get_launch_directory(),
+                        )
+                    };
+                    // the file and the emulator used last are started with the Play button
+                    let startup_file = StartupFile {
+                        collection_file_id: file_id,
+                        file_name: release.get_startup_entry(&file_id),
                     };
-                    match process_files_for_emulator(&options) {
-                        Ok(_) => {}
-                        Err(e) => {
-                            println!("Failed to process files for emulator {:?}", e);
-                            return Action::None;
+                    self.save_startup(Some(startup_file), emulator._id);
+                    return self.launch(options);
+                }
+            }
+            Message::Play => {
+                if let Some(options) = self.get_play_options() {
+                    return self.launch(options);
+                }
+            }
+            Message::PlayRelease(release_id) => {
+                self.update(Message::ReleaseSelected(release_id));
+                return self.update(Message::Play);
+            }
+            Message::EmulatorLauncher(message) => {
+                return Action::Run(
+                    self.emulator_launcher
+                        .update(message)
+                        .map(Message::EmulatorLauncher),
+                );
+            }
+            Message::CopyToClipboard(value) => {
+                return Action::Run(iced::clipboard::write(value));
+            }
+            Message::OverrideEmulatorSelected(emulator) => self.override_emulator = Some(emulator),
+            Message::OverrideArgumentsChanged(arguments) => self.override_arguments = arguments,
+            Message::SaveEmulatorOverride => {
+                let emulator_override =
+                    self.override_emulator
+                        .as_ref()
+                        .map(|emulator| EmulatorOverride {
+                            emulator_id: emulator.id,
+                            arguments: self.override_arguments.trim().to_string(),
+                        });
+                self.save_emulator_override(emulator_override);
+            }
+            Message::ClearEmulatorOverride => {
+                self.override_emulator = None;
+                self.override_arguments.clear();
+                self.save_emulator_override(None);
+            }
+            Message::PatchToggled(patch_id, is_checked) => {
+                self.selected_patches.retain(|id| *id != patch_id);
+                if is_checked {
+                    self.selected_patches.push(patch_id);
+                }
+            }
+            Message::PatchBaseSelected(patch_id, base_file_id) => {
+                if let Some(release) = &self.release {
+                    let patch_file = release.files.iter().find(|f| f._id == Some(patch_id));
+                    let base_file = release.files.iter().find(|f| f._id == Some(base_file_id));
+                    if let (Some(patch_file), Some(base_file)) = (patch_file, base_file) {
+                        match self
+                            .file_path_builder
+                            .build_file_path(&release.system, patch_file)
+                        {
+                            Ok(patch_path) => {
+                                return Action::Run(Task::perform(
+                                    validate_patch_file(
+                                        patch_path,
+                                        patch_file.clone(),
+                                        base_file.clone(),
+                                    ),
+                                    move |result| {
+                                        Message::PatchValidated(patch_id, base_file_id, result)
+                                    },
+                                ));
+                            }
+                            Err(err) => self.patch_error = Some(err.to_string()),
                         }
                     }
-                    return Action::Run(Task::perform(
-                        run_with_emulator_async(options),
-                        Message::FinishedRunningWithEmulator,
-                    ));
                 }
             }
-            Message::FinishedRunningWithEmulator(result) => match result {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("Failed to run with emulator {:?}", e);
+            Message::PatchValidated(patch_id, base_file_id, result) => {
+                match result.and_then(|_| self.save_patch_base(patch_id, base_file_id)) {
+                    Ok(()) => self.patch_error = None,
+                    Err(err) => self.patch_error = Some(err.to_string()),
                 }
-            },
+            }
         }
         Action::None
     }
 
+    fn save_patch_base(&mut self, patch_id: ObjectId, base_file_id: ObjectId) -> Result<(), Error> {
+        let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
+        db.update_collection_file_base_file(&patch_id, &Some(base_file_id))?;
+        if let Some(patch_file) = self
+            .release
+            .as_mut()
+            .and_then(|release| release.files.iter_mut().find(|f| f._id == Some(patch_id)))
+        {
+            patch_file.base_file_id = Some(base_file_id);
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, options: EmulatorRunOptions) -> Action {
+        Action::Run(
+            self.emulator_launcher
+                .update(emulator_launcher_widget::Message::Launch(Box::new(options)))
+                .map(Message::EmulatorLauncher),
+        )
+    }
+
+    fn get_play_options(&self) -> Option<EmulatorRunOptions> {
+        let release = self.release.as_ref()?;
+        if !self.offline_volumes.is_empty() {
+            return None;
+        }
+        create_play_options(
+            release,
+            &self.emulators,
+            &self.file_path_builder,
+            get_launch_directory(),
+            &self.selected_patches,
+        )
+    }
+
+    fn save_startup(&mut self, startup_file: Option<StartupFile>, emulator_id: Option<ObjectId>) {
+        if let Some(release) = &mut self.release {
+            let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
+            if let Err(err) = db.update_release_startup(&release.id, &startup_file, &emulator_id) {
+                println!("Failed to save startup file {:?}", err);
+            }
+            release.startup_file = startup_file;
+            release.emulator_id = emulator_id;
+        }
+    }
+
+    fn save_emulator_override(&mut self, emulator_override: Option<EmulatorOverride>) {
+        if let Some(release) = &mut self.release {
+            let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
+            if let Err(err) = db.update_release_emulator_override(&release.id, &emulator_override) {
+                println!("Failed to save emulator override {:?}", err);
+            }
+            release.emulator_override = emulator_override;
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<Message> {
+        self.emulator_launcher
+            .subscription()
+            .map(Message::EmulatorLauncher)
+    }
+
     pub fn view(&self) -> iced::Element<Message> {
         let selected_games_list = self.create_selected_games_list();
         let emulator_files_list = self.create_emulator_files_list();
         let scan_files_list = self.create_files_list(&CollectionFileType::CoverScan);
         let screenshot_files_list = self.create_files_list(&CollectionFileType::Screenshot);
+        let checksums_list = self.create_checksums_list();
+
+        let availability = text(if self.offline_volumes.is_empty() {
+            String::new()
+        } else {
+            format!("Unavailable, {} offline", self.offline_volumes.join(", "))
+        });
 
         column![
+            availability,
+            self.create_play_button(),
             selected_games_list,
             emulator_files_list,
+            self.create_patches_list(),
+            self.create_emulator_override_view(),
+            self.emulator_launcher.view().map(Message::EmulatorLauncher),
             scan_files_list,
-            screenshot_files_list
+            screenshot_files_list,
+            checksums_list
         ]
         .into()
     }
 
+    fn create_play_button(&self) -> Element<Message> {
+        let play_button =
+            button("Play").on_press_maybe(self.get_play_options().map(|_| Message::Play));
+        let description = match self.get_play_options() {
+            Some(options) => format!(
+                "{} with {}",
+                options.selected_file_name, options.emulator.name
+            ),
+            None => String::new(),
+        };
+        row![play_button, text(description)].spacing(10).into()
+    }
+
+    fn create_patches_list(&self) -> Element<Message> {
+        let Some(release) = &self.release else {
+            return Column::new().into();
+        };
+        let base_files = release
+            .files
+            .iter()
+            .filter(|file| {
+                CollectionFileType::RUNNABLE_FILE_TYPES.contains(&file.collection_file_type)
+            })
+            .cloned()
+            .collect::<Vec<CollectionFile>>();
+        let patches_list = release
+            .files
+            .iter()
+            .filter(|file| file.collection_file_type == CollectionFileType::Patch)
+            .map(|patch_file| {
+                let patch_id = patch_file.id();
+                let base_file = base_files
+                    .iter()
+                    .find(|file| file._id.is_some() && file._id == patch_file.base_file_id)
+                    .cloned();
+                let base_file_picker = pick_list(
+                    base_files.clone(),
+                    base_file.clone(),
+                    move |base_file: CollectionFile| {
+                        Message::PatchBaseSelected(patch_id, base_file.id())
+                    },
+                )
+                .placeholder("Choose the file to patch");
+                let apply_checkbox =
+                    checkbox("Apply when run", self.selected_patches.contains(&patch_id))
+                        .on_toggle_maybe(base_file.map(|_| {
+                            move |is_checked| Message::PatchToggled(patch_id, is_checked)
+                        }));
+                row![
+                    text(patch_file.to_string()),
+                    base_file_picker,
+                    apply_checkbox
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+        if patches_list.is_empty() {
+            return Column::new().into();
+        }
+        column![
+            text("Patches:"),
+            Column::with_children(patches_list),
+            text(self.patch_error.clone().unwrap_or_default())
+        ]
+        .into()
+    }
+
+    fn create_emulator_override_view(&self) -> Element<Message> {
+        let Some(release) = &self.release else {
+            return Column::new().into();
+        };
+        let emulators = release
+            .system
+            .order_emulators(&self.emulators)
+            .into_iter()
+            .map(EmulatorListModel::from)
+            .collect::<Vec<EmulatorListModel>>();
+        let emulator_picker = pick_list(
+            emulators,
+            self.override_emulator.clone(),
+            Message::OverrideEmulatorSelected,
+        );
+        let arguments_input = text_input("Extra arguments", &self.override_arguments)
+            .on_input(Message::OverrideArgumentsChanged);
+        row![
+            text("Emulator override"),
+            emulator_picker,
+            arguments_input,
+            button("Save").on_press_maybe(
+                self.override_emulator
+                    .as_ref()
+                    .map(|_| Message::SaveEmulatorOverride)
+            ),
+            button("Clear").on_press_maybe(
+                release
+                    .emulator_override
+                    .as_ref()
+                    .map(|_| Message::ClearEmulatorOverride)
+            ),
+        ]
+        .spacing(10)
+        .into()
+    }
+
     fn create_selected_games_list(&self) -> Element<Message> {
         let selected_games_title = text("Games in release:");
         if let Some(release) = &self.release {
@@ -147,6 +475,55 @@ impl ReleaseDetails {
         selected_games_title.into()
     }
 
+    fn create_checksums_list(&self) -> Element<Message> {
+        if let Some(release) = &self.release {
+            let files_list = release
+                .files
+                .iter()
+                .map(|file| {
+                    // DAT entries describe the files inside archives, not the archives
+                    let file_label = if file.is_zip {
+                        String::new()
+                    } else {
+                        self.get_verification_label(file, &file.original_file_name)
+                    };
+                    let mut file_column = column![
+                        text!(
+                            "{} ({} bytes){}",
+                            file.original_file_name,
+                            file.size,
+                            file_label
+                        ),
+                        create_checksums_view(&file.checksums)
+                    ];
+                    if let Some(files) = &file.files {
+                        for file_info in files {
+                            file_column = file_column.push(text!(
+                                "  {} ({} bytes){}",
+                                file_info.name,
+                                file_info.size,
+                                self.get_verification_label(file, &file_info.name)
+                            ));
+                            file_column =
+                                file_column.push(create_checksums_view(&file_info.checksums));
+                        }
+                    }
+                    file_column.into()
+                })
+                .collect::<Vec<Element<Message>>>();
+            column![text("Checksums:"), Column::with_children(files_list)].into()
+        } else {
+            Column::new().into()
+        }
+    }
+
+    fn get_verification_label(&self, file: &CollectionFile, file_name: &str) -> String {
+        self.verification_statuses
+            .get(&(file.id(), file_name.to_string()))
+            .map(|status| format!(" - {}", status))
+            .unwrap_or_default()
+    }
+
     fn create_files_list(&self, file_type: &CollectionFileType) -> Element<Message> {
         if let Some(release) = &self.release {
             let scan_files_list = release
@@ -154,21 +531,22 @@ impl ReleaseDetails {
                 .iter()
                 .filter(|f| f.collection_file_type == *file_type)
                 .filter_map(|file| {
-                    if let Ok(thumb_path) =
-                        get_thumbnail_path(file, &self.settings, &release.system)
-                    {
-                        if let Ok(file_path) = self
-                            .file_path_builder
-                            .build_file_path(&release.system, file)
-                        {
-                            let image = image(thumb_path);
-                            let view_image_button =
-                                button(image).on_press(Message::ViewImage(file_path));
-                            return Some(row![view_image_button].into());
-                        }
+                    let file_path = self
+                        .file_path_builder
+                        .build_file_path(&release.system, file)
+                        .ok()?;
+                    let thumb_path = get_thumbnail_path(file, &self.settings);
+                    let view_image_button = if thumb_path.exists() {
+                        button(image(thumb_path))
+                    } else {
+                        button(text(file.to_string()))
                     }
-
-                    None
+                    .on_press_maybe(
+                        self.file_path_builder
+                            .is_volume_online(file.volume.as_deref())
+                            .then_some(Message::ViewImage(file_path)),
+                    );
+                    Some(row![view_image_button].into())
                 })
                 .collect::<Vec<iced::Element<Message>>>();
 
@@ -180,26 +558,23 @@ impl ReleaseDetails {
 
     fn create_emulator_files_list(&self) -> Element<Message> {
         if let Some(release) = &self.release {
-            let emulators_for_system = self
-                .emulators
-                .iter()
-                .filter(|emulator| {
-                    emulator
-                        .system_id
-                        .map_or(false, |system_id| system_id == release.system.id())
-                })
+            let emulators_for_system = release
+                .get_emulators(&self.emulators)
+                .into_iter()
+                // the release can't be run while some of its files are offline
+                .filter(|_| self.offline_volumes.is_empty())
                 .collect::<Vec<&Emulator>>();
 
-            // TODO: get file types supported by emulators for the selected system
-
             let files_list = release
                 .files
                 .iter()
+                // media files are listed even when no emulator has been added for them
                 .filter(|f| {
-                    // TODO: emulator should know it's supported file types and we would filter by the file types supported by emulator
-                    f.collection_file_type == CollectionFileType::Rom
-                        || f.collection_file_type == CollectionFileType::DiskImage
-                        || f.collection_file_type == CollectionFileType::TapeImage
+                    f.collection_file_type.is_media()
+                        || release
+                            .get_emulators(&self.emulators)
+                            .iter()
+                            .any(|emulator| emulator.supports_file(f))
                 })
                 .map(|file| {
                     let container_filename = text(file.to_string());
@@ -210,49 +585,25 @@ impl ReleaseDetails {
                     };
                     let file_picker = pick_list(
                         content_files,
-                        if self.selected_file.contains_key(&file.id()) {
-                            Some(self.selected_file.get(&file.id()).unwrap())
-                        } else {
-                            None
-                        },
+                        release.get_startup_entry(&file.id()),
                         move |selected_file_name| {
                             Message::FileSelected(file.id().clone(), selected_file_name)
                         },
                     );
                     let emulator_buttons = emulators_for_system
                         .iter()
-                        .filter(|e| {
-                            e.supported_file_type_extensions.is_empty()
-                                || e.supported_file_type_extensions.contains(
-                                    &file
-                                        .original_file_name
-                                        .split('.')
-                                        .last()
-                                        .unwrap()
-                                        .to_string(),
-                                )
-                                || file.get_file_extensions().into_iter().any(|extension| {
-                                    e.supported_file_type_extensions.contains(&extension)
-                                })
-                        })
+                        .filter(|emulator| emulator.supports_file(file))
                         .map(|emulator| {
                             button(emulator.name.as_str())
-                                .on_press_maybe({
-                                    let selected_file = self.selected_file.get(&file.id());
-                                    match (selected_file, emulator.extract_files) {
-                                        (Some(file_name), true) => Some(Message::RunWithEmulator(
-                                            (*emulator).clone(),
-                                            file_name.clone(),
-                                            file.collection_file_type.clone(),
-                                        )),
-                                        (_, false) => Some(Message::RunWithEmulator(
-                                            (*emulator).clone(),
-                                            file.clone().original_file_name,
-                                            file.collection_file_type.clone(),
-                                        )),
-                                        (_, _) => None,
-                                    }
-                                })
+                                .on_press_maybe(release.get_file_name_to_run(emulator, file).map(
+                                    |file_name| {
+                                        Message::RunWithEmulator(
+                                            Box::new((*emulator).clone()),
+                                            file.id(),
+                                            file_name,
+                                        )
+                                    },
+                                ))
                                 .into()
                         })
                         .collect::<Vec<iced::Element<Message>>>();
@@ -271,3 +622,26 @@ impl ReleaseDetails {
         }
     }
 }
+
+fn create_checksums_view(checksums: &Checksums) -> Element<Message> {
+    if checksums.is_empty() {
+        return text("  No checksums available").into();
+    }
+    let rows = [
+        ("CRC32", &checksums.crc32),
+        ("MD5", &checksums.md5),
+        ("SHA1", &checksums.sha1),
+        ("SHA256", &checksums.sha256),
+    ]
+    .into_iter()
+    .map(|(label, value)| {
+        row![
+            text(label).width(iced::Length::Fixed(80.0)),
+            text(value.clone()),
+            button("Copy").on_press(Message::CopyToClipboard(value.clone())),
+        ]
+        .into()
+    })
+    .collect::<Vec<Element<Message>>>();
+    Column::with_children(rows).into()
+}
@@ -6,6 +6,7 @@ use iced::{
 
 use crate::{
     model::model::Game,
+    util::file_path_builder::FilePathBuilder,
     view_model::list_models::{get_releases_in_list_model, ReleaseListModel},
 };
 
@@ -37,7 +38,10 @@ impl ReleasesList {
         match message {
             Message::GameSelected(game_id) => {
                 let db = crate::database_with_polo::DatabaseWithPolo::get_instance();
-                let releases = get_releases_in_list_model(db, &game_id);
+                let releases = db.get_settings().and_then(|settings| {
+                    let file_path_builder = FilePathBuilder::from_settings(&settings);
+                    get_releases_in_list_model(db, &game_id, &file_path_builder)
+                });
                 let game = db.get_game(&game_id);
                 self.releases = releases.unwrap_or_else(|err| {
                     println!("Failed to get releases list {:?}", err);
@@ -67,6 +71,7 @@ impl ReleasesList {
                 let release_row = row![
                     text(&release.name).width(Length::Fixed(100.0)),
                     view_release_button,
+                    text(release.availability()),
                 ];
 
                 release_row.into()
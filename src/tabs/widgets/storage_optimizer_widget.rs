@@ -0,0 +1,171 @@
+use iced::{
+    widget::{button, checkbox, column, pick_list, row, text, Column},
+    Task,
+};
+
+use crate::{
+    error::Error,
+    job_queue::JobQueue,
+    model::{
+        job::JobKind,
+        model::{ScanImageFormat, StorageOptimization},
+    },
+    storage_optimizer::{estimate_savings, SystemSavings},
+};
+
+pub struct StorageOptimizerWidget {
+    optimization: StorageOptimization,
+    scan_image_format: ScanImageFormat,
+    is_estimating: bool,
+    savings: Option<Vec<SystemSavings>>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ZipPlainFilesToggled(bool),
+    RecompressArchivesToggled(bool),
+    ConvertScansToggled(bool),
+    ScanImageFormatSelected(ScanImageFormat),
+    Estimate,
+    Estimated(Result<Vec<SystemSavings>, Error>),
+    Optimize,
+}
+
+impl StorageOptimizerWidget {
+    pub fn new() -> Self {
+        Self {
+            optimization: StorageOptimization::default(),
+            scan_image_format: ScanImageFormat::default(),
+            is_estimating: false,
+            savings: None,
+            error: None,
+        }
+    }
+
+    /// The estimate is outdated once the files have been optimized.
+    pub fn clear_estimate(&mut self) {
+        self.savings = None;
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::ZipPlainFilesToggled(zip_plain_files) => {
+                self.optimization.zip_plain_files = zip_plain_files;
+                self.savings = None;
+            }
+            Message::RecompressArchivesToggled(recompress_archives) => {
+                self.optimization.recompress_archives = recompress_archives;
+                self.savings = None;
+            }
+            Message::ConvertScansToggled(convert_scans) => {
+                self.optimization.scan_image_format =
+                    convert_scans.then_some(self.scan_image_format);
+                self.savings = None;
+            }
+            Message::ScanImageFormatSelected(scan_image_format) => {
+                self.scan_image_format = scan_image_format;
+                if self.optimization.scan_image_format.is_some() {
+                    self.optimization.scan_image_format = Some(scan_image_format);
+                }
+                self.savings = None;
+            }
+            Message::Estimate => {
+                self.is_estimating = true;
+                self.error = None;
+                return Task::perform(
+                    estimate_savings(self.optimization.clone()),
+                    Message::Estimated,
+                );
+            }
+            Message::Estimated(result) => {
+                self.is_estimating = false;
+                match result {
+                    Ok(savings) => self.savings = Some(savings),
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+            Message::Optimize => {
+                match JobQueue::get_instance().submit(JobKind::OptimizeStorage {
+                    optimization: self.optimization.clone(),
+                }) {
+                    Ok(_) => self.error = None,
+                    Err(e) => self.error = Some(e.to_string()),
+                }
+            }
+        }
+        Task::none()
+    }
+
+    pub fn view(&self) -> iced::Element<Message> {
+        let title = text("Storage optimization").size(20);
+        let zip_plain_files_checkbox = checkbox(
+            "Zip roms, disk and tape images",
+            self.optimization.zip_plain_files,
+        )
+        .on_toggle(Message::ZipPlainFilesToggled);
+        let recompress_archives_checkbox = checkbox(
+            "Recompress zip archives",
+            self.optimization.recompress_archives,
+        )
+        .on_toggle(Message::RecompressArchivesToggled);
+        let convert_scans_checkbox = checkbox(
+            "Convert BMP and TIFF scans to",
+            self.optimization.scan_image_format.is_some(),
+        )
+        .on_toggle(Message::ConvertScansToggled);
+        let scan_image_format_picker = pick_list(
+            vec![ScanImageFormat::Png, ScanImageFormat::WebP],
+            Some(self.scan_image_format),
+            Message::ScanImageFormatSelected,
+        );
+
+        let is_optimizing = JobQueue::get_instance()
+            .has_unfinished_job(|kind| matches!(kind, JobKind::OptimizeStorage { .. }));
+        let has_selection = self.optimization != StorageOptimization::default();
+        let estimate_button = button("Estimate savings")
+            .on_press_maybe((has_selection && !self.is_estimating).then_some(Message::Estimate));
+        let optimize_button = button("Optimize")
+            .on_press_maybe((has_selection && !is_optimizing).then_some(Message::Optimize));
+
+        let report = match &self.savings {
+            Some(savings) if savings.is_empty() => column![text("Nothing to optimize")],
+            Some(savings) => {
+                let rows = savings
+                    .iter()
+                    .map(|system_savings| {
+                        text(format!(
+                            "{}: {} files, {} -> {} bytes, saves {} bytes",
+                            system_savings.system_name,
+                            system_savings.file_count,
+                            system_savings.current_size,
+                            system_savings.optimized_size,
+                            system_savings.saved_bytes()
+                        ))
+                        .into()
+                    })
+                    .collect::<Vec<iced::Element<Message>>>();
+                let total_saved_bytes = savings.iter().map(SystemSavings::saved_bytes).sum::<u64>();
+                column![
+                    Column::with_children(rows),
+                    text(format!("Saves {} bytes in total", total_saved_bytes))
+                ]
+            }
+            None if self.is_estimating => column![text("Estimating...")],
+            None => column![],
+        };
+        let error = text(self.error.clone().unwrap_or_default());
+
+        column![
+            title,
+            zip_plain_files_checkbox,
+            recompress_archives_checkbox,
+            row![convert_scans_checkbox, scan_image_format_picker].spacing(10),
+            row![estimate_button, optimize_button].spacing(10),
+            report,
+            error
+        ]
+        .spacing(10)
+        .into()
+    }
+}
@@ -33,6 +33,18 @@ impl TitleBar {
         let home_button = button("Home").on_press(Message::TabSelected(Tab::Home));
         let settings_button = button("Settings").on_press(Message::TabSelected(Tab::Settings));
         let games_button = button("Games").on_press(Message::TabSelected(Tab::Games));
-        row![home_button, settings_button, games_button].into()
+        let maintenance_button =
+            button("Maintenance").on_press(Message::TabSelected(Tab::Maintenance));
+        let storage_button = button("Storage").on_press(Message::TabSelected(Tab::Storage));
+        let jobs_button = button("Jobs").on_press(Message::TabSelected(Tab::Jobs));
+        row![
+            home_button,
+            settings_button,
+            games_button,
+            maintenance_button,
+            storage_button,
+            jobs_button
+        ]
+        .into()
     }
 }
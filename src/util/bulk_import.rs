@@ -0,0 +1,657 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    content_store::remove_collection_file,
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    file_importer::{import_collection_file, is_same_import, ImportTarget},
+    files::{read_picked_file, ScannedFile},
+    model::{
+        collection_file::{CollectionFile, CollectionFileType},
+        model::{FileImportMode, Game, HasOid, Release, System},
+    },
+    repository::repository::{CollectionFilesReadRepository, ReleaseReadRepository},
+    util::{
+        checksum::compute_file_checksums, file_operation::ProgressReporter,
+        file_path_builder::FilePathBuilder,
+    },
+};
+
+/// A file imported for a release, kept for rolling back the import when the release can't be
+/// completed.
+struct ImportedFile {
+    collection_file: CollectionFile,
+    source: PathBuf,
+    // the file was adopted where it was in the collection directory or on a storage volume
+    is_adopted: bool,
+    // the source is gone after a Move import, renamed into the collection or removed after
+    // copying
+    source_moved: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposedFile {
+    pub file_path: PathBuf,
+    pub file_name: String,
+    pub file_type: CollectionFileType,
+    pub disk_number: Option<u32>,
+}
+
+/// A game and a release with its files proposed from the files of a scanned folder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportProposal {
+    pub game_name: String,
+    pub release_name: String,
+    pub files: Vec<ProposedFile>,
+    pub accepted: bool,
+}
+
+impl ImportProposal {
+    pub fn is_multi_disk(&self) -> bool {
+        self.files
+            .iter()
+            .filter(|file| file.disk_number.is_some())
+            .count()
+            > 1
+    }
+}
+
+/// Splits a file name without extension to a title and the tags in parentheses and brackets,
+/// e.g. "Game (Europe) (Disk 1 of 2)[cr]" => ("Game", ["(Europe)", "(Disk 1 of 2)", "[cr]"]).
+fn split_tags(file_stem: &str) -> (String, Vec<String>) {
+    let mut title = String::new();
+    let mut tags = Vec::new();
+    let mut tag = String::new();
+    let mut closing = None;
+    for c in file_stem.chars() {
+        match (closing, c) {
+            (None, '(') => {
+                closing = Some(')');
+                tag.push(c);
+            }
+            (None, '[') => {
+                closing = Some(']');
+                tag.push(c);
+            }
+            (None, _) => title.push(c),
+            (Some(closing_char), _) => {
+                tag.push(c);
+                if c == closing_char {
+                    tags.push(std::mem::take(&mut tag));
+                    closing = None;
+                }
+            }
+        }
+    }
+    (collapse_whitespace(&title.replace('_', " ")), tags)
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn get_file_stem(file_name: &str) -> &str {
+    Path::new(file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(file_name)
+}
+
+fn is_disk_tag(tag: &str) -> bool {
+    let tag = tag.to_lowercase();
+    let inner = tag.trim_matches(|c| c == '(' || c == ')' || c == '[' || c == ']');
+    ["disk", "disc", "side", "tape"]
+        .iter()
+        .any(|prefix| inner.starts_with(prefix))
+}
+
+/// Returns the title without tags, used as the game name.
+pub fn normalize_title(file_name: &str) -> String {
+    split_tags(get_file_stem(file_name)).0
+}
+
+/// Returns the title with tags other than disk, side and tape numbers, used as the release name.
+pub fn get_release_name(file_name: &str) -> String {
+    let (title, tags) = split_tags(get_file_stem(file_name));
+    let tags = tags
+        .into_iter()
+        .filter(|tag| !is_disk_tag(tag))
+        .collect::<Vec<String>>();
+    if tags.is_empty() {
+        title
+    } else {
+        format!("{} {}", title, tags.join(" "))
+    }
+}
+
+/// Finds the disk number from tags like "(Disk 2 of 3)", "(Disc 2)" or "(Side B)".
+pub fn get_disk_number(file_name: &str) -> Option<u32> {
+    let (_, tags) = split_tags(get_file_stem(file_name));
+    tags.iter().filter(|tag| is_disk_tag(tag)).find_map(|tag| {
+        let tag = tag.to_lowercase();
+        let mut words = tag
+            .trim_matches(|c| c == '(' || c == ')' || c == '[' || c == ']')
+            .split_whitespace();
+        let _ = words.next();
+        let number = words.next()?;
+        number.parse::<u32>().ok().or_else(|| {
+            let mut chars = number.chars();
+            match (chars.next(), chars.next()) {
+                (Some(side @ 'a'..='z'), None) => Some(side as u32 - 'a' as u32 + 1),
+                _ => None,
+            }
+        })
+    })
+}
+
+/// Guesses the file type from the extension. Images in a folder named
+/// like screenshots or snaps are guessed to be screenshots, others cover scans.
+pub fn guess_file_type(file_path: &Path) -> Option<CollectionFileType> {
+    let extension = file_path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "a26" | "a78" | "bin" | "col" | "crt" | "gb" | "gba" | "gbc" | "gen" | "gg" | "lnx"
+        | "md" | "n64" | "nds" | "nes" | "pce" | "rom" | "sfc" | "smc" | "smd" | "sms" | "v64"
+        | "ws" | "wsc" | "z64" => Some(CollectionFileType::Rom),
+        "adf" | "atr" | "chd" | "cue" | "d64" | "d71" | "d81" | "dms" | "dsk" | "g64" | "gdi"
+        | "hdf" | "img" | "ipf" | "iso" | "msa" | "nib" | "st" => {
+            Some(CollectionFileType::DiskImage)
+        }
+        "cas" | "cdt" | "t64" | "tap" | "tzx" | "uef" => Some(CollectionFileType::TapeImage),
+        "pdf" | "txt" => Some(CollectionFileType::Manual),
+        "bps" | "ips" | "ups" => Some(CollectionFileType::Patch),
+        "bmp" | "gif" | "jpeg" | "jpg" | "png" | "webp" => {
+            let is_screenshot = file_path
+                .parent()
+                .map(|parent| parent.to_string_lossy().to_lowercase())
+                .is_some_and(|parent| parent.contains("screenshot") || parent.contains("snap"));
+            if is_screenshot {
+                Some(CollectionFileType::Screenshot)
+            } else {
+                Some(CollectionFileType::CoverScan)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Guesses the type of a scanned file. Zip archives get the type of the files inside them.
+fn guess_scanned_file_type(scanned_file: &ScannedFile) -> Option<CollectionFileType> {
+    if scanned_file.archived_file_names.is_empty() {
+        guess_file_type(&scanned_file.file_path)
+    } else {
+        scanned_file
+            .archived_file_names
+            .iter()
+            .find_map(|name| guess_file_type(Path::new(name)))
+    }
+}
+
+/// Groups the scanned files to releases by the release name. Files of unknown type are left out.
+pub fn create_proposals(scanned_files: &[ScannedFile]) -> Vec<ImportProposal> {
+    let mut proposals: BTreeMap<String, ImportProposal> = BTreeMap::new();
+    for scanned_file in scanned_files {
+        let (Some(file_type), Some(file_name)) = (
+            guess_scanned_file_type(scanned_file),
+            scanned_file
+                .file_path
+                .file_name()
+                .and_then(|file_name| file_name.to_str()),
+        ) else {
+            continue;
+        };
+        let release_name = get_release_name(file_name);
+        let proposal = proposals
+            .entry(release_name.to_lowercase())
+            .or_insert_with(|| ImportProposal {
+                game_name: normalize_title(file_name),
+                release_name,
+                files: vec![],
+                accepted: true,
+            });
+        proposal.files.push(ProposedFile {
+            file_path: scanned_file.file_path.clone(),
+            file_name: file_name.to_string(),
+            file_type,
+            disk_number: get_disk_number(file_name),
+        });
+    }
+
+    proposals
+        .into_values()
+        .map(|mut proposal| {
+            proposal
+                .files
+                .sort_by(|a, b| (a.disk_number, &a.file_name).cmp(&(b.disk_number, &b.file_name)));
+            proposal
+        })
+        .collect()
+}
+
+/// Brings the files of the proposal to the collection and adds the release with the given id.
+/// The collection files are added with the given ids, one for each file of the proposal.
+/// Files already in the collection directory are adopted where they are.
+/// An existing game with the same name is used for the release.
+/// An import interrupted by closing the app can be run again: when the release exists the
+/// import is complete, and the files imported with the given ids before the release was added
+/// are used instead of importing them again.
+pub async fn import_proposal(
+    proposal: ImportProposal,
+    release_id: ObjectId,
+    collection_file_ids: Vec<ObjectId>,
+    system: System,
+    file_path_builder: FilePathBuilder,
+    file_import_mode: FileImportMode,
+    reporter: ProgressReporter,
+) -> Result<ObjectId, Error> {
+    let db = DatabaseWithPolo::get_instance();
+    if db.get_release(&release_id)?.is_some() {
+        return Ok(release_id);
+    }
+    let mut interrupted_files = db.get_collection_files(&collection_file_ids)?;
+    let existing_game = db
+        .get_all_games()?
+        .into_iter()
+        .find(|game| game.name.eq_ignore_ascii_case(&proposal.game_name));
+    let game_id = match existing_game {
+        Some(game) => game.id(),
+        None => db.add_game(&Game {
+            _id: None,
+            name: proposal.game_name.clone(),
+        })?,
+    };
+
+    let mut imported_files = Vec::new();
+    let target = ImportTarget {
+        file_path_builder: &file_path_builder,
+        system: &system,
+        release_name: &proposal.release_name,
+        release_id: None,
+    };
+    let result = import_proposed_files(
+        &proposal.files,
+        &collection_file_ids,
+        &target,
+        &file_import_mode,
+        &reporter,
+        &mut interrupted_files,
+        &mut imported_files,
+    )
+    .await
+    .and_then(|_| {
+        db.add_release(&Release {
+            _id: Some(release_id),
+            name: proposal.release_name.clone(),
+            system_id: system._id,
+            files: imported_files
+                .iter()
+                .map(|imported_file| imported_file.collection_file.id())
+                .collect(),
+            games: vec![game_id],
+            startup_file: None,
+            emulator_id: None,
+            emulator_override: None,
+            media: vec![],
+        })
+    });
+
+    if result.is_err() {
+        remove_imported_files(&imported_files, &system, &file_path_builder).await;
+    }
+    result
+}
+
+/// Collects the imported files for rolling back the import. A file already imported by an
+/// interrupted import is taken from the interrupted files by its id when its checksums match
+/// the source, or the file in the collection when the source was moved.
+async fn import_proposed_files(
+    proposed_files: &[ProposedFile],
+    collection_file_ids: &[ObjectId],
+    target: &ImportTarget<'_>,
+    file_import_mode: &FileImportMode,
+    reporter: &ProgressReporter,
+    interrupted_files: &mut Vec<CollectionFile>,
+    imported_files: &mut Vec<ImportedFile>,
+) -> Result<(), Error> {
+    for (proposed_file, collection_file_id) in proposed_files.iter().zip(collection_file_ids) {
+        let interrupted_file = interrupted_files
+            .iter()
+            .position(|interrupted_file| interrupted_file._id == Some(*collection_file_id))
+            .map(|index| interrupted_files.remove(index));
+        if !proposed_file.file_path.exists() {
+            if let Some(interrupted_file) = interrupted_file {
+                // the moved file is checked where it was moved to in the collection
+                let file_path = target
+                    .file_path_builder
+                    .build_file_path(target.system, &interrupted_file)?;
+                let (size, checksums) =
+                    spawn_blocking(move || compute_file_checksums(&file_path)).await?;
+                let is_same_file =
+                    size == interrupted_file.size && checksums == interrupted_file.checksums;
+                imported_files.push(ImportedFile {
+                    collection_file: interrupted_file,
+                    source: proposed_file.file_path.clone(),
+                    is_adopted: false,
+                    source_moved: true,
+                });
+                if !is_same_file {
+                    return Err(get_changed_file_error(proposed_file));
+                }
+                continue;
+            }
+        }
+        let picked_file = read_picked_file(&proposed_file.file_path, reporter).await?;
+        let (volume, relative_path) = target
+            .file_path_builder
+            .get_relative_path(&proposed_file.file_path)
+            .unzip();
+        let collection_file = CollectionFile {
+            _id: Some(*collection_file_id),
+            original_file_name: picked_file.file_name.clone(),
+            is_zip: picked_file.is_zip,
+            files: picked_file.files.clone(),
+            collection_file_type: proposed_file.file_type.clone(),
+            size: picked_file.size,
+            checksums: picked_file.checksums.clone(),
+            relative_path,
+            volume: volume.flatten(),
+            content_key: None,
+            base_file_id: None,
+        };
+        let is_adopted = collection_file.relative_path.is_some();
+        if let Some(interrupted_file) = interrupted_file {
+            let is_same_file = is_same_import(&interrupted_file, &collection_file);
+            imported_files.push(ImportedFile {
+                collection_file: interrupted_file,
+                source: proposed_file.file_path.clone(),
+                is_adopted,
+                source_moved: false,
+            });
+            if !is_same_file {
+                return Err(get_changed_file_error(proposed_file));
+            }
+            continue;
+        }
+        let collection_file = import_collection_file(
+            proposed_file.file_path.clone(),
+            collection_file,
+            target,
+            file_import_mode.clone(),
+            reporter,
+        )
+        .await?;
+        imported_files.push(ImportedFile {
+            collection_file,
+            source: proposed_file.file_path.clone(),
+            is_adopted,
+            source_moved: !is_adopted && *file_import_mode == FileImportMode::Move,
+        });
+    }
+    Ok(())
+}
+
+fn get_changed_file_error(proposed_file: &ProposedFile) -> Error {
+    Error::IoError(format!(
+        "{} was changed after the interrupted import, import the release again",
+        proposed_file.file_name
+    ))
+}
+
+/// Removes the files imported for a release that couldn't be completed. Adopted files stay
+/// where they are, moved files are put back at their source and content in the content store
+/// stays while other collection files reference it.
+async fn remove_imported_files(
+    imported_files: &[ImportedFile],
+    system: &System,
+    file_path_builder: &FilePathBuilder,
+) {
+    let db = DatabaseWithPolo::get_instance();
+    for imported_file in imported_files {
+        let collection_file = &imported_file.collection_file;
+        let result = if imported_file.is_adopted {
+            db.delete_collection_file(&collection_file.id())
+        } else if imported_file.source_moved {
+            match file_path_builder.build_file_path(system, collection_file) {
+                Ok(file_path) => match move_back_imported_file(&file_path, imported_file).await {
+                    // the content in the content store is removed when it's not referenced anymore
+                    Ok(()) if collection_file.content_key.is_some() => {
+                        remove_collection_file(collection_file, system, file_path_builder).await
+                    }
+                    Ok(()) => db.delete_collection_file(&collection_file.id()),
+                    Err(err) => Err(err),
+                },
+                Err(err) => Err(err),
+            }
+        } else {
+            remove_collection_file(collection_file, system, file_path_builder).await
+        };
+        if let Err(err) = result {
+            println!("Failed to remove imported file: {}", err);
+        }
+    }
+}
+
+/// Puts a file moved to the collection back at its source. Content in the content store is
+/// copied back since other collection files may reference it, other files are moved back.
+async fn move_back_imported_file(
+    file_path: &Path,
+    imported_file: &ImportedFile,
+) -> Result<(), Error> {
+    let move_back_error = |e: std::io::Error| {
+        Error::IoError(format!(
+            "Failed to move {:?} back to {:?}: {}",
+            file_path, imported_file.source, e
+        ))
+    };
+    if imported_file.collection_file.content_key.is_none()
+        && async_std::fs::rename(file_path, &imported_file.source)
+            .await
+            .is_ok()
+    {
+        return Ok(());
+    }
+    // renaming fails across file systems, the file is copied back instead
+    async_std::fs::copy(file_path, &imported_file.source)
+        .await
+        .map_err(move_back_error)?;
+    if imported_file.collection_file.content_key.is_none() {
+        async_std::fs::remove_file(file_path)
+            .await
+            .map_err(move_back_error)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::checksum::compute_checksums;
+
+    fn create_scanned_file(file_path: &str, archived_file_names: Vec<&str>) -> ScannedFile {
+        ScannedFile {
+            file_path: PathBuf::from(file_path),
+            archived_file_names: archived_file_names
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_titles_and_disk_numbers() {
+        let file_name = "Last Ninja 2_ Back with a Vengeance (1988)(System 3)(Disk 2 of 2)[cr].d64";
+        assert_eq!(
+            normalize_title(file_name),
+            "Last Ninja 2 Back with a Vengeance"
+        );
+        assert_eq!(
+            get_release_name(file_name),
+            "Last Ninja 2 Back with a Vengeance (1988) (System 3) [cr]"
+        );
+        assert_eq!(get_disk_number(file_name), Some(2));
+        assert_eq!(get_disk_number("Game (Europe) (Side B).d64"), Some(2));
+        assert_eq!(get_disk_number("Game (Europe).d64"), None);
+    }
+
+    #[test]
+    fn test_guess_file_type() {
+        assert_eq!(
+            guess_file_type(Path::new("games/Game.D64")),
+            Some(CollectionFileType::DiskImage)
+        );
+        assert_eq!(
+            guess_file_type(Path::new("games/Game.tap")),
+            Some(CollectionFileType::TapeImage)
+        );
+        assert_eq!(
+            guess_file_type(Path::new("games/Snaps/Game.png")),
+            Some(CollectionFileType::Screenshot)
+        );
+        assert_eq!(
+            guess_file_type(Path::new("games/Covers/Game.png")),
+            Some(CollectionFileType::CoverScan)
+        );
+        assert_eq!(
+            guess_file_type(Path::new("games/Game (Translation).ips")),
+            Some(CollectionFileType::Patch)
+        );
+        assert_eq!(guess_file_type(Path::new("games/Game.nfo")), None);
+    }
+
+    #[test]
+    fn test_create_proposals() {
+        let scanned_files = vec![
+            create_scanned_file("c64/Game (Europe) (Disk 2 of 2).d64", vec![]),
+            create_scanned_file("c64/Game (Europe) (Disk 1 of 2).d64", vec![]),
+            create_scanned_file("c64/Game (USA).zip", vec!["Game (USA).tap"]),
+            create_scanned_file("c64/Game (Europe).nfo", vec![]),
+        ];
+        let proposals = create_proposals(&scanned_files);
+        assert_eq!(proposals.len(), 2);
+
+        let europe = &proposals[0];
+        assert_eq!(europe.game_name, "Game");
+        assert_eq!(europe.release_name, "Game (Europe)");
+        assert!(europe.is_multi_disk());
+        assert_eq!(europe.files[0].file_name, "Game (Europe) (Disk 1 of 2).d64");
+        assert_eq!(europe.files[1].disk_number, Some(2));
+
+        let usa = &proposals[1];
+        assert_eq!(usa.game_name, "Game");
+        assert!(!usa.is_multi_disk());
+        assert_eq!(usa.files[0].file_type, CollectionFileType::TapeImage);
+    }
+
+    #[test]
+    fn test_move_back_imported_file() {
+        let directory = std::env::temp_dir().join("test_move_back_imported_file");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("collection")).unwrap();
+        // the first file of a Move import was moved to the collection before the second file
+        // failed, the original is gone from the source
+        let source = directory.join("Game (Disk 1 of 2).d64");
+        let file_path = directory.join("collection").join("1.d64");
+        std::fs::write(&file_path, b"disk 1").unwrap();
+        let mut imported_file = ImportedFile {
+            collection_file: CollectionFile {
+                size: 6,
+                ..CollectionFile::for_test("Game (Disk 1 of 2).d64", CollectionFileType::DiskImage)
+            },
+            source: source.clone(),
+            is_adopted: false,
+            source_moved: true,
+        };
+
+        async_std::task::block_on(move_back_imported_file(&file_path, &imported_file)).unwrap();
+        assert_eq!(std::fs::read(&source).unwrap(), b"disk 1");
+        assert!(!file_path.exists());
+
+        // content in the content store is copied back, other collection files may reference it
+        std::fs::rename(&source, &file_path).unwrap();
+        imported_file.collection_file.content_key = Some("content".to_string());
+        async_std::task::block_on(move_back_imported_file(&file_path, &imported_file)).unwrap();
+        assert_eq!(std::fs::read(&source).unwrap(), b"disk 1");
+        assert!(file_path.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_resume_moved_files() {
+        let directory = std::env::temp_dir().join("test_resume_moved_files");
+        let _ = std::fs::remove_dir_all(&directory);
+        let collection_directory = directory.join("collection");
+        std::fs::create_dir_all(&collection_directory).unwrap();
+        let file_path_builder =
+            FilePathBuilder::new(collection_directory.to_string_lossy().to_string());
+        let system = System {
+            _id: Some(ObjectId::new()),
+            name: "System".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        };
+        // the interrupted Move import moved both files to the collection, the second one has
+        // changed since
+        std::fs::write(collection_directory.join("Disk 1.d64"), b"disk 1").unwrap();
+        std::fs::write(collection_directory.join("Disk 2.d64"), b"changed").unwrap();
+        let (size, checksums) = compute_checksums(b"disk 1");
+        let create_interrupted_file = |name: &str| CollectionFile {
+            size,
+            checksums: checksums.clone(),
+            relative_path: Some(name.to_string()),
+            ..CollectionFile::for_test(name, CollectionFileType::DiskImage)
+        };
+        let disk_1 = create_interrupted_file("Disk 1.d64");
+        let disk_2 = create_interrupted_file("Disk 2.d64");
+        let collection_file_ids = vec![disk_1.id(), disk_2.id()];
+        // a file with the same name imported by another job isn't taken
+        let mut interrupted_files = vec![create_interrupted_file("Disk 1.d64"), disk_1, disk_2];
+        let proposed_files = ["Disk 1.d64", "Disk 2.d64"]
+            .iter()
+            .map(|file_name| ProposedFile {
+                file_path: directory.join(file_name),
+                file_name: file_name.to_string(),
+                file_type: CollectionFileType::DiskImage,
+                disk_number: None,
+            })
+            .collect::<Vec<ProposedFile>>();
+        let target = ImportTarget {
+            file_path_builder: &file_path_builder,
+            system: &system,
+            release_name: "Game",
+            release_id: None,
+        };
+
+        let mut imported_files = vec![];
+        let result = async_std::task::block_on(import_proposed_files(
+            &proposed_files,
+            &collection_file_ids,
+            &target,
+            &FileImportMode::Move,
+            &ProgressReporter::default(),
+            &mut interrupted_files,
+            &mut imported_files,
+        ));
+        assert!(result.is_err());
+        // both files are rolled back, the changed file too
+        assert_eq!(
+            imported_files
+                .iter()
+                .map(|imported_file| imported_file.collection_file.id())
+                .collect::<Vec<ObjectId>>(),
+            collection_file_ids
+        );
+        assert!(imported_files
+            .iter()
+            .all(|imported_file| imported_file.source_moved));
+        assert_eq!(interrupted_files.len(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
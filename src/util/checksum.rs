@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crc32fast::Hasher as Crc32Hasher;
+use md5::Md5;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{
+    error::Error, model::collection_file::Checksums, util::file_operation::ProgressReporter,
+};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Computes CRC32, MD5, SHA1 and SHA256 in a single pass over the data.
+pub struct ChecksumHasher {
+    crc32: Crc32Hasher,
+    md5: Md5,
+    sha1: Sha1,
+    sha256: Sha256,
+    size: u64,
+}
+
+impl ChecksumHasher {
+    pub fn new() -> Self {
+        Self {
+            crc32: Crc32Hasher::new(),
+            md5: Md5::new(),
+            sha1: Sha1::new(),
+            sha256: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc32.update(data);
+        self.md5.update(data);
+        self.sha1.update(data);
+        self.sha256.update(data);
+        self.size += data.len() as u64;
+    }
+
+    /// Returns the size of the hashed data in bytes and the checksums.
+    pub fn finalize(self) -> (u64, Checksums) {
+        let checksums = Checksums {
+            crc32: format!("{:08x}", self.crc32.finalize()),
+            md5: format!("{:x}", self.md5.finalize()),
+            sha1: format!("{:x}", self.sha1.finalize()),
+            sha256: format!("{:x}", self.sha256.finalize()),
+        };
+        (self.size, checksums)
+    }
+}
+
+#[cfg(test)]
+pub fn compute_checksums(data: &[u8]) -> (u64, Checksums) {
+    let mut hasher = ChecksumHasher::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+pub fn compute_reader_checksums<R: Read>(reader: &mut R) -> std::io::Result<(u64, Checksums)> {
+    let mut hasher = ChecksumHasher::new();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+pub fn compute_file_checksums(file_path: &Path) -> Result<(u64, Checksums), Error> {
+    let mut file = File::open(file_path).map_err(|e| {
+        Error::IoError(format!(
+            "Failed opening file {} for checksum calculation: {}",
+            file_path.display(),
+            e
+        ))
+    })?;
+    compute_reader_checksums(&mut file).map_err(|e| {
+        Error::IoError(format!(
+            "Failed reading file {} for checksum calculation: {}",
+            file_path.display(),
+            e
+        ))
+    })
+}
+
+/// Computes the checksums of a file and reports the progress.
+/// This is blocking and should be run with spawn_blocking.
+pub fn compute_file_checksums_with_progress(
+    file_path: &Path,
+    reporter: &ProgressReporter,
+) -> Result<(u64, Checksums), Error> {
+    let mut file = File::open(file_path).map_err(|e| {
+        Error::IoError(format!(
+            "Failed opening file {} for checksum calculation: {}",
+            file_path.display(),
+            e
+        ))
+    })?;
+    let total_bytes = file.metadata().map(|m| m.len()).unwrap_or_default();
+    let description = format!(
+        "Calculating checksums of {}",
+        file_path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut hasher = ChecksumHasher::new();
+    reporter.read_with_progress(&mut file, &description, total_bytes, |chunk| {
+        hasher.update(chunk);
+        Ok(())
+    })?;
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_compute_checksums() {
+        let (size, checksums) = compute_checksums(b"hello world");
+        assert_eq!(size, 11);
+        assert_eq!(checksums.crc32, "0d4a1185");
+        assert_eq!(checksums.md5, "5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(checksums.sha1, "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+        assert_eq!(
+            checksums.sha256,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn test_compute_reader_checksums_matches_single_pass() {
+        let data = vec![0xAB; BUFFER_SIZE * 2 + 17];
+        let from_reader = compute_reader_checksums(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(from_reader, compute_checksums(&data));
+    }
+}
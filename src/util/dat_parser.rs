@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+use crate::{
+    error::Error,
+    model::dat::{DatFile, DatGame, DatRom, DumpStatus},
+};
+
+#[derive(Debug, Clone)]
+pub struct ParsedDat {
+    pub dat_file: DatFile,
+    pub games: Vec<DatGame>,
+}
+
+pub async fn load_dat_file(file_path: PathBuf) -> Result<ParsedDat, Error> {
+    let xml = async_std::fs::read_to_string(&file_path)
+        .await
+        .map_err(|e| {
+            Error::IoError(format!(
+                "Failed reading DAT file {}: {}",
+                file_path.display(),
+                e
+            ))
+        })?;
+    parse_dat(&xml)
+}
+
+/// Parses a DAT file in Logiqx XML format, used by No-Intro, TOSEC and Redump.
+pub fn parse_dat(xml: &str) -> Result<ParsedDat, Error> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document = Document::parse_with_options(xml, options)
+        .map_err(|e| Error::ParseError(format!("Failed parsing DAT file: {}", e)))?;
+
+    let root = document.root_element();
+    if !root.has_tag_name("datafile") {
+        return Err(Error::ParseError(format!(
+            "Expected datafile element, found {}",
+            root.tag_name().name()
+        )));
+    }
+
+    let header = root.children().find(|node| node.has_tag_name("header"));
+    let dat_file = DatFile {
+        _id: None,
+        system_id: None,
+        name: header
+            .and_then(|header| child_text(header, "name"))
+            .unwrap_or_else(|| "Unnamed DAT".to_string()),
+        description: header.and_then(|header| child_text(header, "description")),
+        version: header.and_then(|header| child_text(header, "version")),
+    };
+
+    // MAME derived DATs use machine instead of game
+    let games = root
+        .children()
+        .filter(|node| node.has_tag_name("game") || node.has_tag_name("machine"))
+        .map(parse_game)
+        .collect::<Result<Vec<DatGame>, Error>>()?;
+
+    Ok(ParsedDat { dat_file, games })
+}
+
+fn parse_game(node: Node) -> Result<DatGame, Error> {
+    let name = node
+        .attribute("name")
+        .ok_or_else(|| Error::ParseError("Game without a name in DAT file".to_string()))?;
+    let roms = node
+        .children()
+        .filter(|child| child.has_tag_name("rom"))
+        .map(parse_rom)
+        .collect::<Result<Vec<DatRom>, Error>>()?;
+
+    Ok(DatGame {
+        _id: None,
+        dat_file_id: None,
+        system_id: None,
+        name: name.to_string(),
+        description: child_text(node, "description"),
+        roms,
+    })
+}
+
+fn parse_rom(node: Node) -> Result<DatRom, Error> {
+    let name = node
+        .attribute("name")
+        .ok_or_else(|| Error::ParseError("Rom without a name in DAT file".to_string()))?;
+    let size = match node.attribute("size") {
+        Some(size) => Some(size.parse::<u64>().map_err(|_| {
+            Error::ParseError(format!(
+                "Invalid size {} for rom {} in DAT file",
+                size, name
+            ))
+        })?),
+        None => None,
+    };
+    let status = match node.attribute("status") {
+        Some("baddump") => DumpStatus::BadDump,
+        Some("nodump") => DumpStatus::NoDump,
+        Some("verified") => DumpStatus::Verified,
+        _ => DumpStatus::Good,
+    };
+
+    Ok(DatRom {
+        name: name.to_string(),
+        size,
+        crc32: checksum_attribute(node, "crc"),
+        md5: checksum_attribute(node, "md5"),
+        sha1: checksum_attribute(node, "sha1"),
+        status,
+    })
+}
+
+fn checksum_attribute(node: Node, name: &str) -> Option<String> {
+    node.attribute(name)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_lowercase())
+}
+
+fn child_text(node: Node, tag_name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name(tag_name))
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_DAT: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+    <header>
+        <name>Commodore - 64</name>
+        <description>Commodore - 64</description>
+        <version>20240101-000000</version>
+    </header>
+    <game name="Game A (Europe)">
+        <description>Game A (Europe)</description>
+        <rom name="Game A (Europe).d64" size="174848" crc="ABCDEF01" md5="0123456789ABCDEF0123456789ABCDEF" sha1="0123456789abcdef0123456789abcdef01234567"/>
+    </game>
+    <game name="Game B (USA)">
+        <description>Game B (USA)</description>
+        <rom name="Game B (USA) (Disk 1).d64" size="174848" crc="11111111" status="baddump"/>
+        <rom name="Game B (USA) (Disk 2).d64" size="174848" crc="22222222"/>
+    </game>
+</datafile>"#;
+
+    #[test]
+    fn test_parse_dat() {
+        let parsed = parse_dat(TEST_DAT).unwrap();
+        assert_eq!(parsed.dat_file.name, "Commodore - 64");
+        assert_eq!(parsed.dat_file.version, Some("20240101-000000".to_string()));
+        assert_eq!(parsed.games.len(), 2);
+
+        let game_a = &parsed.games[0];
+        assert_eq!(game_a.name, "Game A (Europe)");
+        assert_eq!(game_a.roms.len(), 1);
+        assert_eq!(game_a.roms[0].size, Some(174848));
+        assert_eq!(game_a.roms[0].crc32, Some("abcdef01".to_string()));
+        assert_eq!(
+            game_a.roms[0].md5,
+            Some("0123456789abcdef0123456789abcdef".to_string())
+        );
+        assert_eq!(game_a.roms[0].status, DumpStatus::Good);
+
+        let game_b = &parsed.games[1];
+        assert_eq!(game_b.roms.len(), 2);
+        assert_eq!(game_b.roms[0].status, DumpStatus::BadDump);
+        assert_eq!(game_b.roms[1].sha1, None);
+    }
+
+    #[test]
+    fn test_parse_dat_rejects_other_xml() {
+        let result = parse_dat("<softwarelist name=\"c64_flop\"></softwarelist>");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+
+use bson::oid::ObjectId;
+
+use crate::model::{
+    collection_file::{Checksums, CollectionFile},
+    dat::{DatGame, DatReport, DatRom, DumpStatus, VerificationStatus},
+    model::HasOid,
+};
+
+/// Returns the name, size and checksums of the files that can be matched against DAT entries:
+/// the files inside a zip archive or the collection file itself.
+pub fn get_verifiable_files(collection_file: &CollectionFile) -> Vec<(&str, u64, &Checksums)> {
+    match (&collection_file.files, collection_file.is_zip) {
+        (Some(files), true) => files
+            .iter()
+            .map(|file| (file.name.as_str(), file.size, &file.checksums))
+            .collect(),
+        _ => vec![(
+            collection_file.original_file_name.as_str(),
+            collection_file.size,
+            &collection_file.checksums,
+        )],
+    }
+}
+
+fn rom_matches(rom: &DatRom, size: u64, checksums: &Checksums) -> bool {
+    if checksums.is_empty() {
+        return false;
+    }
+    if rom.size.is_some_and(|rom_size| rom_size != size) {
+        return false;
+    }
+    // prefer the strongest checksum available in both
+    if let Some(sha1) = &rom.sha1 {
+        if !checksums.sha1.is_empty() {
+            return sha1.eq_ignore_ascii_case(&checksums.sha1);
+        }
+    }
+    if let Some(md5) = &rom.md5 {
+        if !checksums.md5.is_empty() {
+            return md5.eq_ignore_ascii_case(&checksums.md5);
+        }
+    }
+    if let Some(crc32) = &rom.crc32 {
+        if !checksums.crc32.is_empty() {
+            return crc32.eq_ignore_ascii_case(&checksums.crc32);
+        }
+    }
+    false
+}
+
+pub fn verify_checksums(
+    size: u64,
+    checksums: &Checksums,
+    dat_games: &[DatGame],
+) -> VerificationStatus {
+    let matching_statuses = dat_games
+        .iter()
+        .flat_map(|game| game.roms.iter())
+        .filter(|rom| rom_matches(rom, size, checksums))
+        .map(|rom| &rom.status)
+        .collect::<Vec<&DumpStatus>>();
+
+    if matching_statuses.is_empty() {
+        VerificationStatus::Unknown
+    } else if matching_statuses
+        .iter()
+        .any(|status| **status != DumpStatus::BadDump)
+    {
+        VerificationStatus::Verified
+    } else {
+        VerificationStatus::BadDump
+    }
+}
+
+/// Verifies the verifiable files of the collection files, keyed by the id of the collection file
+/// and the name of the verified file. Nothing is verified when there are no DAT games.
+pub fn verify_collection_files(
+    collection_files: &[CollectionFile],
+    dat_games: &[DatGame],
+) -> HashMap<(ObjectId, String), VerificationStatus> {
+    if dat_games.is_empty() {
+        return HashMap::new();
+    }
+    collection_files
+        .iter()
+        .flat_map(|collection_file| {
+            get_verifiable_files(collection_file)
+                .into_iter()
+                .map(|(name, size, checksums)| {
+                    (
+                        (collection_file.id(), name.to_string()),
+                        verify_checksums(size, checksums, dat_games),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Checks which DAT games are fully present in the collection files.
+/// Roms marked as nodump are not expected to be found.
+pub fn create_dat_report(dat_games: &[DatGame], collection_files: &[CollectionFile]) -> DatReport {
+    let verifiable_files = collection_files
+        .iter()
+        .flat_map(get_verifiable_files)
+        .collect::<Vec<(&str, u64, &Checksums)>>();
+    let sha1s = verifiable_files
+        .iter()
+        .map(|(_, _, checksums)| checksums.sha1.to_lowercase())
+        .filter(|sha1| !sha1.is_empty())
+        .collect::<HashSet<String>>();
+
+    let mut report = DatReport::default();
+    for game in dat_games {
+        let is_complete = game
+            .roms
+            .iter()
+            .filter(|rom| rom.status != DumpStatus::NoDump)
+            .all(|rom| {
+                rom.sha1.as_ref().is_some_and(|sha1| sha1s.contains(sha1))
+                    || verifiable_files
+                        .iter()
+                        .any(|(_, size, checksums)| rom_matches(rom, *size, checksums))
+            });
+        if is_complete {
+            report.have.push(game.name.clone());
+        } else {
+            report.missing.push(game.name.clone());
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::{CollectionFileType, FileInfo};
+
+    fn create_test_rom(name: &str, crc32: &str, status: DumpStatus) -> DatRom {
+        DatRom {
+            name: name.to_string(),
+            size: Some(4),
+            crc32: Some(crc32.to_string()),
+            md5: None,
+            sha1: None,
+            status,
+        }
+    }
+
+    fn create_test_game(name: &str, roms: Vec<DatRom>) -> DatGame {
+        DatGame {
+            _id: None,
+            dat_file_id: None,
+            system_id: None,
+            name: name.to_string(),
+            description: None,
+            roms,
+        }
+    }
+
+    fn create_test_checksums(crc32: &str) -> Checksums {
+        Checksums {
+            crc32: crc32.to_string(),
+            md5: "md5".to_string(),
+            sha1: "sha1".to_string(),
+            sha256: "sha256".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_verify_checksums() {
+        let games = vec![
+            create_test_game(
+                "Good",
+                vec![create_test_rom("good.rom", "aaaaaaaa", DumpStatus::Good)],
+            ),
+            create_test_game(
+                "Bad",
+                vec![create_test_rom("bad.rom", "bbbbbbbb", DumpStatus::BadDump)],
+            ),
+        ];
+
+        assert_eq!(
+            verify_checksums(4, &create_test_checksums("AAAAAAAA"), &games),
+            VerificationStatus::Verified
+        );
+        assert_eq!(
+            verify_checksums(4, &create_test_checksums("bbbbbbbb"), &games),
+            VerificationStatus::BadDump
+        );
+        assert_eq!(
+            verify_checksums(5, &create_test_checksums("aaaaaaaa"), &games),
+            VerificationStatus::Unknown
+        );
+        assert_eq!(
+            verify_checksums(4, &Checksums::default(), &games),
+            VerificationStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_verify_collection_files() {
+        let games = vec![create_test_game(
+            "Good",
+            vec![create_test_rom("good.rom", "aaaaaaaa", DumpStatus::Good)],
+        )];
+        let collection_file = CollectionFile {
+            size: 4,
+            checksums: create_test_checksums("aaaaaaaa"),
+            ..CollectionFile::for_test("good.rom", CollectionFileType::Rom)
+        };
+        let zip_file = CollectionFile {
+            is_zip: true,
+            files: Some(vec![FileInfo {
+                name: "other.rom".to_string(),
+                size: 4,
+                checksums: create_test_checksums("cccccccc"),
+            }]),
+            ..CollectionFile::for_test("other.zip", CollectionFileType::Rom)
+        };
+        let collection_files = vec![collection_file, zip_file];
+
+        let statuses = verify_collection_files(&collection_files, &games);
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(
+            statuses.get(&(collection_files[0].id(), "good.rom".to_string())),
+            Some(&VerificationStatus::Verified)
+        );
+        assert_eq!(
+            statuses.get(&(collection_files[1].id(), "other.rom".to_string())),
+            Some(&VerificationStatus::Unknown)
+        );
+        assert!(verify_collection_files(&collection_files, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_create_dat_report() {
+        let games = vec![
+            create_test_game(
+                "Complete",
+                vec![
+                    create_test_rom("disk1.d64", "11111111", DumpStatus::Good),
+                    create_test_rom("disk2.d64", "22222222", DumpStatus::NoDump),
+                ],
+            ),
+            create_test_game(
+                "Missing",
+                vec![create_test_rom("other.d64", "33333333", DumpStatus::Good)],
+            ),
+        ];
+        let collection_files = vec![CollectionFile {
+            _id: None,
+            is_zip: true,
+            files: Some(vec![FileInfo {
+                name: "disk1.d64".to_string(),
+                size: 4,
+                checksums: create_test_checksums("11111111"),
+            }]),
+            size: 100,
+            checksums: create_test_checksums("99999999"),
+            ..CollectionFile::for_test("complete.zip", CollectionFileType::DiskImage)
+        }];
+
+        let report = create_dat_report(&games, &collection_files);
+        assert_eq!(report.have, vec!["Complete".to_string()]);
+        assert_eq!(report.missing, vec!["Missing".to_string()]);
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use bson::oid::ObjectId;
+
+use crate::{
+    model::{
+        collection_file::{CollectionFile, CollectionFileType},
+        model::Release,
+    },
+    util::dat_verifier::get_verifiable_files,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    // the copies are in the releases of the same system and have the same file type, so the
+    // kept file is found at the same path by all the releases
+    pub system_id: Option<ObjectId>,
+    pub collection_file_type: CollectionFileType,
+    pub sha1: String,
+    pub size: u64,
+    pub collection_file_ids: Vec<ObjectId>,
+}
+
+/// Returns the SHA1 checksums of the collection file itself and the files inside it.
+pub fn get_content_sha1s(collection_file: &CollectionFile) -> HashSet<String> {
+    get_verifiable_files(collection_file)
+        .into_iter()
+        .map(|(_, _, checksums)| checksums)
+        .chain(std::iter::once(&collection_file.checksums))
+        .filter(|checksums| !checksums.sha1.is_empty())
+        .map(|checksums| checksums.sha1.to_lowercase())
+        .collect()
+}
+
+/// Finds the existing collection files which have the same content as the given file,
+/// either as a whole or as a file inside a zip archive.
+pub fn find_files_with_same_content<'a>(
+    collection_file: &CollectionFile,
+    collection_files: &'a [CollectionFile],
+) -> Vec<&'a CollectionFile> {
+    let sha1s = get_content_sha1s(collection_file);
+    if sha1s.is_empty() {
+        return vec![];
+    }
+    collection_files
+        .iter()
+        .filter(|existing| collection_file._id.is_none() || existing._id != collection_file._id)
+        .filter(|existing| !get_content_sha1s(existing).is_disjoint(&sha1s))
+        .collect()
+}
+
+/// Groups the collection files which are identical copies of each other by the system of their
+/// releases, the file type and SHA1. The path of a file stored by id depends on the system and
+/// the file type, so only copies found at the same path by all the releases are grouped.
+/// Files in releases of several systems are left out. Only groups with more than one stored
+/// copy are returned, collection files referencing the same content in the content store share
+/// one copy.
+pub fn find_duplicate_groups(
+    collection_files: &[CollectionFile],
+    releases: &[Release],
+) -> Vec<DuplicateGroup> {
+    type GroupKey = (Option<ObjectId>, String, String);
+    let mut groups: BTreeMap<GroupKey, DuplicateGroup> = BTreeMap::new();
+    let mut stored_copies: HashMap<GroupKey, HashSet<String>> = HashMap::new();
+    for collection_file in collection_files {
+        let (Some(id), false) = (
+            collection_file._id,
+            collection_file.checksums.sha1.is_empty(),
+        ) else {
+            continue;
+        };
+        let Some(system_id) = get_file_system_id(&id, releases) else {
+            continue;
+        };
+        let sha1 = collection_file.checksums.sha1.to_lowercase();
+        let key = (
+            system_id,
+            collection_file.collection_file_type.directory().to_string(),
+            sha1.clone(),
+        );
+        stored_copies.entry(key.clone()).or_default().insert(
+            collection_file
+                .content_key
+                .clone()
+                .unwrap_or_else(|| id.to_hex()),
+        );
+        groups
+            .entry(key)
+            .or_insert_with(|| DuplicateGroup {
+                system_id,
+                collection_file_type: collection_file.collection_file_type.clone(),
+                sha1,
+                size: collection_file.size,
+                collection_file_ids: vec![],
+            })
+            .collection_file_ids
+            .push(id);
+    }
+    groups
+        .into_iter()
+        .filter(|(key, _)| stored_copies[key].len() > 1)
+        .map(|(_, group)| group)
+        .collect()
+}
+
+// the system of the releases of the file, a file without releases has no system and a file in
+// releases of several systems is left out with None
+fn get_file_system_id(
+    collection_file_id: &ObjectId,
+    releases: &[Release],
+) -> Option<Option<ObjectId>> {
+    let mut system_ids = get_releases_with_file(collection_file_id, releases)
+        .into_iter()
+        .map(|release| release.system_id)
+        .collect::<Vec<Option<ObjectId>>>();
+    system_ids.sort();
+    system_ids.dedup();
+    match system_ids.as_slice() {
+        [] => Some(None),
+        [system_id] => Some(*system_id),
+        _ => None,
+    }
+}
+
+pub fn get_releases_with_file<'a>(
+    collection_file_id: &ObjectId,
+    releases: &'a [Release],
+) -> Vec<&'a Release> {
+    releases
+        .iter()
+        .filter(|release| release.files.contains(collection_file_id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::{Checksums, CollectionFileType, FileInfo};
+
+    fn create_test_checksums(sha1: &str) -> Checksums {
+        Checksums {
+            sha1: sha1.to_string(),
+            ..Checksums::default()
+        }
+    }
+
+    fn create_test_collection_file(sha1: &str, files: Option<Vec<FileInfo>>) -> CollectionFile {
+        CollectionFile {
+            is_zip: files.is_some(),
+            files,
+            size: 4,
+            checksums: create_test_checksums(sha1),
+            ..CollectionFile::for_test("Test file", CollectionFileType::Rom)
+        }
+    }
+
+    #[test]
+    fn test_find_files_with_same_content() {
+        let zipped = create_test_collection_file(
+            "aaaa",
+            Some(vec![FileInfo {
+                name: "game.rom".to_string(),
+                size: 4,
+                checksums: create_test_checksums("BBBB"),
+            }]),
+        );
+        let other = create_test_collection_file("cccc", None);
+        let existing = vec![zipped.clone(), other];
+
+        let new_file = CollectionFile {
+            _id: None,
+            ..create_test_collection_file("bbbb", None)
+        };
+        let matches = find_files_with_same_content(&new_file, &existing);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0]._id, zipped._id);
+
+        let without_checksums = CollectionFile {
+            _id: None,
+            ..create_test_collection_file("", None)
+        };
+        assert!(find_files_with_same_content(&without_checksums, &existing).is_empty());
+    }
+
+    fn create_test_release(system_id: ObjectId, files: Vec<&CollectionFile>) -> Release {
+        Release {
+            system_id: Some(system_id),
+            files: files.into_iter().filter_map(|file| file._id).collect(),
+            ..Release::default()
+        }
+    }
+
+    #[test]
+    fn test_find_duplicate_groups() {
+        let system_id = ObjectId::new();
+        let first = create_test_collection_file("aaaa", None);
+        let second = create_test_collection_file("AAAA", None);
+        let unique = create_test_collection_file("bbbb", None);
+        let releases = vec![
+            create_test_release(system_id, vec![&first, &unique]),
+            create_test_release(system_id, vec![&second]),
+        ];
+        let groups = find_duplicate_groups(&[first.clone(), unique, second.clone()], &releases);
+        assert_eq!(
+            groups,
+            vec![DuplicateGroup {
+                system_id: Some(system_id),
+                collection_file_type: CollectionFileType::Rom,
+                sha1: "aaaa".to_string(),
+                size: 4,
+                collection_file_ids: vec![first._id.unwrap(), second._id.unwrap()],
+            }]
+        );
+
+        // the copies in another system or of another file type are found at other paths
+        let other_system_releases = vec![
+            create_test_release(system_id, vec![&first]),
+            create_test_release(ObjectId::new(), vec![&second]),
+        ];
+        assert!(
+            find_duplicate_groups(&[first.clone(), second.clone()], &other_system_releases)
+                .is_empty()
+        );
+        let disk_image = CollectionFile {
+            collection_file_type: CollectionFileType::DiskImage,
+            ..second.clone()
+        };
+        assert!(find_duplicate_groups(&[first.clone(), disk_image], &releases).is_empty());
+
+        // files sharing content in the content store are stored once
+        let mut first = first;
+        let mut second = second;
+        first.content_key = Some("aaaa.zip".to_string());
+        second.content_key = Some("aaaa.zip".to_string());
+        assert!(find_duplicate_groups(&[first, second], &releases).is_empty());
+    }
+}
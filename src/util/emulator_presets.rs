@@ -0,0 +1,106 @@
+use std::{fs, path::Path};
+
+use crate::{error::Error, model::emulator_preset::EmulatorPreset};
+
+const BUNDLED_PRESETS: &str = include_str!("../../presets/emulators.json");
+// user presets are read from the JSON files in this directory next to the database
+const USER_PRESETS_DIRECTORY: &str = "emulator_presets";
+
+/// Returns the bundled presets together with the presets in the JSON files of the user presets
+/// directory, sorted by name. A user preset replaces a bundled preset with the same id.
+/// Files that can't be read are skipped.
+pub fn load_emulator_presets() -> Vec<EmulatorPreset> {
+    let mut presets = parse_emulator_presets(BUNDLED_PRESETS).unwrap_or_else(|err| {
+        println!("Failed to parse bundled emulator presets {:?}", err);
+        vec![]
+    });
+    for user_presets in read_user_presets(Path::new(USER_PRESETS_DIRECTORY)) {
+        merge_emulator_presets(&mut presets, user_presets);
+    }
+    presets.sort_by_key(|preset| preset.name.to_lowercase());
+    presets
+}
+
+fn read_user_presets(directory: &Path) -> Vec<Vec<EmulatorPreset>> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return vec![];
+    };
+    let mut file_paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+        })
+        .collect::<Vec<_>>();
+    file_paths.sort();
+    file_paths
+        .into_iter()
+        .filter_map(|file_path| {
+            let result = fs::read_to_string(&file_path)
+                .map_err(|e| Error::IoError(format!("Failed reading {:?}: {}", file_path, e)))
+                .and_then(|json| parse_emulator_presets(&json));
+            match result {
+                Ok(presets) => Some(presets),
+                Err(err) => {
+                    println!("Failed to load emulator presets {:?}", err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+pub fn parse_emulator_presets(json: &str) -> Result<Vec<EmulatorPreset>, Error> {
+    serde_json::from_str(json)
+        .map_err(|e| Error::ParseError(format!("Invalid emulator presets: {}", e)))
+}
+
+fn merge_emulator_presets(presets: &mut Vec<EmulatorPreset>, user_presets: Vec<EmulatorPreset>) {
+    for user_preset in user_presets {
+        match presets
+            .iter_mut()
+            .find(|preset| preset.id == user_preset.id)
+        {
+            Some(preset) => *preset = user_preset,
+            None => presets.push(user_preset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::CollectionFileType;
+
+    #[test]
+    fn test_emulator_presets() {
+        let mut presets = parse_emulator_presets(BUNDLED_PRESETS).unwrap();
+        let vice = presets
+            .iter()
+            .find(|preset| preset.id == "vice-x64sc")
+            .unwrap();
+        assert!(vice.is_for_system("commodore 64"));
+        assert!(vice
+            .supported_file_types
+            .contains(&CollectionFileType::DiskImage));
+
+        let user_presets = parse_emulator_presets(
+            r#"[
+                {"id": "vice-x64sc", "name": "VICE", "executable": "/opt/vice/bin/x64sc"},
+                {"id": "ccs64", "name": "CCS64", "executable": "ccs64", "arguments": "{file}"}
+            ]"#,
+        )
+        .unwrap();
+        let preset_count = presets.len();
+        merge_emulator_presets(&mut presets, user_presets);
+        assert_eq!(presets.len(), preset_count + 1);
+        let vice = presets
+            .iter()
+            .find(|preset| preset.id == "vice-x64sc")
+            .unwrap();
+        assert_eq!(vice.executable, "/opt/vice/bin/x64sc");
+        assert!(vice.supported_file_types.is_empty());
+
+        assert!(parse_emulator_presets("{").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    future::Future,
+    io::{ErrorKind, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use iced::{
+    futures::{
+        channel::mpsc::{self, UnboundedSender},
+        future::join,
+        SinkExt, Stream, StreamExt,
+    },
+    stream,
+};
+
+use crate::error::Error;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+// progress is sent at most once per interval to keep the number of UI updates low
+const REPORT_INTERVAL: u64 = 1024 * 1024;
+
+/// Flag for cancelling a file operation running on another thread.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileOperationProgress {
+    pub description: String,
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+}
+
+impl Display for FileOperationProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:.1} / {:.1} MB",
+            self.description,
+            self.bytes_done as f64 / (1024.0 * 1024.0),
+            self.total_bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FileOperationEvent<T> {
+    Progress(FileOperationProgress),
+    Finished(Result<T, Error>),
+}
+
+/// Sends the progress of a file operation and tells when the operation has been cancelled.
+/// The default reporter doesn't send progress and is never cancelled.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    sender: Option<UnboundedSender<FileOperationProgress>>,
+    cancellation: Cancellation,
+}
+
+impl ProgressReporter {
+    pub fn new(sender: UnboundedSender<FileOperationProgress>, cancellation: Cancellation) -> Self {
+        Self {
+            sender: Some(sender),
+            cancellation,
+        }
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), Error> {
+        if self.cancellation.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sends the progress and returns an error if the operation has been cancelled.
+    pub fn report(
+        &self,
+        description: &str,
+        bytes_done: u64,
+        total_bytes: u64,
+    ) -> Result<(), Error> {
+        if let Some(sender) = &self.sender {
+            // sending fails only when nobody is listening anymore, the operation can still finish
+            let _ = sender.unbounded_send(FileOperationProgress {
+                description: description.to_string(),
+                bytes_done,
+                total_bytes,
+            });
+        }
+        self.check_cancelled()
+    }
+
+    /// Reads the reader to the end in chunks, passes each chunk to consume and reports the progress.
+    /// Returns the number of bytes read.
+    pub fn read_with_progress<R: Read>(
+        &self,
+        reader: &mut R,
+        description: &str,
+        total_bytes: u64,
+        mut consume: impl FnMut(&[u8]) -> std::io::Result<()>,
+    ) -> Result<u64, Error> {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut bytes_done = 0;
+        let mut last_reported = 0;
+        self.report(description, bytes_done, total_bytes)?;
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::IoError(format!("{} failed: {}", description, e))),
+            };
+            consume(&buffer[..read])
+                .map_err(|e| Error::IoError(format!("{} failed: {}", description, e)))?;
+            bytes_done += read as u64;
+            if bytes_done - last_reported >= REPORT_INTERVAL {
+                self.report(description, bytes_done, total_bytes)?;
+                last_reported = bytes_done;
+            }
+        }
+        self.report(description, bytes_done, total_bytes)?;
+        Ok(bytes_done)
+    }
+}
+
+/// Runs a file operation and streams its progress, the last event being the result.
+/// The operation gets a reporter connected to the stream and the given cancellation.
+pub fn run_file_operation<T, F, Fut>(
+    cancellation: Cancellation,
+    operation: F,
+) -> impl Stream<Item = FileOperationEvent<T>>
+where
+    T: Send + 'static,
+    F: FnOnce(ProgressReporter) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T, Error>> + Send + 'static,
+{
+    stream::channel(100, move |mut output| async move {
+        let (sender, mut receiver) = mpsc::unbounded();
+        let operation = operation(ProgressReporter::new(sender, cancellation));
+        let mut progress_output = output.clone();
+        // the receiver ends when the operation has finished and dropped its reporters
+        let forward_progress = async move {
+            while let Some(progress) = receiver.next().await {
+                let _ = progress_output
+                    .send(FileOperationEvent::Progress(progress))
+                    .await;
+            }
+        };
+        let (result, _) = join(operation, forward_progress).await;
+        let _ = output.send(FileOperationEvent::Finished(result)).await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_with_progress() {
+        let (sender, receiver) = mpsc::unbounded();
+        let reporter = ProgressReporter::new(sender, Cancellation::default());
+        let data = vec![1u8; 3 * REPORT_INTERVAL as usize];
+        let mut copy = Vec::new();
+
+        let bytes_done = reporter
+            .read_with_progress(
+                &mut Cursor::new(&data),
+                "Copying",
+                data.len() as u64,
+                |chunk| {
+                    copy.extend_from_slice(chunk);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        drop(reporter);
+
+        assert_eq!(bytes_done, data.len() as u64);
+        assert_eq!(copy, data);
+        let reported = async_std::task::block_on(receiver.collect::<Vec<_>>())
+            .into_iter()
+            .map(|progress| progress.bytes_done)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reported,
+            vec![
+                0,
+                REPORT_INTERVAL,
+                2 * REPORT_INTERVAL,
+                3 * REPORT_INTERVAL,
+                3 * REPORT_INTERVAL
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancelled_read() {
+        let cancellation = Cancellation::default();
+        let (sender, _receiver) = mpsc::unbounded();
+        let reporter = ProgressReporter::new(sender, cancellation.clone());
+        cancellation.cancel();
+
+        let result =
+            reporter.read_with_progress(&mut Cursor::new(vec![1u8; 10]), "Copying", 10, |_| Ok(()));
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+}
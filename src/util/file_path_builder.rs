@@ -3,46 +3,292 @@ use crate::{
     files::get_file_extension,
     model::{
         collection_file::{CollectionFile, CollectionFileType},
-        model::{GetIdString, HasOid, System},
+        model::{GetIdString, HasOid, PlacementRule, Settings, StorageVolume, System},
     },
 };
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+
+/// Human-readable layout offered in the settings.
+pub const DEFAULT_FILE_PATH_TEMPLATE: &str = "{system}/{type}/{release}/{file}";
 
 #[derive(Debug, Clone)]
 pub struct FilePathBuilder {
     pub collection_root_dir: String,
+    // layout of the files added to the collection, files are stored by id when not set
+    pub file_path_template: Option<String>,
+    pub storage_volumes: Vec<StorageVolume>,
+    pub placement_rules: Vec<PlacementRule>,
+    // new files are stored once by content, see build_content_path
+    pub content_addressed_storage: bool,
 }
 
+/// Directory of the content store under the root dir of each volume.
+pub const CONTENT_STORE_DIRECTORY: &str = "objects";
+
+/// Name shown for the collection root dir where volumes are listed.
+pub const COLLECTION_ROOT_VOLUME_NAME: &str = "collection root";
+
 impl FilePathBuilder {
     pub fn new(collection_root_dir: String) -> Self {
         Self {
             collection_root_dir,
+            file_path_template: None,
+            storage_volumes: vec![],
+            placement_rules: vec![],
+            content_addressed_storage: false,
         }
     }
 
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            file_path_template: settings.file_path_template.clone(),
+            storage_volumes: settings.storage_volumes.clone(),
+            placement_rules: settings.placement_rules.clone(),
+            content_addressed_storage: settings.content_addressed_storage,
+            ..Self::new(settings.collection_root_dir.clone())
+        }
+    }
+
+    /// Returns the root dir of the named storage volume, or the collection root dir for None.
+    pub fn get_volume_root(&self, volume: Option<&str>) -> Result<PathBuf, Error> {
+        match volume {
+            None => Ok(PathBuf::from(&self.collection_root_dir)),
+            Some(name) => self
+                .storage_volumes
+                .iter()
+                .find(|storage_volume| storage_volume.name == name)
+                .map(|storage_volume| PathBuf::from(&storage_volume.root_dir))
+                .ok_or_else(|| Error::NotFound(format!("Storage volume {} not found", name))),
+        }
+    }
+
+    /// A volume is online when its root dir exists, an unmounted drive or a disconnected
+    /// network share is offline.
+    pub fn is_volume_online(&self, volume: Option<&str>) -> bool {
+        self.get_volume_root(volume)
+            .is_ok_and(|root| !root.as_os_str().is_empty() && root.is_dir())
+    }
+
+    /// Returns the names of the offline volumes holding any of the files.
+    pub fn get_offline_volumes(&self, collection_files: &[CollectionFile]) -> Vec<String> {
+        let mut offline_volumes = Vec::new();
+        for collection_file in collection_files {
+            let volume = collection_file.volume.as_deref();
+            let name = volume.unwrap_or(COLLECTION_ROOT_VOLUME_NAME).to_string();
+            if !offline_volumes.contains(&name) && !self.is_volume_online(volume) {
+                offline_volumes.push(name);
+            }
+        }
+        offline_volumes
+    }
+
+    /// Returns the volume for a new file by the first matching placement rule.
+    pub fn select_volume(&self, system: &System, file_type: &CollectionFileType) -> Option<String> {
+        self.placement_rules
+            .iter()
+            .find(|rule| rule.matches(system, file_type))
+            .map(|rule| rule.volume.clone())
+    }
+
+    /// Files referencing content in the content store are found by the content key. Files with
+    /// a relative path are in the human-readable layout or were adopted where they were, other
+    /// files are in the id based layout. A file whose migration to the human-readable layout was
+    /// interrupted is still found in the id based layout.
     pub fn build_file_path(
         &self,
         system: &System,
         collection_file: &CollectionFile,
     ) -> Result<PathBuf, Error> {
-        let mut path = PathBuf::from(&self.collection_root_dir);
+        if let Some(content_key) = &collection_file.content_key {
+            return self.build_content_path(collection_file.volume.as_deref(), content_key);
+        }
+        if let Some(relative_path) = &collection_file.relative_path {
+            let path = self
+                .get_volume_root(collection_file.volume.as_deref())?
+                .join(relative_path);
+            if !path.exists() && collection_file._id.is_some() {
+                if let Ok(id_based_path) = self.build_id_based_file_path(system, collection_file) {
+                    if id_based_path.exists() {
+                        return Ok(id_based_path);
+                    }
+                }
+            }
+            return Ok(path);
+        }
+        self.build_id_based_file_path(system, collection_file)
+    }
+
+    /// Returns the path of a file that is found without its system: a file in the content store
+    /// or a file with a relative path. Returns None for a file in the id based layout.
+    pub fn build_file_path_without_system(
+        &self,
+        collection_file: &CollectionFile,
+    ) -> Result<Option<PathBuf>, Error> {
+        let volume = collection_file.volume.as_deref();
+        match (&collection_file.content_key, &collection_file.relative_path) {
+            (Some(content_key), _) => self.build_content_path(volume, content_key).map(Some),
+            (None, Some(relative_path)) => self
+                .get_volume_root(volume)
+                .map(|volume_root| Some(volume_root.join(relative_path))),
+            (None, None) => Ok(None),
+        }
+    }
 
+    /// Content is stored by its key in sub directories named by the first two characters
+    /// of the key, so that no directory gets too many files.
+    pub fn build_content_path(
+        &self,
+        volume: Option<&str>,
+        content_key: &str,
+    ) -> Result<PathBuf, Error> {
+        let prefix = content_key.get(..2).unwrap_or(content_key);
+        Ok(self
+            .get_volume_root(volume)?
+            .join(CONTENT_STORE_DIRECTORY)
+            .join(prefix)
+            .join(content_key))
+    }
+
+    pub fn build_id_based_file_path(
+        &self,
+        system: &System,
+        collection_file: &CollectionFile,
+    ) -> Result<PathBuf, Error> {
         let extension = get_file_extension(Path::new(&collection_file.original_file_name))?;
-        path.push(&system.id().to_string());
-        path.push(&collection_file.collection_file_type.directory());
-        path.push(&collection_file.get_id_string());
-        Ok(path.with_extension(extension))
+        Ok(self
+            .build_target_directory(
+                collection_file.volume.as_deref(),
+                system,
+                &collection_file.collection_file_type,
+            )?
+            .join(collection_file.get_id_string())
+            .with_extension(extension))
     }
 
+    /// Returns the path relative to the collection root for a file added to a release with the
+    /// file path template, or None when files are stored by id. A number is added to the file
+    /// name when the path is already taken.
+    pub fn build_new_relative_path(
+        &self,
+        system: &System,
+        release_name: &str,
+        collection_file: &CollectionFile,
+    ) -> Option<String> {
+        let template = self.file_path_template.as_ref()?;
+        let relative_path = template
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                sanitize_file_name(
+                    &part
+                        .replace("{system}", &system.name)
+                        .replace("{type}", collection_file.collection_file_type.directory())
+                        .replace("{release}", release_name)
+                        .replace("{file}", &collection_file.original_file_name)
+                        .replace(
+                            "{id}",
+                            &collection_file
+                                ._id
+                                .map(|id| id.to_hex())
+                                .unwrap_or_default(),
+                        ),
+                )
+            })
+            .collect::<PathBuf>();
+
+        let root = self
+            .get_volume_root(collection_file.volume.as_deref())
+            .ok()?;
+        let stem = relative_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let extension = relative_path
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+        let mut candidate = relative_path.clone();
+        let mut number = 2;
+        while root.join(&candidate).exists() {
+            candidate = relative_path.with_file_name(format!("{} ({}){}", stem, number, extension));
+            number += 1;
+        }
+        Some(candidate.to_string_lossy().to_string())
+    }
+
+    /// Returns the volume and the path relative to its root when the file is inside the
+    /// collection root dir or a storage volume, meaning that it can be adopted where it is
+    /// instead of copying it.
+    pub fn get_relative_path(&self, file_path: &Path) -> Option<(Option<String>, String)> {
+        let file_path = file_path.canonicalize().ok()?;
+        let volumes = std::iter::once((None, self.collection_root_dir.as_str())).chain(
+            self.storage_volumes.iter().map(|storage_volume| {
+                (
+                    Some(storage_volume.name.clone()),
+                    storage_volume.root_dir.as_str(),
+                )
+            }),
+        );
+        volumes
+            .filter(|(_, root_dir)| !root_dir.is_empty())
+            .find_map(|(volume, root_dir)| {
+                let root_dir = Path::new(root_dir).canonicalize().ok()?;
+                file_path
+                    .strip_prefix(root_dir)
+                    .ok()
+                    .map(|relative_path| (volume, relative_path.to_string_lossy().to_string()))
+            })
+    }
+
+    /// Returns the directory of the system and file type in the id based layout of the volume.
     pub fn build_target_directory(
         &self,
+        volume: Option<&str>,
         system: &System,
         file_type: &CollectionFileType,
-    ) -> PathBuf {
-        let mut path = PathBuf::from(&self.collection_root_dir);
+    ) -> Result<PathBuf, Error> {
+        let mut path = self.get_volume_root(volume)?;
         path.push(&system.id().to_hex());
         path.push(&file_type.directory());
-        path
+        Ok(path)
+    }
+}
+
+/// Checks that the template gives a relative path inside the collection and names each file.
+pub fn validate_file_path_template(template: &str) -> Result<(), Error> {
+    if !template.contains("{file}") && !template.contains("{id}") {
+        return Err(Error::ParseError(
+            "File path template must contain {file} or {id}".to_string(),
+        ));
+    }
+    if Path::new(template)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return Err(Error::ParseError(
+            "File path template must be a relative path without . or ..".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces the characters that aren't allowed in file names on common file systems.
+fn sanitize_file_name(name: &str) -> String {
+    let sanitized = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_end_matches('.')
+        .to_string();
+    match sanitized.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => sanitized,
     }
 }
 
@@ -52,8 +298,8 @@ mod tests {
 
     use super::*;
     use crate::model::{
-        collection_file::{CollectionFileType, FileInfo},
-        model::GetIdString,
+        collection_file::{Checksums, CollectionFileType, FileInfo},
+        model::{GetIdString, PlacementRule, StorageVolume},
     };
     use std::path::PathBuf;
 
@@ -66,17 +312,18 @@ mod tests {
             _id: Some(ObjectId::new()),
             name: "System".to_string(),
             notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
         };
 
         let collection_file = CollectionFile {
-            _id: Some(ObjectId::new()),
-            original_file_name: "file.zip".to_string(),
             is_zip: true,
             files: Some(vec![FileInfo {
                 name: "file1".to_string(),
-                checksum: "checksum".to_string(),
+                size: 0,
+                checksums: Checksums::default(),
             }]),
-            collection_file_type: CollectionFileType::DiskImage,
+            ..CollectionFile::for_test("file.zip", CollectionFileType::DiskImage)
         };
 
         let result = file_path_builder.build_file_path(&system, &collection_file);
@@ -92,6 +339,234 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_adopted_file_path() {
+        let collection_root_dir = std::env::temp_dir().join("test_adopted_file_path");
+        let adopted_file_path = collection_root_dir.join("c64").join("game.d64");
+        std::fs::create_dir_all(adopted_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&adopted_file_path, b"game").unwrap();
+        let outside_file_path = std::env::temp_dir().join("test_adopted_file_path_outside.d64");
+        std::fs::write(&outside_file_path, b"game").unwrap();
+
+        let file_path_builder =
+            FilePathBuilder::new(collection_root_dir.to_string_lossy().to_string());
+        let relative_path = file_path_builder.get_relative_path(&adopted_file_path);
+        assert_eq!(
+            relative_path,
+            Some((
+                None,
+                Path::new("c64")
+                    .join("game.d64")
+                    .to_string_lossy()
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            file_path_builder.get_relative_path(&outside_file_path),
+            None
+        );
+
+        let system = System {
+            _id: Some(ObjectId::new()),
+            name: "System".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        };
+        let collection_file = CollectionFile {
+            size: 4,
+            relative_path: relative_path.map(|(_, relative_path)| relative_path),
+            ..CollectionFile::for_test("game.d64", CollectionFileType::DiskImage)
+        };
+        assert_eq!(
+            file_path_builder
+                .build_file_path(&system, &collection_file)
+                .unwrap(),
+            collection_root_dir.join("c64").join("game.d64")
+        );
+
+        std::fs::remove_dir_all(&collection_root_dir).unwrap();
+        std::fs::remove_file(&outside_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_storage_volumes() {
+        let directory = std::env::temp_dir().join("test_storage_volumes");
+        let _ = std::fs::remove_dir_all(&directory);
+        let scans_root_dir = directory.join("scans");
+        std::fs::create_dir_all(&scans_root_dir).unwrap();
+        std::fs::write(scans_root_dir.join("cover.png"), b"cover").unwrap();
+
+        let system = System {
+            _id: Some(ObjectId::new()),
+            name: "System".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        };
+        let mut file_path_builder =
+            FilePathBuilder::new(directory.join("collection").to_string_lossy().to_string());
+        file_path_builder.storage_volumes = vec![
+            StorageVolume {
+                name: "scans".to_string(),
+                root_dir: scans_root_dir.to_string_lossy().to_string(),
+            },
+            StorageVolume {
+                name: "nas".to_string(),
+                root_dir: directory.join("nas").to_string_lossy().to_string(),
+            },
+        ];
+        file_path_builder.placement_rules = vec![
+            PlacementRule {
+                system_id: None,
+                file_type: Some(CollectionFileType::CoverScan),
+                volume: "scans".to_string(),
+            },
+            PlacementRule {
+                system_id: system._id,
+                file_type: None,
+                volume: "nas".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            file_path_builder.select_volume(&system, &CollectionFileType::CoverScan),
+            Some("scans".to_string())
+        );
+        assert_eq!(
+            file_path_builder.select_volume(&system, &CollectionFileType::DiskImage),
+            Some("nas".to_string())
+        );
+        let other_system = System {
+            _id: Some(ObjectId::new()),
+            name: "Other".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        };
+        assert_eq!(
+            file_path_builder.select_volume(&other_system, &CollectionFileType::DiskImage),
+            None
+        );
+
+        assert_eq!(
+            file_path_builder.get_relative_path(&scans_root_dir.join("cover.png")),
+            Some((Some("scans".to_string()), "cover.png".to_string()))
+        );
+        assert!(file_path_builder.is_volume_online(Some("scans")));
+        assert!(!file_path_builder.is_volume_online(Some("nas")));
+        assert!(!file_path_builder.is_volume_online(Some("unknown")));
+
+        let collection_file = CollectionFile {
+            size: 4,
+            relative_path: Some("game.d64".to_string()),
+            volume: Some("nas".to_string()),
+            ..CollectionFile::for_test("game.d64", CollectionFileType::DiskImage)
+        };
+        assert_eq!(
+            file_path_builder
+                .build_file_path(&system, &collection_file)
+                .unwrap(),
+            directory.join("nas").join("game.d64")
+        );
+        assert_eq!(
+            file_path_builder
+                .build_file_path_without_system(&collection_file)
+                .unwrap(),
+            Some(directory.join("nas").join("game.d64"))
+        );
+        let content_file = CollectionFile {
+            content_key: Some("abcdef.d64".to_string()),
+            ..collection_file.clone()
+        };
+        assert_eq!(
+            file_path_builder
+                .build_file_path_without_system(&content_file)
+                .unwrap(),
+            Some(
+                directory
+                    .join("nas")
+                    .join(CONTENT_STORE_DIRECTORY)
+                    .join("ab")
+                    .join("abcdef.d64")
+            )
+        );
+        let id_based_file = CollectionFile {
+            relative_path: None,
+            ..collection_file.clone()
+        };
+        assert_eq!(
+            file_path_builder
+                .build_file_path_without_system(&id_based_file)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            file_path_builder.get_offline_volumes(&[collection_file]),
+            vec!["nas".to_string()]
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_build_new_relative_path() {
+        let collection_root_dir = std::env::temp_dir().join("test_build_new_relative_path");
+        let _ = std::fs::remove_dir_all(&collection_root_dir);
+        let mut file_path_builder =
+            FilePathBuilder::new(collection_root_dir.to_string_lossy().to_string());
+
+        let system = System {
+            _id: Some(ObjectId::new()),
+            name: "Commodore 64".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        };
+        let collection_file = CollectionFile {
+            size: 4,
+            ..CollectionFile::for_test("game.d64", CollectionFileType::DiskImage)
+        };
+        assert_eq!(
+            file_path_builder.build_new_relative_path(&system, "Game", &collection_file),
+            None
+        );
+
+        file_path_builder.file_path_template = Some(DEFAULT_FILE_PATH_TEMPLATE.to_string());
+        let expected_path = Path::new("Commodore 64")
+            .join("disk_images")
+            .join("Game_ Part 1")
+            .join("game.d64");
+        assert_eq!(
+            file_path_builder.build_new_relative_path(&system, "Game: Part 1", &collection_file),
+            Some(expected_path.to_string_lossy().to_string())
+        );
+
+        let taken_path = collection_root_dir.join(&expected_path);
+        std::fs::create_dir_all(taken_path.parent().unwrap()).unwrap();
+        std::fs::write(&taken_path, b"game").unwrap();
+        assert_eq!(
+            file_path_builder.build_new_relative_path(&system, "Game: Part 1", &collection_file),
+            Some(
+                expected_path
+                    .with_file_name("game (2).d64")
+                    .to_string_lossy()
+                    .to_string()
+            )
+        );
+
+        std::fs::remove_dir_all(&collection_root_dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_file_path_template() {
+        assert!(validate_file_path_template(DEFAULT_FILE_PATH_TEMPLATE).is_ok());
+        assert!(validate_file_path_template("{system}/{id}").is_ok());
+        assert!(validate_file_path_template("{system}/{release}").is_err());
+        assert!(validate_file_path_template("/{system}/{file}").is_err());
+        assert!(validate_file_path_template("../{file}").is_err());
+    }
+
     #[test]
     fn test_build_target_directory() {
         let collection_root_dir = "/home/user/collection".to_string();
@@ -101,11 +576,15 @@ mod tests {
             _id: Some(ObjectId::new()),
             name: "System".to_string(),
             notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
         };
 
         let file_type = CollectionFileType::DiskImage;
 
-        let path = file_path_builder.build_target_directory(&system, &file_type);
+        let path = file_path_builder
+            .build_target_directory(None, &system, &file_type)
+            .unwrap();
         assert_eq!(
             path,
             PathBuf::from(format!(
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use crate::{
+    model::{collection_file::CollectionFile, model::Medium},
+    util::bulk_import::{get_disk_number, guess_file_type},
+};
+
+/// Returns the media of the release in order. The stored media come first, dropping the ones
+/// whose file has been removed from the release. The other media follow ordered by the disk
+/// number in their names: each media file is a medium, or each media entry of an archive when
+/// the archive has them.
+pub fn get_ordered_media(media: &[Medium], files: &[CollectionFile]) -> Vec<Medium> {
+    let all_media = files
+        .iter()
+        .filter(|file| file.collection_file_type.is_media())
+        .flat_map(get_file_media)
+        .collect::<Vec<Medium>>();
+
+    let mut ordered_media = media
+        .iter()
+        .filter_map(|medium| {
+            all_media
+                .iter()
+                .find(|other| is_same_medium(medium, other))
+                .map(|_| medium.clone())
+        })
+        .collect::<Vec<Medium>>();
+
+    let mut other_media = all_media
+        .into_iter()
+        .filter(|medium| {
+            !ordered_media
+                .iter()
+                .any(|ordered| is_same_medium(ordered, medium))
+        })
+        .map(|medium| (get_medium_name(&medium, files), medium))
+        .collect::<Vec<(String, Medium)>>();
+    other_media.sort_by_key(|(name, _)| {
+        (
+            get_disk_number(name).unwrap_or(u32::MAX),
+            name.to_lowercase(),
+        )
+    });
+    ordered_media.extend(other_media.into_iter().map(|(_, medium)| medium));
+    ordered_media
+}
+
+/// Returns the name of the file or the archive entry of the medium.
+pub fn get_medium_name(medium: &Medium, files: &[CollectionFile]) -> String {
+    medium.file_name.clone().unwrap_or_else(|| {
+        files
+            .iter()
+            .find(|file| file._id == Some(medium.collection_file_id))
+            .map(|file| file.original_file_name.clone())
+            .unwrap_or_default()
+    })
+}
+
+fn get_file_media(file: &CollectionFile) -> Vec<Medium> {
+    let Some(collection_file_id) = file._id else {
+        return vec![];
+    };
+    let entries = file
+        .files
+        .iter()
+        .flatten()
+        .filter(|file_info| {
+            guess_file_type(Path::new(&file_info.name))
+                .is_some_and(|file_type| file_type.is_media())
+        })
+        .map(|file_info| Medium {
+            collection_file_id,
+            file_name: Some(file_info.name.clone()),
+            label: String::new(),
+        })
+        .collect::<Vec<Medium>>();
+    if entries.is_empty() {
+        vec![Medium {
+            collection_file_id,
+            file_name: None,
+            label: String::new(),
+        }]
+    } else {
+        entries
+    }
+}
+
+fn is_same_medium(medium: &Medium, other: &Medium) -> bool {
+    medium.collection_file_id == other.collection_file_id && medium.file_name == other.file_name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::CollectionFileType;
+    use bson::oid::ObjectId;
+
+    #[test]
+    fn test_get_ordered_media() {
+        let files =
+            vec![
+                CollectionFile::for_test("Game (Disk 2 of 2).d64", CollectionFileType::DiskImage),
+                CollectionFile::for_test("Game.zip", CollectionFileType::DiskImage)
+                    .with_entries(&["Game (Side B).d64", "Game (Side A).d64", "readme.txt"]),
+                CollectionFile::for_test("Cover.png", CollectionFileType::CoverScan),
+            ];
+        let names = |media: &[Medium]| {
+            media
+                .iter()
+                .map(|medium| get_medium_name(medium, &files))
+                .collect::<Vec<String>>()
+        };
+
+        let media = get_ordered_media(&[], &files);
+        assert_eq!(
+            names(&media),
+            vec![
+                "Game (Side A).d64",
+                "Game (Disk 2 of 2).d64",
+                "Game (Side B).d64"
+            ]
+        );
+
+        // the stored media come first, a medium of a removed file is dropped
+        let stored_media = vec![
+            Medium {
+                label: "Disk 2".to_string(),
+                ..media[1].clone()
+            },
+            Medium {
+                collection_file_id: ObjectId::new(),
+                file_name: None,
+                label: "Removed".to_string(),
+            },
+        ];
+        let media = get_ordered_media(&stored_media, &files);
+        assert_eq!(
+            names(&media),
+            vec![
+                "Game (Disk 2 of 2).d64",
+                "Game (Side A).d64",
+                "Game (Side B).d64"
+            ]
+        );
+        assert_eq!(media[0].label, "Disk 2");
+    }
+}
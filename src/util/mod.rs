@@ -1,2 +1,14 @@
+pub mod bulk_import;
+pub mod checksum;
+pub mod dat_parser;
+pub mod dat_verifier;
+pub mod duplicates;
+pub mod emulator_presets;
+pub mod file_operation;
 pub mod file_path_builder;
 pub mod image;
+pub mod media;
+pub mod patch;
+pub mod retroarch;
+pub mod save_data;
+pub mod storage_report;
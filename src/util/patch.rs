@@ -0,0 +1,567 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use async_std::task::spawn_blocking;
+use zip::ZipArchive;
+
+use crate::{error::Error, model::collection_file::CollectionFile};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+const UPS_MAGIC: &[u8] = b"UPS1";
+// BPS and UPS patches end with the CRC32 of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+// the patched file can be at most this many times the size of the source and the patch, a larger
+// size in the header of a corrupted patch is rejected before allocating the target
+const MAX_TARGET_SIZE_FACTOR: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+    Ups,
+}
+
+impl PatchFormat {
+    pub fn detect(patch: &[u8]) -> Result<Self, Error> {
+        if patch.starts_with(IPS_MAGIC) {
+            Ok(PatchFormat::Ips)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Ok(PatchFormat::Bps)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Ok(PatchFormat::Ups)
+        } else {
+            Err(Error::ParseError(
+                "Unknown patch format, expected IPS, BPS or UPS".to_string(),
+            ))
+        }
+    }
+}
+
+struct Footer {
+    source_crc32: u32,
+    target_crc32: u32,
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        Self { data, position }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.position)
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let truncated = || Error::ParseError("Patch is truncated".to_string());
+        let end = self.position.checked_add(length).ok_or_else(truncated)?;
+        let bytes = self.data.get(self.position..end).ok_or_else(truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<usize, Error> {
+        let bytes = self.read_bytes(2)?;
+        Ok(usize::from(bytes[0]) << 8 | usize::from(bytes[1]))
+    }
+
+    fn read_u24(&mut self) -> Result<usize, Error> {
+        Ok(read_u24(self.read_bytes(3)?))
+    }
+
+    /// Reads a variable length number of BPS and UPS patches.
+    fn read_number(&mut self) -> Result<usize, Error> {
+        let invalid_number = || Error::ParseError("Invalid number in patch".to_string());
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_u8()?;
+            number = usize::from(byte & 0x7f)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or_else(invalid_number)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(invalid_number)?;
+            number = number.checked_add(shift).ok_or_else(invalid_number)?;
+        }
+    }
+
+    /// Reads a relative offset of a BPS patch, the lowest bit is the sign.
+    fn read_offset(&mut self, position: usize) -> Result<usize, Error> {
+        let number = self.read_number()?;
+        let offset = number >> 1;
+        if number & 1 == 1 {
+            position.checked_sub(offset)
+        } else {
+            position.checked_add(offset)
+        }
+        .ok_or_else(|| Error::ParseError("Invalid offset in patch".to_string()))
+    }
+}
+
+fn read_u24(bytes: &[u8]) -> usize {
+    usize::from(bytes[0]) << 16 | usize::from(bytes[1]) << 8 | usize::from(bytes[2])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_footer(patch: &[u8]) -> Result<Footer, Error> {
+    if patch.len() < BPS_MAGIC.len() + FOOTER_SIZE {
+        return Err(Error::ParseError("Patch is truncated".to_string()));
+    }
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    if crc32fast::hash(&patch[..patch.len() - 4]) != read_u32(&footer[8..]) {
+        return Err(Error::ParseError("Patch is corrupted".to_string()));
+    }
+    Ok(Footer {
+        source_crc32: read_u32(&footer[..4]),
+        target_crc32: read_u32(&footer[4..8]),
+    })
+}
+
+/// Returns the CRC32 of the file the patch is made for, IPS patches don't have it.
+pub fn get_source_crc32(patch: &[u8]) -> Result<Option<u32>, Error> {
+    match PatchFormat::detect(patch)? {
+        PatchFormat::Ips => Ok(None),
+        PatchFormat::Bps | PatchFormat::Ups => Ok(Some(read_footer(patch)?.source_crc32)),
+    }
+}
+
+/// Checks that the patch is made for the base file or for one of the files inside it by
+/// comparing the CRC32 stored in the patch with the checksums of the base file. IPS patches and
+/// base files without checksums can't be checked.
+pub fn validate_patch(patch: &[u8], base_file: &CollectionFile) -> Result<(), Error> {
+    let Some(source_crc32) = get_source_crc32(patch)? else {
+        return Ok(());
+    };
+    let source_crc32 = format!("{:08x}", source_crc32);
+    let base_crc32s = std::iter::once(&base_file.checksums)
+        .chain(
+            base_file
+                .files
+                .iter()
+                .flatten()
+                .map(|file_info| &file_info.checksums),
+        )
+        .map(|checksums| checksums.crc32.as_str())
+        .filter(|crc32| !crc32.is_empty())
+        .collect::<Vec<&str>>();
+    if base_crc32s.is_empty()
+        || base_crc32s
+            .iter()
+            .any(|crc32| crc32.eq_ignore_ascii_case(&source_crc32))
+    {
+        Ok(())
+    } else {
+        Err(Error::ParseError(format!(
+            "Patch is made for a file with CRC32 {}, not for {}",
+            source_crc32, base_file.original_file_name
+        )))
+    }
+}
+
+/// Returns the source patched with the patch.
+pub fn apply_patch(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, Error> {
+    match PatchFormat::detect(patch)? {
+        PatchFormat::Ips => apply_ips_patch(patch, source),
+        PatchFormat::Bps => apply_bps_patch(patch, source),
+        PatchFormat::Ups => apply_ups_patch(patch, source),
+    }
+}
+
+fn write_at(target: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    if target.len() < offset + data.len() {
+        target.resize(offset + data.len(), 0);
+    }
+    target[offset..offset + data.len()].copy_from_slice(data);
+}
+
+fn apply_ips_patch(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut target = source.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+    loop {
+        let offset_bytes = reader.read_bytes(3)?;
+        if offset_bytes == IPS_EOF {
+            break;
+        }
+        let offset = read_u24(offset_bytes);
+        let size = reader.read_u16()?;
+        if size == 0 {
+            // run-length encoded record
+            let count = reader.read_u16()?;
+            let value = reader.read_u8()?;
+            write_at(&mut target, offset, &vec![value; count]);
+        } else {
+            write_at(&mut target, offset, reader.read_bytes(size)?);
+        }
+    }
+    // an extension of the format truncates the target to the size after the end marker
+    if reader.remaining() >= 3 {
+        let size = reader.read_u24()?;
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+fn check_source(source: &[u8], source_size: usize, footer: &Footer) -> Result<(), Error> {
+    if source.len() != source_size || crc32fast::hash(source) != footer.source_crc32 {
+        return Err(Error::ParseError(
+            "Patch is not made for this file".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_target_size(target_size: usize, patch: &[u8], source: &[u8]) -> Result<(), Error> {
+    let max_target_size = source
+        .len()
+        .saturating_add(patch.len())
+        .saturating_mul(MAX_TARGET_SIZE_FACTOR);
+    if target_size > max_target_size {
+        return Err(Error::ParseError(format!(
+            "Patch expects a file of {} bytes, which is too large",
+            target_size
+        )));
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], footer: &Footer) -> Result<(), Error> {
+    if crc32fast::hash(target) != footer.target_crc32 {
+        return Err(Error::ParseError(
+            "Patched file doesn't match the checksum of the patch".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn apply_bps_patch(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, Error> {
+    let footer = read_footer(patch)?;
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], BPS_MAGIC.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+    check_source(source, source_size, &footer)?;
+    check_target_size(target_size, patch, source)?;
+
+    let out_of_range = || Error::ParseError("Patch refers outside of the file".to_string());
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_position = 0;
+    let mut target_position = 0;
+    while reader.remaining() > 0 {
+        let action = reader.read_number()?;
+        let length = (action >> 2) + 1;
+        let end = target
+            .len()
+            .checked_add(length)
+            .filter(|end| *end <= target_size)
+            .ok_or_else(|| {
+                Error::ParseError("Patched file is larger than the patch expects".to_string())
+            })?;
+        match action & 3 {
+            // source read
+            0 => {
+                let bytes = source.get(target.len()..end).ok_or_else(out_of_range)?;
+                target.extend_from_slice(bytes);
+            }
+            // target read
+            1 => target.extend_from_slice(reader.read_bytes(length)?),
+            // source copy
+            2 => {
+                source_position = reader.read_offset(source_position)?;
+                let bytes = source_position
+                    .checked_add(length)
+                    .and_then(|end| source.get(source_position..end))
+                    .ok_or_else(out_of_range)?;
+                target.extend_from_slice(bytes);
+                source_position += length;
+            }
+            // target copy, the copied bytes can overlap the bytes being written
+            _ => {
+                target_position = reader.read_offset(target_position)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_position).ok_or_else(out_of_range)?;
+                    target.push(byte);
+                    target_position += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(Error::ParseError(
+            "Patched file has a different size than the patch expects".to_string(),
+        ));
+    }
+    check_target(&target, &footer)?;
+    Ok(target)
+}
+
+fn apply_ups_patch(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, Error> {
+    let footer = read_footer(patch)?;
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], UPS_MAGIC.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    check_source(source, source_size, &footer)?;
+    check_target_size(target_size, patch, source)?;
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+    let invalid_offset = || Error::ParseError("Invalid offset in patch".to_string());
+    let mut position: usize = 0;
+    while reader.remaining() > 0 {
+        position = position
+            .checked_add(reader.read_number()?)
+            .ok_or_else(invalid_offset)?;
+        // bytes are XORed with the source until a zero byte
+        loop {
+            let byte = reader.read_u8()?;
+            position = position.checked_add(1).ok_or_else(invalid_offset)?;
+            if byte == 0 {
+                break;
+            }
+            if let Some(target_byte) = target.get_mut(position - 1) {
+                *target_byte ^= byte;
+            }
+        }
+    }
+    check_target(&target, &footer)?;
+    Ok(target)
+}
+
+/// Reads the patch from the collection, a zipped patch is read from the first file in the
+/// archive.
+pub fn read_patch_file(file_path: &Path, file: &CollectionFile) -> Result<Vec<u8>, Error> {
+    if !file.is_zip {
+        return fs::read(file_path)
+            .map_err(|e| Error::IoError(format!("Failed reading patch {:?}: {}", file_path, e)));
+    }
+    let zip_file = File::open(file_path)
+        .map_err(|e| Error::IoError(format!("Failed opening patch {:?}: {}", file_path, e)))?;
+    let mut zip = ZipArchive::new(zip_file)
+        .map_err(|e| Error::IoError(format!("Failed to create Zip archive: {}", e)))?;
+    for i in 0..zip.len() {
+        let mut entry = zip
+            .by_index(i)
+            .map_err(|e| Error::IoError(format!("Failed to read file in Zip archive: {}", e)))?;
+        if entry.is_file() {
+            let mut patch = Vec::new();
+            entry
+                .read_to_end(&mut patch)
+                .map_err(|e| Error::IoError(format!("Failed reading patch: {}", e)))?;
+            return Ok(patch);
+        }
+    }
+    Err(Error::NotFound(format!(
+        "No patch in {}",
+        file.original_file_name
+    )))
+}
+
+/// Reads the patch from the collection and validates it against the base file.
+pub async fn validate_patch_file(
+    patch_path: PathBuf,
+    patch_file: CollectionFile,
+    base_file: CollectionFile,
+) -> Result<(), Error> {
+    spawn_blocking(move || {
+        let patch = read_patch_file(&patch_path, &patch_file)?;
+        validate_patch(&patch, &base_file)
+    })
+    .await
+}
+
+/// Applies the patches in order to the file, replacing its content. Takes the patches with
+/// their paths in the collection. This is blocking and should be run with spawn_blocking.
+pub fn apply_patches(file_path: &Path, patches: &[(PathBuf, CollectionFile)]) -> Result<(), Error> {
+    let mut content = fs::read(file_path)
+        .map_err(|e| Error::IoError(format!("Failed reading {:?}: {}", file_path, e)))?;
+    for (patch_path, patch_file) in patches {
+        let patch = read_patch_file(patch_path, patch_file)?;
+        content = apply_patch(&patch, &content).map_err(|e| {
+            Error::ParseError(format!(
+                "Failed applying {}: {}",
+                patch_file.original_file_name, e
+            ))
+        })?;
+    }
+    fs::write(file_path, content)
+        .map_err(|e| Error::IoError(format!("Failed writing {:?}: {}", file_path, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::{Checksums, CollectionFileType};
+
+    fn write_number(patch: &mut Vec<u8>, mut number: usize) {
+        loop {
+            let byte = (number & 0x7f) as u8;
+            number >>= 7;
+            if number == 0 {
+                patch.push(0x80 | byte);
+                return;
+            }
+            patch.push(byte);
+            number -= 1;
+        }
+    }
+
+    fn write_footer(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let patch_crc32 = crc32fast::hash(patch);
+        patch.extend_from_slice(&patch_crc32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_apply_ips_patch() {
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 2]);
+        patch.extend_from_slice(b"AB");
+        patch.extend_from_slice(&[0, 0, 5, 0, 0, 0, 3]);
+        patch.push(b'Z');
+        patch.extend_from_slice(IPS_EOF);
+
+        assert_eq!(apply_patch(&patch, b"0123").unwrap(), b"0AB3\0ZZZ");
+        assert_eq!(get_source_crc32(&patch).unwrap(), None);
+
+        // truncated to the size after the end marker
+        patch.extend_from_slice(&[0, 0, 2]);
+        assert_eq!(apply_patch(&patch, b"0123").unwrap(), b"0A");
+    }
+
+    #[test]
+    fn test_apply_bps_patch() {
+        let source = b"hello world";
+        let target = b"hello there worldhello";
+        let mut patch = BPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, target.len());
+        write_number(&mut patch, 0);
+        // source read "hello "
+        write_number(&mut patch, (6 - 1) << 2);
+        // target read "there "
+        write_number(&mut patch, ((6 - 1) << 2) | 1);
+        patch.extend_from_slice(b"there ");
+        // source copy "world" from offset 6
+        write_number(&mut patch, ((5 - 1) << 2) | 2);
+        write_number(&mut patch, 6 << 1);
+        // target copy "hello" from offset 0
+        write_number(&mut patch, ((5 - 1) << 2) | 3);
+        write_number(&mut patch, 0);
+        write_footer(&mut patch, source, target);
+
+        assert_eq!(apply_patch(&patch, source).unwrap(), target);
+        assert!(apply_patch(&patch, b"hello moon!").is_err());
+
+        let mut base_file = CollectionFile {
+            _id: None,
+            is_zip: true,
+            checksums: Checksums {
+                crc32: format!("{:08x}", crc32fast::hash(b"zipped")),
+                ..Checksums::default()
+            },
+            ..CollectionFile::for_test("hello.zip", CollectionFileType::Rom)
+        };
+        assert!(validate_patch(&patch, &base_file).is_err());
+        base_file.files = Some(vec![crate::model::collection_file::FileInfo {
+            name: "hello.bin".to_string(),
+            size: source.len() as u64,
+            checksums: Checksums {
+                crc32: format!("{:08X}", crc32fast::hash(source)),
+                ..Checksums::default()
+            },
+        }]);
+        assert!(validate_patch(&patch, &base_file).is_ok());
+    }
+
+    #[test]
+    fn test_apply_ups_patch() {
+        let source = b"abcdef";
+        let target = b"abXdefgh";
+        let mut patch = UPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, target.len());
+        // skip 2 bytes, change c to X
+        write_number(&mut patch, 2);
+        patch.extend_from_slice(&[b'c' ^ b'X', 0]);
+        // the end of the previous change covers d, skip e and f and append g and h
+        write_number(&mut patch, 2);
+        patch.extend_from_slice(&[b'g', b'h', 0]);
+        write_footer(&mut patch, source, target);
+
+        assert_eq!(apply_patch(&patch, source).unwrap(), target);
+        assert_eq!(
+            get_source_crc32(&patch).unwrap(),
+            Some(crc32fast::hash(source))
+        );
+    }
+    #[test]
+    fn test_apply_truncated_patch() {
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 2]);
+        patch.push(b'A');
+        assert!(apply_patch(&patch, b"0123").is_err());
+
+        let source = b"hello";
+        let mut patch = BPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_footer(&mut patch, source, source);
+        assert!(apply_patch(&patch, source).is_err());
+    }
+
+    #[test]
+    fn test_apply_patch_with_oversized_header() {
+        let source = b"hello";
+
+        // metadata larger than the patch
+        let mut patch = BPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, usize::MAX);
+        write_footer(&mut patch, source, source);
+        assert!(apply_patch(&patch, source).is_err());
+
+        // target too large to be made from the source and the patch
+        let mut patch = BPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, usize::MAX);
+        write_number(&mut patch, 0);
+        write_footer(&mut patch, source, source);
+        assert!(apply_patch(&patch, source).is_err());
+        let mut patch = UPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, usize::MAX);
+        write_footer(&mut patch, source, source);
+        assert!(apply_patch(&patch, source).is_err());
+
+        // actions writing past the target size of the header
+        let mut patch = BPS_MAGIC.to_vec();
+        write_number(&mut patch, source.len());
+        write_number(&mut patch, 2);
+        write_number(&mut patch, 0);
+        write_number(&mut patch, (2 - 1) << 2);
+        write_number(&mut patch, ((usize::MAX >> 2) << 2) | 3);
+        write_number(&mut patch, 0);
+        write_footer(&mut patch, source, b"he");
+        assert!(apply_patch(&patch, source).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::Error,
+    model::model::{Emulator, EmulatorKind, RetroArchCore, System},
+};
+
+const CORE_EXTENSIONS: [&str; 3] = ["so", "dll", "dylib"];
+
+#[derive(Debug, Default, PartialEq)]
+struct CoreInfo {
+    display_name: Option<String>,
+    supported_extensions: Vec<String>,
+}
+
+/// Returns the cores in the cores directory sorted by name. The details of a core are read from
+/// its info file, either next to the core or in the info directory beside the cores directory.
+/// A core without an info file is named after its file and accepts any extension.
+pub fn find_retroarch_cores(cores_directory: &Path) -> Result<Vec<RetroArchCore>, Error> {
+    let entries = fs::read_dir(cores_directory).map_err(|e| {
+        Error::IoError(format!(
+            "Failed reading RetroArch cores directory {:?}: {}",
+            cores_directory, e
+        ))
+    })?;
+    let mut cores = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| {
+                CORE_EXTENSIONS
+                    .iter()
+                    .any(|core_extension| extension.eq_ignore_ascii_case(core_extension))
+            })
+        })
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_string_lossy().to_string();
+            let core_name = path.file_stem()?.to_string_lossy().to_string();
+            let info = read_core_info(cores_directory, &core_name);
+            Some(RetroArchCore {
+                file_name,
+                display_name: info.display_name.unwrap_or(core_name),
+                supported_extensions: info.supported_extensions,
+            })
+        })
+        .collect::<Vec<RetroArchCore>>();
+    cores.sort_by_key(|core| core.display_name.to_lowercase());
+    Ok(cores)
+}
+
+fn read_core_info(cores_directory: &Path, core_name: &str) -> CoreInfo {
+    let info_file_name = format!("{}.info", core_name);
+    let mut info_file_paths = vec![cores_directory.join(&info_file_name)];
+    if let Some(parent) = cores_directory.parent() {
+        info_file_paths.push(parent.join("info").join(&info_file_name));
+    }
+    info_file_paths
+        .iter()
+        .find_map(|info_file_path| fs::read_to_string(info_file_path).ok())
+        .map(|text| parse_core_info(&text))
+        .unwrap_or_default()
+}
+
+/// Parses the `key = "value"` lines of a core info file, the supported extensions are
+/// separated by `|`.
+fn parse_core_info(text: &str) -> CoreInfo {
+    let mut info = CoreInfo::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        match key.trim() {
+            "display_name" => info.display_name = Some(value.to_string()),
+            "supported_extensions" => {
+                info.supported_extensions = value
+                    .split('|')
+                    .map(|extension| extension.trim().to_lowercase())
+                    .filter(|extension| !extension.is_empty())
+                    .collect()
+            }
+            _ => {}
+        }
+    }
+    info
+}
+
+/// Returns the emulators with each RetroArch emulator replaced by an emulator for every system
+/// with a RetroArch core. These keep the id of the RetroArch emulator, so the emulator order
+/// and the overrides refer to them, and accept the extensions of the core.
+pub fn expand_retroarch_emulators(emulators: Vec<Emulator>, systems: &[System]) -> Vec<Emulator> {
+    emulators
+        .into_iter()
+        .flat_map(|emulator| match emulator.kind {
+            EmulatorKind::Standalone => vec![emulator],
+            EmulatorKind::RetroArch => systems
+                .iter()
+                .filter_map(|system| {
+                    let core = system.retroarch_core.as_ref()?;
+                    Some(Emulator {
+                        name: format!("{} ({})", emulator.name, core.display_name),
+                        system_id: system._id,
+                        supported_file_type_extensions: core.supported_extensions.clone(),
+                        ..emulator.clone()
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Returns the path of the RetroArch core of the system in the cores directory.
+pub fn get_retroarch_core_path(
+    system: &System,
+    cores_directory: Option<&str>,
+) -> Result<PathBuf, Error> {
+    let core = system
+        .retroarch_core
+        .as_ref()
+        .ok_or_else(|| Error::NotFound(format!("No RetroArch core chosen for {}", system.name)))?;
+    let cores_directory = cores_directory
+        .ok_or_else(|| Error::NotFound("RetroArch cores directory not set".to_string()))?;
+    let core_path = Path::new(cores_directory).join(&core.file_name);
+    if !core_path.is_file() {
+        return Err(Error::NotFound(format!(
+            "RetroArch core {:?} not found",
+            core_path
+        )));
+    }
+    Ok(core_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::oid::ObjectId;
+    use std::env;
+
+    #[test]
+    fn test_find_retroarch_cores() {
+        let directory = env::temp_dir().join("test_find_retroarch_cores");
+        let cores_directory = directory.join("cores");
+        let info_directory = directory.join("info");
+        fs::create_dir_all(&cores_directory).unwrap();
+        fs::create_dir_all(&info_directory).unwrap();
+        fs::write(cores_directory.join("vice_x64sc_libretro.so"), "").unwrap();
+        fs::write(cores_directory.join("stella_libretro.so"), "").unwrap();
+        fs::write(cores_directory.join("readme.txt"), "").unwrap();
+        fs::write(
+            info_directory.join("vice_x64sc_libretro.info"),
+            "# Software Information\n\
+             display_name = \"Commodore - C64 (VICE x64sc, accurate)\"\n\
+             supported_extensions = \"d64|T64|prg\"\n",
+        )
+        .unwrap();
+
+        let cores = find_retroarch_cores(&cores_directory).unwrap();
+        assert_eq!(
+            cores,
+            vec![
+                RetroArchCore {
+                    file_name: "vice_x64sc_libretro.so".to_string(),
+                    display_name: "Commodore - C64 (VICE x64sc, accurate)".to_string(),
+                    supported_extensions: vec![
+                        "d64".to_string(),
+                        "t64".to_string(),
+                        "prg".to_string()
+                    ],
+                },
+                RetroArchCore {
+                    file_name: "stella_libretro.so".to_string(),
+                    display_name: "stella_libretro".to_string(),
+                    supported_extensions: vec![],
+                },
+            ]
+        );
+
+        let system = System {
+            retroarch_core: Some(cores[1].clone()),
+            ..System::default()
+        };
+        assert_eq!(
+            get_retroarch_core_path(&system, cores_directory.to_str()).unwrap(),
+            cores_directory.join("stella_libretro.so")
+        );
+        assert!(get_retroarch_core_path(&system, None).is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_expand_retroarch_emulators() {
+        let core = RetroArchCore {
+            file_name: "stella_libretro.so".to_string(),
+            display_name: "Stella".to_string(),
+            supported_extensions: vec!["a26".to_string()],
+        };
+        let systems = vec![
+            System {
+                _id: Some(ObjectId::new()),
+                name: "Atari 2600".to_string(),
+                retroarch_core: Some(core),
+                ..System::default()
+            },
+            System {
+                _id: Some(ObjectId::new()),
+                name: "Amiga".to_string(),
+                ..System::default()
+            },
+        ];
+        let retroarch = Emulator {
+            _id: Some(ObjectId::new()),
+            name: "RetroArch".to_string(),
+            kind: EmulatorKind::RetroArch,
+            ..Emulator::default()
+        };
+        let fs_uae = Emulator {
+            _id: Some(ObjectId::new()),
+            name: "FS-UAE".to_string(),
+            system_id: systems[1]._id,
+            ..Emulator::default()
+        };
+
+        let emulators = expand_retroarch_emulators(vec![retroarch.clone(), fs_uae], &systems);
+        assert_eq!(emulators.len(), 2);
+        assert_eq!(emulators[0]._id, retroarch._id);
+        assert_eq!(emulators[0].name, "RetroArch (Stella)");
+        assert_eq!(emulators[0].system_id, systems[0]._id);
+        assert_eq!(emulators[0].supported_file_type_extensions, vec!["a26"]);
+        assert_eq!(emulators[1].name, "FS-UAE");
+        assert_eq!(systems[0].order_emulators(&emulators).len(), 1);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bson::oid::ObjectId;
+
+use crate::{
+    error::Error,
+    files::read_picked_file,
+    job_queue::JobQueue,
+    model::{
+        collection_file::{CollectionFile, CollectionFileType},
+        job::JobKind,
+        model::{FileImportMode, SaveDataKind, SaveDataRule},
+    },
+    util::file_operation::ProgressReporter,
+};
+
+// directory under the temporary directory holding the save data waiting to be imported
+const SAVE_DATA_IMPORT_DIRECTORY: &str = "iced_game_collection_save_data";
+
+/// A file the emulator wrote while playing, found with a save data rule of the emulator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveData {
+    pub kind: SaveDataKind,
+    pub file_path: PathBuf,
+}
+
+// size and modification time of a file, a file is changed when either of them changes
+type FileState = (u64, Option<SystemTime>);
+
+/// Returns the state of the files matching the save data rules before the emulator is run.
+pub fn scan_save_data(
+    target_path: &Path,
+    rules: &[SaveDataRule],
+    name: &str,
+) -> HashMap<PathBuf, FileState> {
+    find_save_data(target_path, rules, name)
+        .into_iter()
+        .filter_map(|save_data| {
+            get_file_state(&save_data.file_path).map(|state| (save_data.file_path, state))
+        })
+        .collect()
+}
+
+/// Returns the files matching the save data rules that are new or have changed since the scan.
+pub fn find_changed_save_data(
+    target_path: &Path,
+    rules: &[SaveDataRule],
+    name: &str,
+    scanned_files: &HashMap<PathBuf, FileState>,
+) -> Vec<SaveData> {
+    find_save_data(target_path, rules, name)
+        .into_iter()
+        .filter(|save_data| {
+            get_file_state(&save_data.file_path).as_ref() != scanned_files.get(&save_data.file_path)
+        })
+        .collect()
+}
+
+/// Copies the memory snapshots of the release to where the save data rules of the emulator
+/// expect them. Snapshots not matching any of the rules are left out.
+pub fn restore_save_data(
+    target_path: &Path,
+    rules: &[SaveDataRule],
+    name: &str,
+    snapshots: &[(PathBuf, CollectionFile)],
+) -> Result<(), Error> {
+    for (file_path, snapshot) in snapshots {
+        let Some(restore_path) =
+            get_restore_path(target_path, rules, name, &snapshot.original_file_name)
+        else {
+            continue;
+        };
+        if let Some(directory) = restore_path.parent() {
+            fs::create_dir_all(directory).map_err(|e| {
+                Error::IoError(format!("Failed creating directory {:?}: {}", directory, e))
+            })?;
+        }
+        fs::copy(file_path, &restore_path).map_err(|e| {
+            Error::IoError(format!(
+                "Failed restoring {} to {:?}: {}",
+                snapshot.original_file_name, restore_path, e
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Reads the save data and imports it to the release as a memory snapshot. The file is copied
+/// to a directory of its own and moved to the collection from there, so the directory the
+/// release was run in can be removed before the import job has run.
+pub async fn import_save_data(
+    save_data: SaveData,
+    release_id: ObjectId,
+    system_id: ObjectId,
+) -> Result<(), Error> {
+    let file_name = save_data
+        .file_path
+        .file_name()
+        .ok_or_else(|| Error::IoError(format!("No file name in {:?}", save_data.file_path)))?
+        .to_string_lossy()
+        .to_string();
+    let collection_file_id = ObjectId::new();
+    let import_directory = std::env::temp_dir().join(SAVE_DATA_IMPORT_DIRECTORY);
+    let file_path = import_directory.join(format!("{}_{}", collection_file_id.to_hex(), file_name));
+    async_std::fs::create_dir_all(&import_directory)
+        .await
+        .map_err(|e| {
+            Error::IoError(format!(
+                "Failed creating directory {:?}: {}",
+                import_directory, e
+            ))
+        })?;
+    async_std::fs::copy(&save_data.file_path, &file_path)
+        .await
+        .map_err(|e| {
+            Error::IoError(format!(
+                "Failed copying {:?} to {:?}: {}",
+                save_data.file_path, file_path, e
+            ))
+        })?;
+
+    let picked_file = read_picked_file(&file_path, &ProgressReporter::default()).await?;
+    let collection_file = CollectionFile {
+        _id: Some(collection_file_id),
+        original_file_name: file_name,
+        is_zip: picked_file.is_zip,
+        files: picked_file.files,
+        collection_file_type: CollectionFileType::MemorySnapshot,
+        size: picked_file.size,
+        checksums: picked_file.checksums,
+        relative_path: None,
+        volume: None,
+        content_key: None,
+        base_file_id: None,
+    };
+    JobQueue::get_instance().submit(JobKind::ImportFile {
+        file_path,
+        collection_file: Box::new(collection_file),
+        system_id,
+        release_id,
+        file_import_mode: FileImportMode::Move,
+    })?;
+    Ok(())
+}
+
+fn find_save_data(target_path: &Path, rules: &[SaveDataRule], name: &str) -> Vec<SaveData> {
+    let mut save_data: Vec<SaveData> = vec![];
+    for rule in rules {
+        let pattern = rule.pattern.replace("{name}", name);
+        let (directory, file_pattern) = split_pattern(&pattern);
+        let Ok(entries) = fs::read_dir(target_path.join(directory)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_path = entry.path();
+            let is_match = file_path.is_file()
+                && matches_pattern(file_pattern, &entry.file_name().to_string_lossy());
+            if is_match
+                && !save_data
+                    .iter()
+                    .any(|existing| existing.file_path == file_path)
+            {
+                save_data.push(SaveData {
+                    kind: rule.kind.clone(),
+                    file_path,
+                });
+            }
+        }
+    }
+    save_data.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    save_data
+}
+
+fn get_restore_path(
+    target_path: &Path,
+    rules: &[SaveDataRule],
+    name: &str,
+    file_name: &str,
+) -> Option<PathBuf> {
+    rules.iter().find_map(|rule| {
+        let pattern = rule.pattern.replace("{name}", name);
+        let (directory, file_pattern) = split_pattern(&pattern);
+        matches_pattern(file_pattern, file_name)
+            .then(|| target_path.join(directory).join(file_name))
+    })
+}
+
+fn get_file_state(file_path: &Path) -> Option<FileState> {
+    let metadata = fs::metadata(file_path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+// splits the pattern into the directory and the file name pattern
+fn split_pattern(pattern: &str) -> (&str, &str) {
+    pattern.rsplit_once(['/', '\\']).unwrap_or(("", pattern))
+}
+
+/// Whether the file name matches the pattern, ignoring the case. `*` matches any characters
+/// and `?` a single character.
+fn matches_pattern(pattern: &str, file_name: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<char>>();
+    let file_name = file_name.to_lowercase().chars().collect::<Vec<char>>();
+    let (mut p, mut f) = (0, 0);
+    // position of the last star in the pattern and of the file name it was matched at
+    let mut star: Option<(usize, usize)> = None;
+    while f < file_name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == file_name[f]) {
+            p += 1;
+            f += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, f));
+            p += 1;
+        } else if let Some((star_p, star_f)) = star {
+            // the star matches one more character
+            p = star_p + 1;
+            f = star_f + 1;
+            star = Some((star_p, star_f + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("Game.srm", "game.SRM"));
+        assert!(matches_pattern("*.state*", "Game.state"));
+        assert!(matches_pattern("*.state*", "Game.state12"));
+        assert!(matches_pattern("Game.st?", "Game.st1"));
+        assert!(!matches_pattern("*.srm", "Game.srm.bak"));
+        assert!(!matches_pattern("Game.st?", "Game.st"));
+    }
+
+    #[test]
+    fn test_save_data() {
+        let target_path = std::env::temp_dir().join(format!("save_data_{}", ObjectId::new()));
+        fs::create_dir_all(target_path.join("saves")).unwrap();
+        fs::write(target_path.join("Game.d64"), "disk").unwrap();
+        fs::write(target_path.join("saves").join("Game.srm"), "save").unwrap();
+        let rules = vec![
+            SaveDataRule {
+                kind: SaveDataKind::BatterySave,
+                pattern: "saves/{name}.srm".to_string(),
+            },
+            SaveDataRule {
+                kind: SaveDataKind::SaveState,
+                pattern: "{name}.state*".to_string(),
+            },
+            SaveDataRule {
+                kind: SaveDataKind::ModifiedMedia,
+                pattern: "*.d64".to_string(),
+            },
+        ];
+
+        let scanned_files = scan_save_data(&target_path, &rules, "Game");
+        assert_eq!(scanned_files.len(), 2);
+        assert!(find_changed_save_data(&target_path, &rules, "Game", &scanned_files).is_empty());
+
+        // the emulator writes a save state and modifies the disk
+        fs::write(target_path.join("Game.state1"), "state").unwrap();
+        fs::write(target_path.join("Game.d64"), "modified disk").unwrap();
+        fs::write(target_path.join("Other.state1"), "state").unwrap();
+        assert_eq!(
+            find_changed_save_data(&target_path, &rules, "Game", &scanned_files),
+            vec![
+                SaveData {
+                    kind: SaveDataKind::ModifiedMedia,
+                    file_path: target_path.join("Game.d64"),
+                },
+                SaveData {
+                    kind: SaveDataKind::SaveState,
+                    file_path: target_path.join("Game.state1"),
+                },
+            ]
+        );
+
+        // the snapshot is restored to the directory of the rule it matches
+        let snapshot = CollectionFile::for_test("Game.srm", CollectionFileType::MemorySnapshot);
+        let snapshot_path = target_path.join("snapshot.srm");
+        fs::write(&snapshot_path, "restored save").unwrap();
+        let unmatched_snapshot = CollectionFile {
+            original_file_name: "Game.txt".to_string(),
+            ..snapshot.clone()
+        };
+        restore_save_data(
+            &target_path,
+            &rules,
+            "Game",
+            &[
+                (snapshot_path.clone(), snapshot),
+                (snapshot_path, unmatched_snapshot),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(target_path.join("saves").join("Game.srm")).unwrap(),
+            "restored save"
+        );
+        assert!(!target_path.join("Game.txt").exists());
+        fs::remove_dir_all(&target_path).unwrap();
+    }
+}
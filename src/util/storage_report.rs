@@ -0,0 +1,405 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+use async_std::task::spawn_blocking;
+use bson::oid::ObjectId;
+
+use crate::{
+    database_with_polo::DatabaseWithPolo,
+    error::Error,
+    model::{
+        collection_file::CollectionFile,
+        model::{Release, System},
+    },
+    util::file_path_builder::FilePathBuilder,
+};
+
+const LARGEST_FILES_COUNT: usize = 20;
+
+/// Number of files and their total size in bytes for a system, a file type, a release or a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRow {
+    pub name: String,
+    pub file_count: usize,
+    pub size: u64,
+}
+
+/// Files added to the collection in a month and the size of the collection at the end of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrowthRow {
+    pub month: String,
+    pub added_file_count: usize,
+    pub added_size: u64,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageReport {
+    pub total_size: u64,
+    pub file_count: usize,
+    pub by_system: Vec<UsageRow>,
+    pub by_file_type: Vec<UsageRow>,
+    pub by_release: Vec<UsageRow>,
+    pub largest_files: Vec<UsageRow>,
+    pub growth: Vec<GrowthRow>,
+}
+
+/// Creates the report from the database with the size of each file read from the collection
+/// tree. The size recorded at import is used for files that can't be read, for example files
+/// on an offline volume.
+pub async fn create_storage_report() -> Result<StorageReport, Error> {
+    let db = DatabaseWithPolo::get_instance();
+    let file_path_builder = FilePathBuilder::from_settings(&db.get_settings()?);
+    let releases = db.get_releases()?;
+    let systems = db.get_systems()?;
+    let collection_files = db.get_all_collection_files()?;
+    spawn_blocking(move || {
+        let file_sizes =
+            read_file_sizes(&file_path_builder, &releases, &systems, &collection_files);
+        Ok(build_storage_report(
+            &releases,
+            &systems,
+            &collection_files,
+            &file_sizes,
+        ))
+    })
+    .await
+}
+
+fn read_file_sizes(
+    file_path_builder: &FilePathBuilder,
+    releases: &[Release],
+    systems: &[System],
+    collection_files: &[CollectionFile],
+) -> HashMap<ObjectId, u64> {
+    let file_systems = get_file_systems(releases, systems);
+    collection_files
+        .iter()
+        .filter_map(|collection_file| {
+            let id = collection_file._id?;
+            let system = file_systems.get(&id)?;
+            let file_path = file_path_builder
+                .build_file_path(system, collection_file)
+                .ok()?;
+            let size = std::fs::metadata(file_path).ok()?.len();
+            Some((id, size))
+        })
+        .collect()
+}
+
+/// Returns the system of each collection file, taken from the first release having the file.
+fn get_file_systems<'a>(
+    releases: &[Release],
+    systems: &'a [System],
+) -> HashMap<ObjectId, &'a System> {
+    let mut file_systems = HashMap::new();
+    for release in releases {
+        let Some(system) = systems
+            .iter()
+            .find(|system| system._id.is_some() && system._id == release.system_id)
+        else {
+            continue;
+        };
+        for file_id in &release.files {
+            file_systems.entry(*file_id).or_insert(system);
+        }
+    }
+    file_systems
+}
+
+/// Builds the report from the collection files, counting each file once for the totals, the
+/// systems and the file types. A file shared by releases is counted in each of them.
+/// Files sharing content in the content store take the space of the content once in the totals,
+/// the systems, the file types and the largest files, the releases show the size of their files.
+/// Takes the sizes read from the collection tree, other files are counted with their
+/// recorded size.
+pub fn build_storage_report(
+    releases: &[Release],
+    systems: &[System],
+    collection_files: &[CollectionFile],
+    file_sizes: &HashMap<ObjectId, u64>,
+) -> StorageReport {
+    let get_size = |collection_file: &CollectionFile| {
+        collection_file
+            ._id
+            .and_then(|id| file_sizes.get(&id).copied())
+            .unwrap_or(collection_file.size)
+    };
+    let file_systems = get_file_systems(releases, systems);
+    let files_by_id = collection_files
+        .iter()
+        .filter_map(|collection_file| collection_file._id.map(|id| (id, collection_file)))
+        .collect::<HashMap<ObjectId, &CollectionFile>>();
+
+    let mut by_system = BTreeMap::new();
+    let mut by_file_type = BTreeMap::new();
+    let mut added_by_month = BTreeMap::new();
+    let mut largest_files = Vec::new();
+    let mut total_size = 0;
+    let mut stored_content_keys = HashSet::new();
+    for collection_file in collection_files {
+        let is_stored = collection_file
+            .content_key
+            .as_ref()
+            .is_none_or(|content_key| stored_content_keys.insert(content_key));
+        let size = if is_stored {
+            let size = get_size(collection_file);
+            largest_files.push(UsageRow {
+                name: collection_file.original_file_name.clone(),
+                file_count: 1,
+                size,
+            });
+            size
+        } else {
+            0
+        };
+        total_size += size;
+        let system_name = collection_file
+            ._id
+            .and_then(|id| file_systems.get(&id))
+            .map_or("No system".to_string(), |system| system.name.clone());
+        add_to_row(&mut by_system, system_name, size);
+        add_to_row(
+            &mut by_file_type,
+            collection_file.collection_file_type.to_string(),
+            size,
+        );
+        // the object id tells when the file was added
+        if let Some(month) = collection_file._id.and_then(|id| {
+            id.timestamp()
+                .try_to_rfc3339_string()
+                .ok()
+                .map(|timestamp| timestamp[..7].to_string())
+        }) {
+            let (file_count, added_size) = added_by_month.entry(month).or_insert((0, 0));
+            *file_count += 1;
+            *added_size += size;
+        }
+    }
+
+    let mut by_release = releases
+        .iter()
+        .map(|release| {
+            let files = release
+                .files
+                .iter()
+                .filter_map(|id| files_by_id.get(id))
+                .collect::<Vec<_>>();
+            UsageRow {
+                name: release.name.clone(),
+                file_count: files.len(),
+                size: files
+                    .iter()
+                    .map(|collection_file| get_size(collection_file))
+                    .sum(),
+            }
+        })
+        .collect::<Vec<UsageRow>>();
+    by_release.sort_by_key(|row| Reverse(row.size));
+
+    largest_files.sort_by_key(|row| Reverse(row.size));
+    largest_files.truncate(LARGEST_FILES_COUNT);
+
+    let mut collection_size = 0;
+    let growth = added_by_month
+        .into_iter()
+        .map(|(month, (added_file_count, added_size))| {
+            collection_size += added_size;
+            GrowthRow {
+                month,
+                added_file_count,
+                added_size,
+                total_size: collection_size,
+            }
+        })
+        .collect();
+
+    StorageReport {
+        total_size,
+        file_count: collection_files.len(),
+        by_system: sorted_by_size(by_system),
+        by_file_type: sorted_by_size(by_file_type),
+        by_release,
+        largest_files,
+        growth,
+    }
+}
+
+fn add_to_row(rows: &mut BTreeMap<String, UsageRow>, name: String, size: u64) {
+    let row = rows.entry(name.clone()).or_insert_with(|| UsageRow {
+        name,
+        file_count: 0,
+        size: 0,
+    });
+    row.file_count += 1;
+    row.size += size;
+}
+
+fn sorted_by_size(rows: BTreeMap<String, UsageRow>) -> Vec<UsageRow> {
+    let mut rows = rows.into_values().collect::<Vec<UsageRow>>();
+    rows.sort_by_key(|row| Reverse(row.size));
+    rows
+}
+
+/// Writes the report as CSV with the section of each row in the first column.
+pub fn storage_report_to_csv(report: &StorageReport) -> String {
+    let mut csv = String::from("section,name,files,bytes,total bytes\n");
+    csv.push_str(&format!(
+        "total,{},{},{},\n",
+        escape_csv_field("Collection"),
+        report.file_count,
+        report.total_size
+    ));
+    for (section, rows) in [
+        ("system", &report.by_system),
+        ("file type", &report.by_file_type),
+        ("release", &report.by_release),
+        ("largest file", &report.largest_files),
+    ] {
+        for row in rows {
+            csv.push_str(&format!(
+                "{},{},{},{},\n",
+                section,
+                escape_csv_field(&row.name),
+                row.file_count,
+                row.size
+            ));
+        }
+    }
+    for row in &report.growth {
+        csv.push_str(&format!(
+            "growth,{},{},{},{}\n",
+            row.month, row.added_file_count, row.added_size, row.total_size
+        ));
+    }
+    csv
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::collection_file::CollectionFileType;
+
+    fn create_collection_file(id: ObjectId, name: &str, size: u64) -> CollectionFile {
+        CollectionFile {
+            _id: Some(id),
+            size,
+            ..CollectionFile::for_test(name, CollectionFileType::DiskImage)
+        }
+    }
+
+    #[test]
+    fn test_build_storage_report() {
+        let system_id = ObjectId::new();
+        let systems = vec![System {
+            _id: Some(system_id),
+            name: "Commodore 64".to_string(),
+            notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
+        }];
+        let disk_id = ObjectId::new();
+        let scan_id = ObjectId::new();
+        let mut scan = create_collection_file(scan_id, "Cover, front.bmp", 300);
+        scan.collection_file_type = CollectionFileType::CoverScan;
+        let collection_files = vec![create_collection_file(disk_id, "Game.d64", 100), scan];
+        let releases = vec![
+            Release {
+                name: "Game".to_string(),
+                system_id: Some(system_id),
+                files: vec![disk_id, scan_id],
+                games: vec![],
+                _id: None,
+                startup_file: None,
+                emulator_id: None,
+                emulator_override: None,
+                media: vec![],
+            },
+            Release {
+                name: "Game (Alt)".to_string(),
+                system_id: Some(system_id),
+                files: vec![disk_id],
+                games: vec![],
+                _id: None,
+                startup_file: None,
+                emulator_id: None,
+                emulator_override: None,
+                media: vec![],
+            },
+        ];
+        // the file in the collection tree is larger than the recorded size
+        let file_sizes = HashMap::from([(disk_id, 150)]);
+
+        let report = build_storage_report(&releases, &systems, &collection_files, &file_sizes);
+        assert_eq!(report.total_size, 450);
+        assert_eq!(report.file_count, 2);
+        assert_eq!(
+            report.by_system,
+            vec![UsageRow {
+                name: "Commodore 64".to_string(),
+                file_count: 2,
+                size: 450
+            }]
+        );
+        assert_eq!(report.by_file_type[0].name, "Cover Scan");
+        assert_eq!(report.by_file_type[1].size, 150);
+        assert_eq!(report.by_release[0].size, 450);
+        assert_eq!(report.by_release[1].size, 150);
+        assert_eq!(report.largest_files[0].name, "Cover, front.bmp");
+        assert_eq!(report.growth.len(), 1);
+        assert_eq!(report.growth[0].total_size, 450);
+
+        let csv = storage_report_to_csv(&report);
+        assert!(csv.starts_with("section,name,files,bytes,total bytes\ntotal,Collection,2,450,\n"));
+        assert!(csv.contains("largest file,\"Cover, front.bmp\",1,300,\n"));
+        assert!(csv.ends_with(&format!("growth,{},2,450,450\n", report.growth[0].month)));
+    }
+
+    #[test]
+    fn test_build_storage_report_with_shared_content() {
+        let disk_id = ObjectId::new();
+        let copy_id = ObjectId::new();
+        let mut disk = create_collection_file(disk_id, "Game.d64", 100);
+        disk.content_key = Some("abc".to_string());
+        let mut copy = create_collection_file(copy_id, "Game (Copy).d64", 100);
+        copy.content_key = Some("abc".to_string());
+        let releases = vec![Release {
+            name: "Game".to_string(),
+            system_id: None,
+            files: vec![disk_id, copy_id],
+            games: vec![],
+            _id: None,
+            startup_file: None,
+            emulator_id: None,
+            emulator_override: None,
+            media: vec![],
+        }];
+
+        let report = build_storage_report(&releases, &[], &[disk, copy], &HashMap::new());
+        assert_eq!(report.total_size, 100);
+        assert_eq!(report.file_count, 2);
+        assert_eq!(
+            report.by_system,
+            vec![UsageRow {
+                name: "No system".to_string(),
+                file_count: 2,
+                size: 100
+            }]
+        );
+        assert_eq!(report.by_file_type[0].size, 100);
+        assert_eq!(report.largest_files.len(), 1);
+        assert_eq!(report.growth[0].total_size, 100);
+        // the release shows the size of both files
+        assert_eq!(report.by_release[0].size, 200);
+    }
+}
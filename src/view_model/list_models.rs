@@ -5,8 +5,12 @@ use bson::oid::ObjectId;
 
 use crate::{
     error::Error,
-    model::model::{Game, HasOid, System},
-    repository::repository::{GamesReadRepository, ReleaseReadRepository, SystemReadRepository},
+    model::model::{Emulator, Game, HasOid, System},
+    repository::repository::{
+        CollectionFilesReadRepository, GamesReadRepository, ReleaseReadRepository,
+        SystemReadRepository,
+    },
+    util::file_path_builder::FilePathBuilder,
 };
 
 #[derive(Debug, Clone)]
@@ -14,6 +18,8 @@ pub struct GameListModel {
     pub id: ObjectId,
     pub name: String,
     pub can_delete: bool,
+    // release started with the Play button of the game
+    pub play_release_id: Option<ObjectId>,
 }
 
 impl From<&Game> for GameListModel {
@@ -22,18 +28,35 @@ impl From<&Game> for GameListModel {
             id: game.id(),
             name: game.name.clone(),
             can_delete: false,
+            play_release_id: None,
         }
     }
 }
 
 pub fn get_games_as_list_model<R>(repository: &R) -> Result<Vec<GameListModel>, Error>
 where
-    R: GamesReadRepository,
+    R: GamesReadRepository + ReleaseReadRepository + SystemReadRepository,
 {
     let games = repository.get_all_games()?;
     let mut list_models: Vec<GameListModel> = games.iter().map(GameListModel::from).collect();
     for game in &mut list_models {
         game.can_delete = !repository.is_game_in_release(&game.id)?;
+        // a game is played from the first of its releases having an emulator to run with
+        for release in repository.get_releases_with_game(&game.id)? {
+            let has_default_emulator = match &release.system_id {
+                Some(system_id) => repository
+                    .get_system(system_id)?
+                    .is_some_and(|system| !system.emulator_order.is_empty()),
+                None => false,
+            };
+            if release.emulator_override.is_some()
+                || release.emulator_id.is_some()
+                || has_default_emulator
+            {
+                game.play_release_id = release._id;
+                break;
+            }
+        }
     }
     Ok(list_models)
 }
@@ -79,20 +102,54 @@ where
     Ok(list_models)
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmulatorListModel {
+    pub id: ObjectId,
+    pub name: String,
+}
+
+impl Display for EmulatorListModel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl From<&Emulator> for EmulatorListModel {
+    fn from(emulator: &Emulator) -> Self {
+        EmulatorListModel {
+            id: emulator.id(),
+            name: emulator.name.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ReleaseListModel {
     pub id: ObjectId,
     pub name: String,
     pub system_name: String,
     pub can_delete: bool,
+    // storage volumes holding files of the release that are offline
+    pub offline_volumes: Vec<String>,
+}
+
+impl ReleaseListModel {
+    pub fn availability(&self) -> String {
+        if self.offline_volumes.is_empty() {
+            String::new()
+        } else {
+            format!("Unavailable, {} offline", self.offline_volumes.join(", "))
+        }
+    }
 }
 
 pub fn get_releases_in_list_model<R>(
     repository: &R,
     game_id: &ObjectId,
+    file_path_builder: &FilePathBuilder,
 ) -> Result<Vec<ReleaseListModel>, Error>
 where
-    R: ReleaseReadRepository + SystemReadRepository,
+    R: ReleaseReadRepository + SystemReadRepository + CollectionFilesReadRepository,
 {
     let releases = repository.get_releases_with_game(game_id)?;
     let mut list_models: Vec<ReleaseListModel> = Vec::new();
@@ -103,11 +160,14 @@ where
             .expect("System not found")
             .name;
         let can_delete = release.files.is_empty();
+        let offline_volumes = file_path_builder
+            .get_offline_volumes(&repository.get_collection_files(&release.files)?);
         list_models.push(ReleaseListModel {
             id: release.id(),
             name: release.name.clone(),
             system_name,
             can_delete,
+            offline_volumes,
         });
     }
     Ok(list_models)
@@ -4,12 +4,13 @@ use crate::{
     error::Error,
     model::{
         collection_file::CollectionFile,
-        model::{Game, HasOid, System},
+        model::{Emulator, EmulatorOverride, Game, HasOid, Medium, StartupFile, System},
     },
     repository::repository::{
         CollectionFilesReadRepository, GamesReadRepository, ReleaseReadRepository,
         SystemReadRepository,
     },
+    util::media::get_ordered_media,
 };
 
 #[derive(Debug, Clone)]
@@ -20,6 +21,148 @@ pub struct ReleaseViewModel {
     pub files: Vec<CollectionFile>,
     // Release can be a single game or compilation of games
     pub games: Vec<Game>,
+    pub startup_file: Option<StartupFile>,
+    pub emulator_id: Option<ObjectId>,
+    pub emulator_override: Option<EmulatorOverride>,
+    pub media: Vec<Medium>,
+}
+
+impl ReleaseViewModel {
+    /// Returns the startup file chosen for the release. When none has been chosen a release
+    /// with a single file to run starts from that file.
+    pub fn get_startup_file(&self) -> Option<StartupFile> {
+        if self.startup_file.is_some() {
+            return self.startup_file.clone();
+        }
+        let mut files_to_run = self
+            .files
+            .iter()
+            .filter(|file| file.collection_file_type.is_media());
+        match (files_to_run.next(), files_to_run.next()) {
+            (Some(file), None) => Some(StartupFile {
+                collection_file_id: file._id?,
+                file_name: match file.files.as_deref() {
+                    Some([file_info]) => Some(file_info.name.clone()),
+                    _ => None,
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the emulators of the system in the order of preference with the emulator
+    /// overridden for the release first.
+    pub fn get_emulators<'a>(&self, emulators: &'a [Emulator]) -> Vec<&'a Emulator> {
+        let mut emulators = self.system.order_emulators(emulators);
+        if let Some(emulator_override) = &self.emulator_override {
+            emulators.sort_by_key(|emulator| emulator._id != Some(emulator_override.emulator_id));
+        }
+        emulators
+    }
+
+    /// Returns the emulator the Play button runs the release with: the overridden emulator,
+    /// the emulator the release was last run with or the default emulator of the system.
+    pub fn get_play_emulator<'a>(&self, emulators: &'a [Emulator]) -> Option<&'a Emulator> {
+        let emulators_for_release = self.get_emulators(emulators);
+        let find_emulator = |emulator_id: ObjectId| {
+            emulators_for_release
+                .iter()
+                .find(|emulator| emulator._id == Some(emulator_id))
+                .copied()
+        };
+        self.emulator_override
+            .as_ref()
+            .and_then(|emulator_override| find_emulator(emulator_override.emulator_id))
+            .or_else(|| self.emulator_id.and_then(find_emulator))
+            .or_else(|| self.system.get_default_emulator(emulators))
+    }
+
+    /// Returns the arguments added for the release when it's run with the emulator.
+    pub fn get_extra_arguments(&self, emulator: &Emulator) -> String {
+        match &self.emulator_override {
+            Some(emulator_override) if emulator._id == Some(emulator_override.emulator_id) => {
+                emulator_override.arguments.clone()
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// Returns the entry chosen inside the file when it's the startup file of the release.
+    pub fn get_startup_entry(&self, file_id: &ObjectId) -> Option<String> {
+        self.get_startup_file()
+            .filter(|startup_file| startup_file.collection_file_id == *file_id)
+            .and_then(|startup_file| startup_file.file_name)
+    }
+
+    /// Returns the name of the file the emulator is started with from the startup file.
+    pub fn get_startup_file_name(&self, emulator: &Emulator) -> Option<String> {
+        let startup_file = self.get_startup_file()?;
+        let file = self
+            .files
+            .iter()
+            .find(|file| file._id == Some(startup_file.collection_file_id))?;
+        self.get_file_name_to_run(emulator, file)
+    }
+
+    /// Returns the names of the files the emulator is given for the media of the release in
+    /// the order of the media. An extracted archive gives the names of its media entries, other
+    /// files their own name.
+    pub fn get_media_file_names(&self, emulator: &Emulator) -> Vec<String> {
+        let mut file_names: Vec<String> = vec![];
+        for medium in get_ordered_media(&self.media, &self.files) {
+            let Some(file) = self
+                .files
+                .iter()
+                .find(|file| file._id == Some(medium.collection_file_id))
+            else {
+                continue;
+            };
+            let startup_file_names = emulator.get_startup_file_names(file);
+            let medium_file_names = match (&medium.file_name, emulator.extract_files) {
+                (Some(file_name), true) => startup_file_names
+                    .into_iter()
+                    .filter(|name| name == file_name)
+                    .collect(),
+                _ => startup_file_names,
+            };
+            for file_name in medium_file_names {
+                if !file_names.contains(&file_name) {
+                    file_names.push(file_name);
+                }
+            }
+        }
+        file_names
+    }
+
+    /// Returns the patches among the selected patches that are applied to the file, in the order
+    /// of the files of the release.
+    pub fn get_patches(&self, file_id: &ObjectId, patch_ids: &[ObjectId]) -> Vec<CollectionFile> {
+        self.files
+            .iter()
+            .filter(|file| {
+                file.base_file_id == Some(*file_id)
+                    && file._id.is_some_and(|id| patch_ids.contains(&id))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the name of the file the emulator is started with from the file. Emulators
+    /// extracting the archives are started with the chosen entry of the archive, others with
+    /// the file itself. Returns None when the emulator doesn't support the file.
+    pub fn get_file_name_to_run(
+        &self,
+        emulator: &Emulator,
+        file: &CollectionFile,
+    ) -> Option<String> {
+        let file_names = emulator.get_startup_file_names(file);
+        match (&file.files, emulator.extract_files) {
+            (Some(_), true) => self
+                .get_startup_entry(&file._id?)
+                .filter(|file_name| file_names.contains(file_name)),
+            _ => file_names.into_iter().next(),
+        }
+    }
 }
 
 impl Default for ReleaseViewModel {
@@ -30,6 +173,10 @@ impl Default for ReleaseViewModel {
             system: System::default(),
             files: vec![],
             games: vec![],
+            startup_file: None,
+            emulator_id: None,
+            emulator_override: None,
+            media: vec![],
         }
     }
 }
@@ -62,6 +209,10 @@ where
                 system,
                 files,
                 games,
+                startup_file: release.startup_file.clone(),
+                emulator_id: release.emulator_id,
+                emulator_override: release.emulator_override.clone(),
+                media: release.media.clone(),
             })),
             // TODO: there probably should be a db model with obligatory system_id and save model with optional system_id
             _ => Ok(None),
@@ -76,8 +227,8 @@ mod tests {
     use super::*;
     use crate::{
         model::{
-            collection_file::{CollectionFile, CollectionFileType},
-            model::{Game, Release, System},
+            collection_file::{Checksums, CollectionFile, CollectionFileType, FileInfo},
+            model::{EmulatorKind, Game, Release, System},
         },
         repository::mock_repository::MockRepository,
     };
@@ -97,6 +248,10 @@ mod tests {
             games: vec![game_id.clone()],
             files: vec![file_id.clone()],
             system_id: Some(system_id.clone()),
+            startup_file: None,
+            emulator_id: None,
+            emulator_override: None,
+            media: vec![],
         };
 
         let game = Game {
@@ -106,16 +261,16 @@ mod tests {
 
         let collection_file = CollectionFile {
             _id: Some(file_id.clone()),
-            original_file_name: "test_file.zip".to_string(),
             is_zip: true,
-            files: None,
-            collection_file_type: CollectionFileType::DiskImage,
+            ..CollectionFile::for_test("test_file.zip", CollectionFileType::DiskImage)
         };
 
         let system = System {
             _id: Some(system_id.clone()),
             name: "Test System".to_string(),
             notes: None,
+            emulator_order: vec![],
+            retroarch_core: None,
         };
 
         let mut releases = HashMap::new();
@@ -149,4 +304,127 @@ mod tests {
         assert_eq!(release_view_model.files.len(), 1);
         assert_eq!(release_view_model.files[0].id(), file_id);
     }
+
+    #[test]
+    fn test_get_startup_file_name() {
+        let file_id = ObjectId::new();
+        let file_info = |name: &str| FileInfo {
+            name: name.to_string(),
+            size: 0,
+            checksums: Checksums::default(),
+        };
+        let mut release = ReleaseViewModel {
+            files: vec![CollectionFile {
+                _id: Some(file_id),
+                is_zip: true,
+                files: Some(vec![file_info("Disk 1.d64")]),
+                ..CollectionFile::for_test("Game.zip", CollectionFileType::DiskImage)
+            }],
+            ..ReleaseViewModel::default()
+        };
+        let mut emulator = Emulator {
+            _id: None,
+            name: "VICE".to_string(),
+            executable: "x64".to_string(),
+            arguments: String::new(),
+            system_id: None,
+            extract_files: true,
+            supported_file_type_extensions: vec![],
+            notes: None,
+            supported_file_types: vec![],
+            multiple_media: false,
+            kind: EmulatorKind::default(),
+            supports_playlists: false,
+            save_data_rules: vec![],
+        };
+
+        // the only entry of the only file to run is used until a startup file is chosen
+        assert_eq!(
+            release.get_startup_file_name(&emulator),
+            Some("Disk 1.d64".to_string())
+        );
+
+        release.files[0].files = Some(vec![file_info("Disk 1.d64"), file_info("Disk 2.d64")]);
+        assert_eq!(release.get_startup_file_name(&emulator), None);
+
+        release.startup_file = Some(StartupFile {
+            collection_file_id: file_id,
+            file_name: Some("Disk 2.d64".to_string()),
+        });
+        assert_eq!(
+            release.get_startup_file_name(&emulator),
+            Some("Disk 2.d64".to_string())
+        );
+
+        emulator.extract_files = false;
+        assert_eq!(
+            release.get_startup_file_name(&emulator),
+            Some("Game.zip".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_play_emulator() {
+        let system_id = ObjectId::new();
+        let create_emulator = |name: &str, system_id: ObjectId| Emulator {
+            _id: Some(ObjectId::new()),
+            name: name.to_string(),
+            executable: name.to_string(),
+            arguments: String::new(),
+            system_id: Some(system_id),
+            extract_files: false,
+            supported_file_type_extensions: vec![],
+            notes: None,
+            supported_file_types: vec![],
+            multiple_media: false,
+            kind: EmulatorKind::default(),
+            supports_playlists: false,
+            save_data_rules: vec![],
+        };
+        let emulators = vec![
+            create_emulator("x64", system_id),
+            create_emulator("x64sc", system_id),
+            create_emulator("fs-uae", ObjectId::new()),
+            create_emulator("micro64", system_id),
+        ];
+        let id = |index: usize| emulators[index]._id.unwrap();
+        let mut release = ReleaseViewModel {
+            system: System {
+                _id: Some(system_id),
+                name: "Commodore 64".to_string(),
+                notes: None,
+                emulator_order: vec![id(1), id(0)],
+                retroarch_core: None,
+            },
+            ..ReleaseViewModel::default()
+        };
+
+        let names = |release: &ReleaseViewModel| {
+            release
+                .get_emulators(&emulators)
+                .iter()
+                .map(|emulator| emulator.name.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(names(&release), vec!["x64sc", "x64", "micro64"]);
+        assert_eq!(release.get_play_emulator(&emulators).unwrap().name, "x64sc");
+
+        release.emulator_id = Some(id(0));
+        assert_eq!(release.get_play_emulator(&emulators).unwrap().name, "x64");
+
+        release.emulator_override = Some(EmulatorOverride {
+            emulator_id: id(3),
+            arguments: "-pal -truedrive".to_string(),
+        });
+        assert_eq!(names(&release), vec!["micro64", "x64sc", "x64"]);
+        assert_eq!(
+            release.get_play_emulator(&emulators).unwrap().name,
+            "micro64"
+        );
+        assert_eq!(
+            release.get_extra_arguments(&emulators[3]),
+            "-pal -truedrive"
+        );
+        assert_eq!(release.get_extra_arguments(&emulators[0]), "");
+    }
 }